@@ -7,108 +7,44 @@ pub mod library;
 use crate::runner;
 use crate::stats::ProgramEvalStats;
 use crate::HarvestResult;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
+pub use run_test_vectors::{parse_test_vectors, TestCase};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::Duration;
 
-/// Represents the expected stdout pattern in a test case
-/// Used for tractor test cases
-#[derive(Default, Debug, Serialize, Deserialize, Clone)]
-pub struct StdoutPattern {
-    pub pattern: String,
-    #[serde(skip_serializing_if = "std::ops::Not::not")]
-    #[serde(default)]
-    pub is_regex: bool,
-}
-
-/// Represents a test case with command arguments, input, and expected output
-/// Used for tractor test cases
-#[derive(Default, Debug, Serialize, Deserialize, Clone)]
-pub struct TestCase {
-    #[serde(default)]
-    pub argv: Vec<String>,
-    #[serde(default)]
-    pub stdin: Option<String>,
-    #[serde(default)]
-    pub stdout: StdoutPattern,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(default)]
-    pub rc: Option<usize>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(default)]
-    pub has_ub: Option<String>,
-    #[serde(skip)] // Don't serialize/deserialize this field as it's not part of the JSON
-    pub filename: String,
-}
-
-impl TestCase {
-    /// Writes the TestCase to a JSON file on disk
-    /// Creates the directory structure {output_dir}/failed_tests/ and
-    /// writes to {output_dir}/failed_tests/{filename}
-    pub fn write_to_disk(&self, output_dir: &Path) -> HarvestResult<()> {
-        let failed_tests_dir = output_dir.join("failed_tests");
-
-        // Create the failed_tests directory if it doesn't exist
-        fs::create_dir_all(&failed_tests_dir).map_err(|e| {
-            format!(
-                "Failed to create directory {}: {}",
-                failed_tests_dir.display(),
-                e
-            )
-        })?;
-
-        let file_path = failed_tests_dir.join(&self.filename);
-
-        log::info!("Saving failed test case to {}", file_path.display());
-
-        let mut json_str = serde_json::to_string_pretty(self)
-            .map_err(|e| format!("Failed to serialize TestCase to JSON: {}", e))?;
-        json_str.push('\n'); // To be consistent with original test vector styling
+/// Writes `test_case` to a JSON file on disk
+/// Creates the directory structure {output_dir}/failed_tests/ and
+/// writes to {output_dir}/failed_tests/{filename}
+pub fn write_failed_test_case(test_case: &TestCase, output_dir: &Path) -> HarvestResult<()> {
+    let failed_tests_dir = output_dir.join("failed_tests");
 
-        fs::write(&file_path, json_str).map_err(|e| {
-            format!(
-                "Failed to write TestCase to file {}: {}",
-                file_path.display(),
-                e
-            )
-        })?;
-
-        Ok(())
-    }
-}
-
-/// Parses a JSON file into a TestCase struct
-pub fn parse_test_case_json<P: AsRef<Path>>(file_path: P) -> HarvestResult<TestCase> {
-    let file_path = file_path.as_ref();
-
-    // Read the JSON content from the file
-    let json_str = fs::read_to_string(file_path).map_err(|e| {
+    // Create the failed_tests directory if it doesn't exist
+    fs::create_dir_all(&failed_tests_dir).map_err(|e| {
         format!(
-            "Failed to read test case file {}: {}",
-            file_path.display(),
+            "Failed to create directory {}: {}",
+            failed_tests_dir.display(),
             e
         )
     })?;
 
-    let mut test_case: TestCase = serde_json::from_str(&json_str).map_err(|e| {
+    let file_path = failed_tests_dir.join(&test_case.filename);
+
+    log::info!("Saving failed test case to {}", file_path.display());
+
+    let mut json_str = serde_json::to_string_pretty(test_case)
+        .map_err(|e| format!("Failed to serialize TestCase to JSON: {}", e))?;
+    json_str.push('\n'); // To be consistent with original test vector styling
+
+    fs::write(&file_path, json_str).map_err(|e| {
         format!(
-            "Failed to parse test case JSON from {}: {}",
+            "Failed to write TestCase to file {}: {}",
             file_path.display(),
             e
         )
     })?;
 
-    // Set the filename field to the file name
-    test_case.filename = file_path
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("")
-        .to_string();
-
-    Ok(test_case)
+    Ok(())
 }
 
 /// Validate that required benchmark subdirectories exist.
@@ -163,45 +99,6 @@ pub fn parse_benchmark_dir(input_dir: &Path) -> HarvestResult<(PathBuf, PathBuf)
     Ok((test_case_dir, test_vectors_dir))
 }
 
-/// Reads all files in a directory and parses them as TestCase JSON files
-/// These sorts of files can be found in the test_vectors/ directory of the benchmark
-pub fn parse_test_vectors<P: AsRef<Path>>(directory_path: P) -> HarvestResult<Vec<TestCase>> {
-    let dir_path = directory_path.as_ref();
-
-    // Read directory entries
-    let entries = fs::read_dir(dir_path)
-        .map_err(|e| format!("Failed to read directory {}: {}", dir_path.display(), e))?;
-
-    // Process each file and collect successful test cases
-    let mut test_cases = Vec::new();
-    for entry in entries {
-        let entry = entry.map_err(|e| {
-            format!(
-                "Failed to read directory entry in {}: {}",
-                dir_path.display(),
-                e
-            )
-        })?;
-        let file_path = entry.path();
-
-        // Only accept plain .json files (exclude backups like .json.bak, directories, etc.)
-        if file_path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| ext != "json")
-            .unwrap_or(true)
-        {
-            continue;
-        }
-
-        // Try to parse the file as a test case JSON
-        if let Ok(test_case) = parse_test_case_json(&file_path) {
-            test_cases.push(test_case);
-        }
-    }
-    Ok(test_cases)
-}
-
 /// Clean up build artifacts from successfully translated Rust projects
 pub fn cleanup_benchmarks(results: &[ProgramEvalStats], output_dir: &Path) {
     let mut cleaned_count = 0;
@@ -277,19 +174,12 @@ pub fn validate_binary_output(
     let actual_stderr = String::from_utf8_lossy(&output.stderr);
 
     // Compare stdout against expected pattern
-    let matches = if test_case.stdout.is_regex {
-        // Use regex matching
-        let regex = Regex::new(&test_case.stdout.pattern).map_err(|e| {
-            format!(
-                "Invalid regex pattern '{}': {}",
-                test_case.stdout.pattern, e
-            )
-        })?;
-        regex.is_match(&actual_stdout)
-    } else {
-        // Use simple equality matching
-        actual_stdout.trim() == test_case.stdout.pattern.trim()
-    };
+    let matches = test_case.stdout_matches(&actual_stdout).map_err(|e| {
+        format!(
+            "Invalid regex pattern '{}': {}",
+            test_case.stdout.pattern, e
+        )
+    })?;
 
     if matches {
         Ok(())
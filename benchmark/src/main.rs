@@ -11,6 +11,7 @@ use crate::error::HarvestResult;
 use crate::harness::feature_combo::{enumerate_combos, FeatureCombo, FeatureCombos};
 use crate::harness::{
     cleanup_benchmarks, parse_benchmark_dir, parse_test_vectors, validate_binary_output,
+    write_failed_test_case,
 };
 use crate::io::{
     collect_program_dirs, ensure_output_directory, log_failing_programs, log_found_programs,
@@ -96,9 +97,13 @@ pub fn translate_c_directory_to_rust_project(
         config: config_overrides.to_vec(),
         force: false,
         modular,
+        skeleton_first: false,
+        translate_units_file: None,
         agentic,
         agentic_verify,
         agentic_agent: None,
+        // The test vectors grade the translation, so they are not used to repair it.
+        test_vectors: None,
         repair_passes,
     }
     .into();
@@ -316,8 +321,7 @@ fn run_test_validation(
                 let error = format!("Test case {} failed: {}", test_case.filename, e);
                 error_messages.push(error);
                 log::info!("❌ Test case {} failed: {}", test_case.filename, e);
-                test_case
-                    .write_to_disk(output_dir)
+                write_failed_test_case(test_case, output_dir)
                     .expect("failed to write test case to disk");
             }
         }
@@ -187,6 +187,19 @@ impl CargoToml {
         }
     }
 
+    /// Adds `name = "<version>"` to `[build-dependencies]`, unless the dependency is already there.
+    pub fn add_build_dependency(&mut self, name: &str, version: &str) {
+        let deps = self
+            .doc
+            .entry("build-dependencies")
+            .or_insert_with(|| Item::Table(Table::new()));
+        if let Some(t) = deps.as_table_mut()
+            && !t.contains_key(name)
+        {
+            t.insert(name, toml_edit::value(version));
+        }
+    }
+
     /// Sets `[package].name` (and `[lib].name` if present) to match the last component of
     /// `project_dir`, sanitized to a valid Cargo package name. Does nothing if the name is
     /// already correct or the directory name cannot be determined.
@@ -479,6 +492,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_add_build_dependency_keeps_existing() {
+        let mut cargo = CargoToml::from_bytes(
+            b"[package]\nname = \"test\"\n\n[build-dependencies]\ncc = \"1.2\"\n",
+        )
+        .unwrap();
+        cargo.add_build_dependency("cc", "1");
+        cargo.add_build_dependency("pkg-config", "0.3");
+        let contents = cargo.to_string();
+        assert!(contents.contains("cc = \"1.2\""));
+        assert!(contents.contains("pkg-config = \"0.3\""));
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_update_dependency_path() {
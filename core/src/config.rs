@@ -55,6 +55,24 @@ pub struct Config {
     // If false, use standard all-at-once translation.
     pub modular: bool,
 
+    /// If true, modular translation first builds and repairs a skeleton crate whose functions all
+    /// have `todo!()` bodies, then translates the bodies one at a time against it (requires
+    /// `modular = true`).
+    #[serde(default)]
+    pub skeleton_first: bool,
+
+    /// C files and functions to translate, for a partial translation that links the rest of the C
+    /// code into the crate (requires `modular = true`). A unit ending in `.c` or `.h` selects
+    /// every function defined in that file; any other unit names a function. Empty for a full
+    /// translation unless `translate_units_file` lists units.
+    #[serde(default)]
+    pub translate_units: Vec<String>,
+
+    /// A file listing further units to translate, one per line, as `translate_units` does. Blank
+    /// lines and lines starting with `#` are ignored.
+    #[serde(default)]
+    pub translate_units_file: Option<PathBuf>,
+
     /// If true, use the agentic translation tool instead of the direct LLM translation tools.
     pub agentic: bool,
 
@@ -68,6 +86,12 @@ pub struct Config {
     #[serde(default)]
     pub agentic_agent: AgentKind,
 
+    /// A directory of test vectors (JSON test cases giving the arguments, standard input and
+    /// expected standard output of a run) to check an executable translation against, and repair
+    /// it until it passes them.
+    #[serde(default)]
+    pub test_vectors: Option<PathBuf>,
+
     /// Filter describing which log messages should be output to stdout. This is in the
     /// `tracing_subscriber::filter::EnvFilter` format.
     pub log_filter: String,
@@ -76,6 +100,11 @@ pub struct Config {
     #[serde(default = "default_max_repair_passes")]
     pub max_repair_passes: usize,
 
+    /// How many repair passes in a row may leave the build no better than the best candidate so
+    /// far before the repair loop gives up.
+    #[serde(default = "default_repair_patience")]
+    pub repair_patience: usize,
+
     /// Sub-configuration for each tool.
     pub tools: HashMap<String, serde_json::Value>,
 
@@ -91,6 +120,10 @@ fn default_max_repair_passes() -> usize {
     2
 }
 
+fn default_repair_patience() -> usize {
+    2
+}
+
 impl Config {
     /// Returns a mock config for testing.
     pub fn mock() -> Self {
@@ -100,16 +133,26 @@ impl Config {
             diagnostics_dir: None,
             force: false,
             modular: false,
+            skeleton_first: false,
+            translate_units: Vec::new(),
+            translate_units_file: None,
             agentic: false,
             agentic_verify: false,
             agentic_agent: AgentKind::Kiro,
+            test_vectors: None,
             log_filter: "off".to_owned(),
             max_repair_passes: 0,
+            repair_patience: default_repair_patience(),
             tools: Default::default(),
             unknown: Default::default(),
         }
     }
 
+    /// Whether only some of the C code is translated; see `translate_units`.
+    pub fn is_partial(&self) -> bool {
+        !self.translate_units.is_empty() || self.translate_units_file.is_some()
+    }
+
     /// Returns formatted llm info.
     /// Printed at the start of translation and benchmarking runs to aid in reproduction of results.
     pub fn model_info(&self) -> Option<String> {
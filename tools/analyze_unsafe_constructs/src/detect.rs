@@ -0,0 +1,410 @@
+//! The libclang walk behind [`analyze_unsafe_constructs`](crate::analyze_unsafe_constructs).
+
+use clang::{Entity, EntityKind, TypeKind};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::{ALLOC_FNS, Finding, FunctionAnalysis, UnsafeConstruct};
+
+const SETJMP_FNS: &[&str] = &[
+    "setjmp",
+    "_setjmp",
+    "sigsetjmp",
+    "__sigsetjmp",
+    "longjmp",
+    "_longjmp",
+    "siglongjmp",
+];
+const FREE_FNS: &[&str] = &["free"];
+/// `va_start` and friends are macros expanding to these builtins.
+const VA_FNS: &[&str] = &[
+    "__builtin_va_start",
+    "__builtin_va_end",
+    "__builtin_va_copy",
+    "__builtin_va_arg",
+];
+const SIGNAL_FNS: &[&str] = &["signal", "sigset", "bsd_signal"];
+/// `struct sigaction` members that hold the handler.
+const SIGACTION_FIELDS: &[&str] = &["sa_handler", "sa_sigaction"];
+
+/// A function registered as a signal handler somewhere in the project.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct HandlerRegistration {
+    pub handler: String,
+}
+
+/// Analyzes every function defined in the main file of `tu`. Also returns the signal handlers
+/// registered in this translation unit; the caller marks them once every file is analyzed, since
+/// a handler may be defined elsewhere.
+pub(crate) fn analyze_translation_unit(
+    rel_path: &Path,
+    tu: Entity<'_>,
+) -> (Vec<FunctionAnalysis>, Vec<HandlerRegistration>) {
+    let mut functions = Vec::new();
+    let mut registrations = Vec::new();
+    for child in tu.get_children() {
+        if child.get_kind() != EntityKind::FunctionDecl
+            || !child.is_definition()
+            || !child.is_in_main_file()
+        {
+            continue;
+        }
+        let Some(name) = child.get_name() else {
+            continue;
+        };
+        let mut findings = Vec::new();
+        check_signature(&child, &mut findings);
+        walk(&child, &mut findings, &mut registrations);
+        functions.push(FunctionAnalysis {
+            name,
+            file: rel_path.to_string_lossy().into_owned(),
+            line: line_of(&child),
+            findings,
+        });
+    }
+    (functions, registrations)
+}
+
+fn line_of(entity: &Entity<'_>) -> u32 {
+    entity
+        .get_location()
+        .map_or(0, |l| l.get_expansion_location().line)
+}
+
+fn push(
+    findings: &mut Vec<Finding>,
+    construct: UnsafeConstruct,
+    at: &Entity<'_>,
+    symbol: Option<String>,
+) {
+    findings.push(Finding {
+        construct,
+        line: line_of(at),
+        symbol,
+    });
+}
+
+fn check_signature(func: &Entity<'_>, findings: &mut Vec<Finding>) {
+    if func.is_variadic() {
+        push(findings, UnsafeConstruct::Varargs, func, Some("...".into()));
+    }
+    for param in func.get_arguments().unwrap_or_default() {
+        let takes_va_list = param
+            .get_type()
+            .is_some_and(|t| t.get_display_name().contains("va_list"));
+        if takes_va_list {
+            push(
+                findings,
+                UnsafeConstruct::Varargs,
+                &param,
+                Some("va_list".into()),
+            );
+        }
+    }
+}
+
+fn walk(
+    entity: &Entity<'_>,
+    findings: &mut Vec<Finding>,
+    registrations: &mut Vec<HandlerRegistration>,
+) {
+    for child in entity.get_children() {
+        inspect(&child, findings, registrations);
+        walk(&child, findings, registrations);
+    }
+}
+
+fn inspect(
+    entity: &Entity<'_>,
+    findings: &mut Vec<Finding>,
+    registrations: &mut Vec<HandlerRegistration>,
+) {
+    match entity.get_kind() {
+        EntityKind::GotoStmt => {
+            let label = entity
+                .get_children()
+                .into_iter()
+                .find(|c| c.get_kind() == EntityKind::LabelRef)
+                .and_then(|c| c.get_name());
+            push(findings, UnsafeConstruct::Goto, entity, label);
+        }
+        EntityKind::IndirectGotoStmt => push(findings, UnsafeConstruct::Goto, entity, None),
+        EntityKind::AsmStmt | EntityKind::MsAsmStmt => {
+            push(findings, UnsafeConstruct::InlineAsm, entity, None)
+        }
+        EntityKind::CallExpr => inspect_call(entity, findings, registrations),
+        EntityKind::MemberRefExpr => {
+            let field = entity.get_reference();
+            let in_union = field
+                .and_then(|f| f.get_semantic_parent())
+                .is_some_and(|p| p.get_kind() == EntityKind::UnionDecl);
+            if in_union {
+                push(
+                    findings,
+                    UnsafeConstruct::UnionAccess,
+                    entity,
+                    entity.get_name(),
+                );
+            }
+        }
+        EntityKind::BinaryOperator | EntityKind::CompoundAssignOperator => {
+            inspect_binary(entity, findings, registrations)
+        }
+        EntityKind::UnaryOperator => {
+            let tokens = tokens_of(entity);
+            let steps = [tokens.first(), tokens.last()]
+                .into_iter()
+                .flatten()
+                .any(|t| t == "++" || t == "--");
+            let operand_is_pointer = entity.get_children().first().is_some_and(is_pointer);
+            if steps && operand_is_pointer {
+                push(findings, UnsafeConstruct::PointerArithmetic, entity, None);
+            }
+        }
+        EntityKind::ArraySubscriptExpr => {
+            if !statically_in_bounds(entity) {
+                push(findings, UnsafeConstruct::UncheckedIndexing, entity, None);
+            }
+        }
+        EntityKind::CStyleCastExpr => {
+            if is_unrelated_pointer_cast(entity) {
+                let target = entity.get_type().map(|t| t.get_display_name());
+                push(
+                    findings,
+                    UnsafeConstruct::UnrelatedPointerCast,
+                    entity,
+                    target,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+fn inspect_call(
+    call: &Entity<'_>,
+    findings: &mut Vec<Finding>,
+    registrations: &mut Vec<HandlerRegistration>,
+) {
+    let Some(callee) = call.get_name() else {
+        return;
+    };
+    let callee = callee.as_str();
+    if SETJMP_FNS.contains(&callee) {
+        push(
+            findings,
+            UnsafeConstruct::SetjmpLongjmp,
+            call,
+            Some(callee.into()),
+        );
+    } else if ALLOC_FNS.contains(&callee) || FREE_FNS.contains(&callee) {
+        push(
+            findings,
+            UnsafeConstruct::ManualMemory,
+            call,
+            Some(callee.into()),
+        );
+    } else if VA_FNS.contains(&callee) {
+        push(
+            findings,
+            UnsafeConstruct::Varargs,
+            call,
+            Some(callee.into()),
+        );
+    } else if SIGNAL_FNS.contains(&callee) {
+        let handler = call
+            .get_arguments()
+            .and_then(|args| args.get(1).and_then(referenced_function));
+        push(
+            findings,
+            UnsafeConstruct::SignalHandler,
+            call,
+            handler.clone(),
+        );
+        registrations.extend(handler.map(|handler| HandlerRegistration { handler }));
+    }
+}
+
+fn inspect_binary(
+    expr: &Entity<'_>,
+    findings: &mut Vec<Finding>,
+    registrations: &mut Vec<HandlerRegistration>,
+) {
+    let children = expr.get_children();
+    let [lhs, rhs] = children.as_slice() else {
+        return;
+    };
+
+    // libclang (without the newer operator-kind API) does not expose the operator directly; it is
+    // the first token after the left operand.
+    let lhs_len = tokens_of(lhs).len();
+    let Some(op) = tokens_of(expr).into_iter().nth(lhs_len) else {
+        return;
+    };
+
+    match op.as_str() {
+        "+" | "-" | "+=" | "-=" if is_pointer(lhs) || is_pointer(rhs) => {
+            push(findings, UnsafeConstruct::PointerArithmetic, expr, None);
+        }
+        // `act.sa_handler = handler;` registers a handler for `sigaction`.
+        "=" if lhs.get_kind() == EntityKind::MemberRefExpr
+            && lhs
+                .get_name()
+                .is_some_and(|n| SIGACTION_FIELDS.contains(&n.as_str())) =>
+        {
+            if let Some(handler) = referenced_function(rhs) {
+                push(
+                    findings,
+                    UnsafeConstruct::SignalHandler,
+                    expr,
+                    Some(handler.clone()),
+                );
+                registrations.push(HandlerRegistration { handler });
+            }
+        }
+        _ => {}
+    }
+}
+
+fn tokens_of(entity: &Entity<'_>) -> Vec<String> {
+    entity
+        .get_range()
+        .map(|r| r.tokenize().iter().map(|t| t.get_spelling()).collect())
+        .unwrap_or_default()
+}
+
+fn is_pointer(entity: &Entity<'_>) -> bool {
+    entity
+        .get_type()
+        .is_some_and(|t| t.get_canonical_type().get_kind() == TypeKind::Pointer)
+}
+
+/// The name of the function `expr` refers to, looking through implicit casts and parentheses.
+fn referenced_function(expr: &Entity<'_>) -> Option<String> {
+    if expr.get_kind() == EntityKind::DeclRefExpr {
+        let target = expr.get_reference()?;
+        return (target.get_kind() == EntityKind::FunctionDecl)
+            .then(|| target.get_name())
+            .flatten();
+    }
+    expr.get_children().iter().find_map(referenced_function)
+}
+
+/// True for `a[i]` where `a` is a constant-size array and `i` an integer literal below its size.
+fn statically_in_bounds(subscript: &Entity<'_>) -> bool {
+    let children = subscript.get_children();
+    let [base, index] = children.as_slice() else {
+        return false;
+    };
+    let Some(size) = array_size(base) else {
+        return false;
+    };
+    let index_tokens = tokens_of(index);
+    let [literal] = index_tokens.as_slice() else {
+        return false;
+    };
+    parse_int_literal(literal).is_some_and(|i| i < size as u64)
+}
+
+/// The size of the constant array `expr` names, looking through the implicit array-to-pointer
+/// decay that libclang reports as an unexposed expression.
+fn array_size(expr: &Entity<'_>) -> Option<usize> {
+    let ty = expr.get_type()?.get_canonical_type();
+    if ty.get_kind() == TypeKind::ConstantArray {
+        return ty.get_size();
+    }
+    match expr.get_children().as_slice() {
+        [inner] => array_size(inner),
+        _ => None,
+    }
+}
+
+/// Parses a C integer literal (decimal, hex, octal; any `u`/`l` suffix).
+pub(crate) fn parse_int_literal(literal: &str) -> Option<u64> {
+    let digits = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+/// Pointer-to-pointer casts where neither side is `void *` and the pointees differ (ignoring
+/// qualifiers), and integer-to-pointer casts other than a literal `0`.
+fn is_unrelated_pointer_cast(cast: &Entity<'_>) -> bool {
+    let Some(target) = cast.get_type().map(|t| t.get_canonical_type()) else {
+        return false;
+    };
+    if target.get_kind() != TypeKind::Pointer {
+        return false;
+    }
+    // The operand is the last child; a leading TypeRef child names the target type.
+    let Some(operand) = cast.get_children().pop() else {
+        return false;
+    };
+    let Some(source) = operand.get_type().map(|t| t.get_canonical_type()) else {
+        return false;
+    };
+    match source.get_kind() {
+        TypeKind::Pointer => {
+            let pointee_name = |t: clang::Type<'_>| {
+                t.get_pointee_type()
+                    .map(|p| unqualified(&p.get_canonical_type().get_display_name()))
+            };
+            match (pointee_name(source), pointee_name(target)) {
+                (Some(from), Some(to)) => from != to && from != "void" && to != "void",
+                _ => false,
+            }
+        }
+        TypeKind::Bool
+        | TypeKind::CharS
+        | TypeKind::CharU
+        | TypeKind::SChar
+        | TypeKind::UChar
+        | TypeKind::Short
+        | TypeKind::UShort
+        | TypeKind::Int
+        | TypeKind::UInt
+        | TypeKind::Long
+        | TypeKind::ULong
+        | TypeKind::LongLong
+        | TypeKind::ULongLong => tokens_of(&operand) != ["0"],
+        _ => false,
+    }
+}
+
+/// Strips `const`/`volatile`/`restrict` from a type spelling.
+pub(crate) fn unqualified(spelling: &str) -> String {
+    spelling
+        .split_whitespace()
+        .filter(|w| !matches!(*w, "const" | "volatile" | "restrict"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn int_literals() {
+        assert_eq!(parse_int_literal("10"), Some(10));
+        assert_eq!(parse_int_literal("0"), Some(0));
+        assert_eq!(parse_int_literal("0x1F"), Some(31));
+        assert_eq!(parse_int_literal("017"), Some(15));
+        assert_eq!(parse_int_literal("4UL"), Some(4));
+        assert_eq!(parse_int_literal("n"), None);
+    }
+
+    #[test]
+    fn qualifiers_are_ignored() {
+        assert_eq!(unqualified("const char"), "char");
+        assert_eq!(unqualified("volatile struct foo"), "struct foo");
+        assert_eq!(unqualified("unsigned int"), "unsigned int");
+    }
+}
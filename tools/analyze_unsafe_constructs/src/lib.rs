@@ -0,0 +1,526 @@
+//! Flags C constructs that have no direct safe-Rust equivalent, per function.
+//!
+//! The analysis walks function bodies with libclang (the [`RichSourceMap`](c_ast::RichSourceMap)
+//! only keeps top-level declarations) and records, for each function defined in the project,
+//! which of the constructs in [`UnsafeConstruct`] it uses and where. Translators use the result to
+//! pick a strategy per function, and the report tells us up front which functions are likely to
+//! need manual attention.
+
+use c_ast::{ClangAST, ParseOptions, TopLevelEntity};
+use full_source::RawSource;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use tracing::info;
+
+mod detect;
+
+/// A C construct that does not translate directly to safe Rust.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsafeConstruct {
+    /// `p + n`, `p - q`, `p++`, `p += n` on a pointer.
+    PointerArithmetic,
+    /// A cast between pointers to unrelated types, or from an integer to a pointer.
+    UnrelatedPointerCast,
+    /// Reading or writing a union member.
+    UnionAccess,
+    /// `goto` (including computed `goto *p`).
+    Goto,
+    /// `setjmp`/`longjmp` and their `sig*` variants.
+    SetjmpLongjmp,
+    /// A variadic function, a `va_list` parameter, or `va_start`/`va_arg` use.
+    Varargs,
+    /// Inline assembly.
+    InlineAsm,
+    /// Registering a signal handler, or being registered as one.
+    SignalHandler,
+    /// Array subscripts that are not provably in bounds.
+    UncheckedIndexing,
+    /// Manual heap management (`malloc`/`free` and friends).
+    ManualMemory,
+}
+
+impl UnsafeConstruct {
+    /// Short human-readable label.
+    pub fn label(self) -> &'static str {
+        match self {
+            UnsafeConstruct::PointerArithmetic => "pointer arithmetic",
+            UnsafeConstruct::UnrelatedPointerCast => "unrelated pointer cast",
+            UnsafeConstruct::UnionAccess => "union access",
+            UnsafeConstruct::Goto => "goto",
+            UnsafeConstruct::SetjmpLongjmp => "setjmp/longjmp",
+            UnsafeConstruct::Varargs => "varargs",
+            UnsafeConstruct::InlineAsm => "inline asm",
+            UnsafeConstruct::SignalHandler => "signal handler",
+            UnsafeConstruct::UncheckedIndexing => "unchecked indexing",
+            UnsafeConstruct::ManualMemory => "manual memory management",
+        }
+    }
+
+    /// The translation strategy we suggest to the LLM for this construct.
+    pub fn strategy(self) -> &'static str {
+        match self {
+            UnsafeConstruct::PointerArithmetic => {
+                "prefer slices with indexing or iterators over raw pointer offsets"
+            }
+            UnsafeConstruct::UnrelatedPointerCast => {
+                "the C code reinterprets memory; use explicit byte conversions (e.g. from_ne_bytes) or confine the cast to a small unsafe block"
+            }
+            UnsafeConstruct::UnionAccess => {
+                "use an enum if the active member is known, otherwise a #[repr(C)] union read inside unsafe blocks"
+            }
+            UnsafeConstruct::Goto => {
+                "restructure the control flow with loops, labeled breaks or early returns"
+            }
+            UnsafeConstruct::SetjmpLongjmp => {
+                "non-local jumps have no Rust equivalent; propagate errors with Result instead"
+            }
+            UnsafeConstruct::Varargs => {
+                "take a slice or fixed arguments; keep a C-variadic signature only where it is part of the exported ABI"
+            }
+            UnsafeConstruct::InlineAsm => {
+                "use core::arch intrinsics or asm! and keep the exact semantics of the original"
+            }
+            UnsafeConstruct::SignalHandler => {
+                "only async-signal-safe work may happen in a handler; communicate through atomics"
+            }
+            UnsafeConstruct::UncheckedIndexing => {
+                "the C code does not bounds-check these accesses; use slice indexing or get() and decide how out-of-range indices are handled"
+            }
+            UnsafeConstruct::ManualMemory => {
+                "use owned types (Box, Vec, String) so allocation and release follow ownership"
+            }
+        }
+    }
+
+    /// Whether this construct usually needs a human to decide on the translation.
+    pub fn needs_manual_attention(self) -> bool {
+        matches!(
+            self,
+            UnsafeConstruct::Goto
+                | UnsafeConstruct::SetjmpLongjmp
+                | UnsafeConstruct::InlineAsm
+                | UnsafeConstruct::SignalHandler
+        )
+    }
+}
+
+/// One occurrence of an [`UnsafeConstruct`] in a function.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Finding {
+    pub construct: UnsafeConstruct,
+    /// 1-indexed line in the function's file.
+    pub line: u32,
+    /// The symbol involved, if any: the callee (`malloc`, `longjmp`), the union field, the
+    /// `goto` label, or the handler function.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+}
+
+/// The unsafe constructs used by one C function definition.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FunctionAnalysis {
+    pub name: String,
+    /// Path of the defining file, relative to the project root.
+    pub file: String,
+    /// Line of the function definition.
+    pub line: u32,
+    pub findings: Vec<Finding>,
+}
+
+/// Allocation functions whose result the caller must eventually `free`.
+pub(crate) const ALLOC_FNS: &[&str] = &[
+    "malloc",
+    "calloc",
+    "realloc",
+    "reallocarray",
+    "aligned_alloc",
+    "posix_memalign",
+    "strdup",
+    "strndup",
+];
+
+/// How a function's allocations and releases pair up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryOwnership {
+    /// Allocates and frees within the function.
+    Balanced,
+    /// Allocates without freeing: ownership escapes (returned or stored).
+    Allocates,
+    /// Frees memory allocated elsewhere: takes ownership of an argument or global.
+    Frees,
+}
+
+impl FunctionAnalysis {
+    /// The distinct constructs this function uses.
+    pub fn constructs(&self) -> BTreeSet<UnsafeConstruct> {
+        self.findings.iter().map(|f| f.construct).collect()
+    }
+
+    pub fn needs_manual_attention(&self) -> bool {
+        self.constructs().iter().any(|c| c.needs_manual_attention())
+    }
+
+    /// Classifies the `malloc`/`free` pairing of the function, or `None` if it does neither.
+    pub fn memory_ownership(&self) -> Option<MemoryOwnership> {
+        let memory_symbols = self
+            .findings
+            .iter()
+            .filter(|f| f.construct == UnsafeConstruct::ManualMemory)
+            .filter_map(|f| f.symbol.as_deref());
+        let (mut allocs, mut frees) = (false, false);
+        for symbol in memory_symbols {
+            if ALLOC_FNS.contains(&symbol) {
+                allocs = true;
+            } else {
+                frees = true;
+            }
+        }
+        match (allocs, frees) {
+            (true, true) => Some(MemoryOwnership::Balanced),
+            (true, false) => Some(MemoryOwnership::Allocates),
+            (false, true) => Some(MemoryOwnership::Frees),
+            (false, false) => None,
+        }
+    }
+
+    /// One note per construct, for inclusion in translation prompts, e.g.
+    /// `"goto (lines 12, 40): restructure the control flow with ..."`.
+    pub fn prompt_notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+        for construct in self.constructs() {
+            let lines: BTreeSet<u32> = self
+                .findings
+                .iter()
+                .filter(|f| f.construct == construct)
+                .map(|f| f.line)
+                .collect();
+            let lines = lines
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let plural = if lines.contains(',') { "lines" } else { "line" };
+            let mut note = format!(
+                "{} ({plural} {lines}): {}",
+                construct.label(),
+                construct.strategy()
+            );
+            if construct == UnsafeConstruct::ManualMemory {
+                match self.memory_ownership() {
+                    Some(MemoryOwnership::Allocates) => {
+                        note.push_str("; the allocation escapes this function")
+                    }
+                    Some(MemoryOwnership::Frees) => {
+                        note.push_str("; this function frees memory it receives")
+                    }
+                    _ => {}
+                }
+            }
+            notes.push(note);
+        }
+        notes
+    }
+}
+
+/// Per-function unsafe-construct analysis of a C project.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnsafeConstructReport {
+    /// Number of function definitions analyzed.
+    pub analyzed: usize,
+    /// Functions using at least one construct, sorted by file then line.
+    pub functions: Vec<FunctionAnalysis>,
+}
+
+impl UnsafeConstructReport {
+    /// Looks up the analysis of function `name` in `file` (relative to the project root). Only
+    /// functions with findings are listed, so a `static` function of another file with the same
+    /// name is never substituted; without a `file`, the first function named `name` is returned.
+    pub fn get(&self, name: &str, file: Option<&str>) -> Option<&FunctionAnalysis> {
+        self.functions
+            .iter()
+            .find(|f| f.name == name && file.is_none_or(|file| f.file == file))
+    }
+
+    /// Looks up the analysis for a function entity from the [`RichSourceMap`](c_ast::RichSourceMap).
+    pub fn for_entity(&self, entity: &TopLevelEntity) -> Option<&FunctionAnalysis> {
+        let Some(ClangAST::FunctionDecl { name, .. }) = &entity.ast else {
+            return None;
+        };
+        self.get(name, Some(&entity.span.file))
+    }
+
+    /// Functions using a construct that usually needs manual attention.
+    pub fn needing_manual_attention(&self) -> impl Iterator<Item = &FunctionAnalysis> {
+        self.functions.iter().filter(|f| f.needs_manual_attention())
+    }
+}
+
+impl fmt::Display for UnsafeConstructReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "UnsafeConstructReport: {} of {} functions use unsafe constructs ({} need manual attention)",
+            self.functions.len(),
+            self.analyzed,
+            self.needing_manual_attention().count()
+        )?;
+        for func in &self.functions {
+            let labels = func
+                .constructs()
+                .iter()
+                .map(|c| c.label())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let attention = if func.needs_manual_attention() {
+                " [manual attention]"
+            } else {
+                ""
+            };
+            writeln!(
+                f,
+                "  {}:{} {}: {labels}{attention}",
+                func.file, func.line, func.name
+            )?;
+            for finding in &func.findings {
+                match &finding.symbol {
+                    Some(symbol) => writeln!(
+                        f,
+                        "    line {}: {} (`{symbol}`)",
+                        finding.line,
+                        finding.construct.label()
+                    )?,
+                    None => writeln!(
+                        f,
+                        "    line {}: {}",
+                        finding.line,
+                        finding.construct.label()
+                    )?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Representation for UnsafeConstructReport {
+    fn name(&self) -> &'static str {
+        "unsafe_construct_report"
+    }
+}
+
+/// Names the cached results of the analysis; bump the suffix when the detection changes, so
+/// results cached by an older version are not reused.
+const CACHE_NAME: &str = "analyze_unsafe_constructs-1";
+
+/// Parses the project with libclang, with the parse settings of `options`, and builds an
+/// [`UnsafeConstructReport`].
+///
+/// Factored out of the tool so it can be driven without a scheduler, as with
+/// [`c_ast::parse_to_ast_with`].
+pub fn analyze_unsafe_constructs(
+    rs: &RawSource,
+    options: &ParseOptions,
+) -> Result<UnsafeConstructReport, Box<dyn std::error::Error>> {
+    let mut analyses = Vec::new();
+    let mut registrations = Vec::new();
+    for (functions, handlers) in
+        c_ast::analyze_translation_units(rs, options, CACHE_NAME, detect::analyze_translation_unit)?
+    {
+        analyses.extend(functions);
+        registrations.extend(handlers);
+    }
+
+    // Handlers may be registered in a different file than the one defining them.
+    for registration in registrations {
+        for func in analyses
+            .iter_mut()
+            .filter(|f| f.name == registration.handler)
+        {
+            func.findings.push(Finding {
+                construct: UnsafeConstruct::SignalHandler,
+                line: func.line,
+                symbol: Some(registration.handler.clone()),
+            });
+        }
+    }
+
+    let analyzed = analyses.len();
+    let mut functions: Vec<FunctionAnalysis> = analyses
+        .into_iter()
+        .filter(|f| !f.findings.is_empty())
+        .map(|mut f| {
+            f.findings.sort();
+            f.findings.dedup();
+            f
+        })
+        .collect();
+    functions.sort_by(|a, b| (&a.file, a.line, &a.name).cmp(&(&b.file, b.line, &b.name)));
+    Ok(UnsafeConstructReport {
+        analyzed,
+        functions,
+    })
+}
+
+pub struct AnalyzeUnsafeConstructs;
+
+impl Tool for AnalyzeUnsafeConstructs {
+    fn name(&self) -> &'static str {
+        "analyze_unsafe_constructs"
+    }
+
+    /// Parses with the settings of `[tools.parse_to_ast]`, so the analysis shares its worker
+    /// count and cache.
+    ///
+    /// Inputs:
+    /// 1. [`RawSource`] id -- the C project to analyze.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let rs = context
+            .ir_snapshot
+            .get::<RawSource>(inputs[0])
+            .ok_or("analyze_unsafe_constructs: no RawSource in IR")?;
+        let options = c_ast::Config::load(&context)?.parse_options();
+        let report = analyze_unsafe_constructs(rs, &options)?;
+        info!(
+            "Unsafe constructs: {} of {} functions flagged, {} need manual attention",
+            report.functions.len(),
+            report.analyzed,
+            report.needing_manual_attention().count()
+        );
+        Ok(Box::new(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(construct: UnsafeConstruct, line: u32, symbol: Option<&str>) -> Finding {
+        Finding {
+            construct,
+            line,
+            symbol: symbol.map(Into::into),
+        }
+    }
+
+    fn analysis(name: &str, file: &str, findings: Vec<Finding>) -> FunctionAnalysis {
+        FunctionAnalysis {
+            name: name.into(),
+            file: file.into(),
+            line: 1,
+            findings,
+        }
+    }
+
+    #[test]
+    fn memory_ownership_classification() {
+        let balanced = analysis(
+            "f",
+            "a.c",
+            vec![
+                finding(UnsafeConstruct::ManualMemory, 2, Some("malloc")),
+                finding(UnsafeConstruct::ManualMemory, 5, Some("free")),
+            ],
+        );
+        assert_eq!(balanced.memory_ownership(), Some(MemoryOwnership::Balanced));
+
+        let escapes = analysis(
+            "g",
+            "a.c",
+            vec![finding(UnsafeConstruct::ManualMemory, 2, Some("strdup"))],
+        );
+        assert_eq!(escapes.memory_ownership(), Some(MemoryOwnership::Allocates));
+
+        let consumes = analysis(
+            "h",
+            "a.c",
+            vec![finding(UnsafeConstruct::ManualMemory, 2, Some("free"))],
+        );
+        assert_eq!(consumes.memory_ownership(), Some(MemoryOwnership::Frees));
+
+        assert_eq!(analysis("i", "a.c", vec![]).memory_ownership(), None);
+    }
+
+    #[test]
+    fn prompt_notes_group_lines_per_construct() {
+        let func = analysis(
+            "f",
+            "a.c",
+            vec![
+                finding(UnsafeConstruct::Goto, 12, Some("fail")),
+                finding(UnsafeConstruct::Goto, 40, Some("fail")),
+                finding(UnsafeConstruct::ManualMemory, 3, Some("malloc")),
+            ],
+        );
+        let notes = func.prompt_notes();
+        assert_eq!(notes.len(), 2);
+        assert!(
+            notes[0].starts_with("goto (lines 12, 40): "),
+            "{}",
+            notes[0]
+        );
+        assert!(
+            notes[1].starts_with("manual memory management (line 3): ")
+                && notes[1].ends_with("the allocation escapes this function"),
+            "{}",
+            notes[1]
+        );
+        assert!(func.needs_manual_attention());
+    }
+
+    #[test]
+    fn lookup_requires_matching_file() {
+        let report = UnsafeConstructReport {
+            analyzed: 2,
+            functions: vec![
+                analysis(
+                    "helper",
+                    "src/a.c",
+                    vec![finding(UnsafeConstruct::Goto, 3, None)],
+                ),
+                analysis(
+                    "helper",
+                    "src/b.c",
+                    vec![finding(UnsafeConstruct::InlineAsm, 7, None)],
+                ),
+            ],
+        };
+        assert_eq!(
+            report.get("helper", Some("src/b.c")).unwrap().file,
+            "src/b.c"
+        );
+        assert_eq!(report.get("helper", None).unwrap().file, "src/a.c");
+        assert!(report.get("helper", Some("src/c.c")).is_none());
+        assert!(report.get("missing", None).is_none());
+    }
+
+    #[test]
+    fn display_lists_functions_and_findings() {
+        let report = UnsafeConstructReport {
+            analyzed: 3,
+            functions: vec![analysis(
+                "parse",
+                "src/parse.c",
+                vec![
+                    finding(UnsafeConstruct::PointerArithmetic, 4, None),
+                    finding(UnsafeConstruct::SetjmpLongjmp, 9, Some("longjmp")),
+                ],
+            )],
+        };
+        let text = report.to_string();
+        assert!(text.starts_with(
+            "UnsafeConstructReport: 1 of 3 functions use unsafe constructs (1 need manual attention)\n"
+        ));
+        assert!(text.contains(
+            "  src/parse.c:1 parse: pointer arithmetic, setjmp/longjmp [manual attention]\n"
+        ));
+        assert!(text.contains("    line 9: setjmp/longjmp (`longjmp`)\n"));
+    }
+}
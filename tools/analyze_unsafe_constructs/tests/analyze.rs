@@ -0,0 +1,130 @@
+//! End-to-end tests for `analyze_unsafe_constructs` that drive libclang.
+//!
+//! Like `c_ast`'s `parse_to_ast` tests, these skip rather than fail when
+//! libclang is unavailable.
+
+#![cfg(not(miri))]
+
+use analyze_unsafe_constructs::{
+    MemoryOwnership, UnsafeConstruct, UnsafeConstructReport, analyze_unsafe_constructs,
+};
+use c_ast::ParseOptions;
+use full_source::RawSource;
+use harvest_core::fs::RawDir;
+
+const UNSAFE_C: &str = r#"
+#include <setjmp.h>
+#include <signal.h>
+#include <stdarg.h>
+#include <stdlib.h>
+
+union word { int i; float f; };
+static jmp_buf env;
+
+int clean(int a, int b) { return a + b; }
+
+int sum(int *p, int n) {
+    int total = 0;
+    for (int i = 0; i < n; i++)
+        total += *(p + i);
+    return total;
+}
+
+float pun(union word *w) { return w->f; }
+
+long *reinterpret(double *d) { return (long *)d; }
+
+int retry(int n) {
+again:
+    if (n-- > 0) goto again;
+    return n;
+}
+
+void jump(void) { longjmp(env, 1); }
+
+int count(int n, ...) {
+    va_list ap;
+    va_start(ap, n);
+    int v = va_arg(ap, int);
+    va_end(ap);
+    return v;
+}
+
+void on_signal(int sig) { (void)sig; }
+
+void install(void) { signal(SIGINT, on_signal); }
+
+int pick(int *xs, int i) {
+    int fixed[4] = {0};
+    return xs[i] + fixed[2];
+}
+
+char *dup(const char *s) { return malloc(8); }
+void drop(char *s) { free(s); }
+"#;
+
+fn run() -> Option<UnsafeConstructReport> {
+    let mut dir = RawDir::default();
+    dir.set_file("src/unsafe.c", UNSAFE_C.as_bytes().to_vec())
+        .unwrap();
+    match analyze_unsafe_constructs(&RawSource { dir }, &ParseOptions::default()) {
+        Ok(report) => Some(report),
+        Err(err) => {
+            eprintln!("Skipping: analysis failed (libclang missing?): {err}");
+            None
+        }
+    }
+}
+
+fn constructs_of(report: &UnsafeConstructReport, name: &str) -> Vec<UnsafeConstruct> {
+    report
+        .get(name, Some("src/unsafe.c"))
+        .map(|f| f.constructs().into_iter().collect())
+        .unwrap_or_default()
+}
+
+#[test]
+fn flags_each_construct_in_its_function() {
+    let Some(report) = run() else {
+        return;
+    };
+
+    assert!(
+        report.get("clean", None).is_none(),
+        "clean() must not be flagged"
+    );
+    use UnsafeConstruct::*;
+    assert_eq!(constructs_of(&report, "sum"), vec![PointerArithmetic]);
+    assert_eq!(constructs_of(&report, "pun"), vec![UnionAccess]);
+    assert_eq!(
+        constructs_of(&report, "reinterpret"),
+        vec![UnrelatedPointerCast]
+    );
+    assert_eq!(constructs_of(&report, "retry"), vec![Goto]);
+    assert_eq!(constructs_of(&report, "jump"), vec![SetjmpLongjmp]);
+    assert_eq!(constructs_of(&report, "count"), vec![Varargs]);
+    assert_eq!(constructs_of(&report, "on_signal"), vec![SignalHandler]);
+    assert_eq!(constructs_of(&report, "install"), vec![SignalHandler]);
+    assert_eq!(constructs_of(&report, "pick"), vec![UncheckedIndexing]);
+    let pick = report.get("pick", None).unwrap();
+    assert_eq!(pick.findings.len(), 1, "fixed[2] is provably in bounds");
+
+    let dup = report.get("dup", None).unwrap();
+    assert_eq!(dup.memory_ownership(), Some(MemoryOwnership::Allocates));
+    let drop = report.get("drop", None).unwrap();
+    assert_eq!(drop.memory_ownership(), Some(MemoryOwnership::Frees));
+}
+
+#[test]
+fn manual_attention_covers_control_flow_constructs() {
+    let Some(report) = run() else {
+        return;
+    };
+
+    let mut names: Vec<&str> = report
+        .needing_manual_attention()
+        .map(|f| f.name.as_str())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["install", "jump", "on_signal", "retry"]);
+}
@@ -0,0 +1,286 @@
+//! Applies the fixes rustc suggests for build errors, so that the LLM repair only sees the errors
+//! that need it.
+//!
+//! Many errors come with a suggestion rustc is sure of (`MachineApplicable`): a missing `&` or
+//! `mut`, a missing `.clone()`, a misspelled name. Less certain ones (`MaybeIncorrect`), such as
+//! a missing import, are applied only if the config asks for it. Suggestions with placeholders
+//! are never applied.
+
+use cargo_metadata::diagnostic::{Applicability, Diagnostic, DiagnosticLevel};
+use full_source::CargoPackage;
+use harvest_core::config::unknown_field_warning;
+use harvest_core::fs::RawDir;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+use try_cargo_build::{CargoBuildResult, CompilerMessage};
+
+/// Configuration for the rustc suggestion tool.
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// Whether suggestions rustc marks `MaybeIncorrect`, such as imports, are applied too.
+    #[serde(default)]
+    pub maybe_incorrect: bool,
+
+    /// How many times suggestions are applied and the package rebuilt before the LLM repair.
+    #[serde(default = "default_max_passes")]
+    pub max_passes: usize,
+
+    #[serde(flatten)]
+    unknown: HashMap<String, Value>,
+}
+
+fn default_max_passes() -> usize {
+    5
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            maybe_incorrect: false,
+            max_passes: default_max_passes(),
+            unknown: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self) {
+        unknown_field_warning("tools.apply_rustc_suggestions", &self.unknown);
+    }
+
+    /// Reads the `apply_rustc_suggestions` config, using the defaults if there is none.
+    pub fn load(config: &harvest_core::config::Config) -> Result<Self, serde_json::Error> {
+        let config = match config.tools.get("apply_rustc_suggestions") {
+            Some(value) => Config::deserialize(value)?,
+            None => Config::default(),
+        };
+        config.validate();
+        Ok(config)
+    }
+}
+
+/// One replacement rustc suggests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    /// Path of the file, relative to the package root.
+    pub file: PathBuf,
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl Edit {
+    /// Whether the two edits touch the same text, so that applying both is ambiguous.
+    fn overlaps(&self, other: &Edit) -> bool {
+        self.file == other.file
+            && ((self.range.start < other.range.end && other.range.start < self.range.end)
+                || (self.range.start == other.range.start
+                    && (self.range.is_empty() || other.range.is_empty())))
+    }
+}
+
+/// The fix rustc suggests for one error: edits that are applied together.
+pub type Suggestion = Vec<Edit>;
+
+/// The suggestions for the errors among `diagnostics` that can be applied without review: those
+/// rustc marks `MachineApplicable` and, with `maybe_incorrect`, `MaybeIncorrect`. Of alternative
+/// suggestions for the same error, the first is taken.
+pub fn suggestions(diagnostics: &[CompilerMessage], maybe_incorrect: bool) -> Vec<Suggestion> {
+    let applicable = |applicability: &Option<Applicability>| match applicability {
+        Some(Applicability::MachineApplicable) => true,
+        Some(Applicability::MaybeIncorrect) => maybe_incorrect,
+        _ => false,
+    };
+    let suggestion = |diagnostic: &Diagnostic| -> Option<Suggestion> {
+        let spans: Vec<_> = diagnostic
+            .spans
+            .iter()
+            .filter(|span| span.suggested_replacement.is_some())
+            .collect();
+        // Absolute paths are outside the package, e.g. in a dependency's source.
+        if spans.is_empty()
+            || !spans.iter().all(|span| {
+                applicable(&span.suggestion_applicability)
+                    && !Path::new(&span.file_name).is_absolute()
+            })
+        {
+            return None;
+        }
+        Some(
+            spans
+                .into_iter()
+                .map(|span| Edit {
+                    file: PathBuf::from(&span.file_name),
+                    range: span.byte_start as usize..span.byte_end as usize,
+                    replacement: span.suggested_replacement.clone().unwrap_or_default(),
+                })
+                .collect(),
+        )
+    };
+    diagnostics
+        .iter()
+        .map(|d| &d.message)
+        .filter(|m| m.level == DiagnosticLevel::Error)
+        .filter_map(|m| std::iter::once(m).chain(&m.children).find_map(suggestion))
+        .collect()
+}
+
+/// Applies the `suggestions` to the files of `dir`, skipping any that touch text an earlier one
+/// changed or a file that is not in `dir`. A suggestion made again, e.g. for an error reported
+/// for two targets, is applied once. Returns how many suggestions were applied.
+pub fn apply(dir: &mut RawDir, suggestions: Vec<Suggestion>) -> usize {
+    let mut accepted: Vec<Edit> = Vec::new();
+    let mut applied = 0;
+    for suggestion in suggestions {
+        let new: Vec<Edit> = suggestion
+            .into_iter()
+            .filter(|edit| !accepted.contains(edit))
+            .collect();
+        let fits = |edit: &Edit| {
+            !accepted.iter().any(|other| other.overlaps(edit))
+                && dir
+                    .get_file(&edit.file)
+                    .is_ok_and(|contents| edit.range.end <= contents.len())
+        };
+        if new.is_empty() || !new.iter().all(fits) {
+            continue;
+        }
+        debug!("Applying rustc suggestion {new:?}");
+        accepted.extend(new);
+        applied += 1;
+    }
+
+    let mut by_file: BTreeMap<PathBuf, Vec<Edit>> = BTreeMap::new();
+    for edit in accepted {
+        by_file.entry(edit.file.clone()).or_default().push(edit);
+    }
+    for (file, mut edits) in by_file {
+        let Ok(contents) = dir.get_file_mut(&file) else {
+            continue;
+        };
+        // From the end of the file, so the earlier ranges stay valid.
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+        for edit in edits {
+            contents.splice(edit.range, edit.replacement.into_bytes());
+        }
+    }
+    applied
+}
+
+/// Applies rustc's suggestions for the errors of a failed build.
+pub struct ApplyRustcSuggestions;
+
+impl Tool for ApplyRustcSuggestions {
+    fn name(&self) -> &'static str {
+        "apply_rustc_suggestions"
+    }
+
+    /// Inputs:
+    /// 1. [`CargoPackage`] id -- the package that failed to build.
+    /// 2. [`CargoBuildResult`] id -- its build.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let config = Config::load(&context.config)?;
+        let mut package = context
+            .ir_snapshot
+            .get::<CargoPackage>(inputs[0])
+            .ok_or("apply_rustc_suggestions: no CargoPackage in IR")?
+            .clone();
+        let build = context
+            .ir_snapshot
+            .get::<CargoBuildResult>(inputs[1])
+            .ok_or("apply_rustc_suggestions: no CargoBuildResult in IR")?;
+
+        let suggestions = suggestions(&build.diagnostics, config.maybe_incorrect);
+        let found = suggestions.len();
+        let applied = apply(&mut package.dir, suggestions);
+        info!("apply_rustc_suggestions: applied {applied} of {found} rustc suggestions");
+        Ok(Box::new(package))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use try_cargo_build::cargo_check;
+
+    const LIB: &str = "\
+pub fn total(names: &[String]) -> usize {
+    let seen: HashSet<usize> = HashSet::new();
+    count(String::new()) + names.len() + seen.len()
+}
+
+fn count(name: &String) -> usize {
+    name.len()
+}
+";
+
+    fn package() -> RawDir {
+        let mut dir = RawDir::default();
+        dir.set_file(
+            "Cargo.toml",
+            b"[package]\nname = \"suggestions\"\nversion = \"0.1.0\"\nedition = \"2024\"\n\n\
+              [workspace]\n"
+                .to_vec(),
+        )
+        .unwrap();
+        dir.set_file("src/lib.rs", LIB.as_bytes().to_vec()).unwrap();
+        dir
+    }
+
+    /// Applies the suggestions for the errors of `dir` and returns the count and the new source.
+    fn fix(dir: &mut RawDir, maybe_incorrect: bool) -> (usize, String) {
+        let root = tempfile::tempdir().unwrap();
+        dir.materialize(root.path()).unwrap();
+        let (_, diagnostics) = cargo_check(root.path()).unwrap();
+        let applied = apply(dir, suggestions(&diagnostics, maybe_incorrect));
+        let lib = String::from_utf8(dir.get_file("src/lib.rs").unwrap().to_vec()).unwrap();
+        (applied, lib)
+    }
+
+    #[test]
+    fn applies_machine_applicable_suggestions_only_by_default() {
+        let mut dir = package();
+        let (applied, lib) = fix(&mut dir, false);
+        assert_eq!(applied, 1);
+        assert!(lib.contains("count(&String::new())"));
+        assert!(!lib.contains("use std::collections::HashSet;"));
+
+        // The import rustc is less sure of, suggested for both uses of `HashSet`, is added once.
+        let (applied, lib) = fix(&mut dir, true);
+        assert_eq!(applied, 1);
+        assert!(lib.starts_with("use std::collections::HashSet;\n"));
+        assert_eq!(lib.matches("use std::collections::HashSet;").count(), 1);
+    }
+
+    #[test]
+    fn skips_overlapping_and_unknown_edits() {
+        let edit = |file: &str, range: Range<usize>, replacement: &str| Edit {
+            file: file.into(),
+            range,
+            replacement: replacement.into(),
+        };
+        let mut dir = package();
+        let applied = apply(
+            &mut dir,
+            vec![
+                vec![],
+                vec![edit("src/lib.rs", 0..3, "fn")],
+                vec![edit("src/lib.rs", 2..5, "xx")],
+                vec![edit("src/main.rs", 0..0, "x")],
+                vec![edit("src/lib.rs", 0..3, "fn")],
+            ],
+        );
+        assert_eq!(applied, 1);
+        let lib = dir.get_file("src/lib.rs").unwrap();
+        assert!(lib.starts_with(b"fn fn total"));
+    }
+}
@@ -266,6 +266,138 @@ fn compose_format(template: &str, vars: &[ConfigVariable]) -> (String, Vec<Strin
     (fmt, args)
 }
 
+/// Render the build-script statements that pass the C project's defines to the `cc::Build` bound
+/// to `builder`, for the Cargo features the crate is built with -- the C-side counterpart of the
+/// `cargo:rustc-env` emissions of [`render_build_rs`].
+///
+/// The statements bind `has_<var>` for each boolean variable, and `has_<var>_<value>` and
+/// `<var>_value` for each enum variable (lowercased), so code rendered after them can test the
+/// active features too. An enum with none of its features enabled takes its default value.
+/// Returns an empty string when the IR is empty.
+pub fn render_cc_defines(cfg: &BuildConfigIR, builder: &str) -> String {
+    if cfg.is_empty {
+        return String::new();
+    }
+
+    let mut out = String::new();
+    for var in &cfg.variables {
+        let upper = var.name.to_uppercase();
+        let lower = var.name.to_lowercase();
+        match &var.kind {
+            ConfigVarKind::Boolean => out.push_str(&format!(
+                "    let has_{lower} = std::env::var(\"CARGO_FEATURE_{upper}\").is_ok();\n"
+            )),
+            ConfigVarKind::Enum { values, .. } => {
+                for val in values {
+                    out.push_str(&format!(
+                        "    let has_{lower}_{val_lc} = std::env::var(\"CARGO_FEATURE_{upper}_{val_uc}\").is_ok();\n",
+                        val_lc = val.to_lowercase(),
+                        val_uc = val.to_uppercase(),
+                    ));
+                }
+                let default = var
+                    .default
+                    .as_ref()
+                    .filter(|d| values.contains(d))
+                    .or(values.first())
+                    .cloned()
+                    .unwrap_or_default();
+                out.push_str(&format!("    let {lower}_value: &str = "));
+                for val in values {
+                    out.push_str(&format!(
+                        "if has_{lower}_{val_lc} {{ \"{val}\" }} else ",
+                        val_lc = val.to_lowercase(),
+                    ));
+                }
+                out.push_str(&format!("{{ \"{default}\" }};\n"));
+            }
+        }
+    }
+
+    for define in &cfg.defines {
+        render_cc_define(&mut out, &cfg.variables, define, builder, "    ");
+    }
+    for ss in &cfg.subdir_selections {
+        let Some(driving) = find_var(&cfg.variables, &ss.driving_var) else {
+            continue;
+        };
+        if !matches!(driving.kind, ConfigVarKind::Enum { .. }) {
+            continue;
+        }
+        let lower = ss.driving_var.to_lowercase();
+        for variant in ss.variants.iter().filter(|v| !v.defines.is_empty()) {
+            let val_lc = variant.value.to_lowercase();
+            out.push_str(&format!("    if has_{lower}_{val_lc} {{\n"));
+            for define in &variant.defines {
+                render_cc_define(&mut out, &cfg.variables, define, builder, "        ");
+            }
+            out.push_str("    }\n");
+        }
+    }
+    out
+}
+
+/// Render one `-D` of the C project as a `<builder>.define(...)` call. Defines whose value
+/// depends on a boolean variable are skipped, as in [`render_define`].
+fn render_cc_define(
+    out: &mut String,
+    vars: &[ConfigVariable],
+    define: &DefineMapping,
+    builder: &str,
+    indent: &str,
+) {
+    let c_name = &define.c_name;
+    match &define.kind {
+        DefineKind::Bare { var } => {
+            if !is_enum(vars, var) {
+                return;
+            }
+            let lower = var.to_lowercase();
+            out.push_str(&format!(
+                "{indent}{builder}.define(\"{c_name}\", {lower}_value);\n"
+            ));
+        }
+        DefineKind::QuotedString { var } => {
+            if !is_enum(vars, var) {
+                return;
+            }
+            let lower = var.to_lowercase();
+            out.push_str(&format!(
+                "{indent}{builder}.define(\"{c_name}\", format!(\"\\\"{{}}\\\"\", {lower}_value).as_str());\n"
+            ));
+        }
+        DefineKind::Composed { template } => {
+            let (fmt, args) = compose_format(template, vars);
+            if args.is_empty() {
+                return;
+            }
+            out.push_str(&format!(
+                "{indent}{builder}.define(\"{c_name}\", format!(\"{fmt}\", {args}).as_str());\n",
+                args = args.join(", "),
+            ));
+        }
+        // A flag CMake only passes under `if(VAR)` or `if(VAR STREQUAL "value")`.
+        DefineKind::GatedFlag {
+            gate_var,
+            gate_value,
+        } => {
+            let lower = gate_var.to_lowercase();
+            let condition = match (find_var(vars, gate_var).map(|v| &v.kind), gate_value) {
+                (Some(ConfigVarKind::Boolean), None) => format!("has_{lower}"),
+                (Some(ConfigVarKind::Enum { values, .. }), Some(value))
+                    if values.contains(value) =>
+                {
+                    format!("has_{lower}_{}", value.to_lowercase())
+                }
+                _ => return,
+            };
+            out.push_str(&format!(
+                "{indent}if {condition} {{ {builder}.define(\"{c_name}\", None); }}\n"
+            ));
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -343,6 +475,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn renders_cc_defines_for_active_features() {
+        let out = render_cc_defines(&example_p02(), "build");
+        assert!(out.contains(
+            "    let has_enable_extra = std::env::var(\"CARGO_FEATURE_ENABLE_EXTRA\").is_ok();\n"
+        ));
+        assert!(out.contains(
+            "    let backend_value: &str = if has_backend_alpha { \"alpha\" } else \
+             if has_backend_beta { \"beta\" } else { \"alpha\" };\n"
+        ));
+        assert!(out.contains(
+            "    build.define(\"APP_MODE_STR\", format!(\"\\\"{}\\\"\", app_mode_value).as_str());\n"
+        ));
+        assert!(out.contains("    build.define(\"WORD_SIZE\", word_size_value);\n"));
+        assert!(out.contains(
+            "    build.define(\"BUILD_PROFILE\", format!(\"{}_{}\", backend_value, word_size_value).as_str());\n"
+        ));
+        assert!(
+            out.contains("    if has_enable_extra { build.define(\"ENABLE_EXTRA\", None); }\n")
+        );
+    }
+
     #[test]
     fn renders_main_skeleton() {
         let out = render_build_rs(&example_p02());
@@ -27,7 +27,7 @@ pub mod ir;
 pub mod prompt_ext;
 pub mod scanner;
 
-pub use build_rs::render_build_rs;
+pub use build_rs::{render_build_rs, render_cc_defines};
 pub use ir::{
     BuildConfigIR, ConditionalTarget, ConfigVarKind, ConfigVariable, DefineKind, DefineMapping,
     SourceSelection, SourceVariant, SubdirSelection, SubdirVariant, TargetDecl, TargetKind,
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 
 use crate::EntityKind;
+use crate::layout::{self, CType, EnumLayout, RecordLayout};
+use crate::utils;
 
 /// Persistent AST representation for the C source code.
 /// We will extend this as we find that we need more info (from libClang).
@@ -9,6 +11,9 @@ use crate::EntityKind;
 pub enum ClangAST {
     TypedefDecl {
         name: String,
+        /// The type the typedef names.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        underlying: Option<CType>,
     },
     FunctionDecl {
         name: String,
@@ -17,9 +22,15 @@ pub enum ClangAST {
     RecordDecl {
         name: Option<String>,
         tag_used: Option<String>,
+        /// Field layout of a definition; `None` for a forward declaration.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layout: Option<RecordLayout>,
     },
     EnumDecl {
         name: Option<String>,
+        /// Underlying type and constants of a definition; `None` for a forward declaration.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layout: Option<EnumLayout>,
     },
     VarDecl {
         name: String,
@@ -30,6 +41,20 @@ pub enum ClangAST {
     },
 }
 
+impl ClangAST {
+    /// The declared name; `None` for anonymous records and enums and for `Other`.
+    pub fn name(&self) -> Option<&str> {
+        let name = match self {
+            ClangAST::TypedefDecl { name, .. }
+            | ClangAST::FunctionDecl { name, .. }
+            | ClangAST::VarDecl { name, .. } => Some(name.as_str()),
+            ClangAST::RecordDecl { name, .. } | ClangAST::EnumDecl { name, .. } => name.as_deref(),
+            ClangAST::Other { .. } => None,
+        };
+        name.filter(|name| !name.is_empty())
+    }
+}
+
 /// Extract info from a libClang Entity and convert it into our ClangAST representation.
 /// Returns None for entities that are removed during preprocessing.
 pub(crate) fn ast_from_entity(
@@ -39,21 +64,24 @@ pub(crate) fn ast_from_entity(
     match decl_kind {
         EntityKind::TypedefDecl => Some(ClangAST::TypedefDecl {
             name: entity.get_name().unwrap_or_default(),
+            underlying: layout::typedef_underlying(entity),
         }),
         EntityKind::FunctionDecl => Some(ClangAST::FunctionDecl {
             name: entity.get_name().unwrap_or_default(),
-            storage_class: None,
+            storage_class: utils::storage_class_keyword(entity),
         }),
         EntityKind::RecordDecl | EntityKind::UnionDecl => Some(ClangAST::RecordDecl {
             name: entity.get_name(),
             tag_used: None,
+            layout: layout::record_layout(entity),
         }),
         EntityKind::EnumDecl => Some(ClangAST::EnumDecl {
             name: entity.get_name(),
+            layout: layout::enum_layout(entity),
         }),
         EntityKind::VarDecl => Some(ClangAST::VarDecl {
             name: entity.get_name().unwrap_or_default(),
-            storage_class: None,
+            storage_class: utils::storage_class_keyword(entity),
         }),
         EntityKind::PreprocessingDirective => None,
         EntityKind::MacroDefinition => None,
@@ -0,0 +1,239 @@
+//! On-disk cache of per-file parse results, so unchanged files are not re-parsed across runs.
+//!
+//! An entry is keyed by the file's path, contents and parse arguments. Analyses of the clang AST
+//! (see [`crate::analyze_translation_units`]) keep their results in a cache of their own, keyed
+//! the same way. Because a translation unit
+//! also depends on the project headers it includes, each entry records the hashes of those headers
+//! and is only used if they still match.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::{Linkage, TopLevelEntity};
+
+/// Bump when the cached data changes shape or meaning, so stale entries are ignored.
+const CACHE_VERSION: &str = "3";
+
+/// A top-level entity as extracted from one translation unit, before `variant_tags` (which
+/// depend on the build configuration, not on the file) are applied.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ParsedEntity {
+    pub entity: TopLevelEntity,
+    /// Whether the declaration is a definition; decides between `app_functions` and
+    /// `app_func_sigs`.
+    pub is_definition: bool,
+    /// Linkage of a function or variable declaration; `None` for other entities.
+    #[serde(default)]
+    pub linkage: Option<Linkage>,
+}
+
+/// Everything extracted from one translation unit.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct FileParse {
+    pub entities: Vec<ParsedEntity>,
+    /// Project files (relative paths) the translation unit includes, directly or transitively.
+    pub includes: Vec<String>,
+    /// Names of the project functions and global variables the file's own code refers to.
+    #[serde(default)]
+    pub references: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<T> {
+    /// Content hash of every included project file at the time of parsing.
+    include_hashes: BTreeMap<String, String>,
+    parse: T,
+}
+
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The cache key for `rel_path` with `contents`, parsed with `args` by the libclang of version
+/// `clang_version`. `args` must not contain run-specific paths (see
+/// [`crate::normalized_parse_args`]).
+pub(crate) fn cache_key(
+    rel_path: &Path,
+    contents: &[u8],
+    args: &[String],
+    clang_version: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        CACHE_VERSION.as_bytes(),
+        clang_version.as_bytes(),
+        rel_path.to_string_lossy().as_bytes(),
+    ] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+    for arg in args {
+        hasher.update(arg.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(contents);
+    hex(&hasher.finalize())
+}
+
+pub(crate) struct ParseCache {
+    dir: PathBuf,
+}
+
+impl ParseCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// Returns the cached parse for `key` if every file it included still has the recorded
+    /// hash in `file_hashes` (relative path -> content hash).
+    pub fn get<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        file_hashes: &HashMap<String, String>,
+    ) -> Option<T> {
+        let bytes = std::fs::read(self.entry_path(key)).ok()?;
+        let entry: CacheEntry<T> = match serde_json::from_slice(&bytes) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Ignoring unreadable parse cache entry {key}: {e}");
+                return None;
+            }
+        };
+        let fresh = entry
+            .include_hashes
+            .iter()
+            .all(|(file, hash)| file_hashes.get(file) == Some(hash));
+        if !fresh {
+            debug!("Parse cache entry {key} is stale: an included file changed");
+            return None;
+        }
+        Some(entry.parse)
+    }
+
+    /// Stores `parse`, which was extracted from a translation unit including the project files
+    /// `includes`, under `key`. Failures are logged, not returned: the cache is an optimization
+    /// and must never fail a run.
+    pub fn put<T: Serialize>(
+        &self,
+        key: &str,
+        parse: &T,
+        includes: &[String],
+        file_hashes: &HashMap<String, String>,
+    ) {
+        let include_hashes = includes
+            .iter()
+            .filter_map(|file| Some((file.clone(), file_hashes.get(file)?.clone())))
+            .collect();
+        let entry = CacheEntry {
+            include_hashes,
+            parse,
+        };
+        if let Err(e) = self.write_entry(key, &entry) {
+            warn!("Failed to write parse cache entry {key}: {e}");
+        }
+    }
+
+    fn write_entry<T: Serialize>(
+        &self,
+        key: &str,
+        entry: &CacheEntry<&T>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.dir)?;
+        // Write then rename, so concurrent runs never observe a partial entry.
+        let mut tmp = tempfile::NamedTempFile::new_in(&self.dir)?;
+        serde_json::to_writer(&mut tmp, entry)?;
+        tmp.persist(self.entry_path(key))?;
+        Ok(())
+    }
+}
+
+#[cfg(not(miri))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityKind, SourcePoint, SourceSpan};
+
+    fn parse_with_include(include: &str) -> FileParse {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset: 0,
+        };
+        FileParse {
+            entities: vec![ParsedEntity {
+                entity: TopLevelEntity {
+                    kind: EntityKind::FunctionDecl,
+                    source_text: "int f(void) { return 0; }".into(),
+                    span: SourceSpan {
+                        file: "src/main.c".into(),
+                        start: point.clone(),
+                        end: point,
+                    },
+                    ast: None,
+                    annotations: Default::default(),
+                    sub_entities: Vec::new(),
+                    variant_tags: Vec::new(),
+                    comments: Default::default(),
+                },
+                is_definition: true,
+                linkage: Some(Linkage::External),
+            }],
+            includes: vec![include.into()],
+            references: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn key_depends_on_path_args_contents_and_clang_version() {
+        let args = vec!["-std=gnu11".to_string()];
+        let key = |path: &str, contents: &[u8], args: &[String], version: &str| {
+            cache_key(Path::new(path), contents, args, version)
+        };
+        let base = key("a.c", b"int x;", &args, "clang version 18.1.3");
+        assert_eq!(base, key("a.c", b"int x;", &args, "clang version 18.1.3"));
+        assert_ne!(base, key("b.c", b"int x;", &args, "clang version 18.1.3"));
+        assert_ne!(base, key("a.c", b"int y;", &args, "clang version 18.1.3"));
+        assert_ne!(base, key("a.c", b"int x;", &[], "clang version 18.1.3"));
+        assert_ne!(base, key("a.c", b"int x;", &args, "clang version 19.1.0"));
+    }
+
+    #[test]
+    fn entry_round_trips_until_an_include_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ParseCache::new(dir.path().join("cache"));
+        let mut hashes = HashMap::from([("include/a.h".to_string(), content_hash(b"v1"))]);
+
+        assert!(cache.get::<FileParse>("k", &hashes).is_none());
+        let parse = parse_with_include("include/a.h");
+        cache.put("k", &parse, &parse.includes, &hashes);
+        let hit: FileParse = cache.get("k", &hashes).expect("fresh entry must hit");
+        assert_eq!(hit.entities.len(), 1);
+        assert_eq!(hit.includes, ["include/a.h"]);
+
+        hashes.insert("include/a.h".into(), content_hash(b"v2"));
+        assert!(
+            cache.get::<FileParse>("k", &hashes).is_none(),
+            "changed include must miss"
+        );
+    }
+
+    #[test]
+    fn corrupt_entry_is_a_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("k.json"), b"not json").unwrap();
+        let cache = ParseCache::new(dir.path().to_path_buf());
+        assert!(cache.get::<FileParse>("k", &HashMap::new()).is_none());
+    }
+}
@@ -0,0 +1,398 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::SourceSpan;
+
+/// The kind of sub-declaration a [`MemberComment`] is attached to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemberKind {
+    /// A struct or union field.
+    Field,
+    /// A function parameter.
+    Parameter,
+    /// An enum constant.
+    EnumConstant,
+}
+
+/// Comments attached to a single field, parameter or enum constant of a top-level entity.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MemberComment {
+    pub kind: MemberKind,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leading: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing: Option<String>,
+}
+
+/// Comments attached to a top-level entity and its members.
+///
+/// Comment text is stored with the comment markers (`//`, `/* */`, leading `*`) already
+/// stripped, so consumers can re-emit it in whatever syntax they need (e.g. `///` rustdoc).
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityComments {
+    /// The comment immediately preceding the entity (or attached to any redeclaration of it).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leading: Option<String>,
+    /// A comment on the same line, directly after the entity.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing: Option<String>,
+    /// Comments on fields, parameters and enum constants, in declaration order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<MemberComment>,
+}
+
+impl EntityComments {
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_none() && self.trailing.is_none() && self.members.is_empty()
+    }
+
+    /// Returns the comment on the member of `kind` named `name`, if any.
+    pub fn member(&self, kind: MemberKind, name: &str) -> Option<&MemberComment> {
+        self.members
+            .iter()
+            .find(|m| m.kind == kind && m.name == name)
+    }
+
+    /// Fills in whatever `self` is missing from `other`. Used when one entity absorbs another
+    /// (e.g. a typedef absorbing the struct it names) so the absorbed comments are not lost.
+    pub(crate) fn merge_from(&mut self, other: &EntityComments) {
+        if self.leading.is_none() {
+            self.leading.clone_from(&other.leading);
+        }
+        if self.trailing.is_none() {
+            self.trailing.clone_from(&other.trailing);
+        }
+        for member in &other.members {
+            if self.member(member.kind, &member.name).is_none() {
+                self.members.push(member.clone());
+            }
+        }
+    }
+}
+
+/// Collects the comments attached to `entity` and to its fields, parameters and enum constants.
+///
+/// Leading comments come from clang's comment API (which, with `-fparse-all-comments`, also
+/// covers ordinary comments and comments on any redeclaration, e.g. the header prototype of a
+/// function defined in a `.c` file). Clang does not attach ordinary trailing comments, and never
+/// attaches comments to macro definitions, so those are recovered by scanning the source text
+/// around `span`. `source` holds the contents of the span's file.
+pub(crate) fn extract_comments(
+    entity: &clang::Entity<'_>,
+    span: &SourceSpan,
+    source: &[u8],
+) -> EntityComments {
+    let (leading, trailing) = comments_around(
+        entity,
+        Path::new(&span.file),
+        source,
+        span.start.offset as usize,
+        span.end.offset as usize,
+    );
+    let mut members = Vec::new();
+    collect_member_comments(entity, Path::new(&span.file), source, &mut members);
+    EntityComments {
+        leading,
+        trailing,
+        members,
+    }
+}
+
+/// `source` holds the contents of `file`; members located in any other file (e.g. fields
+/// produced by a macro defined in a header) only get clang-attached comments.
+fn collect_member_comments(
+    entity: &clang::Entity<'_>,
+    file: &Path,
+    source: &[u8],
+    out: &mut Vec<MemberComment>,
+) {
+    for child in entity.get_children() {
+        let kind = match child.get_kind() {
+            clang::EntityKind::FieldDecl => MemberKind::Field,
+            clang::EntityKind::ParmDecl => MemberKind::Parameter,
+            clang::EntityKind::EnumConstantDecl => MemberKind::EnumConstant,
+            // Inline definitions, e.g. the struct body of `typedef struct { ... } foo_t;`.
+            clang::EntityKind::StructDecl
+            | clang::EntityKind::UnionDecl
+            | clang::EntityKind::EnumDecl => {
+                collect_member_comments(&child, file, source, out);
+                continue;
+            }
+            _ => continue,
+        };
+        let Some(name) = child.get_name() else {
+            continue;
+        };
+        let Some((start, end)) = crate::utils::get_range(&child) else {
+            continue;
+        };
+        let Some(child_file) = start.file.map(|f| f.get_path()) else {
+            continue;
+        };
+        let child_source = if same_path(&child_file, file) {
+            source
+        } else {
+            &[]
+        };
+        let (leading, trailing) = comments_around(
+            &child,
+            &child_file,
+            child_source,
+            start.offset as usize,
+            end.offset as usize,
+        );
+        if leading.is_none() && trailing.is_none() {
+            continue;
+        }
+        out.push(MemberComment {
+            kind,
+            name,
+            leading,
+            trailing,
+        });
+    }
+}
+
+/// Returns the `(leading, trailing)` comments for the declaration spanning `start..end` of
+/// `source`. The clang-attached comment is preferred; it is classified as trailing when it
+/// starts after the declaration in the same file.
+fn comments_around(
+    entity: &clang::Entity<'_>,
+    file: &Path,
+    source: &[u8],
+    start: usize,
+    end: usize,
+) -> (Option<String>, Option<String>) {
+    let mut leading = None;
+    let mut trailing = None;
+    if let (Some(raw), Some(range)) = (entity.get_comment(), entity.get_comment_range()) {
+        let comment_start = range.get_start().get_spelling_location();
+        let same_file = comment_start
+            .file
+            .is_some_and(|f| same_path(&f.get_path(), file));
+        if same_file && comment_start.offset as usize >= end {
+            trailing = normalize_comment(&raw);
+        } else {
+            leading = normalize_comment(&raw);
+        }
+    }
+    if leading.is_none() {
+        leading = leading_comment_before(source, start).and_then(normalize_comment);
+    }
+    if trailing.is_none() {
+        trailing = trailing_comment_after(source, end).and_then(normalize_comment);
+    }
+    (leading, trailing)
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    a == b || a.canonicalize().ok() == b.canonicalize().ok()
+}
+
+/// Finds the comment block that ends on the line directly above `offset` (blank lines break the
+/// association). The text between the start of `offset`'s line and `offset` must be whitespace or
+/// a preprocessor directive prefix such as `#define `, since clang reports macro definitions
+/// starting at the macro name.
+pub(crate) fn leading_comment_before(source: &[u8], offset: usize) -> Option<&str> {
+    let offset = offset.min(source.len());
+    let line_start = source[..offset]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let prefix = str::from_utf8(&source[line_start..offset]).ok()?.trim();
+    if !(prefix.is_empty() || prefix.starts_with('#')) {
+        return None;
+    }
+    let before = str::from_utf8(&source[..line_start]).ok()?;
+    // Exactly one newline (the one ending the previous line) may separate comment and entity.
+    let trimmed = before.trim_end_matches([' ', '\t', '\r']);
+    let trimmed = trimmed.strip_suffix('\n')?;
+    let trimmed = trimmed.trim_end_matches([' ', '\t', '\r']);
+
+    if trimmed.ends_with("*/") {
+        let open = trimmed.rfind("/*")?;
+        // The block must start its own line, not trail some other code.
+        let open_line_start = trimmed[..open].rfind('\n').map_or(0, |i| i + 1);
+        if !trimmed[open_line_start..open].trim().is_empty() {
+            return None;
+        }
+        return Some(&trimmed[open..]);
+    }
+
+    // A run of consecutive `//` lines.
+    let mut block_start = None;
+    let mut cursor = trimmed.len();
+    loop {
+        let line_start = trimmed[..cursor].rfind('\n').map_or(0, |i| i + 1);
+        let line = trimmed[line_start..cursor].trim();
+        if !line.starts_with("//") {
+            break;
+        }
+        block_start = Some(line_start + trimmed[line_start..cursor].find("//")?);
+        if line_start == 0 {
+            break;
+        }
+        cursor = line_start - 1;
+    }
+    block_start.map(|s| &trimmed[s..])
+}
+
+/// Finds a comment starting on the same line as `offset`, after optional whitespace and a
+/// single `;` or `,` terminator (field and parameter extents end before their terminator).
+pub(crate) fn trailing_comment_after(source: &[u8], offset: usize) -> Option<&str> {
+    let rest = str::from_utf8(source.get(offset..)?).ok()?;
+    let rest = rest.trim_start_matches([' ', '\t']);
+    let rest = rest
+        .strip_prefix([';', ','])
+        .unwrap_or(rest)
+        .trim_start_matches([' ', '\t']);
+    if rest.starts_with("//") {
+        let end = rest.find('\n').unwrap_or(rest.len());
+        Some(rest[..end].trim_end())
+    } else if rest.starts_with("/*") {
+        let end = rest.find("*/")? + 2;
+        Some(&rest[..end])
+    } else {
+        None
+    }
+}
+
+/// Strips comment markers from a raw C comment (or several adjacent ones), returning the comment
+/// text with surrounding blank lines removed, or `None` if nothing but markers remains.
+pub(crate) fn normalize_comment(raw: &str) -> Option<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in raw.lines() {
+        let mut text = line.trim();
+        if let Some(rest) = text.strip_prefix("//") {
+            text = rest.trim_start_matches(['/', '!']);
+            text = text.strip_prefix('<').unwrap_or(text);
+        } else {
+            // Strip the terminator first so `/****/` does not lose it to the opener.
+            let closed = text.strip_suffix("*/");
+            text = closed.unwrap_or(text);
+            if let Some(rest) = text.strip_prefix("/*") {
+                text = rest.trim_start_matches(['*', '!']);
+                text = text.strip_prefix('<').unwrap_or(text);
+            } else {
+                text = text.strip_prefix('*').unwrap_or(text);
+            }
+            if closed.is_some() {
+                text = text.trim_end_matches('*');
+            }
+        }
+        lines.push(
+            text.strip_prefix(' ')
+                .unwrap_or(text)
+                .trim_end()
+                .to_string(),
+        );
+    }
+
+    let first = lines.iter().position(|l| !l.is_empty())?;
+    let last = lines.iter().rposition(|l| !l.is_empty())?;
+    Some(lines[first..=last].join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_line_comments() {
+        assert_eq!(normalize_comment("// hello").as_deref(), Some("hello"));
+        assert_eq!(
+            normalize_comment("/// first\n/// second").as_deref(),
+            Some("first\nsecond")
+        );
+        assert_eq!(
+            normalize_comment("///< trailing").as_deref(),
+            Some("trailing")
+        );
+        assert_eq!(normalize_comment("//").as_deref(), None);
+    }
+
+    #[test]
+    fn normalize_block_comments() {
+        assert_eq!(normalize_comment("/* one */").as_deref(), Some("one"));
+        assert_eq!(
+            normalize_comment("/**\n * Adds two ints.\n *\n * Overflow wraps.\n */").as_deref(),
+            Some("Adds two ints.\n\nOverflow wraps.")
+        );
+        assert_eq!(normalize_comment("/**< count */").as_deref(), Some("count"));
+        assert_eq!(normalize_comment("/****/").as_deref(), None);
+    }
+
+    #[test]
+    fn leading_block_comment() {
+        let src = b"int a;\n/* doc */\nint f(void);\n";
+        let offset = src.len() - "int f(void);\n".len();
+        assert_eq!(leading_comment_before(src, offset), Some("/* doc */"));
+    }
+
+    #[test]
+    fn leading_line_comments_stop_at_code() {
+        let src = b"int a; // not mine\n// one\n// two\nint f(void);\n";
+        let offset = src.len() - "int f(void);\n".len();
+        assert_eq!(leading_comment_before(src, offset), Some("// one\n// two"));
+    }
+
+    #[test]
+    fn leading_comment_requires_adjacent_line() {
+        let src = b"// far away\n\nint f(void);\n";
+        let offset = src.len() - "int f(void);\n".len();
+        assert_eq!(leading_comment_before(src, offset), None);
+    }
+
+    #[test]
+    fn leading_comment_skips_define_prefix() {
+        let src = b"// Buffer size.\n#define BUF 64\n";
+        let offset = src.len() - "BUF 64\n".len();
+        assert_eq!(leading_comment_before(src, offset), Some("// Buffer size."));
+    }
+
+    #[test]
+    fn leading_block_comment_must_start_line() {
+        let src = b"int a; /* a's */\nint b;\n";
+        let offset = src.len() - "int b;\n".len();
+        assert_eq!(leading_comment_before(src, offset), None);
+    }
+
+    #[test]
+    fn trailing_comments() {
+        let src = b"    int count; // number of items\n";
+        let end = "    int count".len();
+        assert_eq!(trailing_comment_after(src, end), Some("// number of items"));
+
+        let src = b"int f(int a /* first */, int b);";
+        let end = "int f(int a".len();
+        assert_eq!(trailing_comment_after(src, end), Some("/* first */"));
+
+        let src = b"int x;\n// next line\n";
+        assert_eq!(trailing_comment_after(src, "int x".len()), None);
+    }
+
+    #[test]
+    fn merge_fills_missing_fields() {
+        let mut typedef = EntityComments {
+            leading: Some("typedef doc".into()),
+            ..Default::default()
+        };
+        let record = EntityComments {
+            leading: Some("struct doc".into()),
+            trailing: Some("tail".into()),
+            members: vec![MemberComment {
+                kind: MemberKind::Field,
+                name: "x".into(),
+                leading: Some("x coordinate".into()),
+                trailing: None,
+            }],
+        };
+        typedef.merge_from(&record);
+        assert_eq!(typedef.leading.as_deref(), Some("typedef doc"));
+        assert_eq!(typedef.trailing.as_deref(), Some("tail"));
+        assert_eq!(typedef.members.len(), 1);
+        typedef.merge_from(&record);
+        assert_eq!(typedef.members.len(), 1, "merge must not duplicate members");
+    }
+}
@@ -0,0 +1,382 @@
+//! Memory layout of C types as libclang computes it: field offsets and bit widths, sizes and
+//! alignments, enum values and typedef targets. This is what a layout-preserving Rust lowering
+//! needs, and what an LLM translation of the source text cannot be relied on to keep.
+
+use serde::{Deserialize, Serialize};
+
+/// An integer type, by its C spelling where the size depends on the target.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntKind {
+    /// Plain `char`, whose signedness depends on the target.
+    Char,
+    SChar,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    LongLong,
+    ULongLong,
+    /// An integer of exactly `bits` bits, such as `int32_t`, `wchar_t` or `__int128`.
+    Fixed {
+        bits: u16,
+        signed: bool,
+    },
+    /// `size_t` (unsigned) or `ssize_t`/`ptrdiff_t` (signed).
+    Size {
+        signed: bool,
+    },
+}
+
+/// A C type, as far as its layout is concerned.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", rename_all_fields = "snake_case")]
+pub enum CType {
+    Void,
+    Bool,
+    Int {
+        kind: IntKind,
+    },
+    /// `float` (4) or `double` (8).
+    Float {
+        size: u8,
+    },
+    Pointer {
+        pointee: Box<CType>,
+        /// Whether the pointee is `const`.
+        is_const: bool,
+    },
+    /// `len` is `None` for a flexible array member.
+    Array {
+        element: Box<CType>,
+        len: Option<u64>,
+    },
+    /// A function type; only ever found behind a pointer.
+    Function {
+        ret: Box<CType>,
+        params: Vec<CType>,
+        variadic: bool,
+    },
+    /// A project type, by its C spelling: `struct foo`, `union foo`, `enum foo` or a typedef name.
+    Named {
+        name: String,
+    },
+    /// An anonymous struct or union, defined where it is used.
+    Record {
+        layout: Box<RecordLayout>,
+    },
+    /// A type the lowering has no equivalent for (`long double`, a system struct held by value),
+    /// kept as opaque bytes of the same size and alignment.
+    Opaque {
+        size: u64,
+        align: u64,
+        spelling: String,
+    },
+}
+
+/// Layout of a struct or union definition.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordLayout {
+    pub is_union: bool,
+    pub size: u64,
+    pub align: u64,
+    pub fields: Vec<FieldLayout>,
+}
+
+/// One field of a struct or union.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldLayout {
+    /// `None` for an anonymous struct or union member or an unnamed bit-field.
+    pub name: Option<String>,
+    pub ty: CType,
+    /// Offset from the start of the record, in bits.
+    pub offset_bits: u64,
+    /// Width of a bit-field; `None` for an ordinary field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bit_width: Option<u64>,
+    /// Size and alignment of the field's type; 0 for a flexible array member.
+    pub size: u64,
+    pub align: u64,
+}
+
+/// The integer type and constants of an enum definition.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EnumLayout {
+    pub underlying: CType,
+    pub constants: Vec<EnumConstant>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EnumConstant {
+    pub name: String,
+    /// The value, as a two's-complement `i64` for unsigned enums too.
+    pub value: i64,
+}
+
+/// Typedef names whose width is fixed whatever the target, mapped to that width rather than to
+/// the target's underlying type.
+const FIXED_TYPEDEFS: &[(&str, IntKind)] = &[
+    (
+        "int8_t",
+        IntKind::Fixed {
+            bits: 8,
+            signed: true,
+        },
+    ),
+    (
+        "int16_t",
+        IntKind::Fixed {
+            bits: 16,
+            signed: true,
+        },
+    ),
+    (
+        "int32_t",
+        IntKind::Fixed {
+            bits: 32,
+            signed: true,
+        },
+    ),
+    (
+        "int64_t",
+        IntKind::Fixed {
+            bits: 64,
+            signed: true,
+        },
+    ),
+    (
+        "uint8_t",
+        IntKind::Fixed {
+            bits: 8,
+            signed: false,
+        },
+    ),
+    (
+        "uint16_t",
+        IntKind::Fixed {
+            bits: 16,
+            signed: false,
+        },
+    ),
+    (
+        "uint32_t",
+        IntKind::Fixed {
+            bits: 32,
+            signed: false,
+        },
+    ),
+    (
+        "uint64_t",
+        IntKind::Fixed {
+            bits: 64,
+            signed: false,
+        },
+    ),
+    ("size_t", IntKind::Size { signed: false }),
+    ("uintptr_t", IntKind::Size { signed: false }),
+    ("ssize_t", IntKind::Size { signed: true }),
+    ("ptrdiff_t", IntKind::Size { signed: true }),
+    ("intptr_t", IntKind::Size { signed: true }),
+];
+
+/// The layout of a struct or union definition; `None` for a forward declaration.
+pub(crate) fn record_layout(entity: &clang::Entity<'_>) -> Option<RecordLayout> {
+    if !entity.is_definition() {
+        return None;
+    }
+    let ty = entity.get_type()?;
+    let fields = ty
+        .get_fields()?
+        .iter()
+        .map(|field| {
+            let field_ty = field.get_type()?;
+            Some(FieldLayout {
+                name: field.get_name().filter(|name| !name.is_empty()),
+                ty: c_type(field_ty),
+                offset_bits: field.get_offset_of_field().ok()? as u64,
+                bit_width: field
+                    .is_bit_field()
+                    .then(|| field.get_bit_field_width())
+                    .flatten()
+                    .map(|width| width as u64),
+                size: field_ty.get_sizeof().unwrap_or(0) as u64,
+                align: field_ty.get_alignof().ok()? as u64,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(RecordLayout {
+        is_union: entity.get_kind() == clang::EntityKind::UnionDecl,
+        size: ty.get_sizeof().ok()? as u64,
+        align: ty.get_alignof().ok()? as u64,
+        fields,
+    })
+}
+
+/// The underlying type and constants of an enum definition; `None` for a forward declaration.
+pub(crate) fn enum_layout(entity: &clang::Entity<'_>) -> Option<EnumLayout> {
+    if !entity.is_definition() {
+        return None;
+    }
+    let constants = entity
+        .get_children()
+        .iter()
+        .filter(|child| child.get_kind() == clang::EntityKind::EnumConstantDecl)
+        .map(|constant| {
+            Some(EnumConstant {
+                name: constant.get_name()?,
+                value: constant.get_enum_constant_value()?.0,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(EnumLayout {
+        underlying: c_type(entity.get_enum_underlying_type()?),
+        constants,
+    })
+}
+
+/// The type a typedef names.
+pub(crate) fn typedef_underlying(entity: &clang::Entity<'_>) -> Option<CType> {
+    entity.get_typedef_underlying_type().map(c_type)
+}
+
+fn c_type(ty: clang::Type<'_>) -> CType {
+    use clang::TypeKind as K;
+    let int = |kind| CType::Int { kind };
+    match ty.get_kind() {
+        K::Void => CType::Void,
+        K::Bool => CType::Bool,
+        K::CharS | K::CharU => int(IntKind::Char),
+        K::SChar => int(IntKind::SChar),
+        K::UChar => int(IntKind::UChar),
+        K::Short => int(IntKind::Short),
+        K::UShort => int(IntKind::UShort),
+        K::Int => int(IntKind::Int),
+        K::UInt => int(IntKind::UInt),
+        K::Long => int(IntKind::Long),
+        K::ULong => int(IntKind::ULong),
+        K::LongLong => int(IntKind::LongLong),
+        K::ULongLong => int(IntKind::ULongLong),
+        K::Int128 => int(IntKind::Fixed {
+            bits: 128,
+            signed: true,
+        }),
+        K::UInt128 => int(IntKind::Fixed {
+            bits: 128,
+            signed: false,
+        }),
+        K::WChar | K::Char16 | K::Char32 => int(IntKind::Fixed {
+            bits: ty.get_sizeof().map_or(32, |size| size as u16 * 8),
+            signed: ty.get_kind() == K::WChar,
+        }),
+        K::Float => CType::Float { size: 4 },
+        K::Double => CType::Float { size: 8 },
+        K::Elaborated => match ty.get_elaborated_type() {
+            Some(named) => c_type(named),
+            None => opaque(ty),
+        },
+        K::Typedef => {
+            let Some(decl) = ty.get_declaration() else {
+                return c_type(ty.get_canonical_type());
+            };
+            let name = decl.get_name().unwrap_or_default();
+            if let Some((_, kind)) = FIXED_TYPEDEFS.iter().find(|(fixed, _)| *fixed == name) {
+                return int(*kind);
+            }
+            if decl.is_in_system_header() {
+                c_type(ty.get_canonical_type())
+            } else {
+                CType::Named { name }
+            }
+        }
+        K::Record => {
+            let Some(decl) = ty.get_declaration() else {
+                return opaque(ty);
+            };
+            if decl.is_in_system_header() {
+                return opaque(ty);
+            }
+            match decl.get_name().filter(|name| !name.is_empty()) {
+                Some(name) if !decl.is_anonymous() => {
+                    let tag = match decl.get_kind() {
+                        clang::EntityKind::UnionDecl => "union",
+                        _ => "struct",
+                    };
+                    CType::Named {
+                        name: format!("{tag} {name}"),
+                    }
+                }
+                _ => match record_layout(&decl) {
+                    Some(layout) => CType::Record {
+                        layout: Box::new(layout),
+                    },
+                    None => opaque(ty),
+                },
+            }
+        }
+        K::Enum => {
+            let Some(decl) = ty.get_declaration() else {
+                return opaque(ty);
+            };
+            match decl.get_name().filter(|name| !name.is_empty()) {
+                Some(name) if !decl.is_in_system_header() && !decl.is_anonymous() => CType::Named {
+                    name: format!("enum {name}"),
+                },
+                _ => match decl.get_enum_underlying_type() {
+                    Some(underlying) => c_type(underlying),
+                    None => opaque(ty),
+                },
+            }
+        }
+        K::Pointer => {
+            let Some(pointee) = ty.get_pointee_type() else {
+                return opaque(ty);
+            };
+            // A pointer to a function typedef is a function pointer, whatever the typedef's name.
+            let canonical = pointee.get_canonical_type();
+            let pointee_ty = match canonical.get_kind() {
+                K::FunctionPrototype | K::FunctionNoPrototype => c_type(canonical),
+                _ => c_type(pointee),
+            };
+            CType::Pointer {
+                pointee: Box::new(pointee_ty),
+                is_const: pointee.is_const_qualified(),
+            }
+        }
+        K::ConstantArray => match ty.get_element_type() {
+            Some(element) => CType::Array {
+                element: Box::new(c_type(element)),
+                len: ty.get_size().map(|len| len as u64),
+            },
+            None => opaque(ty),
+        },
+        K::IncompleteArray => match ty.get_element_type() {
+            Some(element) => CType::Array {
+                element: Box::new(c_type(element)),
+                len: None,
+            },
+            None => opaque(ty),
+        },
+        K::FunctionPrototype | K::FunctionNoPrototype => CType::Function {
+            ret: Box::new(ty.get_result_type().map_or(CType::Void, c_type)),
+            params: ty
+                .get_argument_types()
+                .unwrap_or_default()
+                .into_iter()
+                .map(c_type)
+                .collect(),
+            variadic: ty.is_variadic() || ty.get_kind() == K::FunctionNoPrototype,
+        },
+        _ => opaque(ty),
+    }
+}
+
+fn opaque(ty: clang::Type<'_>) -> CType {
+    CType::Opaque {
+        size: ty.get_sizeof().unwrap_or(0) as u64,
+        align: ty.get_alignof().unwrap_or(1) as u64,
+        spelling: ty.get_display_name(),
+    }
+}
@@ -1,22 +1,36 @@
 mod annotations;
 mod ast;
+mod cache;
+mod comments;
+mod layout;
+mod pool;
 mod rsm;
+mod symbols;
 mod utils;
 
 use build_config::{BuildConfigIR, SubdirVariant};
-use clang::{Clang as LibClang, Index};
+use cache::{FileParse, ParseCache, ParsedEntity, content_hash};
+use clang::Index;
+use directories::ProjectDirs;
 use full_source::RawSource;
+use harvest_core::config::unknown_field_warning;
 use harvest_core::{
     Id, Representation,
     tools::{RunContext, Tool},
 };
-use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 use tracing::{debug, warn};
 
 pub use annotations::{EntityAnnotations, annotate_visibility};
 pub use ast::ClangAST;
+pub use comments::{EntityComments, MemberComment, MemberKind};
+pub use layout::{CType, EnumConstant, EnumLayout, FieldLayout, IntKind, RecordLayout};
 pub use rsm::{EntityKind, RichSourceMap, SourcePoint, SourceSpan, TopLevelEntity};
+pub use symbols::{Linkage, Symbol, SymbolKind, SymbolTable};
 
 /// Lookup table from canonicalized absolute file path to the list of
 /// `(driving_var, value)` tags that file participates in. Built once from
@@ -168,6 +182,8 @@ fn relativize_span_file(mut span: SourceSpan, src_root: &Path) -> SourceSpan {
 /// This includes standard flags, include paths, and language specification based on file extension.
 fn generate_parse_args(src_root: &Path, rel_file: &Path) -> Vec<String> {
     let mut parser_arg_values = vec!["-std=gnu11".to_string()];
+    // Attach ordinary (non-doxygen) comments too, so they can be carried into rustdoc.
+    parser_arg_values.push("-fparse-all-comments".to_string());
     parser_arg_values.push(format!("-I{}/include", src_root.to_string_lossy()));
     parser_arg_values.extend(
         utils::language_args_for_file(rel_file)
@@ -177,6 +193,12 @@ fn generate_parse_args(src_root: &Path, rel_file: &Path) -> Vec<String> {
     parser_arg_values
 }
 
+/// [`generate_parse_args`] with the (per-run, temporary) source root replaced by a placeholder,
+/// for use in cache keys.
+fn normalized_parse_args(rel_file: &Path) -> Vec<String> {
+    generate_parse_args(Path::new("<root>"), rel_file)
+}
+
 /// Utility function to instantiate the libclang parser.
 fn build_parser<'a>(index: &'a Index, src_root: &Path, rel_file: &Path) -> clang::Parser<'a> {
     let abs_file = src_root.join(rel_file);
@@ -196,22 +218,31 @@ fn build_parser<'a>(index: &'a Index, src_root: &Path, rel_file: &Path) -> clang
 
 /// Extract top-level entities from the translation unit.
 /// This includes both entities that survive preprocessing (types, functions, globals) and preprocessor directives (includes, defines, compiler args).
+///
+/// Returns `None` if the file fails to parse. `variant_tags` are left empty; they depend on the
+/// build configuration rather than the file, and are applied when results are merged.
 fn extract_entities(
     parser: clang::Parser<'_>,
-    variant_map: &VariantTagMap,
+    abs_file: &Path,
     src_root: &Path,
-    out: &mut RichSourceMap,
-) {
+) -> Option<FileParse> {
     let tu = match parser.parse() {
         Ok(tu) => tu,
         Err(e) => {
             warn!("Skipping due to parse failure: {:?}", e);
-            return;
+            return None;
         }
     };
 
     let root = tu.get_entity();
+    let mut parse = FileParse {
+        entities: Vec::new(),
+        includes: included_project_files(&tu, abs_file, src_root),
+        references: referenced_symbols(root),
+    };
 
+    // Contents of the files the entities are in, each read once per translation unit.
+    let mut sources: HashMap<String, Vec<u8>> = HashMap::new();
     for child in root.get_children() {
         // Ignore entities that we don't care about.
         let Some(decl_kind) = rsm::map_top_level_decl_kind(child.get_kind()) else {
@@ -230,23 +261,158 @@ fn extract_entities(
 
         // Extract the AST for this entity
         let ast = ast::ast_from_entity(decl_kind, &child);
-        let variant_tags = variant_tags_for(&span, variant_map);
-        // Variant-tag matching above uses the absolute canonical path; the
-        // serialized span must be relative to the (ephemeral) source root so
+        // Needs the absolute span file to read the surrounding source text.
+        let source = sources
+            .entry(span.file.clone())
+            .or_insert_with(|| std::fs::read(&span.file).unwrap_or_default());
+        let comments = comments::extract_comments(&child, &span, source);
+        // The serialized span must be relative to the (ephemeral) source root so
         // output is reproducible across runs.
         let span = relativize_span_file(span, src_root);
-        out.push_entity(
-            TopLevelEntity {
+        parse.entities.push(ParsedEntity {
+            entity: TopLevelEntity {
                 kind: decl_kind,
                 source_text,
                 span,
                 ast,
                 annotations: EntityAnnotations::default(),
                 sub_entities: Vec::new(),
-                variant_tags,
+                variant_tags: Vec::new(),
+                comments,
             },
-            &child,
-        );
+            is_definition: child.is_definition(),
+            linkage: matches!(decl_kind, EntityKind::FunctionDecl | EntityKind::VarDecl)
+                .then(|| child.get_linkage().map(Linkage::from_clang))
+                .flatten(),
+        });
+    }
+    Some(parse)
+}
+
+/// Names of the project functions and global variables referred to from code in the main file of
+/// the translation unit, sorted.
+fn referenced_symbols(root: clang::Entity<'_>) -> Vec<String> {
+    let mut names: BTreeSet<String> = BTreeSet::new();
+    root.visit_children(|cursor, _| {
+        if cursor.get_kind() == clang::EntityKind::DeclRefExpr
+            && cursor.get_location().is_some_and(|l| l.is_in_main_file())
+            && let Some(target) = cursor.get_reference()
+            && matches!(
+                target.get_kind(),
+                clang::EntityKind::FunctionDecl | clang::EntityKind::VarDecl
+            )
+            && !target.is_in_system_header()
+            && target
+                .get_linkage()
+                .is_some_and(|l| Linkage::from_clang(l) != Linkage::None)
+            && let Some(name) = target.get_name()
+        {
+            names.insert(name);
+        }
+        clang::EntityVisitResult::Recurse
+    });
+    names.into_iter().collect()
+}
+
+/// Project files (relative to `src_root`) included by `tu`, directly or transitively. System
+/// headers and other files outside the project are left out.
+fn included_project_files(
+    tu: &clang::TranslationUnit<'_>,
+    main_file: &Path,
+    src_root: &Path,
+) -> Vec<String> {
+    let mut seen: BTreeSet<String> = BTreeSet::new();
+    let Some(main_file) = tu.get_file(main_file) else {
+        return Vec::new();
+    };
+    let mut stack = vec![main_file];
+    while let Some(file) = stack.pop() {
+        for include in file.get_includes() {
+            let Some(included) = include.get_file() else {
+                continue;
+            };
+            let path = included.get_path();
+            let path = path.canonicalize().unwrap_or(path);
+            let Ok(rel) = path.strip_prefix(src_root) else {
+                continue;
+            };
+            if seen.insert(rel.to_string_lossy().into_owned()) {
+                stack.push(included);
+            }
+        }
+    }
+    seen.into_iter().collect()
+}
+
+/// Parse settings for [`parse_to_ast_with`].
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Number of translation units parsed concurrently, each with its own `clang::Index`.
+    /// `None` uses the available parallelism.
+    pub jobs: Option<usize>,
+    /// Directory of the per-file parse cache. `None` disables caching.
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Configuration for the `parse_to_ast` tool, read from `[tools.parse_to_ast]`.
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// Number of parallel parse workers. Defaults to the available parallelism; `1` parses on the
+    /// calling thread.
+    #[serde(default)]
+    pub jobs: Option<usize>,
+    /// Whether to cache per-file parse results across runs. Off by default: entries are never
+    /// evicted, so the cache directory grows with every changed file.
+    #[serde(default = "default_cache")]
+    pub cache: bool,
+    /// Cache location. Defaults to `parse_to_ast` in the user's harvest cache directory.
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+
+    #[serde(flatten)]
+    unknown: HashMap<String, Value>,
+}
+
+fn default_cache() -> bool {
+    false
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            jobs: None,
+            cache: default_cache(),
+            cache_dir: None,
+            unknown: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self) {
+        unknown_field_warning("tools.parse_to_ast", &self.unknown);
+    }
+
+    /// Reads the config from `[tools.parse_to_ast]` of the run's config, or the default without
+    /// one. Other tools that parse the C source use it to share the parse settings.
+    pub fn load(context: &RunContext) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(match context.config.tools.get("parse_to_ast") {
+            Some(value) => Config::deserialize(value)?,
+            None => Config::default(),
+        })
+    }
+
+    /// The [`ParseOptions`] this config describes.
+    pub fn parse_options(&self) -> ParseOptions {
+        let cache_dir = self.cache.then(|| {
+            self.cache_dir.clone().or_else(|| {
+                ProjectDirs::from("", "", "harvest").map(|d| d.cache_dir().join("parse_to_ast"))
+            })
+        });
+        ParseOptions {
+            jobs: self.jobs,
+            cache_dir: cache_dir.flatten(),
+        }
     }
 }
 
@@ -287,7 +453,10 @@ impl Tool for ParseToAst {
             .get(1)
             .and_then(|cfg_id| context.ir_snapshot.get::<BuildConfigIR>(*cfg_id));
 
-        let map = parse_to_ast(rs, build_cfg)?;
+        let config = Config::load(&context)?;
+        config.validate();
+
+        let map = parse_to_ast_with(rs, build_cfg, &config.parse_options())?;
         Ok(Box::new(map))
     }
 }
@@ -298,20 +467,30 @@ impl Tool for ParseToAst {
 ///
 /// Factored out so callers can drive parsing without a full
 /// [`RunContext`] / scheduler -- the integration tests use this path.
+/// Parses in parallel without a cache; see [`parse_to_ast_with`].
 pub fn parse_to_ast(
     rs: &RawSource,
     cfg: Option<&BuildConfigIR>,
+) -> Result<RichSourceMap, Box<dyn std::error::Error>> {
+    parse_to_ast_with(rs, cfg, &ParseOptions::default())
+}
+
+/// [`parse_to_ast`] with explicit [`ParseOptions`].
+///
+/// Translation units are parsed concurrently, and merged in file order so the output is the same
+/// as a sequential parse. With a cache directory, files whose contents, parse arguments and
+/// included project headers are unchanged since a previous run are not parsed again; libclang is
+/// not even loaded when every file is cached.
+pub fn parse_to_ast_with(
+    rs: &RawSource,
+    cfg: Option<&BuildConfigIR>,
+    options: &ParseOptions,
 ) -> Result<RichSourceMap, Box<dyn std::error::Error>> {
     let src_dir = tempfile::TempDir::new()?;
     rs.dir.materialize(src_dir.path())?;
 
     let variant_map = build_variant_tag_map(cfg, src_dir.path());
 
-    let clang = LibClang::new().map_err(|e| format!("Failed to initialize libclang: {e}"))?;
-    let index = Index::new(&clang, false, false);
-
-    let mut out = RichSourceMap::new();
-
     // `span.file` comes back from libclang as a canonical absolute path under
     // the temp dir; canonicalize the root the same way so we can strip it to a
     // stable relative path.
@@ -320,16 +499,39 @@ pub fn parse_to_ast(
         .canonicalize()
         .unwrap_or_else(|_| src_dir.path().to_path_buf());
 
-    for (rel_path, _) in rs.dir.files_recursive() {
-        if utils::should_skip_path(&rel_path) {
-            continue;
-        }
-        if !utils::is_c_or_header(&rel_path) {
-            continue;
-        }
-        tracing::info!("Parsing file: {}", rel_path.to_string_lossy());
-        let parser = build_parser(&index, src_dir.path(), &rel_path);
-        extract_entities(parser, &variant_map, &canonical_root, &mut out);
+    let files = source_files(rs);
+    let parses = parse_each(
+        rs,
+        &files,
+        options,
+        options.cache_dir.clone(),
+        |parse: &FileParse| &parse.includes,
+        |index, rel_path| {
+            tracing::info!("Parsing file: {}", rel_path.to_string_lossy());
+            let parser = build_parser(index, src_dir.path(), rel_path);
+            extract_entities(parser, &src_dir.path().join(rel_path), &canonical_root)
+        },
+    )?;
+
+    let mut out = RichSourceMap::new();
+    out.symbols = SymbolTable::build(
+        files
+            .iter()
+            .zip(&parses)
+            .filter_map(|((rel_path, _), parse)| Some((rel_path.to_str()?, parse.as_ref()?))),
+    );
+    for parsed in parses.into_iter().flatten().flat_map(|p| p.entities) {
+        let mut entity = parsed.entity;
+        // Variant tags are keyed by absolute canonical path.
+        let abs_span = SourceSpan {
+            file: canonical_root
+                .join(&entity.span.file)
+                .to_string_lossy()
+                .into_owned(),
+            ..entity.span.clone()
+        };
+        entity.variant_tags = variant_tags_for(&abs_span, &variant_map);
+        out.push_entity(entity, parsed.is_definition);
     }
 
     debug!(
@@ -339,12 +541,147 @@ pub fn parse_to_ast(
     Ok(out)
 }
 
+/// The C and header files of `rs` that are parsed, with their contents.
+pub fn source_files(rs: &RawSource) -> Vec<(PathBuf, &[u8])> {
+    rs.dir
+        .files_recursive()
+        .into_iter()
+        .filter(|(rel_path, _)| {
+            !utils::should_skip_path(rel_path) && utils::is_c_or_header(rel_path)
+        })
+        .collect()
+}
+
+/// Runs `work` on each of `files` (paths relative to the root of `rs`) on the libclang pool of
+/// `options`, reusing the results cached in `cache_dir` for files that did not change. `includes`
+/// gives the project files a result depends on besides its own file. `None` marks a file `work`
+/// could not handle; it is not cached.
+fn parse_each<T: Serialize + DeserializeOwned + Send>(
+    rs: &RawSource,
+    files: &[(PathBuf, &[u8])],
+    options: &ParseOptions,
+    cache_dir: Option<PathBuf>,
+    includes: impl Fn(&T) -> &[String],
+    work: impl Fn(&Index<'_>, &Path) -> Option<T> + Sync,
+) -> Result<Vec<Option<T>>, Box<dyn std::error::Error>> {
+    let cache = cache_dir.map(ParseCache::new);
+    let file_hashes: HashMap<String, String> = match cache {
+        Some(_) => rs
+            .dir
+            .files_recursive()
+            .into_iter()
+            .map(|(path, contents)| (path.to_string_lossy().into_owned(), content_hash(contents)))
+            .collect(),
+        None => HashMap::new(),
+    };
+    // A different libclang may parse the same file differently.
+    let clang_version = match cache {
+        Some(_) => clang::get_version(),
+        None => String::new(),
+    };
+    let keys: Vec<String> = files
+        .iter()
+        .map(|(rel_path, contents)| {
+            let args = normalized_parse_args(rel_path);
+            cache::cache_key(rel_path, contents, &args, &clang_version)
+        })
+        .collect();
+
+    let mut results: Vec<Option<T>> = keys
+        .iter()
+        .map(|key| cache.as_ref()?.get(key, &file_hashes))
+        .collect();
+    let to_parse: Vec<usize> = (0..files.len()).filter(|&i| results[i].is_none()).collect();
+    debug!(
+        "Parse cache: {} of {} files cached",
+        files.len() - to_parse.len(),
+        files.len()
+    );
+
+    if !to_parse.is_empty() {
+        let jobs = options.jobs.unwrap_or_else(pool::default_jobs);
+        let fresh = pool::with_libclang(|clang| {
+            pool::run_parallel(clang, jobs, to_parse.len(), |index, i| {
+                work(index, &files[to_parse[i]].0)
+            })
+        })?;
+        for (i, result) in to_parse.into_iter().zip(fresh) {
+            if let (Some(cache), Some(result)) = (&cache, &result) {
+                cache.put(&keys[i], result, includes(result), &file_hashes);
+            }
+            results[i] = result;
+        }
+    }
+    Ok(results)
+}
+
+/// The result of an analysis of one translation unit, with the project files it depends on.
+#[derive(Serialize, Deserialize)]
+struct UnitAnalysis<T> {
+    includes: Vec<String>,
+    result: T,
+}
+
+/// Parses every C/header file in `rs` with the same arguments [`parse_to_ast`] uses and hands
+/// each translation unit to `analyze`, along with the file's path relative to the source root.
+/// Returns the results in file order.
+///
+/// For analyses that need more of the clang AST than the [`RichSourceMap`] keeps (e.g. function
+/// bodies). Files are parsed in parallel as in [`parse_to_ast_with`], and with a cache in
+/// `options` the results are cached under `analysis`, which must change whenever `analyze` does.
+/// Files that fail to parse are skipped with a warning.
+pub fn analyze_translation_units<T: Serialize + DeserializeOwned + Send>(
+    rs: &RawSource,
+    options: &ParseOptions,
+    analysis: &str,
+    analyze: impl Fn(&Path, clang::Entity<'_>) -> T + Sync,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    let src_dir = tempfile::TempDir::new()?;
+    rs.dir.materialize(src_dir.path())?;
+    let canonical_root = src_dir
+        .path()
+        .canonicalize()
+        .unwrap_or_else(|_| src_dir.path().to_path_buf());
+
+    let files = source_files(rs);
+    let results = parse_each(
+        rs,
+        &files,
+        options,
+        options.cache_dir.as_ref().map(|dir| dir.join(analysis)),
+        |unit: &UnitAnalysis<T>| &unit.includes,
+        |index, rel_path| {
+            let abs_file = src_dir.path().join(rel_path);
+            let tu = match build_parser(index, src_dir.path(), rel_path).parse() {
+                Ok(tu) => tu,
+                Err(e) => {
+                    warn!(
+                        "Skipping {} due to parse failure: {:?}",
+                        rel_path.display(),
+                        e
+                    );
+                    return None;
+                }
+            };
+            Some(UnitAnalysis {
+                includes: included_project_files(&tu, &abs_file, &canonical_root),
+                result: analyze(rel_path, tu.get_entity()),
+            })
+        },
+    )?;
+    Ok(results
+        .into_iter()
+        .flatten()
+        .map(|unit| unit.result)
+        .collect())
+}
+
 #[cfg(not(miri))]
 #[cfg(test)]
 mod tests {
-    //! Unit tests for the variant-tag lookup. The libclang-driven entity
-    //! extraction is covered by `tests/parse_to_ast.rs`; these tests focus on
-    //! the pure-Rust lookup table that maps spans to tags.
+    //! Unit tests for the variant-tag lookup and the tool config. The
+    //! libclang-driven entity extraction is covered by `tests/parse_to_ast.rs`;
+    //! these tests focus on the pure-Rust lookup table that maps spans to tags.
     use super::*;
     use build_config::{ConfigVariable, SourceSelection, SourceVariant};
     use std::fs;
@@ -674,6 +1011,7 @@ mod tests {
             annotations: EntityAnnotations::default(),
             sub_entities: Vec::new(),
             variant_tags: Vec::new(),
+            comments: EntityComments::default(),
         };
         let json = serde_json::to_string(&entity).unwrap();
         assert!(
@@ -692,6 +1030,7 @@ mod tests {
             annotations: EntityAnnotations::default(),
             sub_entities: Vec::new(),
             variant_tags: vec![("BACKEND".into(), "alpha".into())],
+            comments: EntityComments::default(),
         };
         let json = serde_json::to_string(&entity).unwrap();
         assert!(json.contains("variant_tags"));
@@ -716,4 +1055,21 @@ mod tests {
         let parsed: TopLevelEntity = serde_json::from_str(legacy).unwrap();
         assert!(parsed.variant_tags.is_empty());
     }
+
+    #[test]
+    fn cache_is_opt_in() {
+        let config = Config::deserialize(serde_json::json!({})).unwrap();
+        assert!(!config.cache);
+        assert_eq!(config.parse_options().cache_dir, None);
+
+        let config = Config::deserialize(serde_json::json!({
+            "cache": true,
+            "cache_dir": "/tmp/parse_cache",
+        }))
+        .unwrap();
+        assert_eq!(
+            config.parse_options().cache_dir,
+            Some(PathBuf::from("/tmp/parse_cache"))
+        );
+    }
 }
@@ -0,0 +1,191 @@
+//! `c_ast`: parse a C project into a [`RichSourceMap`] and inspect it, without running the
+//! translation pipeline.
+//!
+//! Every subcommand takes either a C project directory, which is parsed, or a JSON file written
+//! by `c_ast parse` (or materialized by a translation run's diagnostics), which is loaded.
+
+use c_ast::{Config, EntityKind, ParseOptions, RichSourceMap, TopLevelEntity, parse_to_ast_with};
+use clap::{Parser, Subcommand};
+use full_source::RawSource;
+use harvest_core::Representation;
+use harvest_core::fs::RawDir;
+use std::path::{Path, PathBuf};
+
+/// Parse a C project and query its top-level entities.
+#[derive(Debug, Parser)]
+#[command(name = "c_ast")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Parse a C project and write its RichSourceMap as JSON.
+    Parse {
+        /// Directory containing the C project.
+        input: PathBuf,
+        /// Where to write the JSON; standard output if omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Number of parallel parse workers.
+        #[arg(long)]
+        jobs: Option<usize>,
+        /// Parse every file, ignoring and not updating the parse cache.
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// List entities, one per line: kind, name and location.
+    List {
+        /// C project directory or RichSourceMap JSON file.
+        input: PathBuf,
+        /// Only list entities of this kind (e.g. `FunctionDecl`, `MacroDefinition`).
+        #[arg(long, value_parser = parse_kind)]
+        kind: Option<EntityKind>,
+    },
+    /// Show the span, annotations, symbol information and source of entities named NAME.
+    Show {
+        /// C project directory or RichSourceMap JSON file.
+        input: PathBuf,
+        name: String,
+        /// Print the entities as JSON instead.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check the map's span invariants; exits with status 1 if they do not hold.
+    Check {
+        /// C project directory or RichSourceMap JSON file.
+        input: PathBuf,
+    },
+}
+
+fn parse_kind(s: &str) -> Result<EntityKind, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string()))
+        .map_err(|_| format!("unknown entity kind `{s}`"))
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    match Args::parse().command {
+        Command::Parse {
+            input,
+            output,
+            jobs,
+            no_cache,
+        } => {
+            let mut options = Config::default().parse_options();
+            options.jobs = jobs;
+            if no_cache {
+                options.cache_dir = None;
+            }
+            let map = parse_dir(&input, &options)?;
+            match output {
+                Some(path) => map.materialize(&path)?,
+                None => println!("{}", serde_json::to_string_pretty(&map)?),
+            }
+        }
+        Command::List { input, kind } => {
+            let map = load(&input)?;
+            for entity in map.iter_all_entities() {
+                if kind.is_none_or(|kind| entity.kind == kind) {
+                    println!(
+                        "{:?}\t{}\t{}",
+                        entity.kind,
+                        entity.name().unwrap_or("-"),
+                        location(entity)
+                    );
+                }
+            }
+        }
+        Command::Show { input, name, json } => {
+            let map = load(&input)?;
+            let entities: Vec<&TopLevelEntity> = map.find_by_name(&name).collect();
+            if entities.is_empty() {
+                return Err(format!("no entity named `{name}`").into());
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&entities)?);
+                return Ok(());
+            }
+            for entity in entities {
+                show(&map, entity);
+            }
+        }
+        Command::Check { input } => {
+            let map = load(&input)?;
+            if !map.well_formed() {
+                return Err(
+                    "RichSourceMap is not well formed: overlapping or invalid spans".into(),
+                );
+            }
+            println!("ok: {map}");
+        }
+    }
+    Ok(())
+}
+
+/// Parses `input` if it is a directory, otherwise loads it as a materialized map.
+fn load(input: &Path) -> Result<RichSourceMap, Box<dyn std::error::Error>> {
+    if input.is_dir() {
+        parse_dir(input, &Config::default().parse_options())
+    } else {
+        RichSourceMap::load(input)
+    }
+}
+
+fn parse_dir(
+    dir: &Path,
+    options: &ParseOptions,
+) -> Result<RichSourceMap, Box<dyn std::error::Error>> {
+    let (dir, _, _) = RawDir::populate_from(std::fs::read_dir(dir)?)?;
+    parse_to_ast_with(&RawSource { dir }, None, options)
+}
+
+fn location(entity: &TopLevelEntity) -> String {
+    let span = &entity.span;
+    format!("{}:{}:{}", span.file, span.start.line, span.start.column)
+}
+
+fn show(map: &RichSourceMap, entity: &TopLevelEntity) {
+    let span = &entity.span;
+    println!("{:?} {}", entity.kind, entity.name().unwrap_or("-"));
+    println!(
+        "  span: {}:{}:{}-{}:{}",
+        span.file, span.start.line, span.start.column, span.end.line, span.end.column
+    );
+    // Only the translations annotate entities, so visibility comes from the symbol table.
+    let symbol = map.symbols.for_entity(entity);
+    println!("  public: {}", symbol.is_some_and(|s| s.is_exported()));
+    if !entity.variant_tags.is_empty() {
+        let tags: Vec<String> = entity
+            .variant_tags
+            .iter()
+            .map(|(var, value)| format!("{var}={value}"))
+            .collect();
+        println!("  variants: {}", tags.join(", "));
+    }
+    if let Some(symbol) = symbol {
+        println!("  linkage: {:?}", symbol.linkage);
+        if !symbol.declaring_headers.is_empty() {
+            println!("  declared in: {}", symbol.declaring_headers.join(", "));
+        }
+        if !symbol.referencing_tus.is_empty() {
+            println!("  referenced from: {}", symbol.referencing_tus.join(", "));
+        }
+        if !symbol.conflicting_definitions.is_empty() {
+            println!(
+                "  conflicting definitions: {}",
+                symbol.conflicting_definitions.len()
+            );
+        }
+    }
+    for line in entity.source_text.lines() {
+        println!("  | {line}");
+    }
+}
@@ -0,0 +1,89 @@
+//! Process-wide libclang access and the worker pool used to parse translation units in parallel.
+
+use clang::{Clang as LibClang, Index};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// `clang::Clang` may only exist once per process. Tools run concurrently (e.g. `ParseToAst`
+/// next to an analysis that also needs libclang), so every user goes through this lock instead
+/// of failing on the second `Clang::new()`.
+static LIBCLANG_LOCK: Mutex<()> = Mutex::new(());
+
+/// Runs `f` with the process' libclang instance, waiting for any other user to finish first.
+pub(crate) fn with_libclang<R>(
+    f: impl FnOnce(&LibClang) -> R,
+) -> Result<R, Box<dyn std::error::Error>> {
+    let _guard = LIBCLANG_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let clang = LibClang::new().map_err(|e| format!("Failed to initialize libclang: {e}"))?;
+    Ok(f(&clang))
+}
+
+/// Lets worker threads borrow the [`LibClang`] token.
+struct SharedClang<'a>(&'a LibClang);
+
+// SAFETY: This goes beyond the `clang` crate, which makes `Clang` `!Send`/`!Sync` to prevent "the
+// use of this library from multiple threads simultaneously". For the crate, `Clang` is only a
+// zero-sized token: without its `runtime` feature (not enabled here), which loads libclang per
+// thread, every call goes straight to the linked libclang. libclang itself has no thread
+// affinity and keeps its parsing state in the index and the translation units parsed from it, so
+// distinct indices may be used on distinct threads at once. The workers rely on exactly that:
+// - each creates its own `Index`, one at a time under `INDEX_LOCK`, so libclang's process-wide
+//   setup in `clang_createIndex` never runs concurrently;
+// - an `Index` and everything parsed from it are `!Send` and stay on the worker that created it;
+// - only owned `T: Send` results leave a worker.
+// With one job nothing is shared: the work runs on the calling thread.
+unsafe impl Sync for SharedClang<'_> {}
+
+/// Serializes the creation of the workers' indices.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Runs `work(index, i)` for every `i` in `0..count` on up to `jobs` threads, each owning its own
+/// [`Index`]. With one job, runs on the calling thread instead. Results are returned in order of
+/// `i`.
+pub(crate) fn run_parallel<T: Send>(
+    clang: &LibClang,
+    jobs: usize,
+    count: usize,
+    work: impl Fn(&Index<'_>, usize) -> T + Sync,
+) -> Vec<T> {
+    let workers = jobs.clamp(1, count.max(1));
+    if workers == 1 {
+        let index = Index::new(clang, false, false);
+        return (0..count).map(|i| work(&index, i)).collect();
+    }
+    let shared = SharedClang(clang);
+    let next = AtomicUsize::new(0);
+
+    let mut indexed: Vec<(usize, T)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                let (shared, next, work) = (&shared, &next, &work);
+                scope.spawn(move || {
+                    let index = {
+                        let _guard = INDEX_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+                        Index::new(shared.0, false, false)
+                    };
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= count {
+                            break done;
+                        }
+                        done.push((i, work(&index, i)));
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+    indexed.sort_by_key(|(i, _)| *i);
+    indexed.into_iter().map(|(_, t)| t).collect()
+}
+
+/// Number of parse workers to use when not configured.
+pub(crate) fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
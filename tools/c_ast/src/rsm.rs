@@ -4,6 +4,8 @@ use std::path::Path;
 
 use crate::ClangAST;
 use crate::EntityAnnotations;
+use crate::EntityComments;
+use crate::SymbolTable;
 
 /// Representaiton of a single point in a source file, used for source mapping.
 /// `column` and `offset` are UTF8 byte offsets, to match Clang's source location representation.
@@ -81,13 +83,35 @@ pub struct TopLevelEntity {
     /// module declarations.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub variant_tags: Vec<(String, String)>,
+    /// C comments attached to the entity and its fields, parameters and enum constants.
+    /// Comments of absorbed `sub_entities` are merged in, so a typedef carries the comments
+    /// written on the struct it names.
+    #[serde(default, skip_serializing_if = "EntityComments::is_empty")]
+    pub comments: EntityComments,
+}
+
+impl TopLevelEntity {
+    /// The declared name, if the entity has one: the identifier of a declaration, or the macro
+    /// name of a `#define`.
+    pub fn name(&self) -> Option<&str> {
+        match &self.ast {
+            Some(ast) => ast.name(),
+            None if self.kind == EntityKind::MacroDefinition => self
+                .source_text
+                .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .find(|word| !word.is_empty() && *word != "define"),
+            None => None,
+        }
+    }
 }
 
 /// This is the output of the parsing step, and therefore this tool.
 /// It contains both the source text and the AST for each top-level entity, as well as preprocessor directives (include paths, defines, etc).
 /// It is designed such that every all text in the source code has exactly one unique representation in the RichSourceMap, either as a top-level entity or as a preprocessor directive.
 /// These source-level entities may then have a corresponding AST representation, depending on whether they persist through preprocessing.
-#[derive(Serialize)]
+///
+/// The JSON written by [`Representation::materialize`] can be read back with [`RichSourceMap::load`].
+#[derive(Serialize, Deserialize)]
 pub struct RichSourceMap {
     pub app_types: Vec<TopLevelEntity>,
     pub app_globals: Vec<TopLevelEntity>,
@@ -96,6 +120,9 @@ pub struct RichSourceMap {
     pub include_paths: Vec<TopLevelEntity>,
     pub defines: Vec<TopLevelEntity>,
     pub compiler_args: Vec<TopLevelEntity>,
+    /// Functions and global variables across all translation units, with linkage.
+    #[serde(default, skip_serializing_if = "SymbolTable::is_empty")]
+    pub symbols: SymbolTable,
 }
 
 impl RichSourceMap {
@@ -108,11 +135,19 @@ impl RichSourceMap {
             include_paths: Vec::new(),
             defines: Vec::new(),
             compiler_args: Vec::new(),
+            symbols: SymbolTable::default(),
         }
     }
 
+    /// Loads a map previously written by [`Representation::materialize`].
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path)?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| format!("{}: not a RichSourceMap: {e}", path.display()).into())
+    }
+
     /// Sort and store entities in the RichSourceMap based on their kind.
-    pub(crate) fn push_entity(&mut self, item: TopLevelEntity, child: &clang::Entity<'_>) {
+    pub(crate) fn push_entity(&mut self, item: TopLevelEntity, is_definition: bool) {
         match item.kind {
             EntityKind::TypedefDecl
             | EntityKind::RecordDecl
@@ -124,7 +159,7 @@ impl RichSourceMap {
                 self.app_globals.push(item);
             }
             EntityKind::FunctionDecl => {
-                if child.is_definition() {
+                if is_definition {
                     self.app_functions.push(item);
                 } else {
                     self.app_func_sigs.push(item);
@@ -155,6 +190,7 @@ impl RichSourceMap {
         // as an immediate child. We do not preserve deeper sub-entity ordering.
         for node in nodes.iter_mut() {
             if Self::span_contains(&node.span, &item.span) {
+                node.comments.merge_from(&item.comments);
                 node.sub_entities.push(item);
                 return;
             }
@@ -165,6 +201,7 @@ impl RichSourceMap {
         while i < nodes.len() {
             if Self::span_contains(&item.span, &nodes[i].span) {
                 let child = nodes.remove(i);
+                item.comments.merge_from(&child.comments);
                 item.sub_entities.push(child);
             } else {
                 i += 1;
@@ -200,7 +237,7 @@ impl RichSourceMap {
     }
 
     /// Iterate over every top-level source entity tracked by the map.
-    fn iter_all_entities(&self) -> impl Iterator<Item = &TopLevelEntity> {
+    pub fn iter_all_entities(&self) -> impl Iterator<Item = &TopLevelEntity> {
         self.app_types
             .iter()
             .chain(self.app_globals.iter())
@@ -211,6 +248,12 @@ impl RichSourceMap {
             .chain(self.compiler_args.iter())
     }
 
+    /// Iterate over the top-level entities named `name` (declarations, definitions and macros).
+    pub fn find_by_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a TopLevelEntity> {
+        self.iter_all_entities()
+            .filter(move |entity| entity.name() == Some(name))
+    }
+
     /// Returns true when the map satisfies its span invariants:
     /// - every span is internally valid (`start.offset <= end.offset`)
     /// - no two top-level entities overlap within the same file
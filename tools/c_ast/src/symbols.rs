@@ -0,0 +1,376 @@
+//! Project-wide symbol table: every function and global variable with linkage, where it is
+//! declared and defined, and which translation units use it.
+//!
+//! Built from the per-file parse results, so it sees each translation unit separately. That
+//! matters for linkage: two `static` functions with the same name in different `.c` files are
+//! distinct symbols, while an external function declared in a header and defined in a `.c` file
+//! is one.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::cache::FileParse;
+use crate::utils::{is_header_file, symbol_name};
+use crate::{EntityKind, SourceSpan, TopLevelEntity};
+
+/// Linkage of a symbol, as reported by libclang.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Linkage {
+    /// Visible to other translation units.
+    External,
+    /// `static`: private to the translation unit that defines it.
+    Internal,
+    /// Not a linker-visible symbol (e.g. a local variable).
+    None,
+}
+
+impl Linkage {
+    pub(crate) fn from_clang(linkage: clang::Linkage) -> Self {
+        match linkage {
+            clang::Linkage::External | clang::Linkage::UniqueExternal => Linkage::External,
+            clang::Linkage::Internal => Linkage::Internal,
+            clang::Linkage::Automatic => Linkage::None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    Function,
+    Variable,
+}
+
+/// One function or global variable of the project.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub linkage: Linkage,
+    /// Non-defining declarations (prototypes, `extern` variables), each listed once.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub declarations: Vec<SourceSpan>,
+    /// The definition, if the project has one. The first one found, in file order, when there
+    /// are several.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub definition: Option<SourceSpan>,
+    /// Further definitions of an externally linked symbol, which would clash at link time.
+    /// Tentative definitions (`int x;`) count, as with `-fno-common`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicting_definitions: Vec<SourceSpan>,
+    /// Headers (relative paths) that declare or define the symbol.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub declaring_headers: Vec<String>,
+    /// `.c` files (relative paths) whose code refers to the symbol.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub referencing_tus: Vec<String>,
+}
+
+impl Symbol {
+    /// Whether the symbol is part of the project's public interface: externally linked and
+    /// declared in a header.
+    pub fn is_exported(&self) -> bool {
+        self.linkage == Linkage::External && !self.declaring_headers.is_empty()
+    }
+
+    /// The file an internal symbol belongs to. `None` for external symbols, which belong to the
+    /// whole program.
+    fn home_file(&self) -> Option<&str> {
+        if self.linkage == Linkage::External {
+            return None;
+        }
+        self.definition
+            .iter()
+            .chain(&self.declarations)
+            .next()
+            .map(|span| span.file.as_str())
+    }
+
+    fn spans(&self) -> impl Iterator<Item = &SourceSpan> {
+        self.definition
+            .iter()
+            .chain(&self.declarations)
+            .chain(&self.conflicting_definitions)
+    }
+}
+
+/// All functions and global variables with linkage, sorted by name.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    /// Resolves `name` as seen from code in `file`: a `static` symbol of that file shadows an
+    /// external one.
+    pub fn get(&self, name: &str, file: &str) -> Option<&Symbol> {
+        let candidates = || self.symbols.iter().filter(move |s| s.name == name);
+        candidates()
+            .find(|s| s.home_file() == Some(file))
+            .or_else(|| candidates().find(|s| s.linkage == Linkage::External))
+    }
+
+    /// The symbol a function or variable entity of the [`RichSourceMap`](crate::RichSourceMap)
+    /// declares or defines.
+    pub fn for_entity(&self, entity: &TopLevelEntity) -> Option<&Symbol> {
+        let name = symbol_name(entity)?;
+        self.symbols
+            .iter()
+            .filter(|s| s.name == name)
+            .find(|s| s.spans().any(|span| *span == entity.span))
+            .or_else(|| self.get(name, &entity.span.file))
+    }
+
+    /// Symbols defined more than once.
+    pub fn conflicts(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols
+            .iter()
+            .filter(|s| !s.conflicting_definitions.is_empty())
+    }
+
+    /// Builds the table from per-file parses, given as `(relative path, parse)` in file order.
+    pub(crate) fn build<'a>(tus: impl IntoIterator<Item = (&'a str, &'a FileParse)>) -> Self {
+        // External symbols are keyed by name alone, internal ones also by their file.
+        let mut symbols: BTreeMap<(String, Option<String>), Symbol> = BTreeMap::new();
+        let mut definitions: BTreeMap<(String, Option<String>), Vec<SourceSpan>> = BTreeMap::new();
+        let tus: Vec<(&str, &FileParse)> = tus.into_iter().collect();
+
+        for (_, parse) in &tus {
+            for parsed in &parse.entities {
+                let kind = match parsed.entity.kind {
+                    EntityKind::FunctionDecl => SymbolKind::Function,
+                    EntityKind::VarDecl => SymbolKind::Variable,
+                    _ => continue,
+                };
+                let linkage = match parsed.linkage {
+                    Some(linkage @ (Linkage::External | Linkage::Internal)) => linkage,
+                    _ => continue,
+                };
+                let Some(name) = symbol_name(&parsed.entity) else {
+                    continue;
+                };
+                let span = &parsed.entity.span;
+                let key = (
+                    name.to_string(),
+                    (linkage == Linkage::Internal).then(|| span.file.clone()),
+                );
+                let symbol = symbols.entry(key.clone()).or_insert_with(|| Symbol {
+                    name: name.to_string(),
+                    kind,
+                    linkage,
+                    declarations: Vec::new(),
+                    definition: None,
+                    conflicting_definitions: Vec::new(),
+                    declaring_headers: Vec::new(),
+                    referencing_tus: Vec::new(),
+                });
+                // A header is parsed once per including file; keep one copy of each span.
+                let spans = if parsed.is_definition {
+                    definitions.entry(key).or_default()
+                } else {
+                    &mut symbol.declarations
+                };
+                if !spans.contains(span) {
+                    spans.push(span.clone());
+                }
+            }
+        }
+
+        // Name -> the `.c` files referring to it, with what each includes.
+        let mut references: HashMap<&str, Vec<(&str, &FileParse)>> = HashMap::new();
+        for (tu, parse) in &tus {
+            if is_header_file(tu) {
+                continue;
+            }
+            for name in &parse.references {
+                references.entry(name).or_default().push((tu, parse));
+            }
+        }
+
+        for ((name, file), symbol) in symbols.iter_mut() {
+            let key = (name.clone(), file.clone());
+            let mut defs = definitions.remove(&key).unwrap_or_default().into_iter();
+            symbol.definition = defs.next();
+            symbol.conflicting_definitions = defs.collect();
+            symbol.declaring_headers = symbol
+                .spans()
+                .map(|span| span.file.clone())
+                .filter(|file| is_header_file(file))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            // An internal symbol is only visible where its file is compiled or included.
+            symbol.referencing_tus = references
+                .get(name.as_str())
+                .into_iter()
+                .flatten()
+                .filter(|(tu, parse)| match file {
+                    None => true,
+                    Some(file) => file == tu || parse.includes.contains(file),
+                })
+                .map(|(tu, _)| tu.to_string())
+                .collect();
+        }
+
+        SymbolTable {
+            symbols: symbols.into_values().collect(),
+        }
+    }
+}
+
+#[cfg(not(miri))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ParsedEntity;
+    use crate::{ClangAST, SourcePoint};
+
+    fn entity(kind: EntityKind, name: &str, file: &str, offset: u32) -> TopLevelEntity {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset,
+        };
+        let ast = match kind {
+            EntityKind::FunctionDecl => ClangAST::FunctionDecl {
+                name: name.into(),
+                storage_class: None,
+            },
+            _ => ClangAST::VarDecl {
+                name: name.into(),
+                storage_class: None,
+            },
+        };
+        TopLevelEntity {
+            kind,
+            source_text: String::new(),
+            span: SourceSpan {
+                file: file.into(),
+                start: point.clone(),
+                end: point,
+            },
+            ast: Some(ast),
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn func(name: &str, file: &str, offset: u32, linkage: Linkage, def: bool) -> ParsedEntity {
+        ParsedEntity {
+            entity: entity(EntityKind::FunctionDecl, name, file, offset),
+            is_definition: def,
+            linkage: Some(linkage),
+        }
+    }
+
+    fn var(name: &str, file: &str, offset: u32, def: bool) -> ParsedEntity {
+        ParsedEntity {
+            entity: entity(EntityKind::VarDecl, name, file, offset),
+            is_definition: def,
+            linkage: Some(Linkage::External),
+        }
+    }
+
+    fn tu(entities: Vec<ParsedEntity>, includes: &[&str], references: &[&str]) -> FileParse {
+        FileParse {
+            entities,
+            includes: includes.iter().map(|s| s.to_string()).collect(),
+            references: references.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// `api.h` declares `api`; `a.c` defines it and a static `helper`; `b.c` calls `api` and
+    /// defines its own static `helper`.
+    fn project() -> SymbolTable {
+        let header_decl = || func("api", "api.h", 0, Linkage::External, false);
+        let api_h = tu(vec![header_decl()], &[], &[]);
+        let a_c = tu(
+            vec![
+                header_decl(),
+                func("helper", "a.c", 0, Linkage::Internal, true),
+                func("api", "a.c", 40, Linkage::External, true),
+            ],
+            &["api.h"],
+            &["helper"],
+        );
+        let b_c = tu(
+            vec![
+                header_decl(),
+                func("helper", "b.c", 0, Linkage::Internal, true),
+                func("main", "b.c", 40, Linkage::External, true),
+            ],
+            &["api.h"],
+            &["api", "helper"],
+        );
+        SymbolTable::build([("api.h", &api_h), ("a.c", &a_c), ("b.c", &b_c)])
+    }
+
+    #[test]
+    fn declarations_and_definition_are_merged_across_tus() {
+        let table = project();
+        let api = table.get("api", "b.c").unwrap();
+        assert_eq!(api.linkage, Linkage::External);
+        assert_eq!(api.declarations.len(), 1, "header decl must be listed once");
+        assert_eq!(api.definition.as_ref().unwrap().file, "a.c");
+        assert_eq!(api.declaring_headers, ["api.h"]);
+        assert_eq!(api.referencing_tus, ["b.c"]);
+        assert!(api.is_exported());
+        assert!(table.conflicts().next().is_none());
+    }
+
+    #[test]
+    fn static_functions_are_separate_per_file() {
+        let table = project();
+        let in_a = table.get("helper", "a.c").unwrap();
+        let in_b = table.get("helper", "b.c").unwrap();
+        assert_ne!(in_a, in_b);
+        assert_eq!(in_a.linkage, Linkage::Internal);
+        assert_eq!(in_a.referencing_tus, ["a.c"]);
+        assert_eq!(in_b.referencing_tus, ["b.c"]);
+        assert!(!in_a.is_exported());
+        assert!(table.get("helper", "c.c").is_none());
+    }
+
+    #[test]
+    fn undeclared_external_function_is_not_exported() {
+        let main = project().get("main", "b.c").cloned().unwrap();
+        assert_eq!(main.linkage, Linkage::External);
+        assert!(!main.is_exported());
+    }
+
+    #[test]
+    fn duplicate_definitions_are_conflicts() {
+        let a_c = tu(vec![var("count", "a.c", 0, true)], &[], &[]);
+        let b_c = tu(
+            vec![var("count", "b.c", 0, true), var("count", "b.c", 20, false)],
+            &[],
+            &["count"],
+        );
+        let table = SymbolTable::build([("a.c", &a_c), ("b.c", &b_c)]);
+        let count = table.get("count", "a.c").unwrap();
+        assert_eq!(count.kind, SymbolKind::Variable);
+        assert_eq!(count.definition.as_ref().unwrap().file, "a.c");
+        assert_eq!(count.conflicting_definitions.len(), 1);
+        assert_eq!(count.conflicting_definitions[0].file, "b.c");
+        assert_eq!(table.conflicts().count(), 1);
+    }
+
+    #[test]
+    fn for_entity_matches_by_span() {
+        let table = project();
+        let def = entity(EntityKind::FunctionDecl, "helper", "b.c", 0);
+        let symbol = table.for_entity(&def).unwrap();
+        assert_eq!(symbol.definition.as_ref(), Some(&def.span));
+    }
+}
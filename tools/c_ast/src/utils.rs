@@ -135,9 +135,14 @@ pub(crate) fn get_span_and_text(child: &clang::Entity<'_>) -> Option<(SourceSpan
     Some((span, source_text))
 }
 
-pub(crate) fn function_name(entity: &TopLevelEntity) -> Option<&str> {
+/// The name of a function or global variable entity.
+pub(crate) fn symbol_name(entity: &TopLevelEntity) -> Option<&str> {
     match entity.ast.as_ref() {
-        Some(ClangAST::FunctionDecl { name, .. }) if !name.is_empty() => Some(name.as_str()),
+        Some(ClangAST::FunctionDecl { name, .. } | ClangAST::VarDecl { name, .. })
+            if !name.is_empty() =>
+        {
+            Some(name.as_str())
+        }
         _ => None,
     }
 }
@@ -146,13 +151,20 @@ pub(crate) fn is_header_file(path: &str) -> bool {
     path.to_ascii_lowercase().ends_with(".h")
 }
 
-pub(crate) fn is_static_function(entity: &TopLevelEntity) -> bool {
-    // Preferred source of truth when available.
-    if let Some(ClangAST::FunctionDecl { storage_class, .. }) = entity.ast.as_ref()
-        && let Some(storage_class) = storage_class
-        && storage_class.eq_ignore_ascii_case("static")
-    {
-        return true;
+/// The storage-class specifier (`static` or `extern`) written on a declaration, if any.
+///
+/// Read from the tokens before the declared name, since the clang bindings only expose the
+/// storage class behind a libclang version feature.
+pub(crate) fn storage_class_keyword(entity: &clang::Entity<'_>) -> Option<String> {
+    let name = entity.get_name()?;
+    for token in entity.get_range()?.tokenize() {
+        let spelling = token.get_spelling();
+        match spelling.as_str() {
+            "static" | "extern" => return Some(spelling),
+            "(" | "=" | "{" | ";" => return None,
+            _ if spelling == name => return None,
+            _ => {}
+        }
     }
-    false
+    None
 }
@@ -9,8 +9,12 @@
 use std::path::PathBuf;
 
 use build_config::{BuildConfigIR, ConfigVarKind, ConfigVariable, SourceSelection, SourceVariant};
-use c_ast::{ClangAST, RichSourceMap, TopLevelEntity, parse_to_ast};
+use c_ast::{
+    CType, ClangAST, IntKind, Linkage, MemberKind, ParseOptions, RichSourceMap, TopLevelEntity,
+    parse_to_ast, parse_to_ast_with,
+};
 use full_source::RawSource;
+use harvest_core::Representation;
 use harvest_core::fs::RawDir;
 
 /// Three tiny self-contained C files. Each declares exactly one function so
@@ -220,3 +224,300 @@ fn no_config_and_empty_ir_produce_byte_equal_json() {
         "missing-IR and empty-IR JSON must be byte-equal"
     );
 }
+
+const COMMENTED_C: &str = r#"
+/* A 2D point. */
+struct point {
+    int x; // horizontal
+    /* vertical */
+    int y;
+};
+
+// Adds one to `x`.
+int add_one(int x /* the input */) { return x + 1; }
+"#;
+
+#[test]
+fn comments_attached_to_entities_and_members() {
+    let mut dir = RawDir::default();
+    dir.set_file("src/commented.c", COMMENTED_C.as_bytes().to_vec())
+        .unwrap();
+    let raw = RawSource { dir };
+    let Some(map) = run(&raw, None) else {
+        return;
+    };
+
+    let add_one = find_function(&map, "add_one").expect("add_one function not found");
+    assert_eq!(
+        add_one.comments.leading.as_deref(),
+        Some("Adds one to `x`.")
+    );
+    let param = add_one
+        .comments
+        .member(MemberKind::Parameter, "x")
+        .expect("parameter comment missing");
+    assert_eq!(param.trailing.as_deref(), Some("the input"));
+
+    let point = map
+        .app_types
+        .iter()
+        .find(|e| e.source_text.contains("struct point"))
+        .expect("struct point not found");
+    assert_eq!(point.comments.leading.as_deref(), Some("A 2D point."));
+    let x = point.comments.member(MemberKind::Field, "x").unwrap();
+    assert_eq!(x.trailing.as_deref(), Some("horizontal"));
+    let y = point.comments.member(MemberKind::Field, "y").unwrap();
+    assert_eq!(y.leading.as_deref(), Some("vertical"));
+}
+
+const LAYOUT_C: &str = r#"
+#include <stdint.h>
+struct flags {
+    unsigned a : 3;
+    unsigned b : 5;
+    int32_t c;
+};
+typedef enum { LOW = -1, HIGH = 7 } level_t;
+"#;
+
+#[test]
+fn records_layout_of_types() {
+    let mut dir = RawDir::default();
+    dir.set_file("src/layout.c", LAYOUT_C.as_bytes().to_vec())
+        .unwrap();
+    let raw = RawSource { dir };
+    let Some(map) = run(&raw, None) else {
+        return;
+    };
+
+    let flags = map
+        .app_types
+        .iter()
+        .find(|e| e.name() == Some("flags"))
+        .expect("struct flags not found");
+    let Some(ClangAST::RecordDecl {
+        layout: Some(layout),
+        ..
+    }) = &flags.ast
+    else {
+        panic!("struct flags has no layout: {:?}", flags.ast);
+    };
+    assert_eq!((layout.size, layout.align, layout.is_union), (8, 4, false));
+    let fields: Vec<_> = layout
+        .fields
+        .iter()
+        .map(|f| (f.name.as_deref(), f.offset_bits, f.bit_width))
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            (Some("a"), 0, Some(3)),
+            (Some("b"), 3, Some(5)),
+            (Some("c"), 32, None)
+        ]
+    );
+    assert_eq!(
+        layout.fields[2].ty,
+        CType::Int {
+            kind: IntKind::Fixed {
+                bits: 32,
+                signed: true
+            }
+        }
+    );
+
+    let level = map
+        .app_types
+        .iter()
+        .find(|e| e.name() == Some("level_t"))
+        .expect("level_t not found");
+    let enumeration = level
+        .sub_entities
+        .iter()
+        .find_map(|e| match &e.ast {
+            Some(ClangAST::EnumDecl {
+                layout: Some(layout),
+                ..
+            }) => Some(layout),
+            _ => None,
+        })
+        .expect("enum of level_t has no layout");
+    let constants: Vec<_> = enumeration
+        .constants
+        .iter()
+        .map(|c| (c.name.as_str(), c.value))
+        .collect();
+    assert_eq!(constants, [("LOW", -1), ("HIGH", 7)]);
+}
+
+#[test]
+fn no_comments_json_has_no_comments_keys() {
+    // Anti-regression: comment-free sources must serialize exactly as before.
+    let raw = mock_raw_source();
+    let Some(map) = run(&raw, None) else {
+        return;
+    };
+
+    let json = serde_json::to_string(&map).unwrap();
+    assert!(
+        !json.contains("\"comments\""),
+        "comment-free JSON unexpectedly contains `comments`; output was:\n{json}"
+    );
+}
+
+fn run_with(rs: &RawSource, options: &ParseOptions) -> Option<RichSourceMap> {
+    match parse_to_ast_with(rs, None, options) {
+        Ok(map) => Some(map),
+        Err(err) => {
+            eprintln!("Skipping: parse_to_ast failed (libclang missing?): {err}");
+            None
+        }
+    }
+}
+
+#[test]
+fn parallel_parse_matches_sequential_parse() {
+    // Files are parsed concurrently but merged in file order, so the worker
+    // count must not change the output.
+    let raw = mock_raw_source();
+    let sequential = ParseOptions {
+        jobs: Some(1),
+        cache_dir: None,
+    };
+    let parallel = ParseOptions {
+        jobs: Some(4),
+        cache_dir: None,
+    };
+    let (Some(seq_map), Some(par_map)) = (run_with(&raw, &sequential), run_with(&raw, &parallel))
+    else {
+        return;
+    };
+    assert_eq!(
+        serde_json::to_string(&seq_map).unwrap(),
+        serde_json::to_string(&par_map).unwrap(),
+        "parallel parse must be byte-equal to sequential parse"
+    );
+}
+
+#[test]
+fn cached_parse_is_byte_equal_and_tracks_changes() {
+    let cache_dir = tempfile::tempdir().unwrap();
+    let options = ParseOptions {
+        jobs: None,
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+    };
+    let mut raw = mock_raw_source();
+    let Some(cold) = run_with(&raw, &options) else {
+        return;
+    };
+    assert!(
+        std::fs::read_dir(cache_dir.path()).unwrap().count() > 0,
+        "first run must populate the cache"
+    );
+    let warm = run_with(&raw, &options).unwrap();
+    assert_eq!(
+        serde_json::to_string(&cold).unwrap(),
+        serde_json::to_string(&warm).unwrap(),
+        "cached parse must be byte-equal to a fresh parse"
+    );
+
+    // Editing a file must invalidate its entry.
+    raw.dir
+        .set_file(
+            "src/main.c",
+            b"int renamed_main(void) { return 0; }\n".to_vec(),
+        )
+        .unwrap();
+    let edited = run_with(&raw, &options).unwrap();
+    assert!(find_function(&edited, "renamed_main").is_some());
+    assert!(find_function(&edited, "main").is_none());
+}
+
+const API_H: &str = "int api(int x);\nextern int counter;\n";
+
+const IMPL_C: &str = r#"
+#include "api.h"
+int counter;
+static int helper(int x) { return x * 2; }
+int api(int x) { return helper(x) + counter; }
+"#;
+
+const USER_C: &str = r#"
+#include "api.h"
+static int helper(void) { return 1; }
+int main(void) { return api(helper()); }
+"#;
+
+const DUPLICATE_C: &str = "int counter;\n";
+
+#[test]
+fn symbol_table_tracks_linkage_across_tus() {
+    let mut dir = RawDir::default();
+    dir.set_file("include/api.h", API_H.as_bytes().to_vec())
+        .unwrap();
+    dir.set_file("src/impl.c", IMPL_C.as_bytes().to_vec())
+        .unwrap();
+    dir.set_file("src/user.c", USER_C.as_bytes().to_vec())
+        .unwrap();
+    dir.set_file("src/duplicate.c", DUPLICATE_C.as_bytes().to_vec())
+        .unwrap();
+    let raw = RawSource { dir };
+    let Some(map) = run(&raw, None) else {
+        return;
+    };
+    let symbols = &map.symbols;
+
+    let api = symbols
+        .get("api", "src/user.c")
+        .expect("api symbol missing");
+    assert_eq!(api.linkage, Linkage::External);
+    assert_eq!(api.definition.as_ref().unwrap().file, "src/impl.c");
+    assert_eq!(api.declaring_headers, ["include/api.h"]);
+    assert_eq!(api.referencing_tus, ["src/user.c"]);
+    assert!(api.is_exported());
+
+    let helper_impl = symbols.get("helper", "src/impl.c").unwrap();
+    let helper_user = symbols.get("helper", "src/user.c").unwrap();
+    assert_eq!(helper_impl.linkage, Linkage::Internal);
+    assert_ne!(helper_impl, helper_user);
+    assert_eq!(helper_impl.referencing_tus, ["src/impl.c"]);
+
+    let counter = symbols.get("counter", "src/impl.c").unwrap();
+    assert_eq!(counter.conflicting_definitions.len(), 1);
+
+    let main = symbols.for_entity(find_function(&map, "main").unwrap());
+    assert!(main.is_some_and(|s| !s.is_exported()));
+    let helper = map
+        .app_functions
+        .iter()
+        .find(|e| e.span.file == "src/impl.c" && e.source_text.contains("helper"))
+        .unwrap();
+    assert!(matches!(
+        helper.ast.as_ref(),
+        Some(ClangAST::FunctionDecl { storage_class: Some(s), .. }) if s == "static"
+    ));
+}
+
+#[test]
+fn materialized_map_loads_back() {
+    let raw = mock_raw_source();
+    let Some(map) = run(&raw, None) else {
+        return;
+    };
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("rsm.json");
+    map.materialize(&path).unwrap();
+
+    let loaded = RichSourceMap::load(&path).unwrap();
+    assert_eq!(
+        serde_json::to_string(&map).unwrap(),
+        serde_json::to_string(&loaded).unwrap()
+    );
+    assert!(loaded.well_formed());
+    let backend: Vec<&TopLevelEntity> = loaded.find_by_name("backend_alpha").collect();
+    assert_eq!(backend.len(), 1);
+    assert_eq!(backend[0].span.file, "src/backend_alpha.c");
+
+    std::fs::write(&path, "{}").unwrap();
+    assert!(RichSourceMap::load(&path).is_err());
+}
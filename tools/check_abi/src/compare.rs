@@ -0,0 +1,344 @@
+//! Compares the symbols of the two libraries and finds the Rust items to fix.
+
+use crate::{AbiProblem, AbiProblemKind, Symbol, SymbolKind};
+use name_map::{NameKind, NameMap};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::PathBuf;
+use syn::spanned::Spanned;
+
+/// A top-level Rust function or static.
+#[derive(Debug)]
+pub(crate) struct RustItem {
+    file: PathBuf,
+    span: Range<usize>,
+    ident: String,
+    kind: SymbolKind,
+    /// The symbol the item is exported under, from `no_mangle` or `export_name`.
+    export: Option<String>,
+}
+
+/// Collects the top-level functions and statics of `files`. Files `syn` cannot parse are skipped.
+pub(crate) fn rust_items<'a>(files: impl IntoIterator<Item = (PathBuf, &'a str)>) -> Vec<RustItem> {
+    let mut items = Vec::new();
+    for (file, source) in files {
+        let Ok(parsed) = syn::parse_file(source) else {
+            continue;
+        };
+        for item in &parsed.items {
+            let (ident, kind, attrs) = match item {
+                syn::Item::Fn(f) => (&f.sig.ident, SymbolKind::Function, &f.attrs),
+                syn::Item::Static(s) => (&s.ident, SymbolKind::Data, &s.attrs),
+                _ => continue,
+            };
+            items.push(RustItem {
+                file: file.clone(),
+                span: item.span().byte_range(),
+                ident: ident.to_string(),
+                kind,
+                export: attrs
+                    .iter()
+                    .find_map(|attr| export_name(&attr.meta, &ident.to_string())),
+            });
+        }
+    }
+    items
+}
+
+/// The symbol an attribute exports its item under, if it is `no_mangle` or `export_name`, plain or
+/// wrapped in `unsafe(...)`.
+fn export_name(meta: &syn::Meta, ident: &str) -> Option<String> {
+    match meta {
+        syn::Meta::Path(path) if path.is_ident("no_mangle") => Some(ident.to_string()),
+        syn::Meta::NameValue(nv) if nv.path.is_ident("export_name") => match &nv.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(s),
+                ..
+            }) => Some(s.value()),
+            _ => None,
+        },
+        syn::Meta::List(list) if list.path.is_ident("unsafe") => {
+            export_name(&list.parse_args().ok()?, ident)
+        }
+        _ => None,
+    }
+}
+
+/// The attribute that exports `rust_name` as `symbol`.
+fn export_attr(symbol: &str, rust_name: &str) -> String {
+    if symbol == rust_name {
+        "#[unsafe(no_mangle)]".to_string()
+    } else {
+        format!("#[unsafe(export_name = \"{symbol}\")]")
+    }
+}
+
+/// The item that should export `symbol` but does not: the one named after it.
+fn unexported<'a>(
+    symbol: &str,
+    kind: SymbolKind,
+    items: &'a [RustItem],
+    names: &NameMap,
+) -> Option<&'a RustItem> {
+    let name_kind = match kind {
+        SymbolKind::Function => NameKind::Function,
+        SymbolKind::Data => NameKind::Global,
+    };
+    let rust_name = names.rust_name(name_kind, symbol);
+    items
+        .iter()
+        .filter(|item| item.export.is_none())
+        .find(|item| item.ident == rust_name)
+        .or_else(|| items.iter().find(|item| item.ident == symbol))
+}
+
+/// How to declare a C symbol of kind `kind` in Rust.
+fn declaration(kind: SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::Function => "a `pub extern \"C\" fn`",
+        SymbolKind::Data => "a `pub static`",
+    }
+}
+
+/// Compares the symbols the C library exports with those the Rust library exports, suggesting a
+/// fix for each difference.
+pub(crate) fn compare(
+    c: &BTreeMap<String, Symbol>,
+    rust: &BTreeMap<String, Symbol>,
+    items: &[RustItem],
+    names: &NameMap,
+) -> Vec<AbiProblem> {
+    let exporting = |symbol: &str| {
+        items
+            .iter()
+            .find(|item| item.export.as_deref() == Some(symbol))
+    };
+    let location = |item: Option<&RustItem>| item.map(|i| (i.file.clone(), i.span.clone()));
+
+    let mut problems = Vec::new();
+    for (symbol, c_symbol) in c {
+        let kind = c_symbol.kind;
+        let Some(rust_symbol) = rust.get(symbol) else {
+            let item = unexported(symbol, kind, items, names);
+            let (description, suggestion) = match item {
+                Some(item) if item.kind == kind => (
+                    format!(
+                        "the C library exports {kind} `{symbol}` but the Rust library does not"
+                    ),
+                    format!(
+                        "mark `{}` {} and make it {}",
+                        item.ident,
+                        export_attr(symbol, &item.ident),
+                        declaration(kind)
+                    ),
+                ),
+                Some(item) => (
+                    format!(
+                        "the C library exports {kind} `{symbol}`, which `{}` translates as {}",
+                        item.ident, item.kind
+                    ),
+                    format!(
+                        "translate `{}` as {} marked {}",
+                        item.ident,
+                        declaration(kind),
+                        export_attr(symbol, &item.ident)
+                    ),
+                ),
+                None => (
+                    format!(
+                        "the C library exports {kind} `{symbol}` but the Rust library does not"
+                    ),
+                    format!(
+                        "add {} marked {}",
+                        declaration(kind),
+                        export_attr(symbol, symbol)
+                    ),
+                ),
+            };
+            problems.push(AbiProblem {
+                symbol: symbol.clone(),
+                kind: AbiProblemKind::Missing,
+                description,
+                suggestion,
+                location: location(item),
+            });
+            continue;
+        };
+        let item = exporting(symbol);
+        let rust_name = item.map_or(symbol.as_str(), |i| i.ident.as_str());
+        if rust_symbol.kind != kind {
+            problems.push(AbiProblem {
+                symbol: symbol.clone(),
+                kind: AbiProblemKind::KindMismatch,
+                description: format!(
+                    "`{symbol}` is {kind} in the C library but {} in the Rust library",
+                    rust_symbol.kind
+                ),
+                suggestion: format!(
+                    "translate `{rust_name}` as {} marked {}",
+                    declaration(kind),
+                    export_attr(symbol, rust_name)
+                ),
+                location: location(item),
+            });
+        } else if kind == SymbolKind::Data && rust_symbol.size != c_symbol.size {
+            problems.push(AbiProblem {
+                symbol: symbol.clone(),
+                kind: AbiProblemKind::SizeMismatch,
+                description: format!(
+                    "`{symbol}` is {} bytes in the C library but {} bytes in the Rust library",
+                    c_symbol.size, rust_symbol.size
+                ),
+                suggestion: format!("give `{rust_name}` a type of the size of its C declaration"),
+                location: location(item),
+            });
+        }
+    }
+
+    for (symbol, rust_symbol) in rust {
+        if c.contains_key(symbol) {
+            continue;
+        }
+        let item = exporting(symbol);
+        let rust_name = item.map_or(symbol.as_str(), |i| i.ident.as_str());
+        problems.push(AbiProblem {
+            symbol: symbol.clone(),
+            kind: AbiProblemKind::Extra,
+            description: format!(
+                "the Rust library exports {} `{symbol}` but the C library does not",
+                rust_symbol.kind
+            ),
+            suggestion: format!(
+                "remove `no_mangle` or `export_name` from `{rust_name}`; it is internal to the library"
+            ),
+            location: location(item),
+        });
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use name_map::NameEntry;
+
+    const LIB: &str = "\
+#[unsafe(no_mangle)]
+pub extern \"C\" fn list_new() {}
+
+pub extern \"C\" fn list_push_back() {}
+
+#[unsafe(export_name = \"listLen\")]
+pub extern \"C\" fn list_len() {}
+
+#[no_mangle]
+pub extern \"C\" fn debug_dump() {}
+
+#[unsafe(no_mangle)]
+pub static mut LIST_COUNT: i64 = 0;
+
+#[unsafe(no_mangle)]
+pub extern \"C\" fn list_version() {}
+";
+
+    fn symbol(kind: SymbolKind, size: u64) -> Symbol {
+        Symbol { kind, size }
+    }
+
+    #[test]
+    fn collects_exports_of_functions_and_statics() {
+        let items = rust_items([(PathBuf::from("src/lib.rs"), LIB)]);
+        let exports: Vec<_> = items
+            .iter()
+            .map(|i| (i.ident.as_str(), i.export.as_deref()))
+            .collect();
+        assert_eq!(
+            exports,
+            [
+                ("list_new", Some("list_new")),
+                ("list_push_back", None),
+                ("list_len", Some("listLen")),
+                ("debug_dump", Some("debug_dump")),
+                ("LIST_COUNT", Some("LIST_COUNT")),
+                ("list_version", Some("list_version")),
+            ]
+        );
+        assert!(LIB[items[2].span.clone()].starts_with("#[unsafe(export_name"));
+    }
+
+    #[test]
+    fn reports_missing_extra_and_mismatched_symbols() {
+        let items = rust_items([(PathBuf::from("src/lib.rs"), LIB)]);
+        let names = NameMap {
+            entries: vec![
+                NameEntry {
+                    c_name: "listPushBack".to_string(),
+                    kind: NameKind::Function,
+                    rust_name: "list_push_back".to_string(),
+                    export_name: Some("listPushBack".to_string()),
+                },
+                NameEntry {
+                    c_name: "listCount".to_string(),
+                    kind: NameKind::Global,
+                    rust_name: "LIST_COUNT".to_string(),
+                    export_name: None,
+                },
+            ],
+        };
+        let c = BTreeMap::from([
+            ("list_new".to_string(), symbol(SymbolKind::Function, 10)),
+            ("listPushBack".to_string(), symbol(SymbolKind::Function, 10)),
+            ("listLen".to_string(), symbol(SymbolKind::Function, 10)),
+            ("list_version".to_string(), symbol(SymbolKind::Data, 4)),
+            ("LIST_COUNT".to_string(), symbol(SymbolKind::Data, 4)),
+            ("list_free".to_string(), symbol(SymbolKind::Function, 10)),
+        ]);
+        let rust = BTreeMap::from([
+            ("list_new".to_string(), symbol(SymbolKind::Function, 20)),
+            ("listLen".to_string(), symbol(SymbolKind::Function, 20)),
+            ("debug_dump".to_string(), symbol(SymbolKind::Function, 20)),
+            ("LIST_COUNT".to_string(), symbol(SymbolKind::Data, 8)),
+            ("list_version".to_string(), symbol(SymbolKind::Function, 20)),
+        ]);
+
+        let problems = compare(&c, &rust, &items, &names);
+        let summary: Vec<_> = problems
+            .iter()
+            .map(|p| (p.symbol.as_str(), p.kind, p.suggestion.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "LIST_COUNT",
+                    AbiProblemKind::SizeMismatch,
+                    "give `LIST_COUNT` a type of the size of its C declaration"
+                ),
+                (
+                    "listPushBack",
+                    AbiProblemKind::Missing,
+                    "mark `list_push_back` #[unsafe(export_name = \"listPushBack\")] and make it \
+                     a `pub extern \"C\" fn`"
+                ),
+                (
+                    "list_free",
+                    AbiProblemKind::Missing,
+                    "add a `pub extern \"C\" fn` marked #[unsafe(no_mangle)]"
+                ),
+                (
+                    "list_version",
+                    AbiProblemKind::KindMismatch,
+                    "translate `list_version` as a `pub static` marked #[unsafe(no_mangle)]"
+                ),
+                (
+                    "debug_dump",
+                    AbiProblemKind::Extra,
+                    "remove `no_mangle` or `export_name` from `debug_dump`; it is internal to \
+                     the library"
+                ),
+            ]
+        );
+        assert!(problems[1].location.is_some());
+        assert_eq!(problems[2].location, None);
+    }
+}
@@ -0,0 +1,226 @@
+//! Reads the dynamic symbol table (`.dynsym`) of an ELF shared object.
+
+use crate::{Symbol, SymbolKind};
+use std::collections::BTreeMap;
+
+const SHT_DYNSYM: u32 = 11;
+const SHN_UNDEF: u16 = 0;
+
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STB_GNU_UNIQUE: u8 = 10;
+
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_TLS: u8 = 6;
+const STT_GNU_IFUNC: u8 = 10;
+
+const STV_DEFAULT: u8 = 0;
+const STV_PROTECTED: u8 = 3;
+
+/// Symbols every shared object defines, which are not part of the library's interface.
+const LINKER_SYMBOLS: &[&str] = &["_init", "_fini"];
+
+/// Reads a 32-bit or 64-bit ELF file of either byte order.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn slice(&self, offset: usize, len: usize) -> Result<&[u8], String> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or_else(|| format!("ELF file truncated at byte {offset}"))
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8, String> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, String> {
+        let bytes = self.slice(offset, 2)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, String> {
+        let bytes = self.slice(offset, 4)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, String> {
+        let bytes = self.slice(offset, 8)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    /// A field that is 4 bytes wide in ELF32 and 8 bytes wide in ELF64.
+    fn word(&self, offset: usize) -> Result<usize, String> {
+        let value = if self.is_64 {
+            self.u64(offset)?
+        } else {
+            self.u32(offset)?.into()
+        };
+        usize::try_from(value).map_err(|_| format!("ELF offset {value} out of range"))
+    }
+
+    /// The NUL-terminated string at `offset` in the string table at `table`.
+    fn str(&self, table: usize, offset: usize) -> Result<&str, String> {
+        let start = table + offset;
+        let bytes = self.bytes.get(start..).ok_or("ELF string out of range")?;
+        let len = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("ELF string not terminated")?;
+        str::from_utf8(&bytes[..len]).map_err(|e| format!("ELF symbol name is not UTF-8: {e}"))
+    }
+}
+
+/// The functions and data a shared object exports, by name.
+pub(crate) fn exported_symbols(bytes: &[u8]) -> Result<BTreeMap<String, Symbol>, String> {
+    if bytes.get(..4) != Some(b"\x7fELF") {
+        return Err("not an ELF file".to_string());
+    }
+    let elf = Reader {
+        bytes,
+        is_64: match bytes.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unknown ELF class".to_string()),
+        },
+        big_endian: match bytes.get(5) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unknown ELF byte order".to_string()),
+        },
+    };
+
+    let (shoff, shentsize, shnum) = if elf.is_64 {
+        (elf.word(0x28)?, elf.u16(0x3a)?, elf.u16(0x3c)?)
+    } else {
+        (elf.word(0x20)?, elf.u16(0x2e)?, elf.u16(0x30)?)
+    };
+    let section = |index: usize| shoff + index * usize::from(shentsize);
+    // Offsets of sh_type, sh_offset, sh_size, sh_link and sh_entsize.
+    let (type_at, offset_at, size_at, link_at, entsize_at) = if elf.is_64 {
+        (4, 24, 32, 40, 56)
+    } else {
+        (4, 16, 20, 24, 36)
+    };
+
+    let mut symbols = BTreeMap::new();
+    for index in 0..usize::from(shnum) {
+        let header = section(index);
+        if elf.u32(header + type_at)? != SHT_DYNSYM {
+            continue;
+        }
+        let table = elf.word(header + offset_at)?;
+        let size = elf.word(header + size_at)?;
+        let entsize = elf.word(header + entsize_at)?;
+        let strtab = section(elf.u32(header + link_at)? as usize);
+        let strings = elf.word(strtab + offset_at)?;
+        if entsize == 0 {
+            return Err("ELF symbol table has no entry size".to_string());
+        }
+
+        for entry in (table..table + size).step_by(entsize) {
+            // Offsets of st_info, st_other, st_shndx and st_size.
+            let (info_at, other_at, shndx_at, size_at) = if elf.is_64 {
+                (4, 5, 6, 16)
+            } else {
+                (12, 13, 14, 8)
+            };
+            let info = elf.u8(entry + info_at)?;
+            let visibility = elf.u8(entry + other_at)? & 0x3;
+            let defined = elf.u16(entry + shndx_at)? != SHN_UNDEF;
+            let kind = match info & 0xf {
+                STT_FUNC | STT_GNU_IFUNC => SymbolKind::Function,
+                STT_OBJECT | STT_TLS => SymbolKind::Data,
+                _ => continue,
+            };
+            if !defined
+                || !matches!(info >> 4, STB_GLOBAL | STB_WEAK | STB_GNU_UNIQUE)
+                || !matches!(visibility, STV_DEFAULT | STV_PROTECTED)
+            {
+                continue;
+            }
+            let name = elf.str(strings, elf.u32(entry)? as usize)?;
+            if name.is_empty() || LINKER_SYMBOLS.contains(&name) {
+                continue;
+            }
+            let size = if elf.is_64 {
+                elf.u64(entry + size_at)?
+            } else {
+                elf.u32(entry + size_at)?.into()
+            };
+            symbols.insert(name.to_string(), Symbol { kind, size });
+        }
+    }
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn reads_exported_functions_and_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("lib.c");
+        let so = dir.path().join("lib.so");
+        std::fs::write(
+            &source,
+            "int counter = 1;\n\
+             long table[4];\n\
+             static int hidden_count;\n\
+             static int helper(void) { return hidden_count; }\n\
+             __attribute__((visibility(\"hidden\"))) int internal(void) { return 0; }\n\
+             int bump(void) { return ++counter + helper(); }\n\
+             extern int imported(void);\n\
+             int call_imported(void) { return imported(); }\n",
+        )
+        .unwrap();
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&so)
+            .arg(&source)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let symbols = exported_symbols(&std::fs::read(&so).unwrap()).unwrap();
+        let names: Vec<&str> = symbols.keys().map(String::as_str).collect();
+        assert_eq!(names, ["bump", "call_imported", "counter", "table"]);
+        assert_eq!(symbols["bump"].kind, SymbolKind::Function);
+        assert_eq!(
+            symbols["counter"],
+            Symbol {
+                kind: SymbolKind::Data,
+                size: 4
+            }
+        );
+        assert_eq!(
+            symbols["table"].size,
+            4 * size_of::<std::ffi::c_long>() as u64
+        );
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(exported_symbols(b"#!/bin/sh\n").is_err());
+    }
+}
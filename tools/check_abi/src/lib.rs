@@ -0,0 +1,190 @@
+//! Checks that the translated library exports the symbols of the C library.
+//!
+//! The dynamic symbol tables of the C shared library and of the Rust package built as a `cdylib`
+//! are read directly from the ELF files and compared: every function and variable the C library
+//! exports must be exported by the Rust library, under the same name and as the same kind of
+//! symbol, and the Rust library should export nothing else. Each difference comes with a fix,
+//! which `fix_declarations_llm` applies like a compiler error.
+//!
+//! Without this check, a missing export shows up only when `run_difftest` fails to `dlsym` it.
+
+use build_c_artifact::CArtifact;
+use full_source::CargoPackage;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use name_map::NameMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+use tracing::info;
+
+mod compare;
+mod elf;
+
+/// Whether a symbol is code or data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    /// A variable, thread-local or not.
+    Data,
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SymbolKind::Function => "a function",
+            SymbolKind::Data => "data",
+        })
+    }
+}
+
+/// A symbol exported by a shared library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    /// The size in bytes, as recorded in the symbol table.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbiProblemKind {
+    /// Exported by the C library but not by the Rust library.
+    Missing,
+    /// Exported by the Rust library but not by the C library.
+    Extra,
+    /// A function in one library and data in the other.
+    KindMismatch,
+    /// Data of different sizes.
+    SizeMismatch,
+}
+
+/// One difference between the symbols of the two libraries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbiProblem {
+    pub symbol: String,
+    pub kind: AbiProblemKind,
+    pub description: String,
+    /// How to fix the Rust library.
+    pub suggestion: String,
+    /// Package-relative path of the Rust file defining the item to fix, and the byte range of the
+    /// item; `None` if there is no such item.
+    pub location: Option<(PathBuf, Range<usize>)>,
+}
+
+impl AbiProblem {
+    /// The difference and its fix as one error message.
+    pub fn message(&self) -> String {
+        format!("{}; {}", self.description, self.suggestion)
+    }
+}
+
+/// The result of comparing the symbols exported by the C library and by its translation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AbiCheckResult {
+    /// How many symbols the C library exports.
+    pub c_symbols: usize,
+    pub problems: Vec<AbiProblem>,
+}
+
+impl AbiCheckResult {
+    /// How many of the C library's symbols the Rust library exports compatibly.
+    pub fn matching(&self) -> usize {
+        let mismatched = self
+            .problems
+            .iter()
+            .filter(|p| p.kind != AbiProblemKind::Extra)
+            .count();
+        self.c_symbols - mismatched
+    }
+}
+
+impl fmt::Display for AbiCheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "AbiCheckResult: {}/{} symbols match",
+            self.matching(),
+            self.c_symbols
+        )?;
+        for problem in &self.problems {
+            writeln!(f, "  {}", problem.message())?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for AbiCheckResult {
+    fn name(&self) -> &'static str {
+        "abi_check_result"
+    }
+}
+
+pub struct CheckAbi;
+
+impl Tool for CheckAbi {
+    fn name(&self) -> &'static str {
+        "check_abi"
+    }
+
+    /// Inputs:
+    /// 1. [`CArtifact`] id -- the C shared library.
+    /// 2. [`CargoPackage`] id -- the translation.
+    /// 3. [`NameMap`] id (optional) -- the Rust names of the C functions and variables, to find
+    ///    the ones that are not exported; C names are assumed without it.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let c_artifact = context
+            .ir_snapshot
+            .get::<CArtifact>(inputs[0])
+            .ok_or("check_abi: no CArtifact in IR")?;
+        let cargo_package = context
+            .ir_snapshot
+            .get::<CargoPackage>(inputs[1])
+            .ok_or("check_abi: no CargoPackage in IR")?;
+        let names = inputs
+            .get(2)
+            .and_then(|id| context.ir_snapshot.get::<NameMap>(*id))
+            .cloned()
+            .unwrap_or_default();
+
+        let c_so = c_artifact
+            .so_path
+            .as_ref()
+            .ok_or("check_abi: project is not a library")?;
+        let c_symbols = elf::exported_symbols(&std::fs::read(c_so)?)
+            .map_err(|e| format!("check_abi: reading {}: {e}", c_so.display()))?;
+
+        let rust_root = tempfile::tempdir()?;
+        let rust_so = run_difftest::build_cdylib(cargo_package, rust_root.path())?;
+        let rust_symbols = elf::exported_symbols(&std::fs::read(&rust_so)?)
+            .map_err(|e| format!("check_abi: reading {}: {e}", rust_so.display()))?;
+
+        let files: Vec<(PathBuf, &str)> = cargo_package
+            .dir
+            .files_recursive()
+            .into_iter()
+            .filter(|(path, _)| path.extension().is_some_and(|e| e == "rs"))
+            .filter_map(|(path, source)| Some((path, str::from_utf8(source).ok()?)))
+            .collect();
+        let items = compare::rust_items(files);
+
+        let result = AbiCheckResult {
+            c_symbols: c_symbols.len(),
+            problems: compare::compare(&c_symbols, &rust_symbols, &items, &names),
+        };
+        info!(
+            "check_abi: {}/{} symbols of the C library exported compatibly, {} extra",
+            result.matching(),
+            result.c_symbols,
+            result
+                .problems
+                .iter()
+                .filter(|p| p.kind == AbiProblemKind::Extra)
+                .count()
+        );
+        Ok(Box::new(result))
+    }
+}
@@ -0,0 +1,230 @@
+//! Checks that the translated Rust types have the size, alignment and field offsets of the C
+//! types they translate.
+//!
+//! A C probe, compiled against the project's own headers, prints `sizeof`, `_Alignof` and
+//! `offsetof` for every struct and union a header defines. A test module added to each Rust file
+//! that translates one prints `size_of`, `align_of` and `offset_of!` for the translation. The
+//! differences are reported per type in a [`LayoutCheckResult`], which `fix_declarations_llm`
+//! repairs like compiler errors.
+//!
+//! ABI differences do not show up in the build, so without this check they surface, if at all, as
+//! wrong results in differential tests.
+
+use c_ast::RichSourceMap;
+use full_source::{CargoPackage, RawSource};
+use harvest_core::cargo_utils::CargoToml;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use name_map::NameMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::process::Command;
+use tracing::info;
+
+mod probe;
+
+/// The layout differences between one C type and its translation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutMismatch {
+    /// The C type, e.g. `struct foo`.
+    pub c_name: String,
+    pub rust_name: String,
+    /// Package-relative path of the Rust file defining the translation, and the byte range of the
+    /// definition; `None` if no translation was found.
+    pub location: Option<(PathBuf, Range<usize>)>,
+    /// Each difference, as a sentence.
+    pub problems: Vec<String>,
+}
+
+impl LayoutMismatch {
+    /// The differences as one error message.
+    pub fn message(&self) -> String {
+        format!(
+            "layout of `{}` differs from C `{}`: {}",
+            self.rust_name,
+            self.c_name,
+            self.problems.join("; ")
+        )
+    }
+}
+
+/// The result of comparing the layout of the project's public C types with their translations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayoutCheckResult {
+    /// How many C types were checked.
+    pub checked: usize,
+    pub mismatches: Vec<LayoutMismatch>,
+}
+
+impl fmt::Display for LayoutCheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "LayoutCheckResult: {}/{} types match",
+            self.checked - self.mismatches.len(),
+            self.checked
+        )?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "  {}", mismatch.message())?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for LayoutCheckResult {
+    fn name(&self) -> &'static str {
+        "layout_check_result"
+    }
+}
+
+pub struct CheckLayout;
+
+impl Tool for CheckLayout {
+    fn name(&self) -> &'static str {
+        "check_layout"
+    }
+
+    /// Inputs:
+    /// 1. [`RawSource`] id -- the C project, whose headers the C probe is compiled against.
+    /// 2. [`RichSourceMap`] id -- the types to check.
+    /// 3. [`CargoPackage`] id -- the translation.
+    /// 4. [`NameMap`] id (optional) -- the Rust names of the types; C names are assumed without it.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let raw_source = context
+            .ir_snapshot
+            .get::<RawSource>(inputs[0])
+            .ok_or("check_layout: no RawSource in IR")?;
+        let map = context
+            .ir_snapshot
+            .get::<RichSourceMap>(inputs[1])
+            .ok_or("check_layout: no RichSourceMap in IR")?;
+        let cargo_package = context
+            .ir_snapshot
+            .get::<CargoPackage>(inputs[2])
+            .ok_or("check_layout: no CargoPackage in IR")?;
+        let names = inputs
+            .get(3)
+            .and_then(|id| context.ir_snapshot.get::<NameMap>(*id))
+            .cloned()
+            .unwrap_or_default();
+
+        let types = probe::checked_types(map, &names);
+        if types.is_empty() {
+            info!("check_layout: no struct or union defined in a header; nothing to check");
+            return Ok(Box::new(LayoutCheckResult::default()));
+        }
+
+        let c_layout = run_c_probe(raw_source, &types)?;
+
+        let files: Vec<(PathBuf, &str)> = cargo_package
+            .dir
+            .files_recursive()
+            .into_iter()
+            .filter(|(path, _)| path.extension().is_some_and(|e| e == "rs"))
+            .filter_map(|(path, source)| Some((path, str::from_utf8(source).ok()?)))
+            .collect();
+        let items = probe::rust_items(files);
+        let located: Vec<_> = types.iter().map(|ty| probe::locate(ty, &items)).collect();
+        let rust_layout = run_rust_probe(cargo_package, &types, &located)?;
+
+        let result = LayoutCheckResult {
+            checked: types.len(),
+            mismatches: probe::compare(&types, &located, &c_layout, &rust_layout),
+        };
+        info!(
+            "check_layout: {}/{} types match their C layout",
+            result.checked - result.mismatches.len(),
+            result.checked
+        );
+        Ok(Box::new(result))
+    }
+}
+
+/// Compiles and runs the C probe against the project's headers.
+fn run_c_probe(
+    raw_source: &RawSource,
+    types: &[probe::CheckedType],
+) -> Result<HashMap<String, u64>, Box<dyn std::error::Error>> {
+    let root = tempfile::tempdir()?;
+    raw_source.dir.materialize(root.path())?;
+    let headers: Vec<PathBuf> = types.iter().map(|t| PathBuf::from(&t.header)).collect();
+    let probe_dir = tempfile::tempdir()?;
+    let probe_path = probe_dir.path().join("layout_probe.c");
+    let bin_path = probe_dir.path().join("layout_probe");
+    std::fs::write(&probe_path, probe::c_probe(types))?;
+
+    info!("check_layout: compiling the C layout probe");
+    let output = Command::new("clang")
+        .arg("-std=gnu11")
+        .args(
+            probe::include_dirs(root.path(), &headers)
+                .iter()
+                .map(|dir| format!("-I{}", dir.display())),
+        )
+        .arg(&probe_path)
+        .arg("-o")
+        .arg(&bin_path)
+        .output()
+        .map_err(|e| format!("check_layout: failed to run clang: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "check_layout: clang failed to compile the layout probe:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    let output = Command::new(&bin_path)
+        .output()
+        .map_err(|e| format!("check_layout: failed to run the C layout probe: {e}"))?;
+    Ok(probe::parse_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Adds the Rust probe modules to a copy of the package and runs them with `cargo test`.
+fn run_rust_probe(
+    cargo_package: &CargoPackage,
+    types: &[probe::CheckedType],
+    located: &[Option<probe::Located>],
+) -> Result<HashMap<String, u64>, Box<dyn std::error::Error>> {
+    let probes = probe::rust_probes(types, located);
+    if probes.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let root = tempfile::tempdir()?;
+    let mut package = cargo_package.clone();
+    for (file, probe) in probes {
+        package
+            .dir
+            .get_file_mut(&file)?
+            .extend_from_slice(probe.as_bytes());
+    }
+    package.materialize(root.path())?;
+    let mut cargo_toml = CargoToml::open(&root.path().join("Cargo.toml"))?;
+    cargo_toml.add_workspace();
+    cargo_toml.save()?;
+
+    info!("check_layout: running the Rust layout probe");
+    let output = Command::new("cargo")
+        .args(["test", "--quiet", probe::RUST_PROBE_MODULE, "--"])
+        .args(["--nocapture", "--test-threads=1"])
+        .current_dir(root.path())
+        .output()
+        .map_err(|e| format!("check_layout: failed to run cargo test: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "check_layout: the Rust layout probe failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    Ok(probe::parse_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
@@ -0,0 +1,426 @@
+//! Generation of the C and Rust layout probes, and comparison of what they print.
+//!
+//! Both probes print one line per measurement, keyed by the index of the checked type:
+//! `size 3 16`, `align 3 8`, `offset 3 next 8` (with the C field name).
+
+use c_ast::{ClangAST, EntityKind, RichSourceMap};
+use name_map::{NameKind, NameMap};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+
+use crate::LayoutMismatch;
+
+/// Name of the test module added to each Rust file that defines a checked type.
+pub(crate) const RUST_PROBE_MODULE: &str = "harvest_layout_probe";
+
+/// A C struct or union, defined in a header, whose layout is checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CheckedType {
+    /// The C type, as written in the probe: `struct foo` or a typedef name.
+    pub c_name: String,
+    /// Project-relative path of the header defining it.
+    pub header: String,
+    pub rust_name: String,
+    /// The named, non-bit-field fields, whose offsets can be taken.
+    pub fields: Vec<String>,
+}
+
+/// Where the translation of a [`CheckedType`] is defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Located {
+    /// Package-relative path of the Rust file.
+    pub file: PathBuf,
+    /// Byte range of the definition in the file.
+    pub span: Range<usize>,
+    /// The Rust field matching each C field, if any.
+    pub fields: Vec<Option<String>>,
+}
+
+/// A struct, union or type alias defined at the top level of a Rust file.
+#[derive(Debug)]
+pub(crate) struct RustItem {
+    name: String,
+    file: PathBuf,
+    span: Range<usize>,
+    /// Named fields, and whether they are visible outside the item's module.
+    fields: Vec<(String, bool)>,
+    /// For a type alias, the name of the type it aliases.
+    alias_of: Option<String>,
+}
+
+/// The structs and unions defined in the headers of `map`, named after their translations in
+/// `names`. A typedef of an anonymous struct is checked under the typedef name.
+pub(crate) fn checked_types(map: &RichSourceMap, names: &NameMap) -> Vec<CheckedType> {
+    let mut types: Vec<CheckedType> = Vec::new();
+    for entity in map.app_types.iter().filter(|e| e.span.file.ends_with(".h")) {
+        let typedef = match &entity.ast {
+            Some(ClangAST::TypedefDecl { name, .. }) => Some(name.as_str()),
+            _ => None,
+        };
+        for record in std::iter::once(entity).chain(&entity.sub_entities) {
+            let Some(ClangAST::RecordDecl {
+                layout: Some(layout),
+                ..
+            }) = &record.ast
+            else {
+                continue;
+            };
+            let (c_name, ident) = match (record.name(), typedef) {
+                (Some(name), _) => {
+                    let keyword = if layout.is_union { "union" } else { "struct" };
+                    (format!("{keyword} {name}"), name)
+                }
+                (None, Some(typedef)) if record.kind != EntityKind::TypedefDecl => {
+                    (typedef.to_string(), typedef)
+                }
+                _ => continue,
+            };
+            if types.iter().any(|t| t.c_name == c_name) {
+                continue;
+            }
+            types.push(CheckedType {
+                rust_name: names.rust_name(NameKind::Type, ident).to_string(),
+                c_name,
+                header: entity.span.file.clone(),
+                fields: layout
+                    .fields
+                    .iter()
+                    .filter(|f| f.bit_width.is_none())
+                    .filter_map(|f| f.name.clone())
+                    .collect(),
+            });
+        }
+    }
+    types
+}
+
+/// The C probe: includes the headers defining `types` and prints their layout.
+pub(crate) fn c_probe(types: &[CheckedType]) -> String {
+    let mut headers: Vec<&str> = Vec::new();
+    for ty in types {
+        if !headers.contains(&ty.header.as_str()) {
+            headers.push(&ty.header);
+        }
+    }
+    let mut source = String::from("#include <stddef.h>\n#include <stdio.h>\n");
+    for header in headers {
+        source.push_str(&format!("#include \"{header}\"\n"));
+    }
+    source.push_str("\nint main(void) {\n");
+    for (i, ty) in types.iter().enumerate() {
+        let c_name = &ty.c_name;
+        source.push_str(&format!(
+            "    printf(\"size {i} %zu\\n\", sizeof({c_name}));\n"
+        ));
+        source.push_str(&format!(
+            "    printf(\"align {i} %zu\\n\", _Alignof({c_name}));\n"
+        ));
+        for field in &ty.fields {
+            source.push_str(&format!(
+                "    printf(\"offset {i} {field} %zu\\n\", offsetof({c_name}, {field}));\n"
+            ));
+        }
+    }
+    source.push_str("    return 0;\n}\n");
+    source
+}
+
+/// The top-level structs, unions and type aliases of the Rust `files`, given as package-relative
+/// paths and sources. Files that do not parse are skipped.
+pub(crate) fn rust_items<'a>(files: impl IntoIterator<Item = (PathBuf, &'a str)>) -> Vec<RustItem> {
+    let mut items = Vec::new();
+    for (file, source) in files {
+        let Ok(parsed) = syn::parse_file(source) else {
+            continue;
+        };
+        for item in &parsed.items {
+            let (ident, generics, fields, alias_of) = match item {
+                syn::Item::Struct(s) => (&s.ident, &s.generics, Some(&s.fields), None),
+                syn::Item::Union(u) => (&u.ident, &u.generics, None, None),
+                syn::Item::Type(t) => {
+                    let alias_of = match t.ty.as_ref() {
+                        syn::Type::Path(path) => {
+                            path.path.segments.last().map(|s| s.ident.to_string())
+                        }
+                        _ => None,
+                    };
+                    (&t.ident, &t.generics, None, alias_of)
+                }
+                _ => continue,
+            };
+            if !generics.params.is_empty() {
+                continue;
+            }
+            let named = match (item, fields) {
+                (syn::Item::Union(u), _) => u.fields.named.iter().collect(),
+                (_, Some(syn::Fields::Named(named))) => named.named.iter().collect(),
+                _ => Vec::new(),
+            };
+            items.push(RustItem {
+                name: ident.to_string(),
+                file: file.clone(),
+                span: item.span().byte_range(),
+                fields: named
+                    .into_iter()
+                    .filter_map(|f| {
+                        let visible = !matches!(f.vis, syn::Visibility::Inherited);
+                        Some((f.ident.as_ref()?.to_string(), visible))
+                    })
+                    .collect(),
+                alias_of,
+            });
+        }
+    }
+    items
+}
+
+/// Finds the translation of `ty` among `items`, and the Rust field for each of its C fields.
+/// The fields of a type alias are those of the type it aliases, if that is defined in the same
+/// file or its fields are visible.
+pub(crate) fn locate(ty: &CheckedType, items: &[RustItem]) -> Option<Located> {
+    let item = items.iter().find(|item| item.name == ty.rust_name)?;
+    let fields: Vec<(String, bool)> = match &item.alias_of {
+        Some(target) => items
+            .iter()
+            .find(|t| &t.name == target && t.alias_of.is_none())
+            .map(|t| {
+                let same_file = t.file == item.file;
+                t.fields
+                    .iter()
+                    .filter(|(_, visible)| same_file || *visible)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default(),
+        None => item.fields.clone(),
+    };
+    Some(Located {
+        file: item.file.clone(),
+        span: item.span.clone(),
+        fields: ty
+            .fields
+            .iter()
+            .map(|c_field| {
+                fields
+                    .iter()
+                    .find(|(rust_field, _)| normalize(rust_field) == normalize(c_field))
+                    .map(|(rust_field, _)| rust_field.clone())
+            })
+            .collect(),
+    })
+}
+
+/// Field names compare equal across naming conventions and keyword escapes: `nextNode`,
+/// `next_node` and `next_node_` all match.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// The test module printing the layout of the located types defined in one Rust file, keyed by
+/// package-relative path.
+pub(crate) fn rust_probes(
+    types: &[CheckedType],
+    located: &[Option<Located>],
+) -> BTreeMap<PathBuf, String> {
+    let mut probes: BTreeMap<PathBuf, String> = BTreeMap::new();
+    for (i, (ty, located)) in types.iter().zip(located).enumerate() {
+        let Some(located) = located else {
+            continue;
+        };
+        let probe = probes.entry(located.file.clone()).or_default();
+        let rust_name = &ty.rust_name;
+        probe.push_str(&format!(
+            "        println!(\"size {i} {{}}\", core::mem::size_of::<super::{rust_name}>());\n"
+        ));
+        probe.push_str(&format!(
+            "        println!(\"align {i} {{}}\", core::mem::align_of::<super::{rust_name}>());\n"
+        ));
+        for (c_field, rust_field) in ty.fields.iter().zip(&located.fields) {
+            if let Some(rust_field) = rust_field {
+                probe.push_str(&format!(
+                    "        println!(\"offset {i} {c_field} {{}}\", \
+                     core::mem::offset_of!(super::{rust_name}, {rust_field}));\n"
+                ));
+            }
+        }
+    }
+    for body in probes.values_mut() {
+        *body = format!(
+            "\n#[cfg(test)]\nmod {RUST_PROBE_MODULE} {{\n    #[test]\n    fn print_layout() {{\n{body}    }}\n}}\n"
+        );
+    }
+    probes
+}
+
+/// The measurements printed by a probe.
+pub(crate) fn parse_output(output: &str) -> HashMap<String, u64> {
+    output
+        .lines()
+        .filter(|line| {
+            line.starts_with("size ") || line.starts_with("align ") || line.starts_with("offset ")
+        })
+        .filter_map(|line| {
+            let (key, value) = line.trim().rsplit_once(' ')?;
+            Some((key.to_string(), value.parse().ok()?))
+        })
+        .collect()
+}
+
+/// The types whose Rust layout, as printed by the Rust probe, differs from the C layout.
+pub(crate) fn compare(
+    types: &[CheckedType],
+    located: &[Option<Located>],
+    c: &HashMap<String, u64>,
+    rust: &HashMap<String, u64>,
+) -> Vec<LayoutMismatch> {
+    let mut mismatches = Vec::new();
+    for (i, (ty, located)) in types.iter().zip(located).enumerate() {
+        let mut problems = Vec::new();
+        match located {
+            None => problems.push(format!(
+                "no Rust definition of `{}` was found",
+                ty.rust_name
+            )),
+            Some(located) => {
+                let differs = |key: String, what: &str| {
+                    let (c, rust) = (c.get(&key)?, rust.get(&key)?);
+                    (c != rust).then(|| format!("{what} is {c} in C but {rust} in Rust"))
+                };
+                problems.extend(differs(format!("size {i}"), "size"));
+                problems.extend(differs(format!("align {i}"), "alignment"));
+                for (c_field, rust_field) in ty.fields.iter().zip(&located.fields) {
+                    match rust_field {
+                        Some(rust_field) => problems.extend(differs(
+                            format!("offset {i} {c_field}"),
+                            &format!("the offset of field `{c_field}` (`{rust_field}` in Rust)"),
+                        )),
+                        None => problems.push(format!(
+                            "field `{c_field}` has no counterpart in the Rust definition"
+                        )),
+                    }
+                }
+            }
+        }
+        if !problems.is_empty() {
+            mismatches.push(LayoutMismatch {
+                c_name: ty.c_name.clone(),
+                rust_name: ty.rust_name.clone(),
+                location: located.as_ref().map(|l| (l.file.clone(), l.span.clone())),
+                problems,
+            });
+        }
+    }
+    mismatches
+}
+
+/// Directories to search for the headers: the source root, its `include` directory and every
+/// directory containing a header.
+pub(crate) fn include_dirs(root: &Path, headers: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf(), root.join("include")];
+    for header in headers {
+        if let Some(parent) = header.parent() {
+            let dir = root.join(parent);
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node() -> CheckedType {
+        CheckedType {
+            c_name: "struct node".into(),
+            header: "include/list.h".into(),
+            rust_name: "Node".into(),
+            fields: vec!["value".into(), "nextNode".into()],
+        }
+    }
+
+    #[test]
+    fn c_probe_prints_layout_of_each_type() {
+        let probe = c_probe(&[node()]);
+        assert!(probe.contains("#include \"include/list.h\"\n"));
+        assert!(probe.contains("printf(\"size 0 %zu\\n\", sizeof(struct node));"));
+        assert!(probe.contains("printf(\"align 0 %zu\\n\", _Alignof(struct node));"));
+        assert!(
+            probe
+                .contains("printf(\"offset 0 nextNode %zu\\n\", offsetof(struct node, nextNode));")
+        );
+    }
+
+    #[test]
+    fn locates_translations_and_matches_fields_across_conventions() {
+        let lib = "pub type Node = ListNode;\n\
+                   pub struct Other { x: i32 }";
+        let list = "#[repr(C)]\npub struct ListNode {\n    pub value: i32,\n    pub next_node: *mut ListNode,\n}";
+        let items = rust_items([
+            (PathBuf::from("src/lib.rs"), lib),
+            (PathBuf::from("src/list.rs"), list),
+        ]);
+
+        let located = locate(&node(), &items).expect("Node not found");
+        assert_eq!(located.file, PathBuf::from("src/lib.rs"));
+        assert_eq!(&lib[located.span.clone()], "pub type Node = ListNode;");
+        assert_eq!(
+            located.fields,
+            [Some("value".to_string()), Some("next_node".to_string())]
+        );
+
+        let probes = rust_probes(&[node()], &[Some(located)]);
+        let probe = &probes[&PathBuf::from("src/lib.rs")];
+        assert!(probe.contains(&format!("mod {RUST_PROBE_MODULE} {{")));
+        assert!(probe.contains(
+            "println!(\"offset 0 nextNode {}\", core::mem::offset_of!(super::Node, next_node));"
+        ));
+    }
+
+    #[test]
+    fn reports_each_difference() {
+        let types = [
+            node(),
+            CheckedType {
+                rust_name: "Missing".into(),
+                ..node()
+            },
+        ];
+        let located = [
+            Some(Located {
+                file: PathBuf::from("src/list.rs"),
+                span: 0..10,
+                fields: vec![Some("value".into()), None],
+            }),
+            None,
+        ];
+        let c = parse_output("size 0 16\nalign 0 8\noffset 0 value 0\noffset 0 nextNode 8\n");
+        let rust = parse_output("running 1 test\nsize 0 12\nalign 0 8\noffset 0 value 0\n");
+
+        let mismatches = compare(&types, &located, &c, &rust);
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(
+            mismatches[0].problems,
+            [
+                "size is 16 in C but 12 in Rust",
+                "field `nextNode` has no counterpart in the Rust definition"
+            ]
+        );
+        assert_eq!(
+            mismatches[0].location,
+            Some((PathBuf::from("src/list.rs"), 0..10))
+        );
+        assert_eq!(
+            mismatches[1].problems,
+            ["no Rust definition of `Missing` was found"]
+        );
+        assert_eq!(mismatches[1].location, None);
+    }
+}
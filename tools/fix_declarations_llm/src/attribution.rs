@@ -1,36 +1,97 @@
-use crate::patches::ErrorSpan;
 use cargo_metadata::diagnostic::{Diagnostic, DiagnosticLevel};
 use full_source::CargoPackage;
 use quantize_rust_spans::RustItemMap;
-use std::collections::HashMap;
-use std::ops::{Bound, RangeBounds as _};
-use std::path::PathBuf;
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use tracing::info;
 use try_cargo_build::CargoBuildResult;
 
-fn find_enclosing_decl(
-    item_map: &RustItemMap,
-    file_name: &PathBuf,
-    byte_start: usize,
-    byte_end: usize,
-) -> Result<(Bound<usize>, Bound<usize>), Box<dyn std::error::Error>> {
-    let items = item_map
-        .items
-        .get(file_name)
-        .ok_or("FixDeclarationsLlm: no items found for file")?;
-
-    for v in items {
-        if v.contains(&byte_start) && v.contains(&byte_end) {
-            return Ok((v.start_bound().cloned(), v.end_bound().cloned()));
+/// How many items defining names an error mentions are fixed along with the item it occurs in.
+const MAX_REFERENCED_DEFINITIONS: usize = 4;
+
+/// A file and the byte range of an item in it (the whole file if the error could not be
+/// attributed to an item).
+pub(crate) type ItemSpan = (PathBuf, Range<usize>);
+
+/// Errors that are fixed together, in one LLM request, with the items they involve.
+#[derive(Debug, Default)]
+pub(crate) struct FixGroup {
+    /// The items to fix, none overlapping another.
+    pub items: Vec<ItemSpan>,
+    pub messages: Vec<String>,
+    /// The names the errors mention, in order.
+    pub names: Vec<String>,
+    /// The codes of the errors, such as `E0308`.
+    pub codes: BTreeSet<String>,
+}
+
+impl FixGroup {
+    fn overlaps(&self, item: &ItemSpan) -> bool {
+        self.items.iter().any(|(file, range)| {
+            file == &item.0 && range.start < item.1.end && item.1.start < range.end
+        })
+    }
+
+    /// Adds `item`, unless an item containing it is already there.
+    fn add_item(&mut self, item: ItemSpan) {
+        let contains = |outer: &ItemSpan, inner: &ItemSpan| {
+            outer.0 == inner.0 && outer.1.start <= inner.1.start && inner.1.end <= outer.1.end
+        };
+        if self.items.iter().any(|existing| contains(existing, &item)) {
+            return;
+        }
+        self.items.retain(|existing| !contains(&item, existing));
+        self.items.push(item);
+    }
+
+    fn merge(&mut self, other: FixGroup) {
+        for item in other.items {
+            self.add_item(item);
+        }
+        self.messages.extend(other.messages);
+        for name in other.names {
+            if !self.names.contains(&name) {
+                self.names.push(name);
+            }
+        }
+        self.codes.extend(other.codes);
+    }
+}
+
+/// Adds the errors of `new` to `groups`, merging every group that shares an item with it, so
+/// that no two groups edit the same item.
+fn add_to_groups(groups: &mut Vec<FixGroup>, new: FixGroup) {
+    let mut group = FixGroup::default();
+    let mut i = 0;
+    while i < groups.len() {
+        if new.items.iter().any(|item| groups[i].overlaps(item)) {
+            group.merge(groups.remove(i));
+        } else {
+            i += 1;
         }
     }
+    group.merge(new);
+    groups.push(group);
+}
 
-    Ok((Bound::Unbounded, Bound::Unbounded))
+/// The smallest item of `item_map` containing `range` of `file`, such as a method rather than
+/// its impl block, or the whole file if no item does.
+fn find_enclosing_decl(
+    item_map: &RustItemMap,
+    cargo_package: &CargoPackage,
+    file: &Path,
+    range: Range<usize>,
+) -> Option<ItemSpan> {
+    let source = cargo_package.dir.get_file(file).ok()?;
+    let item = item_map
+        .enclosing_items(file, &range)
+        .last()
+        .map_or(0..source.len(), |item| item.range.clone());
+    Some((file.to_path_buf(), item))
 }
 
-fn get_error_diagnostics(
-    build_result: &CargoBuildResult,
-) -> Vec<&cargo_metadata::diagnostic::Diagnostic> {
+pub(crate) fn get_error_diagnostics(build_result: &CargoBuildResult) -> Vec<&Diagnostic> {
     let mut error_diagnostics = vec![];
     for d in &build_result.diagnostics {
         if d.message.level == DiagnosticLevel::Error {
@@ -49,69 +110,216 @@ fn get_error_diagnostics(
     error_diagnostics
 }
 
-pub(crate) fn attribute_errors(
-    build_result: &CargoBuildResult,
+/// The names an error mentions, in order: the identifiers quoted in its messages and labels, and
+/// those in the code its primary spans highlight.
+fn referenced_names(msg: &Diagnostic) -> Vec<String> {
+    let mut text = String::new();
+    let mut quoted = |diagnostic: &Diagnostic| {
+        let labels = diagnostic.spans.iter().filter_map(|s| s.label.as_deref());
+        for message in std::iter::once(diagnostic.message.as_str()).chain(labels) {
+            for part in message.split('`').skip(1).step_by(2) {
+                text.push_str(part);
+                text.push(' ');
+            }
+        }
+    };
+    quoted(msg);
+    msg.children.iter().for_each(&mut quoted);
+    for span in msg.spans.iter().filter(|s| s.is_primary) {
+        for line in &span.text {
+            let highlighted = line
+                .text
+                .chars()
+                .skip(line.highlight_start.saturating_sub(1))
+                .take(line.highlight_end.saturating_sub(line.highlight_start));
+            text.extend(highlighted);
+            text.push(' ');
+        }
+    }
+
+    let mut names: Vec<String> = Vec::new();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        if word.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && !names.iter().any(|name| name == word)
+        {
+            names.push(word.to_string());
+        }
+    }
+    names
+}
+
+/// The items involved in the error `msg`: those its primary spans are in, those its other spans
+/// and its notes point to, and those defining up to [`MAX_REFERENCED_DEFINITIONS`] of the names
+/// it mentions. The first item is the one the error occurs in, if it is in the package.
+fn involved_items(
+    msg: &Diagnostic,
     item_map: &RustItemMap,
     cargo_package: &CargoPackage,
-) -> Result<HashMap<ErrorSpan, Vec<Diagnostic>>, Box<dyn std::error::Error>> {
-    let error_diagnostics = get_error_diagnostics(build_result);
+) -> Vec<ItemSpan> {
+    let child_spans = msg.children.iter().flat_map(|child| &child.spans);
+    let (primary, secondary): (Vec<_>, Vec<_>) = msg.spans.iter().partition(|s| s.is_primary);
+    let mut items: Vec<ItemSpan> = primary
+        .into_iter()
+        .chain(secondary)
+        .chain(child_spans)
+        .filter_map(|span| {
+            find_enclosing_decl(
+                item_map,
+                cargo_package,
+                Path::new(&span.file_name),
+                span.byte_start as usize..span.byte_end as usize,
+            )
+        })
+        .collect();
+    if items.is_empty() {
+        return items;
+    }
+    let definitions = referenced_names(msg)
+        .into_iter()
+        .filter_map(|name| item_map.definition(&name).cloned())
+        .filter(|definition| !items.contains(definition))
+        .take(MAX_REFERENCED_DEFINITIONS)
+        .collect::<Vec<_>>();
+    items.extend(definitions);
+    items
+}
 
-    let mut decl_errors: HashMap<ErrorSpan, Vec<Diagnostic>> = HashMap::new();
+/// An error found by a check other than the build, such as `check_layout`.
+pub(crate) struct Finding<'a> {
+    /// The file and byte range of the item the error is about, if there is one.
+    pub location: Option<&'a (PathBuf, Range<usize>)>,
+    pub message: String,
+}
 
-    for msg in error_diagnostics {
-        for span in &msg.spans {
-            let file_name = PathBuf::new().join(&span.file_name);
-            cargo_package.dir.get_file(&file_name)?;
+/// Groups the compiler `diagnostics` to fix, and the findings of other checks, with the items
+/// they involve: for a diagnostic, the item it occurs in and the items it points to or mentions.
+/// Errors involving the same item are grouped together, and an error that could not be
+/// attributed to an item involves its whole file.
+pub(crate) fn attribute_errors(
+    diagnostics: Vec<&Diagnostic>,
+    findings: Vec<Finding>,
+    item_map: &RustItemMap,
+    cargo_package: &CargoPackage,
+) -> Vec<FixGroup> {
+    let mut groups: Vec<FixGroup> = Vec::new();
 
-            let (decl_start, decl_end) = find_enclosing_decl(
-                item_map,
-                &file_name,
-                span.byte_start as usize,
-                span.byte_end as usize,
-            )?;
-
-            decl_errors
-                .entry((file_name, decl_start, decl_end))
-                .or_default()
-                .push(msg.clone());
+    for msg in diagnostics {
+        let items = involved_items(msg, item_map, cargo_package);
+        if items.is_empty() {
+            info!(
+                "FixDeclarationsLlm: no declaration to fix for: {}",
+                msg.message
+            );
+            continue;
         }
+        let message = msg.rendered.clone().unwrap_or_else(|| msg.message.clone());
+        add_to_groups(
+            &mut groups,
+            FixGroup {
+                items,
+                messages: vec![message],
+                names: referenced_names(msg),
+                codes: msg.code.iter().map(|code| code.code.clone()).collect(),
+            },
+        );
+    }
+
+    // A finding is fixed by rewriting the item it is about, so it is attributed to the
+    // declaration containing the start of the item.
+    for finding in findings {
+        let Some(item) = finding.location.and_then(|(file_name, span)| {
+            find_enclosing_decl(item_map, cargo_package, file_name, span.start..span.start)
+        }) else {
+            info!(
+                "FixDeclarationsLlm: no declaration to fix for: {}",
+                finding.message
+            );
+            continue;
+        };
+        add_to_groups(
+            &mut groups,
+            FixGroup {
+                items: vec![item],
+                messages: vec![finding.message],
+                ..FixGroup::default()
+            },
+        );
     }
 
-    Ok(coalesce_errors(decl_errors))
+    groups
 }
 
-/// If any error in a file could not be attributed to a specific declaration (i.e. its span
-/// resolved to `Unbounded`), pull all of that file's diagnostics into the single
-/// `(Unbounded, Unbounded)` entry so the whole file is sent to the LLM as one unit.
-fn coalesce_errors(
-    mut decl_errors: HashMap<ErrorSpan, Vec<Diagnostic>>,
-) -> HashMap<ErrorSpan, Vec<Diagnostic>> {
-    let unbounded_files: Vec<PathBuf> = decl_errors
-        .keys()
-        .filter(|(_, start, end)| {
-            matches!(start, Bound::Unbounded) && matches!(end, Bound::Unbounded)
-        })
-        .map(|(file, _, _)| file.clone())
-        .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for file in unbounded_files {
-        let specific_keys: Vec<_> = decl_errors
-            .keys()
-            .filter(|(f, start, end)| {
-                f == &file
-                    && !(matches!(start, Bound::Unbounded) && matches!(end, Bound::Unbounded))
-            })
-            .cloned()
-            .collect();
-
-        for key in specific_keys {
-            let diagnostics = decl_errors.remove(&key).unwrap();
-            decl_errors
-                .entry((file.clone(), Bound::Unbounded, Bound::Unbounded))
-                .or_default()
-                .extend(diagnostics);
-        }
+    #[test]
+    fn errors_sharing_an_item_are_fixed_together() {
+        let item = |file: &str, range: Range<usize>| (PathBuf::from(file), range);
+        let error = |items: Vec<ItemSpan>, message: &str| FixGroup {
+            items,
+            messages: vec![message.into()],
+            names: vec![message.into()],
+            codes: BTreeSet::from([format!("E{message}")]),
+        };
+        let mut groups = Vec::new();
+        add_to_groups(&mut groups, error(vec![item("src/a.rs", 0..10)], "1"));
+        add_to_groups(&mut groups, error(vec![item("src/a.rs", 20..30)], "2"));
+        add_to_groups(&mut groups, error(vec![item("src/b.rs", 0..5)], "3"));
+        add_to_groups(
+            &mut groups,
+            error(vec![item("src/b.rs", 0..5), item("src/a.rs", 20..30)], "4"),
+        );
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].messages, ["2", "3", "4"]);
+        assert_eq!(groups[1].names, ["2", "3", "4"]);
+        assert_eq!(
+            groups[1].items,
+            [item("src/a.rs", 20..30), item("src/b.rs", 0..5)]
+        );
+
+        // An error in no item takes in the whole file.
+        add_to_groups(&mut groups, error(vec![item("src/a.rs", 0..40)], "5"));
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].items,
+            [item("src/b.rs", 0..5), item("src/a.rs", 0..40)]
+        );
+        assert_eq!(groups[0].codes.len(), 5);
     }
 
-    decl_errors
+    #[test]
+    fn names_come_from_quotes_and_highlighted_code() {
+        let msg: Diagnostic = serde_json::from_value(serde_json::json!({
+            "message": "mismatched types",
+            "code": {"code": "E0308", "explanation": null},
+            "level": "error",
+            "spans": [{
+                "file_name": "src/lib.rs",
+                "byte_start": 40,
+                "byte_end": 47,
+                "line_start": 3,
+                "line_end": 3,
+                "column_start": 13,
+                "column_end": 20,
+                "is_primary": true,
+                "text": [{"text": "    let n = p.count + 1;", "highlight_start": 13, "highlight_end": 20}],
+                "label": "expected `u8`, found `i32`",
+                "suggested_replacement": null,
+                "suggestion_applicability": null,
+                "expansion": null
+            }],
+            "children": [{
+                "message": "`Point` is defined here",
+                "code": null,
+                "level": "note",
+                "spans": [],
+                "children": [],
+                "rendered": null
+            }],
+            "rendered": null
+        }))
+        .unwrap();
+        assert_eq!(referenced_names(&msg), ["u8", "i32", "Point", "p", "count"]);
+    }
 }
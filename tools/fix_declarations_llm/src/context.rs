@@ -0,0 +1,308 @@
+//! The reference context of a repair request, built from the errors being fixed: the C source
+//! the failing declarations were translated from, as the provenance map links them or else by
+//! name, the full definitions of the names the errors
+//! mention, and rustc's explanations of the error codes. The stubbed declarations of the rest of
+//! the crate fill what is left of the token budget.
+
+use crate::attribution::{FixGroup, ItemSpan};
+use crate::interface_ctx::stub_declaration;
+use c_ast::RichSourceMap;
+use full_source::CargoPackage;
+use name_map::NameMap;
+use provenance_map::ProvenanceMap;
+use quantize_rust_spans::RustItemMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::process::Command;
+
+/// Builds the reference context for each group of errors.
+pub(crate) struct ErrorContext<'a> {
+    item_map: &'a RustItemMap,
+    cargo_package: &'a CargoPackage,
+    /// The C declarations the crate was translated from, if the schedule provides them.
+    c_ast: Option<&'a RichSourceMap>,
+    /// The Rust names of the C names, if the schedule provides them.
+    names: Option<&'a NameMap>,
+    /// The C declarations the items of the crate come from, if the schedule provides them.
+    provenance: Option<ProvenanceMap>,
+    /// Approximate token budget of a context.
+    token_budget: usize,
+    /// `rustc --explain` output by error code, `None` if there is none.
+    explanations: RefCell<HashMap<String, Option<String>>>,
+}
+
+/// A context under construction: sections of pieces, each added whole if it fits the budget.
+struct Builder {
+    sections: Vec<(&'static str, Vec<String>)>,
+    tokens_left: usize,
+}
+
+impl Builder {
+    /// Adds `piece` to the section `title` if it fits the tokens left; returns whether it did.
+    fn add(&mut self, title: &'static str, piece: String) -> bool {
+        // Rough token count, at four bytes per token.
+        let tokens = piece.len() / 4 + 1;
+        if tokens > self.tokens_left {
+            return false;
+        }
+        self.tokens_left -= tokens;
+        match self.sections.iter_mut().find(|(t, _)| *t == title) {
+            Some((_, pieces)) => pieces.push(piece),
+            None => self.sections.push((title, vec![piece])),
+        }
+        true
+    }
+
+    fn build(self) -> String {
+        self.sections
+            .into_iter()
+            .map(|(title, pieces)| format!("{title}:\n{}", pieces.join("\n\n")))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl<'a> ErrorContext<'a> {
+    pub(crate) fn new(
+        item_map: &'a RustItemMap,
+        cargo_package: &'a CargoPackage,
+        c_ast: Option<&'a RichSourceMap>,
+        names: Option<&'a NameMap>,
+        provenance: Option<ProvenanceMap>,
+        token_budget: usize,
+    ) -> Self {
+        Self {
+            item_map,
+            cargo_package,
+            c_ast,
+            names,
+            provenance,
+            token_budget,
+            explanations: RefCell::default(),
+        }
+    }
+
+    /// The reference context for fixing `group`, in order of priority: the C source of its
+    /// items, the definitions of the names its errors mention, the explanations of its error
+    /// codes, and the stubbed declarations of the other items.
+    pub(crate) fn for_group(&self, group: &FixGroup) -> String {
+        let mut builder = Builder {
+            sections: Vec::new(),
+            tokens_left: self.token_budget,
+        };
+        let mut shown: Vec<&ItemSpan> = group.items.iter().collect();
+
+        for item in &group.items {
+            if let Some(c_source) = self.c_source(item) {
+                builder.add("ORIGINAL C SOURCE OF THE ITEMS TO FIX", c_source);
+            }
+        }
+
+        for name in &group.names {
+            let Some(definition) = self.item_map.definition(name) else {
+                continue;
+            };
+            if shown.iter().any(|item| contains(item, definition)) {
+                continue;
+            }
+            if let Some(source) = self.source(definition)
+                && builder.add(
+                    "DEFINITIONS OF NAMES IN THE ERRORS",
+                    source.trim_end().to_string(),
+                )
+            {
+                shown.push(definition);
+            }
+        }
+
+        for code in &group.codes {
+            if let Some(explanation) = self.explanation(code) {
+                builder.add(
+                    "EXPLANATIONS OF THE ERROR CODES",
+                    format!("{code}:\n{}", explanation.trim_end()),
+                );
+            }
+        }
+
+        let files: BTreeMap<_, _> = self.item_map.items.iter().collect();
+        for (file, items) in files {
+            for top_level in items {
+                let item = (file.clone(), top_level.range.clone());
+                if shown.iter().any(|shown| contains(shown, &item)) {
+                    continue;
+                }
+                if let Some(stub) = self
+                    .source(&item)
+                    .and_then(|s| stub_declaration(s.as_bytes()))
+                {
+                    builder.add("OTHER DECLARATIONS (function bodies stubbed)", stub);
+                }
+            }
+        }
+
+        builder.build()
+    }
+
+    fn source(&self, (file, range): &ItemSpan) -> Option<&'a str> {
+        let source = self.cargo_package.dir.get_file(file).ok()?;
+        str::from_utf8(source.get(range.clone())?).ok()
+    }
+
+    /// The C declaration the Rust `item` was translated from: the one the provenance map links
+    /// it to, or else the one of the name it declares.
+    fn c_source(&self, item: &ItemSpan) -> Option<String> {
+        let c_ast = self.c_ast?;
+        if let Some(entry) = self
+            .provenance
+            .as_ref()
+            .and_then(|provenance| provenance.for_item(&item.0, &item.1))
+            && let Some(entity) = c_ast
+                .iter_all_entities()
+                .find(|entity| entity.span == entry.c_span)
+        {
+            return Some(format!(
+                "// `{}` translates this C declaration from {}:{}:\n{}",
+                entry.rust_name,
+                entity.span.file,
+                entity.span.start.line,
+                entity.source_text.trim_end()
+            ));
+        }
+        let ident = item_ident(self.source(item)?)?;
+        let c_ident = self
+            .names
+            .and_then(|names| names.entries.iter().find(|e| e.rust_name == ident))
+            .map_or(ident.as_str(), |entry| entry.c_ident());
+        let entity = c_ast.find_by_name(c_ident).next()?;
+        Some(format!(
+            "// `{ident}` translates this C declaration from {}:\n{}",
+            entity.span.file,
+            entity.source_text.trim_end()
+        ))
+    }
+
+    /// `rustc --explain code`, remembered across the groups.
+    fn explanation(&self, code: &str) -> Option<String> {
+        self.explanations
+            .borrow_mut()
+            .entry(code.to_string())
+            .or_insert_with(|| {
+                let output = Command::new("rustc")
+                    .args(["--explain", code])
+                    .output()
+                    .ok()?;
+                output
+                    .status
+                    .success()
+                    .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+            })
+            .clone()
+    }
+}
+
+fn contains(outer: &ItemSpan, inner: &ItemSpan) -> bool {
+    outer.0 == inner.0 && outer.1.start <= inner.1.start && inner.1.end <= outer.1.end
+}
+
+/// The name the Rust item `source` declares: the type of an impl block, the identifier of any
+/// other item.
+fn item_ident(source: &str) -> Option<String> {
+    let ident = match syn::parse_str::<syn::Item>(source).ok()? {
+        syn::Item::Const(item) => item.ident,
+        syn::Item::Enum(item) => item.ident,
+        syn::Item::Fn(item) => item.sig.ident,
+        syn::Item::Static(item) => item.ident,
+        syn::Item::Struct(item) => item.ident,
+        syn::Item::Type(item) => item.ident,
+        syn::Item::Union(item) => item.ident,
+        syn::Item::Impl(item) => match *item.self_ty {
+            syn::Type::Path(path) => path.path.segments.last()?.ident.clone(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(ident.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harvest_core::Id;
+    use harvest_core::fs::RawDir;
+    use quantize_rust_spans::{ItemKind, RustItem};
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    const LIB: &str = "\
+pub struct Point {
+    pub x: u8,
+}
+
+pub fn shift(p: &Point) -> i32 {
+    p.x + 1i32
+}
+
+pub fn unrelated(a: i32) -> i32 {
+    a * 2
+}
+";
+
+    #[test]
+    fn context_leads_with_the_definitions_the_errors_mention() {
+        let mut dir = RawDir::default();
+        dir.set_file("src/lib.rs", LIB.as_bytes().to_vec()).unwrap();
+        let cargo_package = CargoPackage { dir };
+        let file = PathBuf::from("src/lib.rs");
+        let item = |code: &str| {
+            let start = LIB.find(code).unwrap();
+            let end = start + LIB[start..].find("\n}\n").unwrap() + 2;
+            (file.clone(), start..end)
+        };
+        let [point, shift, unrelated] =
+            [item("pub struct"), item("pub fn shift"), item("pub fn u")];
+        let item_map = RustItemMap {
+            cargo_pkg_idx: Id::new(),
+            items: HashMap::from([(
+                file.clone(),
+                [
+                    (ItemKind::Struct, &point),
+                    (ItemKind::Fn, &shift),
+                    (ItemKind::Fn, &unrelated),
+                ]
+                .map(|(kind, (_, range))| RustItem {
+                    kind,
+                    name: None,
+                    range: range.clone(),
+                    children: Vec::new(),
+                })
+                .to_vec(),
+            )]),
+            definitions: HashMap::from([
+                ("Point".to_string(), vec![point.clone()]),
+                ("x".to_string(), vec![point.clone()]),
+                ("shift".to_string(), vec![shift.clone()]),
+                ("unrelated".to_string(), vec![unrelated.clone()]),
+            ]),
+        };
+        let group = FixGroup {
+            items: vec![shift],
+            messages: vec!["mismatched types".into()],
+            names: vec!["p".into(), "x".into(), "Point".into()],
+            codes: BTreeSet::new(),
+        };
+
+        let context = ErrorContext::new(&item_map, &cargo_package, None, None, None, 1000);
+        assert_eq!(
+            context.for_group(&group),
+            "DEFINITIONS OF NAMES IN THE ERRORS:\npub struct Point {\n    pub x: u8,\n}\n\n\
+             OTHER DECLARATIONS (function bodies stubbed):\n\
+             pub fn unrelated(a: i32) -> i32 { todo!() }"
+        );
+
+        let context = ErrorContext::new(&item_map, &cargo_package, None, None, None, 10);
+        assert_eq!(
+            context.for_group(&group),
+            "DEFINITIONS OF NAMES IN THE ERRORS:\npub struct Point {\n    pub x: u8,\n}"
+        );
+    }
+}
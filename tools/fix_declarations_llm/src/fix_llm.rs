@@ -11,10 +11,18 @@ pub struct Config {
     #[serde(flatten)]
     pub llm: LLMConfig,
 
+    /// Approximate token budget of the reference context of a repair request.
+    #[serde(default = "default_context_token_budget")]
+    pub context_token_budget: usize,
+
     #[serde(flatten)]
     unknown: HashMap<String, Value>,
 }
 
+fn default_context_token_budget() -> usize {
+    8000
+}
+
 impl Config {
     pub fn validate(&self) {
         unknown_field_warning("tools.fix_declarations_llm", &self.unknown);
@@ -25,7 +33,15 @@ impl Config {
 
 #[derive(Debug, Deserialize)]
 struct FixResult {
-    fixed_code: String,
+    fixes: Vec<ItemFix>,
+}
+
+/// The new source of one of the items given to [`FixLlm::fix_items`].
+#[derive(Debug, Deserialize)]
+pub struct ItemFix {
+    /// The 1-based number of the item.
+    pub item: usize,
+    pub fixed_code: String,
 }
 
 pub struct FixLlm {
@@ -40,16 +56,25 @@ impl FixLlm {
         Ok(FixLlm { llm })
     }
 
-    pub fn fix_declaration(
+    /// Asks for fixes of `errors_text`, which may need changes to any of `items`, given as where
+    /// they are (their file, and the item they are in if any) and their source. Returns the items
+    /// to change with their new source.
+    pub fn fix_items(
         &self,
-        decl_source: &str,
+        items: &[(String, &str)],
         errors_text: &str,
         context: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<ItemFix>, Box<dyn std::error::Error>> {
+        let items_text = items
+            .iter()
+            .enumerate()
+            .map(|(i, (location, source))| format!("ITEM {} ({location}):\n{source}", i + 1))
+            .collect::<Vec<_>>()
+            .join("\n\n");
         let prompt = include_str!("prompts/fix/user_prompt.txt")
             .replace("{context}", context)
             .replace("{errors}", errors_text)
-            .replace("{declaration}", decl_source);
+            .replace("{items}", &items_text);
 
         let messages = vec![ChatMessage::user().content(&prompt).build()];
         let (response, _usage) = self.llm.invoke(&messages)?;
@@ -57,6 +82,13 @@ impl FixLlm {
             format!("Failed to parse fix LLM response as JSON: {e}\nResponse: {response}")
         })?;
 
-        Ok(result.fixed_code.trim_end_matches('\n').to_string())
+        Ok(result
+            .fixes
+            .into_iter()
+            .map(|fix| ItemFix {
+                item: fix.item,
+                fixed_code: fix.fixed_code.trim_end_matches('\n').to_string(),
+            })
+            .collect())
     }
 }
@@ -1,5 +1,3 @@
-use full_source::CargoPackage;
-use quantize_rust_spans::RustItemMap;
 use syn::spanned::Spanned;
 
 // Stub helpers (for interface context)
@@ -30,7 +28,8 @@ fn stub_item(item: syn::Item, source: &[u8]) -> String {
     String::from_utf8(source).unwrap()
 }
 
-fn stub_declaration(source: &[u8]) -> Option<String> {
+/// `source` with the bodies of its functions and methods replaced by `todo!()`.
+pub(crate) fn stub_declaration(source: &[u8]) -> Option<String> {
     let sstr = str::from_utf8(source).ok()?;
     let Ok(file) = syn::parse_file(sstr) else {
         return Some(sstr.trim_end_matches('\n').to_string());
@@ -43,20 +42,6 @@ fn stub_declaration(source: &[u8]) -> Option<String> {
         .into()
 }
 
-pub(crate) fn get_interface_ctx(item_map: &RustItemMap, cargo_package: &CargoPackage) -> String {
-    item_map
-        .items
-        .iter()
-        .flat_map(|(file_name, irs)| Some((cargo_package.dir.get_file(file_name).ok()?, irs)))
-        .flat_map(|(src, item_ranges)| {
-            item_ranges
-                .iter()
-                .flat_map(|r| stub_declaration(&src[r.clone()]))
-        })
-        .collect::<Vec<_>>()
-        .join("\n\n")
-}
-
 #[cfg(test)]
 mod tests {
     #[test]
@@ -1,21 +1,95 @@
 //! `FixDeclarationsLlm`: calls the LLM to repair declarations that have compiler errors,
-//! producing an updated `SplitPackage` with fixed declarations and a recomputed line index.
+//! producing the fixed declarations, which `ApplyDeclarationFixes` splices into the package.
+//! An error is fixed together with the declarations it points to or mentions, such as the
+//! struct whose field type causes a mismatch, so one response can patch all of them.
+//! Layout mismatches found by `check_layout`, symbol mismatches found by `check_abi` and the
+//! warnings of `try_cargo_clippy` are repaired the same way, as are the functions whose results
+//! differ from those of the C functions in the differential tests of `run_difftest`, and the
+//! programs that print the wrong output for the test vectors of `run_test_vectors`.
+//! A fix that does not parse as items is rejected, and the fixes of a file are rolled back if the
+//! file no longer parses with them, so that later passes keep its item structure. The rejected
+//! fixes are kept in the [`DeclarationFixes`] with the reason.
 
+use attribution::Finding;
+use c_ast::RichSourceMap;
+use check_abi::AbiCheckResult;
+use check_layout::LayoutCheckResult;
+use context::ErrorContext;
 use full_source::CargoPackage;
 use harvest_core::tools::{RunContext, Tool};
 use harvest_core::{Id, Representation};
+use name_map::{NameKind, NameMap};
+use provenance_map::ProvenanceMap;
 use quantize_rust_spans::RustItemMap;
+use run_difftest::DiffTestResult;
+use run_test_vectors::TestVectorResult;
 use serde::Deserialize;
-use tracing::info;
-use try_cargo_build::CargoBuildResult;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use tracing::{info, warn};
+use try_cargo_build::{CargoBuildResult, CargoClippyResult};
 
 mod attribution;
+mod context;
 mod fix_llm;
 mod interface_ctx;
 mod patches;
 
-/// Calls the LLM to fix each declaration that has compiler errors, and returns a new
-/// `SplitPackage` with updated declarations and a freshly computed line index.
+pub use patches::{PatchSet, RejectedPatch};
+
+/// The fixes `FixDeclarationsLlm` made to the declarations of a package, and those it rejected.
+#[derive(Debug, Clone)]
+pub struct DeclarationFixes {
+    /// The [`CargoPackage`] the fixes are for.
+    pub cargo_pkg_idx: Id,
+    /// The fixes to apply, by file. The fixes of each file are known to keep it parsable.
+    pub fixes: BTreeMap<PathBuf, PatchSet>,
+    /// The fixes that were not applied, with the reason.
+    pub rejected: Vec<RejectedPatch>,
+}
+
+impl DeclarationFixes {
+    /// How many declarations the fixes replace.
+    pub fn applied(&self) -> usize {
+        self.fixes.values().map(BTreeMap::len).sum()
+    }
+}
+
+impl fmt::Display for DeclarationFixes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "DeclarationFixes: {} fixes, {} rejected",
+            self.applied(),
+            self.rejected.len()
+        )?;
+        for (file, patches) in &self.fixes {
+            for ((start, end), code) in patches {
+                writeln!(f, "  {} {start}..{end}:\n{code}", file.display())?;
+            }
+        }
+        for r in &self.rejected {
+            writeln!(
+                f,
+                "  rejected {} {:?}: {}",
+                r.file.display(),
+                r.range,
+                r.reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for DeclarationFixes {
+    fn name(&self) -> &'static str {
+        "declaration_fixes"
+    }
+}
+
+/// Calls the LLM to fix each declaration that has compiler errors, and returns the
+/// [`DeclarationFixes`] to apply and those rejected.
 pub struct FixDeclarationsLlm;
 
 impl Tool for FixDeclarationsLlm {
@@ -23,6 +97,14 @@ impl Tool for FixDeclarationsLlm {
         "fix_declarations_llm"
     }
 
+    /// Inputs:
+    /// 1. [`RustItemMap`] id -- the declarations of the package to fix.
+    /// 2. [`CargoBuildResult`] id -- the errors to fix.
+    /// 3. Any number of [`LayoutCheckResult`], [`AbiCheckResult`], [`CargoClippyResult`],
+    ///    [`DiffTestResult`] or [`TestVectorResult`] ids -- further errors, warnings or wrong
+    ///    results to fix --
+    ///    and optionally a [`RichSourceMap`], a [`NameMap`] and a [`ProvenanceMap`] id, whose C
+    ///    source of the declarations to fix goes in the repair context.
     fn run(
         self: Box<Self>,
         context: RunContext,
@@ -42,39 +124,174 @@ impl Tool for FixDeclarationsLlm {
             .get::<RustItemMap>(inputs[0])
             .ok_or("DiagnosticAttributor: no RustItemMap found in IR")?;
 
-        let mut cargo_package = context
+        let cargo_package = context
             .ir_snapshot
             .get::<CargoPackage>(item_map.cargo_pkg_idx)
-            .ok_or("DiagnosticAttributor: no CargoPackage found in IR")?
-            .clone();
+            .ok_or("DiagnosticAttributor: no CargoPackage found in IR")?;
 
         let build_result = context
             .ir_snapshot
             .get::<CargoBuildResult>(inputs[1])
             .ok_or("DiagnosticAttributor: no CargoBuildResult found in IR")?;
 
-        // Group compiler errors by enclosing declaration so each declaration can be fixed once
-        let decl_errors = attribution::attribute_errors(build_result, item_map, &cargo_package)?;
+        let mut diagnostics = attribution::get_error_diagnostics(build_result);
+        let mut findings = Vec::new();
+        let mut difftests = Vec::new();
+        let mut c_ast = None;
+        let mut names = None;
+        let mut provenance = None;
+        for id in inputs.iter().skip(2) {
+            if let Some(layout) = context.ir_snapshot.get::<LayoutCheckResult>(*id) {
+                findings.extend(layout.mismatches.iter().map(|m| Finding {
+                    location: m.location.as_ref(),
+                    message: m.message(),
+                }));
+            } else if let Some(abi) = context.ir_snapshot.get::<AbiCheckResult>(*id) {
+                findings.extend(abi.problems.iter().map(|p| Finding {
+                    location: p.location.as_ref(),
+                    message: p.message(),
+                }));
+            } else if let Some(clippy) = context.ir_snapshot.get::<CargoClippyResult>(*id) {
+                diagnostics.extend(clippy.warnings());
+            } else if let Some(difftest) = context.ir_snapshot.get::<DiffTestResult>(*id) {
+                difftests.push(difftest);
+            } else if let Some(vectors) = context.ir_snapshot.get::<TestVectorResult>(*id) {
+                // Wrong output is fixed starting from `main`; the context shows what it calls.
+                findings.extend(vectors.failures.iter().map(|f| Finding {
+                    location: item_map.definition("main"),
+                    message: f.message(),
+                }));
+            } else if let Some(map) = context.ir_snapshot.get::<RichSourceMap>(*id) {
+                c_ast = Some(map);
+            } else if let Some(map) = context.ir_snapshot.get::<NameMap>(*id) {
+                names = Some(map);
+            } else if let Some(map) = context.ir_snapshot.get::<ProvenanceMap>(*id) {
+                // The map may be of an earlier version of the package.
+                provenance = Some(map.rebase(item_map, cargo_package));
+            } else {
+                return Err(
+                    "DiagnosticAttributor: no LayoutCheckResult, AbiCheckResult, CargoClippyResult, \
+                     DiffTestResult, TestVectorResult, RichSourceMap, NameMap or ProvenanceMap \
+                     found in IR"
+                        .into(),
+                );
+            }
+        }
+
+        // A function that returns the wrong result is fixed where the Rust function of the same
+        // name, or of the Rust name of the C function, is defined.
+        for failure in difftests.iter().flat_map(|d| d.mismatches()) {
+            let rust_name = names.map_or(failure.function.as_str(), |names| {
+                names.rust_name(NameKind::Function, &failure.function)
+            });
+            findings.push(Finding {
+                location: item_map.definition(rust_name),
+                message: format!(
+                    "differential test {} of `{}`: {}",
+                    failure.test, failure.function, failure.message
+                ),
+            });
+        }
+
+        // Group compiler errors with the declarations they involve, so that each declaration is
+        // fixed once, together with the other declarations its errors need changed
+        let groups = attribution::attribute_errors(diagnostics, findings, item_map, cargo_package);
 
-        // Build declaration-only context (with stubbed bodies) to guide LLM fixes
-        let interface_ctx = interface_ctx::get_interface_ctx(item_map, &cargo_package);
+        // Build the context of each group from its errors to guide LLM fixes
+        let error_ctx = ErrorContext::new(
+            item_map,
+            cargo_package,
+            c_ast,
+            names,
+            provenance,
+            config.context_token_budget,
+        );
 
-        // Use the LLM to generate patches
-        let (mut fixes, fixed_count) =
-            patches::generate_patches(&decl_errors, &cargo_package, &fix_llm, &interface_ctx)?;
+        // Use the LLM to generate patches, leaving out those that do not parse as items
+        let mut patches =
+            patches::generate_patches(&groups, item_map, cargo_package, &fix_llm, &error_ctx)?;
 
-        // Apply all generated patches into source files
-        for (file_name, patch_set) in fixes.drain() {
-            let source = cargo_package.dir.get_file_mut(&file_name)?;
-            patches::apply_patches(source, patch_set);
+        // Check the patches of each file, rejecting them all if the file no longer parses
+        let mut fixes = BTreeMap::new();
+        for (file_name, patch_set) in patches.by_file {
+            let mut source = cargo_package.dir.get_file(&file_name)?.to_vec();
+            let ranges: Vec<_> = patch_set.keys().map(|&(start, end)| start..end).collect();
+            match patches::apply_patches_checked(&mut source, patch_set.clone()) {
+                Ok(()) => {
+                    fixes.insert(file_name, patch_set);
+                }
+                Err(reason) => {
+                    warn!(
+                        "FixDeclarationsLlm: rolled back the fixes of {}: {reason}",
+                        file_name.display()
+                    );
+                    patches
+                        .rejected
+                        .extend(ranges.into_iter().map(|range| RejectedPatch {
+                            file: file_name.clone(),
+                            range,
+                            reason: reason.clone(),
+                        }));
+                }
+            }
         }
+        let fixes = DeclarationFixes {
+            cargo_pkg_idx: item_map.cargo_pkg_idx,
+            fixes,
+            rejected: patches.rejected,
+        };
 
         info!(
-            "FixDeclarationsLlm: Applied fixes to {}/{} declarations",
-            fixed_count,
-            decl_errors.len()
+            "FixDeclarationsLlm: Fixed {} declarations for {} groups of errors",
+            fixes.applied(),
+            groups.len()
         );
+        if !fixes.rejected.is_empty() {
+            warn!(
+                "FixDeclarationsLlm: {} fixes rejected:\n{}",
+                fixes.rejected.len(),
+                fixes
+                    .rejected
+                    .iter()
+                    .map(|r| format!("  {} {:?}: {}", r.file.display(), r.range, r.reason))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+
+        Ok(Box::new(fixes))
+    }
+}
+
+/// Applies the [`DeclarationFixes`] of `FixDeclarationsLlm` to their package.
+pub struct ApplyDeclarationFixes;
+
+impl Tool for ApplyDeclarationFixes {
+    fn name(&self) -> &'static str {
+        "apply_declaration_fixes"
+    }
 
+    /// Inputs:
+    /// 1. [`DeclarationFixes`] id -- the fixes and, through them, the package to apply them to.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let fixes = context
+            .ir_snapshot
+            .get::<DeclarationFixes>(inputs[0])
+            .ok_or("apply_declaration_fixes: no DeclarationFixes in IR")?;
+        let mut cargo_package = context
+            .ir_snapshot
+            .get::<CargoPackage>(fixes.cargo_pkg_idx)
+            .ok_or("apply_declaration_fixes: no CargoPackage in IR")?
+            .clone();
+        for (file_name, patch_set) in &fixes.fixes {
+            let source = cargo_package.dir.get_file_mut(file_name)?;
+            patches::apply_patches(source, patch_set.clone());
+        }
+        info!("apply_declaration_fixes: applied {} fixes", fixes.applied());
         Ok(Box::new(cargo_package))
     }
 }
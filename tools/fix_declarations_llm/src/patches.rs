@@ -1,12 +1,99 @@
-use cargo_metadata::diagnostic::Diagnostic;
+use crate::attribution::{FixGroup, ItemSpan};
+use crate::context::ErrorContext;
 use full_source::CargoPackage;
-use std::collections::{BTreeMap, HashMap};
-use std::ops::Bound;
+use quantize_rust_spans::{ItemKind, RustItem, RustItemMap};
+use std::collections::BTreeMap;
+use std::ops::Range;
 use std::path::PathBuf;
 use tracing::{info, warn};
 
-pub(crate) type PatchSet = BTreeMap<(usize, usize), String>;
-pub(crate) type ErrorSpan = (PathBuf, Bound<usize>, Bound<usize>);
+/// Replacements of items, keyed by the byte range of the item each replaces.
+pub type PatchSet = BTreeMap<(usize, usize), String>;
+
+/// The patches generated for a set of fix groups.
+#[derive(Default)]
+pub(crate) struct Patches {
+    /// The patches to apply, by file.
+    pub by_file: BTreeMap<PathBuf, PatchSet>,
+    /// The patches that were not applied, with the reason.
+    pub rejected: Vec<RejectedPatch>,
+}
+
+/// A patch the LLM returned that was not applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedPatch {
+    pub file: PathBuf,
+    /// The byte range of the item the patch replaces.
+    pub range: Range<usize>,
+    pub reason: String,
+}
+
+/// Checks that `code`, which replaces an item in `container` (none for a top-level item), parses
+/// as one or more items of that container, so that splicing it in keeps the file parsable: items
+/// of an impl block or trait in one, and any items elsewhere. Inner attributes are only allowed
+/// at the start of the file.
+fn validate_patch(
+    code: &str,
+    container: Option<ItemKind>,
+    at_file_start: bool,
+) -> Result<(), String> {
+    let members = match container {
+        Some(ItemKind::Impl) => {
+            syn::parse_str::<syn::ItemImpl>(&format!("impl Patched {{\n{code}\n}}"))
+                .map(|block| block.items.len())
+        }
+        Some(ItemKind::Trait) => {
+            syn::parse_str::<syn::ItemTrait>(&format!("trait Patched {{\n{code}\n}}"))
+                .map(|block| block.items.len())
+        }
+        _ => {
+            let file =
+                syn::parse_file(code).map_err(|e| format!("does not parse as items: {e}"))?;
+            if !at_file_start && !file.attrs.is_empty() {
+                return Err(
+                    "has inner attributes, which only the start of a file may have".to_string(),
+                );
+            }
+            Ok(file.items.len())
+        }
+    };
+    let kind = match container {
+        Some(ItemKind::Impl) => "items of an impl block",
+        Some(ItemKind::Trait) => "items of a trait",
+        _ => "items",
+    };
+    match members {
+        Err(e) => Err(format!("does not parse as {kind}: {e}")),
+        Ok(0) => Err("contains no item".to_string()),
+        Ok(_) => Ok(()),
+    }
+}
+
+/// The item containing `item` in `item_map`, if `item` is nested in one.
+fn container<'a>(item_map: &'a RustItemMap, (file, range): &ItemSpan) -> Option<&'a RustItem> {
+    let mut enclosing = item_map.enclosing_items(file, range);
+    if enclosing.last().is_some_and(|item| item.range == *range) {
+        enclosing.pop();
+    }
+    enclosing.pop()
+}
+
+/// Applies `patches` to `source` like [`apply_patches`], unless `source` parses and would not
+/// after them, in which case `source` is left as it was and the reason is returned.
+pub(crate) fn apply_patches_checked(source: &mut Vec<u8>, patches: PatchSet) -> Result<(), String> {
+    let parse = |source: &[u8]| -> Result<(), String> {
+        let source = str::from_utf8(source).map_err(|e| e.to_string())?;
+        syn::parse_file(source).map(drop).map_err(|e| e.to_string())
+    };
+    let parsed = parse(source).is_ok();
+    let original = source.clone();
+    apply_patches(source, patches);
+    if parsed && let Err(e) = parse(source) {
+        *source = original;
+        return Err(format!("the patched file does not parse: {e}"));
+    }
+    Ok(())
+}
 
 /// Apply a set of non-overlapping byte-range patches to `source` in ascending order.
 ///
@@ -25,55 +112,110 @@ pub(crate) fn apply_patches(source: &mut Vec<u8>, patches: PatchSet) {
     }
 }
 
+/// Asks the LLM to fix the errors of each group, and returns the patches to the items it fixed,
+/// leaving out those that do not parse as items of the items' containers in `item_map`.
 pub(crate) fn generate_patches(
-    decl_errors: &HashMap<ErrorSpan, Vec<Diagnostic>>,
+    groups: &[FixGroup],
+    item_map: &RustItemMap,
     cargo_package: &CargoPackage,
     fix_llm: &crate::fix_llm::FixLlm,
-    interface_ctx: &str,
-) -> Result<(HashMap<PathBuf, PatchSet>, usize), Box<dyn std::error::Error>> {
-    let mut fixed_count = 0usize;
-    let mut fixes: HashMap<PathBuf, PatchSet> = HashMap::new();
+    context: &ErrorContext,
+) -> Result<Patches, Box<dyn std::error::Error>> {
+    let mut patches = Patches::default();
 
-    for ((file_name, start, end), diagnostics) in decl_errors {
-        let source = cargo_package.dir.get_file(file_name)?;
-        let decl_source = str::from_utf8(&source[(*start, *end)])?;
-        let errors_text = diagnostics
+    for group in groups {
+        let containers: Vec<_> = group
+            .items
             .iter()
-            .map(|d| d.message.clone())
-            .collect::<Vec<_>>()
-            .join("\n\n");
+            .map(|item| container(item_map, item))
+            .collect();
+        let mut items = Vec::with_capacity(group.items.len());
+        for ((file_name, range), container) in group.items.iter().zip(&containers) {
+            let source = cargo_package.dir.get_file(file_name)?;
+            let location = match container {
+                Some(container) => {
+                    format!("{}, in the {}", file_name.display(), container.describe())
+                }
+                None => file_name.display().to_string(),
+            };
+            items.push((location, str::from_utf8(&source[range.clone()])?));
+        }
+        let errors_text = group.messages.join("\n\n");
 
-        info!(
-            "FixDeclarationsLlm: LLM input declaration:\n{}",
-            decl_source
-        );
-        match fix_llm.fix_declaration(decl_source, &errors_text, interface_ctx) {
-            Ok(fixed) if !fixed.is_empty() => {
-                info!("FixDeclarationsLlm: LLM output:\n{}", fixed);
-                let start = match start {
-                    Bound::Included(i) => *i,
-                    Bound::Excluded(i) => i + 1,
-                    Bound::Unbounded => 0,
-                };
-                let end = match end {
-                    Bound::Included(i) => i + 1,
-                    Bound::Excluded(i) => *i,
-                    Bound::Unbounded => source.len(),
-                };
-                fixes
-                    .entry(file_name.clone())
-                    .or_default()
-                    .insert((start, end), fixed);
-                fixed_count += 1;
+        for (location, decl_source) in &items {
+            info!("FixDeclarationsLlm: LLM input declaration ({location}):\n{decl_source}");
+        }
+        let item_fixes = match fix_llm.fix_items(&items, &errors_text, &context.for_group(group)) {
+            Ok(item_fixes) => item_fixes,
+            Err(e) => {
+                warn!("FixDeclarationsLlm: LLM fix failed for declaration: {}", e);
+                continue;
             }
-            Ok(_) => {
+        };
+        for fix in item_fixes {
+            let Some(i) = fix.item.checked_sub(1).filter(|&i| i < group.items.len()) else {
+                warn!("FixDeclarationsLlm: LLM fixed unknown item {}", fix.item);
+                continue;
+            };
+            if fix.fixed_code.is_empty() {
                 warn!("FixDeclarationsLlm: LLM returned empty response for declaration",);
+                continue;
             }
-            Err(e) => {
-                warn!("FixDeclarationsLlm: LLM fix failed for declaration: {}", e);
+            info!("FixDeclarationsLlm: LLM output:\n{}", fix.fixed_code);
+            let (file_name, range) = &group.items[i];
+            let container = containers[i].map(|container| container.kind);
+            if let Err(reason) = validate_patch(&fix.fixed_code, container, range.start == 0) {
+                warn!(
+                    "FixDeclarationsLlm: rejected the fix of {} at {range:?}: {reason}",
+                    file_name.display()
+                );
+                patches.rejected.push(RejectedPatch {
+                    file: file_name.clone(),
+                    range: range.clone(),
+                    reason,
+                });
+                continue;
             }
+            patches
+                .by_file
+                .entry(file_name.clone())
+                .or_default()
+                .insert((range.start, range.end), fix.fixed_code);
         }
     }
 
-    Ok((fixes, fixed_count))
+    Ok(patches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches_must_keep_the_file_parsable() {
+        assert!(validate_patch("fn a() {}\n\nstruct B;", None, false).is_ok());
+        assert!(validate_patch("#![allow(dead_code)]\nfn a() {}", None, true).is_ok());
+        assert!(validate_patch("#![allow(dead_code)]\nfn a() {}", None, false).is_err());
+        assert!(validate_patch("// only a comment", None, false).is_err());
+        assert!(validate_patch("fn a() {", None, false).is_err());
+        // The members of an impl block or trait are checked as such.
+        let method = "fn norm(&self) -> i32 { self.x }";
+        assert!(validate_patch(method, Some(ItemKind::Impl), false).is_ok());
+        assert!(validate_patch("fn area(&self) -> i32;", Some(ItemKind::Trait), false).is_ok());
+        let block = "impl Point { fn norm(&self) -> i32 { self.x } }";
+        assert!(validate_patch(block, Some(ItemKind::Impl), false).is_err());
+
+        let original = b"fn a() {}\nfn b() {}\n".to_vec();
+        let mut source = original.clone();
+        let patches = PatchSet::from([((0, 9), "fn a() -> u8 { 1 }".to_string())]);
+        assert_eq!(apply_patches_checked(&mut source, patches), Ok(()));
+        assert_eq!(source, b"fn a() -> u8 { 1 }\nfn b() {}\n");
+
+        // A patch that parses can still break the file, here because its range is not a whole
+        // item; the file is then left as it was.
+        let mut source = original.clone();
+        let patches = PatchSet::from([((0, 5), "fn c() {}".to_string())]);
+        assert!(apply_patches_checked(&mut source, patches).is_err());
+        assert_eq!(source, original);
+    }
 }
@@ -197,6 +197,33 @@ pub(crate) fn is_string_type(ty: &str) -> bool {
     )
 }
 
+/// The `printf` conversion that prints a value of the scalar type `ty`, and the cast the value
+/// needs for it.
+fn scalar_format(ty: &str) -> (&'static str, &'static str) {
+    match ty.trim() {
+        "float" | "double" | "long double" => ("%.17Lg", "long double"),
+        "size_t" | "uint8_t" | "uint16_t" | "uint32_t" | "uint64_t" | "uintptr_t" | "bool"
+        | "_Bool" => ("%llu", "unsigned long long"),
+        ty if ty.starts_with("unsigned") => ("%llu", "unsigned long long"),
+        _ => ("%lld", "long long"),
+    }
+}
+
+/// `text` as a C string literal.
+fn c_string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
 fn generate_difftest_c(tests: &[TestVector], sigs: &HashMap<String, FnSig>) -> String {
     let mut out = TMPL_HEADER.to_string();
 
@@ -218,19 +245,23 @@ fn generate_difftest_c(tests: &[TestVector], sigs: &HashMap<String, FnSig>) -> S
         } else {
             sig.param_types.join(", ")
         };
+        let values: Vec<&str> = (0..sig.param_types.len())
+            .map(|j| test.args.get(j).map(String::as_str).unwrap_or("0"))
+            .collect();
         let arg_decls = sig
             .param_types
             .iter()
+            .zip(&values)
             .enumerate()
-            .map(|(j, ty)| {
-                let val = test.args.get(j).map(String::as_str).unwrap_or("0");
-                format!("    {} arg{j} = {};\n", ty.trim(), val)
-            })
+            .map(|(j, (ty, val))| format!("    {} arg{j} = {};\n", ty.trim(), val))
             .collect::<String>();
         let args = (0..sig.param_types.len())
             .map(|j| format!("arg{j}"))
             .collect::<Vec<_>>()
             .join(", ");
+        // The arguments as written, printed in the report of a failing call.
+        let call_args = c_string_literal(&values.join(", "));
+        let (format, cast) = scalar_format(ret);
 
         let subs = &[
             ("TEST_ID", test_id.as_str()),
@@ -239,6 +270,9 @@ fn generate_difftest_c(tests: &[TestVector], sigs: &HashMap<String, FnSig>) -> S
             ("PARAM_TYPES", param_type_str.as_str()),
             ("ARG_DECLS", arg_decls.as_str()),
             ("ARGS", args.as_str()),
+            ("CALL_ARGS", call_args.as_str()),
+            ("FMT", format),
+            ("CAST", cast),
         ];
 
         if is_void_type(ret) || is_scalar_type(ret) || is_string_type(ret) {
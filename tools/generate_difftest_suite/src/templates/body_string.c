@@ -2,6 +2,10 @@
     const char *rust_result = (const char *)rust_fn({ARGS});
     if (c_result == NULL && rust_result == NULL) { printf("PASS diff_{TEST_ID} {FN_NAME}\n"); (*passed)++; }
     else if (c_result != NULL && rust_result != NULL && strcmp(c_result, rust_result) == 0) { printf("PASS diff_{TEST_ID} {FN_NAME}\n"); (*passed)++; }
-    else { printf("FAIL diff_{TEST_ID} {FN_NAME} mismatch\n"); (*failed)++; }
+    else {
+        printf("FAIL diff_{TEST_ID} {FN_NAME} mismatch: {FN_NAME}(%s) returned ", {CALL_ARGS});
+        print_string(c_result); printf(" in C, "); print_string(rust_result); printf(" in Rust\n");
+        (*failed)++;
+    }
 }
 
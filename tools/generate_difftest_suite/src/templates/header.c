@@ -8,3 +8,8 @@
 static void *c_lib;
 static void *rust_lib;
 
+/* Prints a string result quoted, or NULL. */
+static void print_string(const char *s) {
+    if (s) printf("\"%s\"", s); else printf("NULL");
+}
+
@@ -0,0 +1,113 @@
+//! Deterministic, layout-preserving lowering of C types to Rust, in the manner of bindgen.
+//!
+//! Every struct, union, enum and typedef of the project is lowered from the layout libclang
+//! computed for it (see [`c_ast::RecordLayout`]) rather than from its source text:
+//! - structs and unions become `#[repr(C)]` types whose fields sit at the C offsets, with explicit
+//!   padding, `packed` or `align` wherever `repr(C)` alone would place them differently;
+//! - runs of bit-fields become byte arrays with a getter and setter per bit-field;
+//! - enums become an integer type alias plus one constant per enumerator, so every value the C
+//!   code can store stays representable.
+//!
+//! `modular_translation_llm` uses the lowering in place of, or as a reference for, the LLM's type
+//! translation.
+
+use c_ast::{RichSourceMap, TopLevelEntity};
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use name_map::NameMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tracing::info;
+
+mod lower;
+
+/// The lowering of one type declaration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LoweredType {
+    /// The declared C name, for diagnostics; `None` for an anonymous declaration.
+    pub c_name: Option<String>,
+    /// The Rust definitions, or `None` if the declaration has no exact lowering (a layout libclang
+    /// did not report, a struct both packed and over-aligned, ...).
+    pub rust_code: Option<String>,
+}
+
+/// The lowering of every type declaration of a project, parallel to
+/// [`RichSourceMap::app_types`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoweredTypes {
+    pub types: Vec<LoweredType>,
+}
+
+impl LoweredTypes {
+    /// How many of the declarations were lowered.
+    pub fn lowered(&self) -> usize {
+        self.types.iter().filter(|t| t.rust_code.is_some()).count()
+    }
+}
+
+/// Lowers each of `types`, naming the Rust types and constants after `names`.
+pub fn lower_types(types: &[TopLevelEntity], names: &NameMap) -> LoweredTypes {
+    let lowerer = lower::Lowerer::new(types, names);
+    LoweredTypes {
+        types: types
+            .iter()
+            .map(|entity| LoweredType {
+                c_name: entity.name().map(str::to_string),
+                rust_code: lowerer.lower(entity),
+            })
+            .collect(),
+    }
+}
+
+impl fmt::Display for LoweredTypes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for lowered in &self.types {
+            let c_name = lowered.c_name.as_deref().unwrap_or("<anonymous>");
+            match &lowered.rust_code {
+                Some(code) => writeln!(f, "// {c_name}\n{code}\n")?,
+                None => writeln!(f, "// {c_name}: not lowered\n")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Representation for LoweredTypes {
+    fn name(&self) -> &'static str {
+        "lowered_types"
+    }
+}
+
+pub struct LowerTypes;
+
+impl Tool for LowerTypes {
+    fn name(&self) -> &'static str {
+        "lower_types"
+    }
+
+    /// Inputs:
+    /// 1. [`RichSourceMap`] id -- the type declarations and their layouts.
+    /// 2. [`NameMap`] id (optional) -- the Rust names to use; C names are kept without it.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let map = context
+            .ir_snapshot
+            .get::<RichSourceMap>(inputs[0])
+            .ok_or("lower_types: no RichSourceMap in IR")?;
+        let names = inputs
+            .get(1)
+            .and_then(|id| context.ir_snapshot.get::<NameMap>(*id))
+            .cloned()
+            .unwrap_or_default();
+        let lowered = lower_types(&map.app_types, &names);
+        info!(
+            "Type lowering: {} of {} type declarations lowered",
+            lowered.lowered(),
+            lowered.types.len()
+        );
+        Ok(Box::new(lowered))
+    }
+}
@@ -0,0 +1,933 @@
+//! Lowering of C type layouts to Rust definitions.
+
+use c_ast::{
+    CType, ClangAST, EntityKind, EnumLayout, FieldLayout, IntKind, RecordLayout, TopLevelEntity,
+};
+use name_map::{NameKind, NameMap, is_keyword, upper_camel_case};
+use std::collections::{HashMap, HashSet};
+
+/// Bit-field storage is read and written through a `u128`, so a run of bit-fields sharing one
+/// storage array spans at most this many bytes.
+const MAX_BITFIELD_STORAGE: u64 = 16;
+
+/// Lowers the type declarations of one project.
+pub(crate) struct Lowerer<'a> {
+    names: &'a NameMap,
+    /// Tags (`struct foo`) defined somewhere in the project. Forward declarations of these lower
+    /// to nothing, since the definition provides the type.
+    defined: HashSet<String>,
+    /// Project types, by C spelling, whose lowering is `Copy`. A record derives `Copy` only if all
+    /// its fields are, and a union wraps the fields that are not in `ManuallyDrop`.
+    copy: HashSet<String>,
+}
+
+impl<'a> Lowerer<'a> {
+    pub(crate) fn new(types: &[TopLevelEntity], names: &'a NameMap) -> Self {
+        let declared = || {
+            types
+                .iter()
+                .flat_map(|entity| std::iter::once(entity).chain(&entity.sub_entities))
+        };
+        let defined = declared()
+            .filter_map(|entity| match &entity.ast {
+                Some(ClangAST::RecordDecl {
+                    layout: Some(_), ..
+                })
+                | Some(ClangAST::EnumDecl {
+                    layout: Some(_), ..
+                }) => tag(entity),
+                _ => None,
+            })
+            .collect();
+        let mut lowerer = Lowerer {
+            names,
+            defined,
+            copy: HashSet::new(),
+        };
+
+        // A type is `Copy` once it is lowered and the types it holds are, which takes as many
+        // rounds as types are nested; types that hold themselves never are.
+        let definitions: HashMap<String, CType> = types
+            .iter()
+            .filter(|entity| lowerer.lower(entity).is_some())
+            .flat_map(|entity| std::iter::once(entity).chain(&entity.sub_entities))
+            .filter_map(definition)
+            .collect();
+        loop {
+            let copy: Vec<String> = definitions
+                .iter()
+                .filter(|(name, ty)| !lowerer.copy.contains(*name) && lowerer.is_copy(ty))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if copy.is_empty() {
+                break;
+            }
+            lowerer.copy.extend(copy);
+        }
+        lowerer
+    }
+
+    /// Whether the lowering of `ty` is `Copy`.
+    fn is_copy(&self, ty: &CType) -> bool {
+        match ty {
+            CType::Array { element, .. } => self.is_copy(element),
+            CType::Function { .. } => false,
+            CType::Named { name } => self.copy.contains(name),
+            CType::Record { layout } => layout.fields.iter().all(|f| self.is_copy(&f.ty)),
+            _ => true,
+        }
+    }
+
+    /// The Rust definitions of `entity` and of the tags it encloses, or `None` if any of them has
+    /// no exact lowering.
+    pub(crate) fn lower(&self, entity: &TopLevelEntity) -> Option<String> {
+        let mut items = Vec::new();
+        let typedef = match &entity.ast {
+            Some(ClangAST::TypedefDecl { name, .. }) => Some(name.as_str()),
+            _ => None,
+        };
+        for enclosed in entity
+            .sub_entities
+            .iter()
+            .filter(|e| e.kind != EntityKind::TypedefDecl)
+        {
+            self.tag_decl(enclosed, typedef, &mut items)?;
+        }
+        match entity.ast.as_ref()? {
+            ClangAST::TypedefDecl { name, underlying } => {
+                self.typedef(name, underlying.as_ref()?, &mut items)?
+            }
+            ClangAST::RecordDecl { .. } | ClangAST::EnumDecl { .. } => {
+                self.tag_decl(entity, None, &mut items)?
+            }
+            _ => return None,
+        }
+        Some(items.join("\n\n"))
+    }
+
+    /// Lowers a struct, union or enum declaration. Anonymous ones are lowered where they are
+    /// used, except for the constants of an anonymous enum, which are typed as its `typedef`.
+    fn tag_decl(
+        &self,
+        entity: &TopLevelEntity,
+        typedef: Option<&str>,
+        items: &mut Vec<String>,
+    ) -> Option<()> {
+        match entity.ast.as_ref()? {
+            ClangAST::RecordDecl { layout, .. } => match (tag(entity), layout) {
+                (Some(tag), Some(layout)) => self.record(&self.type_name(&tag), layout, items),
+                (Some(tag), None) if !self.defined.contains(&tag) => {
+                    items.push(format!(
+                        "#[repr(C)]\npub struct {} {{\n    _private: [u8; 0],\n}}",
+                        self.type_name(&tag)
+                    ));
+                    Some(())
+                }
+                _ => Some(()),
+            },
+            ClangAST::EnumDecl { layout, .. } => match (tag(entity), layout) {
+                (Some(tag), Some(layout)) => {
+                    let name = self.type_name(&tag);
+                    let underlying = self.rust_type(&layout.underlying, &name, items)?;
+                    items.push(format!("pub type {name} = {underlying};"));
+                    self.enum_constants(&name, layout, items)
+                }
+                (None, Some(layout)) => {
+                    let ty = match typedef {
+                        Some(typedef) => self.type_name(typedef),
+                        None => self.rust_type(&layout.underlying, "", items)?,
+                    };
+                    self.enum_constants(&ty, layout, items)
+                }
+                (Some(tag), None) if self.defined.contains(&tag) => Some(()),
+                _ => None,
+            },
+            _ => Some(()),
+        }
+    }
+
+    fn typedef(&self, name: &str, underlying: &CType, items: &mut Vec<String>) -> Option<()> {
+        let rust_name = self.type_name(name);
+        match underlying {
+            CType::Record { layout } => self.record(&rust_name, layout, items),
+            // `typedef struct foo foo;` and typedefs the name map merged with their tag.
+            CType::Named { name } if self.type_name(name) == rust_name => Some(()),
+            _ => {
+                let ty = self.rust_type(underlying, &rust_name, items)?;
+                items.push(format!("pub type {rust_name} = {ty};"));
+                Some(())
+            }
+        }
+    }
+
+    fn enum_constants(&self, ty: &str, layout: &EnumLayout, items: &mut Vec<String>) -> Option<()> {
+        let unsigned = signed(&layout.underlying) == Some(false);
+        for constant in &layout.constants {
+            let name = ident(self.names.rust_name(NameKind::Constant, &constant.name));
+            let value = match unsigned {
+                true => (constant.value as u64).to_string(),
+                false => constant.value.to_string(),
+            };
+            items.push(format!("pub const {name}: {ty} = {value};"));
+        }
+        Some(())
+    }
+
+    /// Lowers the struct or union `name`. Anonymous records among its fields are lowered first,
+    /// into `items`, as types named after `name` and the field.
+    fn record(&self, name: &str, layout: &RecordLayout, items: &mut Vec<String>) -> Option<()> {
+        let mut fields = Vec::new();
+        let mut accessors = Vec::new();
+        let mut anonymous = 0;
+        let copy = layout.fields.iter().all(|f| self.is_copy(&f.ty));
+        let mut field_decl = |field: &FieldLayout, items: &mut Vec<String>| {
+            let (field_name, hint) = match &field.name {
+                Some(field_name) => (ident(field_name), upper_camel_case(field_name)),
+                None => {
+                    anonymous += 1;
+                    (format!("anon_{anonymous}"), format!("Anon{anonymous}"))
+                }
+            };
+            let mut ty = self.rust_type(&field.ty, &format!("{name}{hint}"), items)?;
+            // A union field must be `Copy` or not need dropping.
+            if layout.is_union && !self.is_copy(&field.ty) {
+                ty = format!("core::mem::ManuallyDrop<{ty}>");
+            }
+            Some(format!("pub {field_name}: {ty},"))
+        };
+
+        let (repr, keyword) = if layout.is_union {
+            if layout.fields.iter().any(|f| f.bit_width.is_some()) {
+                return None;
+            }
+            let mut size = 0;
+            let mut align = 1;
+            for field in &layout.fields {
+                fields.push(field_decl(field, items)?);
+                size = size.max(field.size);
+                align = align.max(field.align);
+            }
+            let repr = match align.cmp(&layout.align) {
+                std::cmp::Ordering::Less => format!("C, align({})", layout.align),
+                std::cmp::Ordering::Equal => "C".to_string(),
+                std::cmp::Ordering::Greater => return None,
+            };
+            match size
+                .next_multiple_of(align.max(layout.align))
+                .cmp(&layout.size)
+            {
+                std::cmp::Ordering::Less => {
+                    fields.push(format!("pub _padding: [u8; {}],", layout.size))
+                }
+                std::cmp::Ordering::Equal => {}
+                std::cmp::Ordering::Greater => return None,
+            }
+            if fields.is_empty() {
+                return None;
+            }
+            (repr, "union")
+        } else {
+            // An ordinary field off its natural alignment, or aligned beyond the struct, means
+            // the struct is packed; all its padding is then explicit.
+            let packed = layout.fields.iter().any(|f| {
+                f.bit_width.is_none()
+                    && (f.align > layout.align
+                        || !(f.offset_bits / 8).is_multiple_of(f.align.max(1)))
+            });
+            let cap = if packed { layout.align } else { u64::MAX };
+            let mut offset = 0;
+            let mut align = 1;
+            let mut paddings = 0;
+            let mut storages = 0;
+            let mut pad_to = |offset: &mut u64, to: u64, fields: &mut Vec<String>| {
+                paddings += 1;
+                fields.push(format!("pub _padding_{paddings}: [u8; {}],", to - *offset));
+                *offset = to;
+            };
+            let mut i = 0;
+            while i < layout.fields.len() {
+                let field = &layout.fields[i];
+                let start = field.offset_bits / 8;
+                if start < offset {
+                    return None;
+                }
+                if field.bit_width.is_none() {
+                    let field_align = field.align.clamp(1, cap);
+                    if !start.is_multiple_of(field_align) {
+                        return None;
+                    }
+                    if start != offset.next_multiple_of(field_align) {
+                        pad_to(&mut offset, start, &mut fields);
+                    }
+                    fields.push(field_decl(field, items)?);
+                    offset = start + field.size;
+                    align = align.max(field_align);
+                    i += 1;
+                    continue;
+                }
+
+                // A run of consecutive bit-fields shares one storage array.
+                let mut end_bits = field.offset_bits;
+                let mut run = Vec::new();
+                while let Some(field) = layout.fields.get(i) {
+                    let Some(width) = field.bit_width else {
+                        break;
+                    };
+                    let field_end = field.offset_bits + width;
+                    if !run.is_empty() && field_end.div_ceil(8) - start > MAX_BITFIELD_STORAGE {
+                        break;
+                    }
+                    end_bits = end_bits.max(field_end);
+                    run.push((field, width));
+                    i += 1;
+                }
+                let len = end_bits.div_ceil(8) - start;
+                if len == 0 {
+                    continue;
+                }
+                if start != offset {
+                    pad_to(&mut offset, start, &mut fields);
+                }
+                storages += 1;
+                let storage = format!("_bitfield_{storages}");
+                fields.push(format!("pub {storage}: [u8; {len}],"));
+                for (field, width) in run {
+                    let (Some(field_name), true) = (&field.name, width > 0) else {
+                        continue;
+                    };
+                    let ty = self.rust_type(&field.ty, name, items)?;
+                    accessors.push(bitfield_accessors(
+                        field_name,
+                        &ty,
+                        signed(&field.ty),
+                        &storage,
+                        len,
+                        field.offset_bits - start * 8,
+                        width,
+                    ));
+                }
+                offset = start + len;
+            }
+
+            let repr = match align.cmp(&layout.align) {
+                std::cmp::Ordering::Less if packed => return None,
+                std::cmp::Ordering::Less => format!("C, align({})", layout.align),
+                _ if packed && layout.align == 1 => "C, packed".to_string(),
+                _ if packed => format!("C, packed({})", layout.align),
+                _ => "C".to_string(),
+            };
+            match offset.next_multiple_of(layout.align).cmp(&layout.size) {
+                std::cmp::Ordering::Less => pad_to(&mut offset, layout.size, &mut fields),
+                std::cmp::Ordering::Equal => {}
+                std::cmp::Ordering::Greater => return None,
+            }
+            (repr, "struct")
+        };
+
+        let body: String = fields.iter().map(|f| format!("    {f}\n")).collect();
+        let derive = if copy { "#[derive(Clone, Copy)]\n" } else { "" };
+        items.push(format!(
+            "#[repr({repr})]\n{derive}pub {keyword} {name} {{\n{body}}}"
+        ));
+        if !accessors.is_empty() {
+            items.push(format!("impl {name} {{\n{}}}", accessors.join("\n")));
+        }
+        Some(())
+    }
+
+    /// The Rust spelling of `ty`. An anonymous record is lowered into `items` as a type named
+    /// `hint`.
+    fn rust_type(&self, ty: &CType, hint: &str, items: &mut Vec<String>) -> Option<String> {
+        let ty = match ty {
+            CType::Void => "core::ffi::c_void".to_string(),
+            CType::Bool => "bool".to_string(),
+            CType::Int { kind } => int_type(*kind),
+            CType::Float { size: 4 } => "f32".to_string(),
+            CType::Float { size: 8 } => "f64".to_string(),
+            CType::Float { .. } => return None,
+            CType::Pointer { pointee, is_const } => match pointee.as_ref() {
+                CType::Function {
+                    ret,
+                    params,
+                    variadic,
+                } => {
+                    let mut params = params
+                        .iter()
+                        .map(|param| self.rust_type(param, hint, items))
+                        .collect::<Option<Vec<_>>>()?;
+                    if *variadic && !params.is_empty() {
+                        params.push("...".to_string());
+                    }
+                    let ret = match ret.as_ref() {
+                        CType::Void => String::new(),
+                        ret => format!(" -> {}", self.rust_type(ret, hint, items)?),
+                    };
+                    format!("Option<unsafe extern \"C\" fn({}){ret}>", params.join(", "))
+                }
+                pointee => {
+                    let pointee = match pointee {
+                        CType::Opaque { .. } => "core::ffi::c_void".to_string(),
+                        pointee => self.rust_type(pointee, hint, items)?,
+                    };
+                    let mutability = if *is_const { "const" } else { "mut" };
+                    format!("*{mutability} {pointee}")
+                }
+            },
+            CType::Array { element, len } => {
+                let element = self.rust_type(element, hint, items)?;
+                format!("[{element}; {}]", len.unwrap_or(0))
+            }
+            CType::Function { .. } => return None,
+            CType::Named { name } => self.type_name(name),
+            CType::Record { layout } => {
+                self.record(hint, layout, items)?;
+                hint.to_string()
+            }
+            CType::Opaque { size, align, .. } => {
+                let unit = match align {
+                    1 => "u8",
+                    2 => "u16",
+                    4 => "u32",
+                    8 => "u64",
+                    16 => "u128",
+                    _ => return None,
+                };
+                format!("[{unit}; {}]", size / align)
+            }
+        };
+        Some(ty)
+    }
+
+    /// The Rust name of the type the C spelling `c_name` (`struct foo`, `foo_t`) denotes.
+    fn type_name(&self, c_name: &str) -> String {
+        let entry = self
+            .names
+            .entries
+            .iter()
+            .find(|e| e.kind == NameKind::Type && e.c_name == c_name);
+        match entry {
+            Some(entry) => entry.rust_name.clone(),
+            None => ident(c_name.rsplit_once(' ').map_or(c_name, |(_, ident)| ident)),
+        }
+    }
+}
+
+/// The C spelling of the type `entity` defines and the type it stands for; `None` for a forward
+/// declaration or an anonymous tag.
+fn definition(entity: &TopLevelEntity) -> Option<(String, CType)> {
+    match entity.ast.as_ref()? {
+        ClangAST::RecordDecl {
+            layout: Some(layout),
+            ..
+        } => Some((
+            tag(entity)?,
+            CType::Record {
+                layout: Box::new(layout.clone()),
+            },
+        )),
+        ClangAST::EnumDecl {
+            layout: Some(layout),
+            ..
+        } => Some((tag(entity)?, layout.underlying.clone())),
+        ClangAST::TypedefDecl {
+            name,
+            underlying: Some(underlying),
+        } => Some((name.clone(), underlying.clone())),
+        _ => None,
+    }
+}
+
+/// The C spelling of the tag `entity` declares, e.g. `union foo`; `None` if it is anonymous.
+fn tag(entity: &TopLevelEntity) -> Option<String> {
+    let keyword = match &entity.ast {
+        Some(ClangAST::RecordDecl {
+            layout: Some(layout),
+            ..
+        }) if layout.is_union => "union",
+        Some(ClangAST::RecordDecl { .. })
+            if entity.kind == EntityKind::UnionDecl
+                || entity.source_text.trim_start().starts_with("union") =>
+        {
+            "union"
+        }
+        Some(ClangAST::RecordDecl { .. }) => "struct",
+        Some(ClangAST::EnumDecl { .. }) => "enum",
+        _ => return None,
+    };
+    Some(format!("{keyword} {}", entity.name()?))
+}
+
+fn int_type(kind: IntKind) -> String {
+    let ty = match kind {
+        IntKind::Char => "core::ffi::c_char",
+        IntKind::SChar => "core::ffi::c_schar",
+        IntKind::UChar => "core::ffi::c_uchar",
+        IntKind::Short => "core::ffi::c_short",
+        IntKind::UShort => "core::ffi::c_ushort",
+        IntKind::Int => "core::ffi::c_int",
+        IntKind::UInt => "core::ffi::c_uint",
+        IntKind::Long => "core::ffi::c_long",
+        IntKind::ULong => "core::ffi::c_ulong",
+        IntKind::LongLong => "core::ffi::c_longlong",
+        IntKind::ULongLong => "core::ffi::c_ulonglong",
+        IntKind::Fixed { bits, signed } => {
+            return format!("{}{bits}", if signed { 'i' } else { 'u' });
+        }
+        IntKind::Size { signed: true } => "isize",
+        IntKind::Size { signed: false } => "usize",
+    };
+    ty.to_string()
+}
+
+/// Whether the integer type `ty` is signed; `None` if that depends on the target (`char`) or
+/// cannot be told from `ty` alone (a typedef).
+fn signed(ty: &CType) -> Option<bool> {
+    let CType::Int { kind } = ty else {
+        return None;
+    };
+    match kind {
+        IntKind::Char => None,
+        IntKind::SChar | IntKind::Short | IntKind::Int | IntKind::Long | IntKind::LongLong => {
+            Some(true)
+        }
+        IntKind::Fixed { signed, .. } | IntKind::Size { signed } => Some(*signed),
+        _ => Some(false),
+    }
+}
+
+/// `name`, made usable as a Rust identifier.
+fn ident(name: &str) -> String {
+    if is_keyword(name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+/// The getter and setter of bit-field `name`, `width` bits at bit `shift` of the little-endian
+/// storage array `storage` of `len` bytes. Values are sign-extended when `signed`; when that is
+/// unknown, when the type's minimum is below zero.
+fn bitfield_accessors(
+    name: &str,
+    ty: &str,
+    signed: Option<bool>,
+    storage: &str,
+    len: u64,
+    shift: u64,
+    width: u64,
+) -> String {
+    let mask = format!("{:#x}", (1u128 << width) - 1);
+    let rest = 128 - width;
+    let sign_extended = format!("((raw << {rest}) as i128 >> {rest}) as {ty}");
+    let value = match (ty, signed) {
+        ("bool", _) => "raw != 0".to_string(),
+        (_, Some(true)) => sign_extended,
+        (_, Some(false)) => format!("raw as {ty}"),
+        (_, None) => format!("if {ty}::MIN == 0 {{ raw as {ty} }} else {{ {sign_extended} }}"),
+    };
+    let getter = ident(name);
+    format!(
+        "    pub fn {getter}(&self) -> {ty} {{
+        let mut unit = [0u8; 16];
+        unit[..{len}].copy_from_slice(&self.{storage});
+        let raw = (u128::from_le_bytes(unit) >> {shift}) & {mask};
+        {value}
+    }}
+
+    pub fn set_{name}(&mut self, value: {ty}) {{
+        let mut unit = [0u8; 16];
+        unit[..{len}].copy_from_slice(&self.{storage});
+        let unit = (u128::from_le_bytes(unit) & !({mask} << {shift}))
+            | ((value as u128 & {mask}) << {shift});
+        self.{storage}.copy_from_slice(&unit.to_le_bytes()[..{len}]);
+    }}
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::{EnumConstant, SourcePoint, SourceSpan};
+    use name_map::NameEntry;
+
+    fn entity(kind: EntityKind, ast: ClangAST, source_text: &str) -> TopLevelEntity {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset: 0,
+        };
+        TopLevelEntity {
+            kind,
+            source_text: source_text.into(),
+            span: SourceSpan {
+                file: "types.h".into(),
+                start: point.clone(),
+                end: point,
+            },
+            ast: Some(ast),
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn record(
+        name: Option<&str>,
+        layout: Option<RecordLayout>,
+        source_text: &str,
+    ) -> TopLevelEntity {
+        entity(
+            EntityKind::RecordDecl,
+            ClangAST::RecordDecl {
+                name: name.map(Into::into),
+                tag_used: None,
+                layout,
+            },
+            source_text,
+        )
+    }
+
+    fn typedef(name: &str, underlying: CType, source_text: &str) -> TopLevelEntity {
+        entity(
+            EntityKind::TypedefDecl,
+            ClangAST::TypedefDecl {
+                name: name.into(),
+                underlying: Some(underlying),
+            },
+            source_text,
+        )
+    }
+
+    fn int(kind: IntKind) -> CType {
+        CType::Int { kind }
+    }
+
+    fn field(name: &str, ty: CType, offset_bits: u64, size: u64) -> FieldLayout {
+        FieldLayout {
+            name: Some(name.into()),
+            ty,
+            offset_bits,
+            bit_width: None,
+            size,
+            align: size,
+        }
+    }
+
+    fn bitfield(name: &str, offset_bits: u64, width: u64) -> FieldLayout {
+        FieldLayout {
+            bit_width: Some(width),
+            ..field(name, int(IntKind::UInt), offset_bits, 4)
+        }
+    }
+
+    fn names(entries: &[(&str, NameKind, &str)]) -> NameMap {
+        NameMap {
+            entries: entries
+                .iter()
+                .map(|&(c_name, kind, rust_name)| NameEntry {
+                    c_name: c_name.into(),
+                    kind,
+                    rust_name: rust_name.into(),
+                    export_name: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Lowers `types`, checking that each lowering is valid Rust.
+    fn lower(types: &[TopLevelEntity], names: &NameMap) -> Vec<String> {
+        let lowerer = Lowerer::new(types, names);
+        types
+            .iter()
+            .map(|entity| {
+                let code = lowerer.lower(entity).expect("not lowered");
+                if let Err(e) = syn::parse_file(&code) {
+                    panic!("invalid Rust ({e}):\n{code}");
+                }
+                code
+            })
+            .collect()
+    }
+
+    #[test]
+    fn struct_with_bitfields_under_typedef() {
+        let layout = RecordLayout {
+            is_union: false,
+            size: 24,
+            align: 8,
+            fields: vec![
+                field("v", int(IntKind::Int), 0, 4),
+                field(
+                    "next",
+                    CType::Pointer {
+                        pointee: Box::new(CType::Named {
+                            name: "struct node_s".into(),
+                        }),
+                        is_const: false,
+                    },
+                    64,
+                    8,
+                ),
+                bitfield("flag", 128, 1),
+                bitfield("type", 129, 3),
+            ],
+        };
+        let mut node_t = typedef(
+            "node_t",
+            CType::Named {
+                name: "struct node_s".into(),
+            },
+            "typedef struct node_s { .. } node_t",
+        );
+        node_t
+            .sub_entities
+            .push(record(Some("node_s"), Some(layout), "struct node_s { .. }"));
+        let names = names(&[
+            ("node_t", NameKind::Type, "Node"),
+            ("struct node_s", NameKind::Type, "Node"),
+        ]);
+
+        let code = &lower(&[node_t], &names)[0];
+        assert!(code.starts_with(
+            "#[repr(C)]\n#[derive(Clone, Copy)]\npub struct Node {\n    \
+             pub v: core::ffi::c_int,\n    pub next: *mut Node,\n    pub _bitfield_1: [u8; 1],\n}"
+        ));
+        assert!(!code.contains("pub type"));
+        assert!(code.contains("pub fn flag(&self) -> core::ffi::c_uint"));
+        assert!(code.contains("pub fn type_(&self) -> core::ffi::c_uint"));
+        assert!(code.contains("pub fn set_type(&mut self, value: core::ffi::c_uint)"));
+        assert!(code.contains("let raw = (u128::from_le_bytes(unit) >> 1) & 0x7;"));
+    }
+
+    #[test]
+    fn keeps_packing_alignment_and_padding() {
+        let packed = record(
+            Some("header"),
+            Some(RecordLayout {
+                is_union: false,
+                size: 5,
+                align: 1,
+                fields: vec![
+                    field("tag", int(IntKind::Char), 0, 1),
+                    field("len", int(IntKind::Int), 8, 4),
+                ],
+            }),
+            "struct __attribute__((packed)) header { .. }",
+        );
+        let aligned = record(
+            Some("vec"),
+            Some(RecordLayout {
+                is_union: false,
+                size: 16,
+                align: 16,
+                fields: vec![field("x", CType::Float { size: 4 }, 0, 4)],
+            }),
+            "struct __attribute__((aligned(16))) vec { .. }",
+        );
+        let spaced = record(
+            Some("spaced"),
+            Some(RecordLayout {
+                is_union: false,
+                size: 16,
+                align: 4,
+                fields: vec![
+                    field("a", int(IntKind::Char), 0, 1),
+                    field("b", int(IntKind::Char), 64, 1),
+                ],
+            }),
+            "struct spaced { char a; char b __attribute__((aligned(8))); }",
+        );
+
+        let code = lower(&[packed, aligned, spaced], &NameMap::default());
+        assert_eq!(
+            code[0],
+            "#[repr(C, packed)]\n#[derive(Clone, Copy)]\npub struct header {\n    \
+             pub tag: core::ffi::c_char,\n    pub len: core::ffi::c_int,\n}"
+        );
+        assert!(code[1].starts_with("#[repr(C, align(16))]"));
+        assert!(!code[1].contains("_padding"));
+        assert!(code[2].contains(
+            "pub a: core::ffi::c_char,\n    pub _padding_1: [u8; 7],\n    \
+             pub b: core::ffi::c_char,\n    pub _padding_2: [u8; 7],"
+        ));
+    }
+
+    #[test]
+    fn enums_are_integer_aliases_with_constants() {
+        let enumeration = |name: Option<&str>, constants: &[(&str, i64)]| {
+            entity(
+                EntityKind::EnumDecl,
+                ClangAST::EnumDecl {
+                    name: name.map(Into::into),
+                    layout: Some(EnumLayout {
+                        underlying: int(IntKind::UInt),
+                        constants: constants
+                            .iter()
+                            .map(|&(name, value)| EnumConstant {
+                                name: name.into(),
+                                value,
+                            })
+                            .collect(),
+                    }),
+                },
+                "enum { .. }",
+            )
+        };
+        let mut color_t = typedef("color_t", int(IntKind::UInt), "typedef enum { .. } color_t");
+        color_t
+            .sub_entities
+            .push(enumeration(None, &[("red", 0), ("darkGreen", 2)]));
+        let mode = enumeration(Some("mode"), &[("MODE_ALL", 0xffff_ffff)]);
+        let names = names(&[
+            ("color_t", NameKind::Type, "Color"),
+            ("darkGreen", NameKind::Constant, "DARK_GREEN"),
+            ("red", NameKind::Constant, "RED"),
+        ]);
+
+        let code = lower(&[color_t, mode], &names);
+        assert_eq!(
+            code[0],
+            "pub const RED: Color = 0;\n\npub const DARK_GREEN: Color = 2;\n\n\
+             pub type Color = core::ffi::c_uint;"
+        );
+        assert_eq!(
+            code[1],
+            "pub type mode = core::ffi::c_uint;\n\npub const MODE_ALL: mode = 4294967295;"
+        );
+    }
+
+    #[test]
+    fn unions_anonymous_members_and_forward_declarations() {
+        let inner = RecordLayout {
+            is_union: false,
+            size: 8,
+            align: 4,
+            fields: vec![
+                field("lo", int(IntKind::Int), 0, 4),
+                field("hi", int(IntKind::Int), 32, 4),
+            ],
+        };
+        let callback = CType::Pointer {
+            pointee: Box::new(CType::Function {
+                ret: Box::new(CType::Void),
+                params: vec![CType::Pointer {
+                    pointee: Box::new(CType::Void),
+                    is_const: false,
+                }],
+                variadic: false,
+            }),
+            is_const: false,
+        };
+        let value = record(
+            Some("value"),
+            Some(RecordLayout {
+                is_union: true,
+                size: 8,
+                align: 8,
+                fields: vec![
+                    FieldLayout {
+                        name: None,
+                        ..field(
+                            "",
+                            CType::Record {
+                                layout: Box::new(inner),
+                            },
+                            0,
+                            8,
+                        )
+                    },
+                    field("cb", callback, 0, 8),
+                ],
+            }),
+            "union value { .. }",
+        );
+        let opaque = record(Some("handle"), None, "struct handle;");
+        let forward = record(Some("value"), None, "union value;");
+
+        let code = lower(&[value, opaque, forward], &NameMap::default());
+        assert!(code[0].contains("pub struct valueAnon1 {"));
+        assert!(code[0].contains(
+            "pub union value {\n    pub anon_1: valueAnon1,\n    \
+             pub cb: Option<unsafe extern \"C\" fn(*mut core::ffi::c_void)>,\n}"
+        ));
+        assert!(code[0].contains("#[repr(C)]\n#[derive(Clone, Copy)]\npub union value"));
+        assert_eq!(
+            code[1],
+            "#[repr(C)]\npub struct handle {\n    _private: [u8; 0],\n}"
+        );
+        assert_eq!(code[2], "");
+    }
+
+    #[test]
+    fn copy_only_when_every_field_is_copy() {
+        let named = |name: &str| CType::Named { name: name.into() };
+        let point = record(
+            Some("point"),
+            Some(RecordLayout {
+                is_union: false,
+                size: 8,
+                align: 4,
+                fields: vec![
+                    field("x", int(IntKind::Int), 0, 4),
+                    field("y", int(IntKind::Int), 32, 4),
+                ],
+            }),
+            "struct point { .. }",
+        );
+        let point_t = typedef(
+            "point_t",
+            named("struct point"),
+            "typedef struct point point_t",
+        );
+        // `struct blob` is not a type of the project, so its lowering is unknown.
+        let line = record(
+            Some("line"),
+            Some(RecordLayout {
+                is_union: false,
+                size: 24,
+                align: 4,
+                fields: vec![
+                    FieldLayout {
+                        align: 4,
+                        ..field("from", named("point_t"), 0, 8)
+                    },
+                    FieldLayout {
+                        align: 4,
+                        ..field("blob", named("struct blob"), 64, 16)
+                    },
+                ],
+            }),
+            "struct line { .. }",
+        );
+        let shape = record(
+            Some("shape"),
+            Some(RecordLayout {
+                is_union: true,
+                size: 16,
+                align: 4,
+                fields: vec![
+                    FieldLayout {
+                        align: 4,
+                        ..field("at", named("struct point"), 0, 8)
+                    },
+                    FieldLayout {
+                        align: 4,
+                        ..field("blob", named("struct blob"), 0, 16)
+                    },
+                ],
+            }),
+            "union shape { .. }",
+        );
+
+        let code = lower(&[point, point_t, line, shape], &NameMap::default());
+        assert!(code[0].starts_with("#[repr(C)]\n#[derive(Clone, Copy)]\npub struct point"));
+        assert!(code[2].starts_with("#[repr(C)]\npub struct line {"));
+        assert_eq!(
+            code[3],
+            "#[repr(C)]\npub union shape {\n    pub at: point,\n    \
+             pub blob: core::mem::ManuallyDrop<blob>,\n}"
+        );
+    }
+}
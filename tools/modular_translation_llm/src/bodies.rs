@@ -0,0 +1,343 @@
+//! Second half of skeleton-first translation: fills in the `todo!()` bodies of a skeleton crate
+//! one function at a time, keeping each body only if the crate still passes `cargo check`.
+
+use analyze_unsafe_constructs::UnsafeConstructReport;
+use build_config::BuildConfigIR;
+use build_project_spec::{ProjectKind, ProjectSpec};
+use c_ast::{RichSourceMap, TopLevelEntity};
+use cargo_metadata::diagnostic::DiagnosticLevel;
+use full_source::CargoPackage;
+use harvest_core::cargo_utils::CargoToml;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use name_map::{NameKind, NameMap};
+use std::ops::Range;
+use std::path::PathBuf;
+use syn::spanned::Spanned;
+use tracing::{info, warn};
+use try_cargo_build::{CompilerMessage, cargo_check};
+
+use crate::memory::TranslationMemory;
+use crate::modules::ModuleLayout;
+use crate::translation_llm::ModularTranslationLLM;
+
+/// Translates the function bodies of a skeleton crate produced by
+/// [`ModularSkeletonLlm`](crate::ModularSkeletonLlm), against its frozen interface.
+///
+/// Inputs: the skeleton [`CargoPackage`], the [`RichSourceMap`], the [`ProjectSpec`], the
+/// [`BuildConfigIR`] and, optionally, an [`UnsafeConstructReport`] and a [`NameMap`]. Each body
+/// is kept only if it does not add `cargo check` errors; a function whose body never does stays a
+/// `todo!()` stub.
+pub struct TranslateBodiesLlm;
+
+impl Tool for TranslateBodiesLlm {
+    fn name(&self) -> &'static str {
+        "translate_bodies_llm"
+    }
+
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let config = crate::load_config(&context)?;
+
+        let mut package = context
+            .ir_snapshot
+            .get::<CargoPackage>(inputs[0])
+            .ok_or("No CargoPackage representation found in IR")?
+            .clone();
+        let clang_ast = context
+            .ir_snapshot
+            .get::<RichSourceMap>(inputs[1])
+            .ok_or("No RichSourceMap representation found in IR")?;
+        let project_kind = &context
+            .ir_snapshot
+            .get::<ProjectSpec>(inputs[2])
+            .ok_or("No ProjectSpec representation found in IR")?
+            .kind;
+        let build_cfg = context
+            .ir_snapshot
+            .get::<BuildConfigIR>(inputs[3])
+            .ok_or("No BuildConfigIR representation found in IR")?;
+        let unsafe_report = crate::optional_input::<UnsafeConstructReport>(&context, &inputs);
+        let names = crate::optional_input::<NameMap>(&context, &inputs)
+            .cloned()
+            .unwrap_or_default();
+
+        let modular_llm = ModularTranslationLLM::build(&config, build_cfg, names.clone())?;
+        let memory = TranslationMemory::open(&config, project_kind, build_cfg);
+        let layout = ModuleLayout::new(clang_ast, project_kind);
+
+        // The interface every body is written against: the skeleton as it is now.
+        let interface: Vec<(String, String)> = package
+            .dir
+            .files_recursive()
+            .into_iter()
+            .filter(|(path, _)| path.extension().is_some_and(|e| e == "rs") || path == "Cargo.toml")
+            .map(|(path, contents)| {
+                (
+                    path.display().to_string(),
+                    String::from_utf8_lossy(contents).into_owned(),
+                )
+            })
+            .collect();
+
+        // Bodies are checked in a copy of the package, updated as they are accepted.
+        let check_dir = tempfile::tempdir()?;
+        package.materialize(check_dir.path())?;
+        let mut cargo = CargoToml::open(&check_dir.path().join("Cargo.toml"))?;
+        cargo.add_workspace();
+        cargo.save()?;
+        let baseline = error_count(&cargo_check(check_dir.path())?.1);
+        if baseline > 0 {
+            warn!("Skeleton has {baseline} cargo check errors; translating bodies anyway");
+        }
+
+        let functions = &clang_ast.app_functions;
+        let mut translated = 0;
+        for function in functions {
+            let Some(name) = function.name() else {
+                continue;
+            };
+            let name = names.rust_name(NameKind::Function, name);
+            let candidates = candidate_files(&package, &layout, function, project_kind);
+            let Some((file, stub)) = candidates.into_iter().find_map(|file| {
+                let source = std::str::from_utf8(package.dir.get_file(&file).ok()?).ok()?;
+                find_stub(source, name).map(|stub| (file, stub))
+            }) else {
+                warn!("No `todo!()` stub found for `{name}`, skipping its body");
+                continue;
+            };
+            let original = package.dir.get_file(&file)?.to_vec();
+            let original_str = String::from_utf8(original.clone())?;
+            let signature = &original_str[stub.item.clone()];
+            let unsafe_constructs = unsafe_report
+                .and_then(|report| report.for_entity(function))
+                .map(|analysis| analysis.prompt_notes())
+                .unwrap_or_default();
+
+            // A remembered body is checked like a fresh one, since the crate around it may have
+            // changed; if it fails, its errors go to the first LLM attempt.
+            let key = memory.as_ref().map(|memory| {
+                let relevant = names.relevant([function.source_text.as_str()]);
+                let mut dependencies = vec![
+                    signature.to_string(),
+                    serde_json::to_string(&relevant).unwrap_or_default(),
+                ];
+                dependencies.extend(unsafe_constructs.iter().cloned());
+                memory.key("body", &function.source_text, &dependencies)
+            });
+            let mut recalled: Option<String> = memory
+                .as_ref()
+                .zip(key.as_deref())
+                .and_then(|(memory, key)| memory.get(key));
+            let attempts = config.max_body_attempts + usize::from(recalled.is_some());
+
+            let mut previous_errors: Option<String> = None;
+            let mut accepted = false;
+            for attempt in 1..=attempts {
+                let rust_code = match recalled.take() {
+                    Some(rust_code) => rust_code,
+                    None => match modular_llm.translate_body(
+                        function,
+                        signature,
+                        &interface,
+                        unsafe_constructs.clone(),
+                        previous_errors.as_deref(),
+                    ) {
+                        Ok(rust_code) => rust_code,
+                        Err(e) => {
+                            warn!("Body translation of `{name}` failed (attempt {attempt}): {e}");
+                            continue;
+                        }
+                    },
+                };
+                let Some(body) = function_body(&rust_code) else {
+                    previous_errors = Some("The response is not a Rust function.".into());
+                    continue;
+                };
+                let updated = splice(&original_str, stub.body.clone(), body);
+                std::fs::write(check_dir.path().join(&file), &updated)?;
+                let (_, diagnostics) = cargo_check(check_dir.path())?;
+                if error_count(&diagnostics) <= baseline {
+                    *package.dir.get_file_mut(&file)? = updated.into_bytes();
+                    if let Some((memory, key)) = memory.as_ref().zip(key.as_deref()) {
+                        memory.put(key, &rust_code);
+                    }
+                    accepted = true;
+                    break;
+                }
+                previous_errors = Some(render_errors(&diagnostics));
+            }
+            if accepted {
+                translated += 1;
+            } else {
+                std::fs::write(check_dir.path().join(&file), &original)?;
+                warn!(
+                    "No body for `{name}` passed cargo check after {attempts} attempts, leaving it as `todo!()`"
+                );
+            }
+        }
+
+        let usage = modular_llm.usage_by_call().bodies;
+        info!(
+            "token usage [bodies] - prompt: {}, output: {}, total: {}",
+            usage.prompt_tokens, usage.output_tokens, usage.total_tokens
+        );
+        info!(
+            "Translated {}/{} function bodies",
+            translated,
+            functions.len()
+        );
+        if let Some(memory) = &memory {
+            let (hits, misses) = memory.stats();
+            info!("Translation memory: {hits} bodies recalled, {misses} not found");
+        }
+        Ok(Box::new(package))
+    }
+}
+
+/// A function with a `todo!()` body in a source file.
+#[derive(Debug, PartialEq)]
+struct Stub {
+    /// Byte range of the whole function, attributes included.
+    item: Range<usize>,
+    /// Byte range of the body block, braces included.
+    body: Range<usize>,
+}
+
+/// The source files that may hold the stub of `function`: its own module's file first, then
+/// every other Rust file of the package.
+fn candidate_files(
+    package: &CargoPackage,
+    layout: &ModuleLayout,
+    function: &TopLevelEntity,
+    project_kind: &ProjectKind,
+) -> Vec<PathBuf> {
+    let mut preferred = Vec::new();
+    match layout.module_of(&function.span.file) {
+        Some(path) if !path.is_empty() => {
+            preferred.push(PathBuf::from(format!("src/{}.rs", path.join("/"))));
+            preferred.push(PathBuf::from(format!("src/{}/mod.rs", path.join("/"))));
+        }
+        _ => preferred.push(PathBuf::from(match project_kind {
+            ProjectKind::Executable => "src/main.rs",
+            ProjectKind::Library => "src/lib.rs",
+        })),
+    }
+    let others = package
+        .dir
+        .files_recursive()
+        .into_iter()
+        .map(|(path, _)| path)
+        .filter(|path| path.extension().is_some_and(|e| e == "rs") && !preferred.contains(path));
+    let mut candidates: Vec<PathBuf> = preferred.clone();
+    candidates.extend(others);
+    candidates.retain(|path| package.dir.get_file(path).is_ok());
+    candidates
+}
+
+/// Finds the top-level function `name` in `source` whose body is only `todo!()`.
+fn find_stub(source: &str, name: &str) -> Option<Stub> {
+    let file = syn::parse_file(source).ok()?;
+    file.items.iter().find_map(|item| match item {
+        syn::Item::Fn(f) if f.sig.ident == name && is_todo(&f.block) => Some(Stub {
+            item: f.span().byte_range(),
+            body: f.block.span().byte_range(),
+        }),
+        _ => None,
+    })
+}
+
+/// Whether `block` consists of a single `todo!()`.
+fn is_todo(block: &syn::Block) -> bool {
+    let [stmt] = block.stmts.as_slice() else {
+        return false;
+    };
+    let mac = match stmt {
+        syn::Stmt::Macro(m) => &m.mac,
+        syn::Stmt::Expr(syn::Expr::Macro(m), _) => &m.mac,
+        _ => return false,
+    };
+    mac.path.is_ident("todo")
+}
+
+/// The body block, braces included, of the function in `rust_code`.
+fn function_body(rust_code: &str) -> Option<&str> {
+    let file = syn::parse_file(rust_code).ok()?;
+    file.items.iter().find_map(|item| match item {
+        syn::Item::Fn(f) => Some(&rust_code[f.block.span().byte_range()]),
+        _ => None,
+    })
+}
+
+/// Replaces `range` of `source` with `replacement`.
+fn splice(source: &str, range: Range<usize>, replacement: &str) -> String {
+    let mut out = String::with_capacity(source.len() + replacement.len());
+    out.push_str(&source[..range.start]);
+    out.push_str(replacement);
+    out.push_str(&source[range.end..]);
+    out
+}
+
+fn error_count(diagnostics: &[CompilerMessage]) -> usize {
+    diagnostics
+        .iter()
+        .filter(|d| d.message.level == DiagnosticLevel::Error)
+        .count()
+}
+
+/// The rendered compiler errors, as fed back to the LLM.
+fn render_errors(diagnostics: &[CompilerMessage]) -> String {
+    diagnostics
+        .iter()
+        .filter(|d| d.message.level == DiagnosticLevel::Error)
+        .map(|d| {
+            d.message
+                .rendered
+                .clone()
+                .unwrap_or_else(|| d.message.message.clone())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKELETON: &str = "use crate::util::*;\n\n\
+        pub fn add(a: i32, b: i32) -> i32 { todo!() }\n\n\
+        fn done() -> i32 {\n    1\n}\n\n\
+        #[unsafe(no_mangle)]\npub extern \"C\" fn run() {\n    todo!();\n}\n";
+
+    #[test]
+    fn finds_only_todo_stubs() {
+        let stub = find_stub(SKELETON, "add").unwrap();
+        assert_eq!(
+            &SKELETON[stub.item],
+            "pub fn add(a: i32, b: i32) -> i32 { todo!() }"
+        );
+        assert_eq!(&SKELETON[stub.body], "{ todo!() }");
+
+        let stub = find_stub(SKELETON, "run").unwrap();
+        assert!(SKELETON[stub.item].starts_with("#[unsafe(no_mangle)]"));
+
+        assert_eq!(find_stub(SKELETON, "done"), None);
+        assert_eq!(find_stub(SKELETON, "missing"), None);
+    }
+
+    #[test]
+    fn splices_the_translated_body() {
+        let translated = "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}";
+        let body = function_body(translated).unwrap();
+        assert_eq!(body, "{\n    a + b\n}");
+
+        let stub = find_stub(SKELETON, "add").unwrap();
+        let updated = splice(SKELETON, stub.body, body);
+        assert!(updated.contains("pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n"));
+        assert!(updated.contains("{\n    todo!();\n}"));
+        assert_eq!(function_body("struct S;"), None);
+    }
+}
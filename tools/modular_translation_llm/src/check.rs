@@ -0,0 +1,211 @@
+//! Scratch crate in which each translated function or global is checked with `cargo check` as
+//! soon as it is translated, so its errors can be fed back to the LLM while it is still in focus.
+//!
+//! The crate is a single `src/lib.rs` holding the macro and type translations followed by one
+//! slot per function or global. Every slot starts out as the declaration's interface signature
+//! (a `todo!()` stub) and is replaced by the translation once it checks cleanly.
+
+use cargo_metadata::diagnostic::DiagnosticLevel;
+use std::collections::BTreeSet;
+use tempfile::TempDir;
+use try_cargo_build::{CompilerMessage, cargo_check};
+
+use crate::translation::RustDeclaration;
+
+/// Edition of the crate being translated, which the Cargo.toml prompt asks for.
+const EDITION: &str = "2021";
+
+/// Path roots that are not crates to depend on.
+const BUILTIN_ROOTS: &[&str] = &["std", "core", "alloc", "crate", "self", "super"];
+
+pub(crate) struct ScratchCrate {
+    dir: TempDir,
+    /// Macro and type translations, emitted ahead of the slots.
+    prelude: Vec<String>,
+    /// Imports of the prelude and of every accepted translation.
+    imports: BTreeSet<String>,
+    /// Current code and declared name of each slot.
+    slots: Vec<(String, Option<String>)>,
+}
+
+impl ScratchCrate {
+    /// Creates a scratch crate from the macro and type translations, with one slot per interface
+    /// signature.
+    pub(crate) fn new(
+        macros: &[String],
+        types: &[RustDeclaration],
+        signatures: &[String],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut prelude = macros.to_vec();
+        prelude.extend(types.iter().map(|t| t.rust_code.clone()));
+        Ok(Self {
+            dir: tempfile::tempdir()?,
+            prelude,
+            imports: types
+                .iter()
+                .flat_map(|t| t.dependencies.iter().cloned())
+                .collect(),
+            slots: signatures
+                .iter()
+                .map(|s| (s.clone(), declared_name(s)))
+                .collect(),
+        })
+    }
+
+    /// The slot holding the declaration `name`, added empty if the interface had none.
+    pub(crate) fn slot(&mut self, name: Option<&str>) -> usize {
+        if let Some(i) = name.and_then(|name| {
+            self.slots
+                .iter()
+                .position(|(_, slot_name)| slot_name.as_deref() == Some(name))
+        }) {
+            return i;
+        }
+        self.slots
+            .push((String::new(), name.map(ToString::to_string)));
+        self.slots.len() - 1
+    }
+
+    /// Runs `cargo check` with `translation` in `slot` and returns the rendered errors located in
+    /// it, or a note of the failure if the check failed without compiling, e.g. because a crate
+    /// the translation imports does not resolve. The slot keeps its previous code until
+    /// [`ScratchCrate::accept`] is called.
+    pub(crate) fn check(
+        &self,
+        slot: usize,
+        translation: &RustDeclaration,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut imports = self.imports.clone();
+        imports.extend(translation.dependencies.iter().cloned());
+
+        let mut lib = String::new();
+        for import in &imports {
+            lib.push_str(&format!("use {import};\n"));
+        }
+        for code in &self.prelude {
+            lib.push_str(&format!("\n{code}\n"));
+        }
+        let mut lines = 0..0;
+        for (i, (code, _)) in self.slots.iter().enumerate() {
+            let code = if i == slot {
+                &translation.rust_code
+            } else {
+                code
+            };
+            lib.push('\n');
+            let start = lib.lines().count() + 1;
+            lib.push_str(code);
+            lib.push('\n');
+            if i == slot {
+                lines = start..lib.lines().count() + 1;
+            }
+        }
+
+        std::fs::create_dir_all(self.dir.path().join("src"))?;
+        std::fs::write(self.dir.path().join("src/lib.rs"), lib)?;
+        std::fs::write(self.dir.path().join("Cargo.toml"), manifest(&imports))?;
+        let (success, diagnostics) = cargo_check(self.dir.path())?;
+        let compiled = diagnostics
+            .iter()
+            .any(|d| d.message.level == DiagnosticLevel::Error);
+        if !success && !compiled {
+            return Ok(vec![format!(
+                "cargo check failed before compiling; check that the crates the translation \
+                 imports exist ({})",
+                crates(&imports).into_iter().collect::<Vec<_>>().join(", ")
+            )]);
+        }
+        Ok(errors_in(&diagnostics, lines))
+    }
+
+    /// Keeps `translation` in `slot` for the checks of later declarations.
+    pub(crate) fn accept(&mut self, slot: usize, translation: &RustDeclaration) {
+        self.slots[slot].0 = translation.rust_code.clone();
+        self.imports
+            .extend(translation.dependencies.iter().cloned());
+    }
+}
+
+/// The name of the function or static declared by `code`, if it parses.
+pub(crate) fn declared_name(code: &str) -> Option<String> {
+    let file = syn::parse_file(code).ok()?;
+    file.items.iter().find_map(|item| match item {
+        syn::Item::Fn(f) => Some(f.sig.ident.to_string()),
+        syn::Item::Static(s) => Some(s.ident.to_string()),
+        syn::Item::Const(c) => Some(c.ident.to_string()),
+        _ => None,
+    })
+}
+
+/// The external crates `imports` refer to.
+fn crates(imports: &BTreeSet<String>) -> BTreeSet<&str> {
+    imports
+        .iter()
+        .filter_map(|import| import.split("::").next())
+        .map(str::trim)
+        .filter(|root| !root.is_empty() && !BUILTIN_ROOTS.contains(root))
+        .collect()
+}
+
+/// A manifest depending on every crate the imports refer to.
+fn manifest(imports: &BTreeSet<String>) -> String {
+    let mut manifest = format!(
+        "[package]\nname = \"scratch\"\nversion = \"0.1.0\"\nedition = \"{EDITION}\"\n\n\
+         [workspace]\n\n[dependencies]\n",
+    );
+    for krate in crates(imports) {
+        manifest.push_str(&format!("{krate} = \"*\"\n"));
+    }
+    manifest
+}
+
+/// The rendered errors whose primary span starts on one of `lines` of `src/lib.rs`.
+fn errors_in(diagnostics: &[CompilerMessage], lines: std::ops::Range<usize>) -> Vec<String> {
+    diagnostics
+        .iter()
+        .map(|d| &d.message)
+        .filter(|m| m.level == DiagnosticLevel::Error)
+        .filter(|m| {
+            m.spans.iter().any(|s| {
+                s.is_primary && s.file_name == "src/lib.rs" && lines.contains(&s.line_start)
+            })
+        })
+        .map(|m| m.rendered.clone().unwrap_or_else(|| m.message.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_found_by_declared_name() {
+        let mut scratch = ScratchCrate::new(
+            &[],
+            &[],
+            &[
+                "pub fn add(a: i32, b: i32) -> i32 { todo!() }".into(),
+                "pub static COUNTER: Mutex<i32> = todo!();".into(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(scratch.slot(Some("COUNTER")), 1);
+        assert_eq!(scratch.slot(Some("add")), 0);
+        assert_eq!(scratch.slot(Some("missing")), 2);
+        assert_eq!(scratch.slot(None), 3);
+    }
+
+    #[test]
+    fn manifest_depends_on_external_crates_only() {
+        let imports: BTreeSet<String> = [
+            "std::io::Read",
+            "libc::c_int",
+            "crate::util::*",
+            "libc::size_t",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        assert!(manifest(&imports).ends_with("[dependencies]\nlibc = \"*\"\n"));
+    }
+}
@@ -0,0 +1,411 @@
+//! Per-declaration prompt context: selects the macro, type and interface translations a function
+//! or global refers to, rather than sending every translation with every request.
+//!
+//! References are found lexically: the identifiers in a declaration's C source are matched
+//! against the C and Rust names of the translated items, and the items those bring in are
+//! scanned the same way until nothing new is referenced.
+
+use c_ast::{EntityKind, TopLevelEntity};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use tracing::warn;
+
+use crate::translation::{
+    InterfaceTranslationResult, MacroTranslationResult, TypeTranslationResult,
+};
+
+/// The translations included as context in a function or global translation request.
+#[derive(Debug, Default, PartialEq)]
+pub struct PromptContext {
+    pub macros: Vec<String>,
+    pub types: Vec<String>,
+    pub interface: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Macro,
+    Type,
+    Interface,
+}
+
+struct ContextItem {
+    section: Section,
+    code: String,
+    /// Identifiers that are not names of another item are harmless, so this is every identifier
+    /// of the item's Rust code and, for macros and types, of its C source.
+    refs: BTreeSet<String>,
+}
+
+/// Index of the translated items that can be included as prompt context.
+pub struct ContextIndex {
+    items: Vec<ContextItem>,
+    /// Items declaring each C or Rust name.
+    by_name: HashMap<String, Vec<usize>>,
+    /// Items whose names are unknown, included in every context.
+    unnamed: Vec<usize>,
+    /// Approximate token budget of a selected context; `None` always selects the full context.
+    budget: Option<usize>,
+}
+
+impl ContextIndex {
+    /// Indexes the macro, type and interface translations. `functions` and `globals` are the
+    /// declarations the interface signatures were translated from, in that order.
+    ///
+    /// With a `budget`, [`ContextIndex::select`] returns the items a declaration transitively
+    /// refers to, nearest first, until their estimated token count exceeds it. Without one, or if
+    /// a translation cannot be matched to its C declaration, it returns the full context.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        defines: &[TopLevelEntity],
+        macros: &MacroTranslationResult,
+        app_types: &[TopLevelEntity],
+        types: &TypeTranslationResult,
+        functions: &[TopLevelEntity],
+        globals: &[TopLevelEntity],
+        interface: &InterfaceTranslationResult,
+        budget: Option<usize>,
+    ) -> Self {
+        let mut index = Self {
+            items: Vec::new(),
+            by_name: HashMap::new(),
+            unnamed: Vec::new(),
+            budget,
+        };
+        let entities: Vec<&TopLevelEntity> = functions.iter().chain(globals).collect();
+        let sections = [
+            (
+                Section::Macro,
+                macros.macros.clone(),
+                defines.iter().collect::<Vec<_>>(),
+            ),
+            (
+                Section::Type,
+                types
+                    .translations
+                    .iter()
+                    .map(|t| t.rust_code.clone())
+                    .collect(),
+                app_types.iter().collect(),
+            ),
+            (Section::Interface, interface.signatures.clone(), entities),
+        ];
+        for (section, codes, entities) in sections {
+            if codes.len() != entities.len() {
+                warn!(
+                    "{} {:?} translations for {} C declarations; using the full prompt context",
+                    codes.len(),
+                    section,
+                    entities.len()
+                );
+                index.budget = None;
+            }
+            for (i, code) in codes.into_iter().enumerate() {
+                index.add(section, code, entities.get(i).copied());
+            }
+        }
+        index
+    }
+
+    fn add(&mut self, section: Section, code: String, entity: Option<&TopLevelEntity>) {
+        let mut names = rust_names(&code);
+        let mut refs = identifiers(&code);
+        if let Some(entity) = entity {
+            for entity in std::iter::once(entity).chain(&entity.sub_entities) {
+                names.extend(entity.name().map(ToString::to_string));
+                // Enumerators are referenced by name, so an enum is named by each of them.
+                if entity.kind == EntityKind::EnumDecl {
+                    names.extend(enumerators(&entity.source_text));
+                }
+            }
+            // A signature needs only the types of its Rust signature, not those of the C body.
+            if section != Section::Interface {
+                refs.extend(identifiers(&entity.source_text));
+            }
+        }
+        let id = self.items.len();
+        if names.is_empty() {
+            self.unnamed.push(id);
+        }
+        for name in names {
+            refs.remove(&name);
+            self.by_name.entry(name).or_default().push(id);
+        }
+        self.items.push(ContextItem {
+            section,
+            code,
+            refs,
+        });
+    }
+
+    /// The prompt context for translating `decl`.
+    pub fn select(&self, decl: &TopLevelEntity) -> PromptContext {
+        let Some(budget) = self.budget else {
+            return self.context((0..self.items.len()).collect());
+        };
+
+        let mut selected: BTreeSet<usize> = self.unnamed.iter().copied().collect();
+        let mut tokens: usize = selected.iter().map(|&i| self.tokens(i)).sum();
+        let mut queue: VecDeque<usize> = self.referenced(&identifiers(&decl.source_text));
+        while let Some(id) = queue.pop_front() {
+            if selected.contains(&id) {
+                continue;
+            }
+            // An item over the budget is left out, but smaller ones may still fit.
+            if tokens + self.tokens(id) > budget {
+                continue;
+            }
+            tokens += self.tokens(id);
+            selected.insert(id);
+            queue.extend(self.referenced(&self.items[id].refs));
+        }
+        self.context(selected)
+    }
+
+    /// Items declaring any of `identifiers`.
+    fn referenced(&self, identifiers: &BTreeSet<String>) -> VecDeque<usize> {
+        identifiers
+            .iter()
+            .filter_map(|ident| self.by_name.get(ident))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Rough token count of an item, at four bytes per token.
+    fn tokens(&self, id: usize) -> usize {
+        self.items[id].code.len() / 4 + 1
+    }
+
+    /// The selected items, in their original order.
+    fn context(&self, selected: BTreeSet<usize>) -> PromptContext {
+        let mut context = PromptContext::default();
+        for id in selected {
+            let item = &self.items[id];
+            let section = match item.section {
+                Section::Macro => &mut context.macros,
+                Section::Type => &mut context.types,
+                Section::Interface => &mut context.interface,
+            };
+            section.push(item.code.clone());
+        }
+        context
+    }
+}
+
+/// The identifiers in `source`, C or Rust.
+pub(crate) fn identifiers(source: &str) -> BTreeSet<String> {
+    source
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|word| word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// The enumerators of the C enum declared by `source`.
+pub(crate) fn enumerators(source: &str) -> Vec<String> {
+    let (Some(open), Some(close)) = (source.find('{'), source.rfind('}')) else {
+        return Vec::new();
+    };
+    if close < open {
+        return Vec::new();
+    }
+    source[open + 1..close]
+        .split(',')
+        .filter_map(|enumerator| identifiers(enumerator.split('=').next()?).pop_first())
+        .collect()
+}
+
+/// Names of the items declared by the Rust `code`, including enum variants.
+fn rust_names(code: &str) -> BTreeSet<String> {
+    let Ok(file) = syn::parse_file(code) else {
+        return BTreeSet::new();
+    };
+    let mut names = BTreeSet::new();
+    for item in &file.items {
+        match item {
+            syn::Item::Struct(s) => {
+                names.insert(s.ident.to_string());
+            }
+            syn::Item::Union(u) => {
+                names.insert(u.ident.to_string());
+            }
+            syn::Item::Type(t) => {
+                names.insert(t.ident.to_string());
+            }
+            syn::Item::Enum(e) => {
+                names.insert(e.ident.to_string());
+                names.extend(e.variants.iter().map(|v| v.ident.to_string()));
+            }
+            syn::Item::Const(c) => {
+                names.insert(c.ident.to_string());
+            }
+            syn::Item::Static(s) => {
+                names.insert(s.ident.to_string());
+            }
+            syn::Item::Fn(f) => {
+                names.insert(f.sig.ident.to_string());
+            }
+            syn::Item::Macro(m) => {
+                names.extend(m.ident.as_ref().map(ToString::to_string));
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translation::RustDeclaration;
+    use c_ast::{SourcePoint, SourceSpan};
+
+    fn entity(kind: EntityKind, source_text: &str) -> TopLevelEntity {
+        TopLevelEntity {
+            kind,
+            source_text: source_text.into(),
+            span: SourceSpan {
+                file: "main.c".into(),
+                start: SourcePoint {
+                    line: 1,
+                    column: 1,
+                    offset: 0,
+                },
+                end: SourcePoint {
+                    line: 1,
+                    column: 1,
+                    offset: 0,
+                },
+            },
+            ast: None,
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn index(budget: Option<usize>) -> ContextIndex {
+        let types = [
+            "struct point { int x; int y; };",
+            "struct line { struct point a; struct point b; };",
+            "enum color { RED, GREEN };",
+            "typedef int unused_t;",
+        ];
+        let rust_types = [
+            "pub struct point { pub x: i32, pub y: i32 }",
+            "pub struct line { pub a: point, pub b: point }",
+            "pub enum color { RED, GREEN }",
+            "pub type unused_t = i32;",
+        ];
+        let kinds = [
+            EntityKind::RecordDecl,
+            EntityKind::RecordDecl,
+            EntityKind::EnumDecl,
+            EntityKind::TypedefDecl,
+        ];
+        let app_types: Vec<TopLevelEntity> = types
+            .iter()
+            .zip(kinds)
+            .map(|(source, kind)| entity(kind, source))
+            .collect();
+        let functions = [
+            entity(
+                EntityKind::FunctionDecl,
+                "int len(struct line l) { return l.b.x - l.a.x; }",
+            ),
+            entity(
+                EntityKind::FunctionDecl,
+                "int red(void) { return RED + MAX; }",
+            ),
+        ];
+        ContextIndex::new(
+            &[entity(EntityKind::MacroDefinition, "#define MAX 10")],
+            &MacroTranslationResult {
+                macros: vec!["macro_rules! MAX { () => { 10 }; }".into()],
+            },
+            &app_types,
+            &TypeTranslationResult {
+                translations: rust_types
+                    .iter()
+                    .map(|code| RustDeclaration {
+                        rust_code: code.to_string(),
+                        dependencies: Vec::new(),
+                        comments: Default::default(),
+                        source_file: String::new(),
+                        check_errors: Vec::new(),
+                    })
+                    .collect(),
+            },
+            &functions,
+            &[],
+            &InterfaceTranslationResult {
+                signatures: vec![
+                    "pub fn len(l: line) -> i32 { todo!() }".into(),
+                    "pub fn red() -> i32 { todo!() }".into(),
+                ],
+            },
+            budget,
+        )
+    }
+
+    #[test]
+    fn selects_transitively_referenced_items() {
+        let decl = entity(
+            EntityKind::FunctionDecl,
+            "int total(struct line l) { return len(l); }",
+        );
+        let context = index(Some(1000)).select(&decl);
+        assert_eq!(
+            context,
+            PromptContext {
+                macros: vec![],
+                types: vec![
+                    "pub struct point { pub x: i32, pub y: i32 }".into(),
+                    "pub struct line { pub a: point, pub b: point }".into(),
+                ],
+                interface: vec!["pub fn len(l: line) -> i32 { todo!() }".into()],
+            }
+        );
+
+        let decl = entity(EntityKind::FunctionDecl, "int blue(void) { return red(); }");
+        let context = index(Some(1000)).select(&decl);
+        assert_eq!(context.interface, vec!["pub fn red() -> i32 { todo!() }"]);
+        assert!(context.types.is_empty() && context.macros.is_empty());
+    }
+
+    #[test]
+    fn enumerators_and_macros_are_referenced_by_name() {
+        let decl = entity(
+            EntityKind::FunctionDecl,
+            "int green(void) { return GREEN * MAX; }",
+        );
+        let context = index(Some(1000)).select(&decl);
+        assert_eq!(context.types, vec!["pub enum color { RED, GREEN }"]);
+        assert_eq!(context.macros.len(), 1);
+    }
+
+    #[test]
+    fn budget_limits_and_full_context_fallback() {
+        let decl = entity(
+            EntityKind::FunctionDecl,
+            "int total(struct line l) { return len(l); }",
+        );
+        let context = index(Some(12)).select(&decl);
+        assert_eq!(context.types.len() + context.interface.len(), 1);
+
+        // `line` does not fit, but the smaller `red` after it does.
+        let decl = entity(
+            EntityKind::FunctionDecl,
+            "int blue(struct line l) { return red(); }",
+        );
+        let context = index(Some(10)).select(&decl);
+        assert!(context.types.is_empty());
+        assert_eq!(context.interface, vec!["pub fn red() -> i32 { todo!() }"]);
+
+        let context = index(None).select(&decl);
+        assert_eq!(context.types.len(), 4);
+        assert_eq!(context.interface.len(), 2);
+        assert_eq!(context.macros.len(), 1);
+    }
+}
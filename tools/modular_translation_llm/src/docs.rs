@@ -0,0 +1,285 @@
+//! Renders C comments captured by `c_ast` as rustdoc on the translated Rust code.
+//!
+//! The LLM is free to drop or rewrite comments, so rather than asking it to carry them over we
+//! attach them deterministically at recombination time: entity comments become `///` lines on the
+//! translated item, field and enum-constant comments are inserted above the matching Rust field or
+//! variant, and parameter comments (which rustdoc cannot attach to parameters) are listed in a
+//! `# Parameters` section of the item docs.
+
+use c_ast::{EntityComments, MemberKind};
+use syn::spanned::Spanned;
+
+/// Returns `rust_code` with `comments` rendered as rustdoc.
+///
+/// Item docs are skipped when the translation already starts with a doc comment, and member docs
+/// are skipped for fields/variants that already carry one, so nothing the LLM wrote is duplicated.
+/// Item docs are also skipped unless the translation parses to at least one item, since on empty
+/// or comment-only code they would dangle or document whatever follows.
+pub(crate) fn document(rust_code: &str, comments: &EntityComments) -> String {
+    if comments.is_empty() {
+        return rust_code.to_string();
+    }
+    let code = document_members(rust_code, comments);
+    let item_docs = item_doc_lines(comments);
+    let trimmed = code.trim_start();
+    if item_docs.is_empty()
+        || trimmed.starts_with("///")
+        || trimmed.starts_with("#[doc")
+        || syn::parse_file(&code).map_or(true, |file| file.items.is_empty())
+    {
+        return code;
+    }
+    format!("{item_docs}{trimmed}")
+}
+
+/// Renders the entity-level comments plus the `# Parameters` section as `///` lines, each
+/// terminated by a newline.
+fn item_doc_lines(comments: &EntityComments) -> String {
+    let mut paragraphs: Vec<String> = Vec::new();
+    paragraphs.extend(comments.leading.clone());
+    paragraphs.extend(comments.trailing.clone());
+
+    let params: Vec<String> = comments
+        .members
+        .iter()
+        .filter(|m| m.kind == MemberKind::Parameter)
+        .map(|m| {
+            let text = [m.leading.as_deref(), m.trailing.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("- `{}`: {}", m.name, text.replace('\n', " "))
+        })
+        .collect();
+    if !params.is_empty() {
+        paragraphs.push(format!("# Parameters\n\n{}", params.join("\n")));
+    }
+
+    doc_lines(&paragraphs.join("\n\n"), "")
+}
+
+/// Prefixes every line of `text` with `indent` and `///`.
+fn doc_lines(text: &str, indent: &str) -> String {
+    let mut out = String::new();
+    for line in text.lines() {
+        if line.is_empty() {
+            out.push_str(&format!("{indent}///\n"));
+        } else {
+            out.push_str(&format!("{indent}/// {line}\n"));
+        }
+    }
+    out
+}
+
+/// Inserts field and variant docs. Leaves the code untouched if it does not parse.
+fn document_members(rust_code: &str, comments: &EntityComments) -> String {
+    if !comments
+        .members
+        .iter()
+        .any(|m| matches!(m.kind, MemberKind::Field | MemberKind::EnumConstant))
+    {
+        return rust_code.to_string();
+    }
+    let Ok(file) = syn::parse_file(rust_code) else {
+        return rust_code.to_string();
+    };
+
+    // (byte offset to insert at, doc text)
+    let mut insertions: Vec<(usize, String)> = Vec::new();
+    for item in &file.items {
+        match item {
+            syn::Item::Struct(s) => {
+                collect_fields(s.fields.iter(), comments, &mut insertions);
+            }
+            syn::Item::Union(u) => {
+                collect_fields(u.fields.named.iter(), comments, &mut insertions);
+            }
+            syn::Item::Enum(e) => {
+                for variant in &e.variants {
+                    let start = variant
+                        .attrs
+                        .first()
+                        .map_or(variant.ident.span(), |a| a.pound_token.span);
+                    push_insertion(
+                        &variant.attrs,
+                        &variant.ident.to_string(),
+                        MemberKind::EnumConstant,
+                        start.byte_range().start,
+                        comments,
+                        &mut insertions,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Insert back to front so earlier offsets stay valid.
+    insertions.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut code = rust_code.to_string();
+    for (offset, text) in insertions {
+        let line_start = code[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let indent = &code[line_start..offset];
+        if !indent.trim().is_empty() {
+            // Not the first token on its line (e.g. a one-line struct); skip.
+            continue;
+        }
+        let docs = doc_lines(&text, indent);
+        // `docs` ends in a newline and already carries the indentation of the member line.
+        code.insert_str(line_start, &docs);
+    }
+    code
+}
+
+fn collect_fields<'a>(
+    fields: impl Iterator<Item = &'a syn::Field>,
+    comments: &EntityComments,
+    insertions: &mut Vec<(usize, String)>,
+) {
+    for field in fields {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let start = match (field.attrs.first(), &field.vis) {
+            (Some(attr), _) => attr.pound_token.span,
+            (None, syn::Visibility::Inherited) => ident.span(),
+            (None, vis) => vis.span(),
+        };
+        push_insertion(
+            &field.attrs,
+            &ident.to_string(),
+            MemberKind::Field,
+            start.byte_range().start,
+            comments,
+            insertions,
+        );
+    }
+}
+
+fn push_insertion(
+    attrs: &[syn::Attribute],
+    rust_name: &str,
+    kind: MemberKind,
+    offset: usize,
+    comments: &EntityComments,
+    insertions: &mut Vec<(usize, String)>,
+) {
+    if attrs.iter().any(|a| a.path().is_ident("doc")) {
+        return;
+    }
+    let key = name_key(rust_name);
+    let Some(member) = comments
+        .members
+        .iter()
+        .find(|m| m.kind == kind && name_key(&m.name) == key)
+    else {
+        return;
+    };
+    let text = [member.leading.as_deref(), member.trailing.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    insertions.push((offset, text));
+}
+
+/// Normalizes a C or Rust identifier for matching across case conventions and raw identifiers,
+/// e.g. the C enum constant `COLOR_DARK_RED` and the Rust variant `ColorDarkRed`.
+fn name_key(name: &str) -> String {
+    name.trim_start_matches("r#")
+        .chars()
+        .filter(|c| *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::MemberComment;
+
+    fn member(
+        kind: MemberKind,
+        name: &str,
+        leading: Option<&str>,
+        trailing: Option<&str>,
+    ) -> MemberComment {
+        MemberComment {
+            kind,
+            name: name.into(),
+            leading: leading.map(Into::into),
+            trailing: trailing.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn no_comments_leaves_code_untouched() {
+        let code = "pub struct Point {\n    pub x: i32,\n}";
+        assert_eq!(document(code, &EntityComments::default()), code);
+    }
+
+    #[test]
+    fn item_docs_include_parameters() {
+        let comments = EntityComments {
+            leading: Some("Adds one.\n\nNever fails.".into()),
+            trailing: None,
+            members: vec![member(MemberKind::Parameter, "x", None, Some("the input"))],
+        };
+        let out = document("pub fn add_one(x: i32) -> i32 {\n    x + 1\n}", &comments);
+        assert_eq!(
+            out,
+            "/// Adds one.\n///\n/// Never fails.\n///\n/// # Parameters\n///\n/// - `x`: the input\n\
+             pub fn add_one(x: i32) -> i32 {\n    x + 1\n}"
+        );
+    }
+
+    #[test]
+    fn existing_item_docs_are_kept() {
+        let comments = EntityComments {
+            leading: Some("C doc".into()),
+            ..Default::default()
+        };
+        let code = "/// LLM doc\npub const N: usize = 4;";
+        assert_eq!(document(code, &comments), code);
+    }
+
+    #[test]
+    fn field_and_variant_docs_are_inserted() {
+        let comments = EntityComments {
+            leading: None,
+            trailing: None,
+            members: vec![
+                member(MemberKind::Field, "x", None, Some("horizontal")),
+                member(MemberKind::Field, "type", Some("kind tag"), None),
+                member(
+                    MemberKind::EnumConstant,
+                    "COLOR_DARK_RED",
+                    Some("dark"),
+                    None,
+                ),
+            ],
+        };
+        let code = "#[repr(C)]\npub struct Point {\n    pub x: i32,\n    #[allow(dead_code)]\n    r#type: i32,\n    /// kept\n    y: i32,\n}\n\
+                    pub enum Color {\n    ColorDarkRed = 1,\n}";
+        let out = document(code, &comments);
+        assert_eq!(
+            out,
+            "#[repr(C)]\npub struct Point {\n    /// horizontal\n    pub x: i32,\n    /// kind tag\n    #[allow(dead_code)]\n    r#type: i32,\n    /// kept\n    y: i32,\n}\n\
+             pub enum Color {\n    /// dark\n    ColorDarkRed = 1,\n}"
+        );
+    }
+
+    #[test]
+    fn code_without_items_gets_no_item_docs() {
+        let comments = EntityComments {
+            leading: Some("doc".into()),
+            trailing: None,
+            members: vec![member(MemberKind::Field, "x", Some("x"), None)],
+        };
+        assert_eq!(document("struct {", &comments), "struct {");
+        assert_eq!(document("", &comments), "");
+        let dropped = "// The macro has no Rust equivalent.\n";
+        assert_eq!(document(dropped, &comments), dropped);
+    }
+}
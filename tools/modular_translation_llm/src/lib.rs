@@ -5,27 +5,52 @@
 //! - Type declarations (TypedefDecl, RecordDecl, EnumDecl) establish data layout
 //! - Interface (FunctionDecl and VarDecl signatures) use type context
 //! - Functions and globals (FunctionDecl, VarDecl) use type/interface context
+//!
+//! Skeleton-first translation splits the last step: [`ModularSkeletonLlm`] emits functions as
+//! their interface signatures with `todo!()` bodies, and once that crate builds,
+//! [`TranslateBodiesLlm`] translates the bodies one at a time against it.
+//!
+//! [`ModularProvenance`] links the items of the translated crate to the C declarations they were
+//! translated from.
 
+use analyze_unsafe_constructs::UnsafeConstructReport;
 use build_config::BuildConfigIR;
 use build_project_spec::{ProjectKind, ProjectSpec};
 use c_ast::{RichSourceMap, TopLevelEntity, annotate_visibility};
-use full_source::RawSource;
+use directories::ProjectDirs;
+use full_source::{CargoPackage, RawSource};
 use harvest_core::config::unknown_field_warning;
 use harvest_core::llm::LLMConfig;
 use harvest_core::tools::{RunContext, Tool};
 use harvest_core::{Id, Representation};
+use lower_types::LoweredTypes;
+use name_map::NameMap;
+use partial_translation::TranslationUnits;
 use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
-use tracing::info;
+use std::path::PathBuf;
+use tracing::{info, warn};
 
+mod bodies;
+mod check;
+mod context;
+mod docs;
+mod memory;
+mod modules;
+mod provenance;
 mod recombine;
 mod translation;
 mod translation_llm;
+pub use bodies::TranslateBodiesLlm;
+pub use context::{ContextIndex, PromptContext};
+pub use memory::TranslationMemory;
+pub use provenance::ModularProvenance;
+pub use recombine::CHECK_ERRORS_FILE;
 pub use translation::{
-    InterfaceTranslationResult, MacroTranslationResult, RustDeclaration, TranslationResult,
-    TypeTranslationResult, translate_decls, translate_functions, translate_interface,
-    translate_macros, translate_types,
+    FunctionBodies, InterfaceTranslationResult, MacroTranslationResult, RustDeclaration,
+    TranslationResult, TypeTranslationResult, translate_decls, translate_functions,
+    translate_interface, translate_macros, translate_types,
 };
 pub use translation_llm::ModularTranslationLLM;
 
@@ -35,15 +60,102 @@ pub struct Config {
     #[serde(flatten)]
     pub llm: LLMConfig,
 
+    /// How many times [`TranslateBodiesLlm`] asks for a function body that passes `cargo check`
+    /// before leaving the function as a `todo!()` stub.
+    #[serde(default = "default_max_body_attempts")]
+    pub max_body_attempts: usize,
+
+    /// Whether each translated function and global is checked with `cargo check` in a scratch
+    /// crate as soon as it is translated, and retranslated with its errors if it fails.
+    #[serde(default = "default_check_decls")]
+    pub check_decls: bool,
+
+    /// How many times a declaration that fails `cargo check` is retranslated with its errors.
+    #[serde(default = "default_max_check_retries")]
+    pub max_check_retries: usize,
+
+    /// Whether function and global requests include only the macro, type and interface
+    /// translations their declaration refers to, rather than all of them.
+    #[serde(default = "default_scoped_context")]
+    pub scoped_context: bool,
+
+    /// Approximate token budget of a scoped context.
+    #[serde(default = "default_context_token_budget")]
+    pub context_token_budget: usize,
+
+    /// Whether translations are remembered across runs, so that declarations that did not
+    /// change, and whose dependencies did not either, are not translated again. Off by default:
+    /// the memory is never evicted, so it grows with every translated project.
+    #[serde(default = "default_memory")]
+    pub memory: bool,
+
+    /// Translation memory location. Defaults to `translation_memory` in the user's harvest cache
+    /// directory.
+    #[serde(default)]
+    pub memory_dir: Option<PathBuf>,
+
+    /// How type translation uses the [`LoweredTypes`] of the type declarations, when the schedule
+    /// provides them.
+    #[serde(default)]
+    pub type_lowering: TypeLowering,
+
     #[serde(flatten)]
     unknown: HashMap<String, Value>,
 }
 
+/// How [`translate_types`] uses the deterministic, layout-preserving lowering of a type
+/// declaration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TypeLowering {
+    /// Ignore the lowering; the LLM translates every type from its C source.
+    Llm,
+    /// Give the LLM the lowering as a reference whose layout it must keep.
+    #[default]
+    Reference,
+    /// Use the lowering as the translation; only declarations without one go to the LLM.
+    Direct,
+}
+
+fn default_max_body_attempts() -> usize {
+    3
+}
+
+fn default_check_decls() -> bool {
+    true
+}
+
+fn default_max_check_retries() -> usize {
+    2
+}
+
+fn default_scoped_context() -> bool {
+    true
+}
+
+fn default_context_token_budget() -> usize {
+    8000
+}
+
+fn default_memory() -> bool {
+    false
+}
+
 impl Config {
     pub fn validate(&self) {
         unknown_field_warning("tools.modular_translation_llm", &self.unknown);
     }
 
+    /// The translation memory directory, or `None` if the memory is disabled.
+    pub fn memory_dir(&self) -> Option<PathBuf> {
+        if !self.memory {
+            return None;
+        }
+        self.memory_dir.clone().or_else(|| {
+            ProjectDirs::from("", "", "harvest").map(|d| d.cache_dir().join("translation_memory"))
+        })
+    }
+
     /// Returns a mock config for testing.
     pub fn mock() -> Self {
         Self {
@@ -56,6 +168,14 @@ impl Config {
                 retry_count: None,
                 retry_delay_secs: None,
             },
+            max_body_attempts: default_max_body_attempts(),
+            check_decls: default_check_decls(),
+            max_check_retries: default_max_check_retries(),
+            scoped_context: default_scoped_context(),
+            context_token_budget: default_context_token_budget(),
+            memory: default_memory(),
+            memory_dir: None,
+            type_lowering: TypeLowering::default(),
             unknown: HashMap::new(),
         }
     }
@@ -64,6 +184,12 @@ impl Config {
 /// The main tool struct for modular translation.
 pub struct ModularTranslationLlm;
 
+/// First half of skeleton-first translation: translates macros, types, globals and function
+/// signatures, producing a crate whose functions all have `todo!()` bodies. Takes the same inputs
+/// as [`ModularTranslationLlm`]; once the skeleton builds, [`TranslateBodiesLlm`] fills in the
+/// bodies.
+pub struct ModularSkeletonLlm;
+
 /// Extracts and validates the tool's input arguments from the context.
 fn extract_args<'a>(
     context: &'a RunContext,
@@ -106,58 +232,165 @@ impl Tool for ModularTranslationLlm {
         context: RunContext,
         inputs: Vec<Id>,
     ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
-        let config = Config::deserialize(
-            context
-                .config
-                .tools
-                .get("modular_translation_llm")
-                .ok_or("No modular_translation_llm config found")?,
-        )?;
-        config.validate();
-
-        let (raw_source, clang_ast, project_kind, build_cfg) = extract_args(&context, &inputs)?;
-
-        let app_types: &[TopLevelEntity] = &clang_ast.app_types;
-        let app_globals: &[TopLevelEntity] = &clang_ast.app_globals;
-        let defines: &[TopLevelEntity] = &clang_ast.defines;
-        let mut app_functions: Vec<TopLevelEntity> = clang_ast.app_functions.clone();
-
-        annotate_visibility(&mut app_functions, &clang_ast.app_func_sigs);
-        info!("Visibility annotation complete");
-
-        info!(
-            "Extracted {} macro definitions, {} type declarations, {} global declarations, {} function declarations",
-            defines.len(),
-            app_types.len(),
-            app_globals.len(),
-            app_functions.len()
-        );
+        Ok(Box::new(translate_package(
+            &context,
+            &inputs,
+            FunctionBodies::Translate,
+        )?))
+    }
+}
+
+impl Tool for ModularSkeletonLlm {
+    fn name(&self) -> &'static str {
+        "modular_skeleton_llm"
+    }
+
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        Ok(Box::new(translate_package(
+            &context,
+            &inputs,
+            FunctionBodies::Stub,
+        )?))
+    }
+}
+
+/// The optional input of type `R`, following the four required ones in `inputs`.
+fn optional_input<'a, R: Representation>(context: &'a RunContext, inputs: &[Id]) -> Option<&'a R> {
+    inputs
+        .iter()
+        .skip(4)
+        .find_map(|id| context.ir_snapshot.get::<R>(*id))
+}
 
-        // Translation flow:
-        // Macros (MacroDefinition) - establish macro translations
-        // Types (TypedefDecl, RecordDecl, EnumDecl) - establish data layout
-        // Interface (FunctionDecl and VarDecl signatures) - with type context
-        // Functions and Globals (FunctionDecl, VarDecl) - with type/interface context
-        let translation_result = translation::translate_decls(
-            defines,
-            app_types,
-            app_globals,
-            &app_functions,
-            raw_source,
-            project_kind,
-            build_cfg,
-            &config,
-        )
-        .map_err(|e| format!("Translation failed: {}", e))?;
-
-        info!(
-            "Translation complete: {} total declarations translated",
-            translation_result.translations.len()
+/// Reads the `modular_translation_llm` config.
+fn load_config(context: &RunContext) -> Result<Config, Box<dyn std::error::Error>> {
+    let config = Config::deserialize(
+        context
+            .config
+            .tools
+            .get("modular_translation_llm")
+            .ok_or("No modular_translation_llm config found")?,
+    )?;
+    config.validate();
+    Ok(config)
+}
+
+/// Translates the project and assembles the result into a Cargo package.
+fn translate_package(
+    context: &RunContext,
+    inputs: &[Id],
+    bodies: FunctionBodies,
+) -> Result<CargoPackage, Box<dyn std::error::Error>> {
+    let config = load_config(context)?;
+
+    let (raw_source, clang_ast, project_kind, build_cfg) = extract_args(context, inputs)?;
+    // Optional inputs, so schedules without an analysis, or whose analysis failed, keep working.
+    let unsafe_report = optional_input::<UnsafeConstructReport>(context, inputs);
+    // Without a name map the LLM chooses the Rust names.
+    let names = optional_input::<NameMap>(context, inputs)
+        .cloned()
+        .unwrap_or_default();
+    // Without lowered types every type is translated by the LLM alone.
+    let lowered = optional_input::<LoweredTypes>(context, inputs);
+    // Only for a partial translation; without it everything is translated.
+    let units = optional_input::<TranslationUnits>(context, inputs);
+
+    let app_types: &[TopLevelEntity] = &clang_ast.app_types;
+    let defines: &[TopLevelEntity] = &clang_ast.defines;
+    let mut app_globals: Vec<TopLevelEntity> = clang_ast.app_globals.clone();
+    let mut app_functions: Vec<TopLevelEntity> = clang_ast.app_functions.clone();
+
+    annotate_visibility(&mut app_globals, &clang_ast.symbols);
+    annotate_visibility(&mut app_functions, &clang_ast.symbols);
+    for symbol in clang_ast.symbols.conflicts() {
+        let files: Vec<&str> = symbol
+            .definition
+            .iter()
+            .chain(&symbol.conflicting_definitions)
+            .map(|span| span.file.as_str())
+            .collect();
+        warn!(
+            "`{}` has conflicting definitions in {}",
+            symbol.name,
+            files.join(", ")
         );
+    }
+    info!("Visibility annotation complete");
 
-        // Assemble translations into a CargoPackage representation
-        let cargo_package = recombine::recombine_decls(translation_result, project_kind)?;
+    info!(
+        "Extracted {} macro definitions, {} type declarations, {} global declarations, {} function declarations",
+        defines.len(),
+        app_types.len(),
+        app_globals.len(),
+        app_functions.len()
+    );
+
+    // Translation flow:
+    // Macros (MacroDefinition) - establish macro translations
+    // Types (TypedefDecl, RecordDecl, EnumDecl) - establish data layout
+    // Interface (FunctionDecl and VarDecl signatures) - with type context
+    // Functions and Globals (FunctionDecl, VarDecl) - with type/interface context
+    let translation_result = translation::translate_decls(
+        defines,
+        app_types,
+        &app_globals,
+        &app_functions,
+        &clang_ast.symbols,
+        raw_source,
+        project_kind,
+        build_cfg,
+        unsafe_report,
+        names,
+        lowered,
+        &config,
+        bodies,
+        units,
+    )
+    .map_err(|e| format!("Translation failed: {}", e))?;
+
+    info!(
+        "Translation complete: {} total declarations translated",
+        translation_result.translations.len()
+    );
+
+    // Assemble translations into a CargoPackage representation, one module per C file
+    let layout = modules::ModuleLayout::new(clang_ast, project_kind);
+    recombine::recombine_decls(translation_result, project_kind, &layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_config_has_the_defaults() {
+        let config = Config::deserialize(serde_json::json!({
+            "backend": "mock_llm",
+            "model": "mock_model",
+            "max_tokens": 4000,
+        }))
+        .unwrap();
+        let mock = Config::mock();
+        assert_eq!(config.max_body_attempts, mock.max_body_attempts);
+        assert_eq!(config.check_decls, mock.check_decls);
+        assert_eq!(config.max_check_retries, mock.max_check_retries);
+        assert_eq!(config.scoped_context, mock.scoped_context);
+        assert_eq!(config.context_token_budget, mock.context_token_budget);
+        assert_eq!(config.memory, mock.memory);
+        assert_eq!(config.type_lowering, mock.type_lowering);
+        assert!(config.unknown.is_empty());
+    }
 
-        Ok(Box::new(cargo_package))
+    #[test]
+    fn memory_is_opt_in() {
+        let mut config = Config::mock();
+        assert_eq!(config.memory_dir(), None);
+        config.memory = true;
+        config.memory_dir = Some("/tmp/memory".into());
+        assert_eq!(config.memory_dir(), Some(PathBuf::from("/tmp/memory")));
     }
 }
@@ -0,0 +1,285 @@
+//! Translation memory: the translations of earlier runs, reused for declarations that have not
+//! changed so that only changed or affected declarations are sent to the LLM.
+//!
+//! An entry is keyed by the pass, the model and build configuration, the declaration's
+//! normalized C source and the signatures of what it depends on: the C declarations it refers
+//! to, the translations it is given as context and the Rust names it must use. Editing a type
+//! therefore also retranslates the signatures and functions that use it, once its new
+//! translation differs from the old one.
+
+use build_config::BuildConfigIR;
+use build_project_spec::ProjectKind;
+use c_ast::{EntityKind, TopLevelEntity};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::path::PathBuf;
+use tracing::warn;
+
+use crate::Config;
+use crate::context::{enumerators, identifiers};
+
+/// Bump when the prompts or the stored data change meaning, so stale entries are ignored.
+const MEMORY_VERSION: &str = "1";
+
+pub struct TranslationMemory {
+    dir: PathBuf,
+    /// Model and build configuration the translations were made with.
+    scope: String,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+}
+
+impl TranslationMemory {
+    /// A memory stored in `dir`, whose entries only apply to translations made in the same
+    /// `scope`.
+    pub fn new(dir: PathBuf, scope: String) -> Self {
+        Self {
+            dir,
+            scope,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    /// The memory configured in `config`, scoped to its model, `project_kind` and `build_cfg`.
+    /// `None` if the memory is disabled.
+    pub fn open(
+        config: &Config,
+        project_kind: &ProjectKind,
+        build_cfg: &BuildConfigIR,
+    ) -> Option<Self> {
+        let scope = format!(
+            "{}/{}/{}/{}",
+            config.llm.backend,
+            config.llm.model,
+            project_kind,
+            serde_json::to_string(build_cfg).unwrap_or_default()
+        );
+        Some(Self::new(config.memory_dir()?, scope))
+    }
+
+    /// The key of the `pass` translation of `source`, given `dependencies`.
+    pub fn key(&self, pass: &str, source: &str, dependencies: &[String]) -> String {
+        let mut hasher = Sha256::new();
+        for part in [MEMORY_VERSION, &self.scope, pass, &normalize(source)] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        for dependency in dependencies {
+            hasher.update(normalize(dependency).as_bytes());
+            hasher.update([0]);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// The translation stored under `key`, if any.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let entry =
+            std::fs::read(self.entry_path(key)).ok().and_then(
+                |bytes| match serde_json::from_slice(&bytes) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        warn!("Ignoring unreadable translation memory entry {key}: {e}");
+                        None
+                    }
+                },
+            );
+        let counter = if entry.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.set(counter.get() + 1);
+        entry
+    }
+
+    /// Stores `translation` under `key`. Failures are logged, not returned: the memory is an
+    /// optimization and must never fail a run.
+    pub fn put<T: Serialize>(&self, key: &str, translation: &T) {
+        if let Err(e) = self.write_entry(key, translation) {
+            warn!("Failed to write translation memory entry {key}: {e}");
+        }
+    }
+
+    fn write_entry<T: Serialize>(
+        &self,
+        key: &str,
+        translation: &T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.dir)?;
+        // Write then rename, so concurrent runs never observe a partial entry.
+        let mut tmp = tempfile::NamedTempFile::new_in(&self.dir)?;
+        serde_json::to_writer(&mut tmp, translation)?;
+        tmp.persist(self.entry_path(key))?;
+        Ok(())
+    }
+
+    /// How many lookups found a translation, and how many did not.
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits.get(), self.misses.get())
+    }
+}
+
+/// Translates the `count` items of a batch pass, reusing those `memory` holds. `translate` is
+/// called once, with the indices of the items to translate, and must return their translations
+/// in that order; they are stored only if it returns one per item.
+pub(crate) fn recall_batch<T: Serialize + DeserializeOwned>(
+    memory: Option<&TranslationMemory>,
+    count: usize,
+    key: impl Fn(&TranslationMemory, usize) -> String,
+    translate: impl FnOnce(&[usize]) -> Result<Vec<T>, Box<dyn std::error::Error>>,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    let Some(memory) = memory else {
+        return translate(&(0..count).collect::<Vec<_>>());
+    };
+    let keys: Vec<String> = (0..count).map(|i| key(memory, i)).collect();
+    let recalled: Vec<Option<T>> = keys.iter().map(|key| memory.get(key)).collect();
+    let misses: Vec<usize> = (0..count).filter(|&i| recalled[i].is_none()).collect();
+    let fresh = if misses.is_empty() {
+        Vec::new()
+    } else {
+        translate(&misses)?
+    };
+    if fresh.len() == misses.len() {
+        for (&i, translation) in misses.iter().zip(&fresh) {
+            memory.put(&keys[i], translation);
+        }
+    }
+
+    // Fresh translations fill the gaps in order; if there are too few or too many, the result
+    // has the wrong length, which later passes already treat as misaligned.
+    let mut fresh = fresh.into_iter();
+    let mut merged: Vec<T> = recalled
+        .into_iter()
+        .filter_map(|recalled| recalled.or_else(|| fresh.next()))
+        .collect();
+    merged.extend(fresh);
+    Ok(merged)
+}
+
+/// The C source of a declaration with runs of whitespace collapsed, so reformatting does not
+/// cause a retranslation.
+fn normalize(source: &str) -> String {
+    source.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Indices of the `candidates` that `source` refers to by name, or by one of their enumerators.
+pub(crate) fn referenced(source: &str, candidates: &[TopLevelEntity]) -> Vec<usize> {
+    let idents = identifiers(source);
+    candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| {
+            let mut names: Vec<String> = candidate
+                .name()
+                .map(ToString::to_string)
+                .into_iter()
+                .collect();
+            if candidate.kind == EntityKind::EnumDecl {
+                names.extend(enumerators(&candidate.source_text));
+            }
+            names.iter().any(|name| idents.contains(name))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(dir: &tempfile::TempDir) -> TranslationMemory {
+        TranslationMemory::new(dir.path().join("memory"), "mock/model".into())
+    }
+
+    #[test]
+    fn key_ignores_formatting_but_not_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = memory(&dir);
+        let base = memory.key(
+            "function",
+            "int f(void) { return 0; }",
+            &["struct a".into()],
+        );
+        assert_eq!(
+            base,
+            memory.key(
+                "function",
+                "int f(void)\n{\n    return 0;\n}",
+                &["struct  a".into()]
+            )
+        );
+        assert_ne!(
+            base,
+            memory.key(
+                "function",
+                "int f(void) { return 1; }",
+                &["struct a".into()]
+            )
+        );
+        assert_ne!(
+            base,
+            memory.key(
+                "function",
+                "int f(void) { return 0; }",
+                &["struct b".into()]
+            )
+        );
+        assert_ne!(
+            base,
+            memory.key("type", "int f(void) { return 0; }", &["struct a".into()])
+        );
+        let other = TranslationMemory::new(dir.path().join("memory"), "mock/other".into());
+        assert_ne!(
+            base,
+            other.key(
+                "function",
+                "int f(void) { return 0; }",
+                &["struct a".into()]
+            )
+        );
+    }
+
+    #[test]
+    fn batch_translates_only_what_it_does_not_recall() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = memory(&dir);
+        let key = |_: &TranslationMemory, i: usize| format!("k{i}");
+
+        let first = recall_batch(Some(&memory), 2, key, |misses| {
+            assert_eq!(misses, [0, 1]);
+            Ok(vec!["a".to_string(), "b".to_string()])
+        })
+        .unwrap();
+        assert_eq!(first, ["a", "b"]);
+
+        let key = |_: &TranslationMemory, i: usize| ["k0", "k2", "k1"][i].to_string();
+        let second = recall_batch(Some(&memory), 3, key, |misses| {
+            assert_eq!(misses, [1]);
+            Ok(vec!["c".to_string()])
+        })
+        .unwrap();
+        assert_eq!(second, ["a", "c", "b"]);
+        assert_eq!(memory.stats(), (2, 3));
+    }
+
+    #[test]
+    fn miscounted_batch_is_not_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = memory(&dir);
+        let key = |_: &TranslationMemory, i: usize| format!("k{i}");
+        let result = recall_batch(Some(&memory), 2, key, |_| Ok(vec!["a".to_string()])).unwrap();
+        assert_eq!(result, ["a"]);
+        assert!(memory.get::<String>("k0").is_none());
+    }
+}
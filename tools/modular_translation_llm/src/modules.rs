@@ -0,0 +1,450 @@
+//! Lays the translated crate out as one Rust module per C file, mirroring the C source tree.
+//!
+//! `src/util/list.c` becomes the module `util::list`. A `.c` file and the header of the same name
+//! in the same directory share a module, the way a Rust module holds both an interface and its
+//! implementation. Directories common to every file (typically `src/`) are dropped. For
+//! executables, the file defining `main` becomes the crate root; a single-file project is laid
+//! out as the crate root alone.
+//!
+//! Files carrying build-variant tags get their own module, gated on the variant's `cfg`. Each
+//! module imports the modules of the headers it includes and of the definitions of the symbols
+//! it references.
+
+use build_project_spec::ProjectKind;
+use c_ast::{RichSourceMap, TopLevelEntity};
+use name_map::is_keyword;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Component, Path};
+
+/// Path of a module below the crate root, one identifier per level. Empty for the root.
+pub(crate) type ModulePath = Vec<String>;
+
+/// Top-level module names that Cargo would pick up as separate targets under `src/`.
+const RESERVED_TOP_LEVEL: &[&str] = &["main", "lib", "bin"];
+
+/// Which module each C file is translated into, and how the modules relate.
+#[derive(Debug, Default)]
+pub(crate) struct ModuleLayout {
+    /// Module of each C file, keyed by project-relative path.
+    modules: HashMap<String, ModulePath>,
+    /// `cfg` predicate of each module holding build-variant files.
+    cfgs: HashMap<ModulePath, String>,
+    /// Modules whose items each module uses.
+    deps: HashMap<ModulePath, BTreeSet<ModulePath>>,
+}
+
+impl ModuleLayout {
+    /// Computes the layout of the C files that `map` has entities in.
+    pub(crate) fn new(map: &RichSourceMap, project_kind: &ProjectKind) -> Self {
+        // Group files into modules: same directory, same stem, same variant gate.
+        let mut keys: BTreeMap<(Vec<String>, Option<String>), BTreeSet<String>> = BTreeMap::new();
+        for entity in map.iter_definitions().chain(&map.defines) {
+            let cfg = variant_cfg(&entity.variant_tags);
+            keys.entry((module_key(&entity.span.file), cfg))
+                .or_default()
+                .insert(entity.span.file.clone());
+        }
+
+        let root_file = match project_kind {
+            ProjectKind::Executable => map
+                .app_functions
+                .iter()
+                .find(|f| f.name() == Some("main"))
+                .map(|f| f.span.file.as_str()),
+            ProjectKind::Library => None,
+        };
+        let single_module = keys.len() <= 1;
+        let paths = module_paths(keys.keys().map(|(key, _)| key.clone()).collect());
+
+        let mut layout = ModuleLayout::default();
+        for (((_, cfg), files), path) in keys.iter().zip(paths) {
+            let is_root = single_module || root_file.is_some_and(|f| files.contains(f));
+            let path = if is_root { Vec::new() } else { path };
+            if let Some(cfg) = cfg {
+                layout.cfgs.insert(path.clone(), cfg.clone());
+            }
+            for file in files {
+                layout.modules.insert(file.clone(), path.clone());
+            }
+        }
+
+        for (file, deps) in file_dependencies(map) {
+            let Some(module) = layout.modules.get(&file) else {
+                continue;
+            };
+            let dep_modules: BTreeSet<ModulePath> = deps
+                .iter()
+                .filter_map(|dep| layout.modules.get(dep))
+                .filter(|dep| *dep != module)
+                .cloned()
+                .collect();
+            layout
+                .deps
+                .entry(module.clone())
+                .or_default()
+                .extend(dep_modules);
+        }
+        layout
+    }
+
+    /// The module declarations from `file` are placed in; `None` for files without entities.
+    pub(crate) fn module_of(&self, file: &str) -> Option<&ModulePath> {
+        self.modules.get(file)
+    }
+
+    /// The `cfg` predicate gating `module` itself, if it holds build-variant files.
+    pub(crate) fn cfg(&self, module: &[String]) -> Option<&str> {
+        self.cfgs.get(module).map(String::as_str)
+    }
+
+    /// The `cfg` predicate under which `module` exists: its own gate combined with its ancestors'.
+    pub(crate) fn effective_cfg(&self, module: &[String]) -> Option<String> {
+        let gates: Vec<&str> = (1..=module.len())
+            .filter_map(|len| self.cfg(&module[..len]))
+            .collect();
+        match gates.as_slice() {
+            [] => None,
+            [gate] => Some(gate.to_string()),
+            gates => Some(format!("all({})", gates.join(", "))),
+        }
+    }
+
+    /// Modules whose items `module` uses.
+    pub(crate) fn deps(&self, module: &[String]) -> impl Iterator<Item = &ModulePath> {
+        self.deps.get(module).into_iter().flatten()
+    }
+}
+
+/// The `cfg` predicate selecting the build variants in `tags`: any of the tagged values of each
+/// driving variable, for all variables. `None` for untagged files.
+fn variant_cfg(tags: &[(String, String)]) -> Option<String> {
+    let mut by_var: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (var, value) in tags {
+        by_var.entry(var).or_default().insert(value);
+    }
+    let preds: Vec<String> = by_var
+        .iter()
+        .map(|(var, values)| {
+            let cfgs: Vec<String> = values.iter().map(|v| format!("{var}_{v}")).collect();
+            match cfgs.as_slice() {
+                [cfg] => cfg.clone(),
+                cfgs => format!("any({})", cfgs.join(", ")),
+            }
+        })
+        .collect();
+    match preds.as_slice() {
+        [] => None,
+        [pred] => Some(pred.clone()),
+        preds => Some(format!("all({})", preds.join(", "))),
+    }
+}
+
+/// The directories of `file` followed by its stem.
+fn module_key(file: &str) -> Vec<String> {
+    let path = Path::new(file);
+    let mut key: Vec<String> = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|c| match c {
+            Component::Normal(dir) => Some(dir.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    key.push(
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    );
+    key
+}
+
+/// Turns module keys into distinct module paths, in order: drops directories shared by every
+/// key, sanitizes each component into an identifier and disambiguates clashes with a suffix.
+fn module_paths(mut keys: Vec<Vec<String>>) -> Vec<ModulePath> {
+    while !keys.is_empty()
+        && keys.iter().all(|k| k.len() > 1)
+        && keys.windows(2).all(|w| w[0][0] == w[1][0])
+    {
+        for key in &mut keys {
+            key.remove(0);
+        }
+    }
+
+    let mut used: BTreeSet<ModulePath> = BTreeSet::new();
+    keys.into_iter()
+        .map(|key| {
+            let mut path: ModulePath = key.iter().map(|c| module_ident(c)).collect();
+            if RESERVED_TOP_LEVEL.contains(&path[0].as_str()) {
+                path[0].push('_');
+            }
+            let base = path.last().cloned().unwrap_or_default();
+            let mut n = 2;
+            while used.contains(&path) {
+                *path.last_mut().unwrap() = format!("{base}_{n}");
+                n += 1;
+            }
+            used.insert(path.clone());
+            path
+        })
+        .collect()
+}
+
+/// A lowercase Rust identifier for the C file or directory name `name`.
+fn module_ident(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '_' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '_',
+        })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if is_keyword(&ident) {
+        ident.push('_');
+    }
+    ident
+}
+
+/// For every file, the files whose declarations it may use: the project headers it includes,
+/// directly or transitively, and the files defining the functions and globals it references.
+fn file_dependencies(map: &RichSourceMap) -> BTreeMap<String, BTreeSet<String>> {
+    let files: BTreeSet<&str> = map
+        .iter_all_entities()
+        .map(|e| e.span.file.as_str())
+        .collect();
+
+    let mut includes: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for directive in &map.include_paths {
+        let includer = directive.span.file.as_str();
+        if let Some(included) = resolve_include(directive, &files) {
+            includes.entry(includer).or_default().insert(included);
+        }
+    }
+
+    let mut deps: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for &file in &files {
+        let mut seen: BTreeSet<&str> = BTreeSet::new();
+        let mut stack = vec![file];
+        while let Some(current) = stack.pop() {
+            for &included in includes.get(current).into_iter().flatten() {
+                if seen.insert(included) {
+                    stack.push(included);
+                }
+            }
+        }
+        seen.remove(file);
+        deps.insert(
+            file.to_string(),
+            seen.into_iter().map(String::from).collect(),
+        );
+    }
+
+    for symbol in map.symbols.iter() {
+        let Some(definition) = &symbol.definition else {
+            continue;
+        };
+        for tu in &symbol.referencing_tus {
+            if *tu != definition.file {
+                deps.entry(tu.clone())
+                    .or_default()
+                    .insert(definition.file.clone());
+            }
+        }
+    }
+    deps
+}
+
+/// The project file an `#include` directive names: relative to the including file's directory
+/// if it exists there, otherwise any project file with that path suffix.
+fn resolve_include<'a>(directive: &TopLevelEntity, files: &BTreeSet<&'a str>) -> Option<&'a str> {
+    let text = &directive.source_text;
+    let open = text.find(['"', '<'])?;
+    let close = if text[open..].starts_with('"') {
+        '"'
+    } else {
+        '>'
+    };
+    let rest = &text[open + 1..];
+    let name = &rest[..rest.find(close)?];
+
+    let mut local: Vec<&str> = directive.span.file.split('/').collect();
+    local.pop();
+    for part in name.split('/') {
+        match part {
+            "." => {}
+            ".." => {
+                local.pop();
+            }
+            part => local.push(part),
+        }
+    }
+    let local = local.join("/");
+    let suffix = format!("/{name}");
+    files
+        .get(local.as_str())
+        .or_else(|| files.iter().find(|f| **f == name || f.ends_with(&suffix)))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::{
+        ClangAST, EntityKind, Linkage, SourcePoint, SourceSpan, Symbol, SymbolKind, SymbolTable,
+    };
+
+    fn span(file: &str) -> SourceSpan {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset: 0,
+        };
+        SourceSpan {
+            file: file.into(),
+            start: point.clone(),
+            end: point,
+        }
+    }
+
+    fn entity(kind: EntityKind, file: &str, text: &str, ast: Option<ClangAST>) -> TopLevelEntity {
+        TopLevelEntity {
+            kind,
+            source_text: text.into(),
+            span: span(file),
+            ast,
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn function(file: &str, name: &str) -> TopLevelEntity {
+        let ast = ClangAST::FunctionDecl {
+            name: name.into(),
+            storage_class: None,
+        };
+        entity(EntityKind::FunctionDecl, file, name, Some(ast))
+    }
+
+    fn typedef(file: &str, name: &str) -> TopLevelEntity {
+        let ast = ClangAST::TypedefDecl {
+            name: name.into(),
+            underlying: None,
+        };
+        entity(EntityKind::TypedefDecl, file, name, Some(ast))
+    }
+
+    fn include(file: &str, header: &str) -> TopLevelEntity {
+        let text = match header.strip_prefix('<') {
+            Some(_) => format!("include {header}"),
+            None => format!("include \"{header}\""),
+        };
+        entity(EntityKind::InclusionDirective, file, &text, None)
+    }
+
+    fn path(p: &[&str]) -> ModulePath {
+        p.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn project() -> RichSourceMap {
+        let mut map = RichSourceMap::new();
+        map.app_types.push(typedef("src/util/list.h", "list"));
+        map.app_types.push(typedef("src/type.h", "kind"));
+        map.app_functions
+            .push(function("src/util/list.c", "list_push"));
+        map.app_functions.push(function("src/main.c", "main"));
+        map.include_paths.push(include("src/main.c", "util/list.h"));
+        map.include_paths
+            .push(include("src/util/list.h", "../type.h"));
+        map.include_paths
+            .push(include("src/util/list.h", "<stdio.h>"));
+        map
+    }
+
+    #[test]
+    fn modules_mirror_the_c_tree() {
+        let layout = ModuleLayout::new(&project(), &ProjectKind::Library);
+        assert_eq!(
+            layout.module_of("src/util/list.c"),
+            Some(&path(&["util", "list"]))
+        );
+        assert_eq!(
+            layout.module_of("src/util/list.h"),
+            Some(&path(&["util", "list"]))
+        );
+        assert_eq!(layout.module_of("src/type.h"), Some(&path(&["type_"])));
+        assert_eq!(layout.module_of("src/main.c"), Some(&path(&["main_"])));
+        assert_eq!(layout.module_of("elsewhere.c"), None);
+    }
+
+    #[test]
+    fn executable_root_is_the_main_file() {
+        let layout = ModuleLayout::new(&project(), &ProjectKind::Executable);
+        assert_eq!(layout.module_of("src/main.c"), Some(&path(&[])));
+        assert_eq!(
+            layout.module_of("src/util/list.c"),
+            Some(&path(&["util", "list"]))
+        );
+    }
+
+    #[test]
+    fn single_file_is_the_root() {
+        let mut map = RichSourceMap::new();
+        map.app_functions.push(function("src/lib.c", "api"));
+        map.app_types.push(typedef("src/lib.c", "handle"));
+        let layout = ModuleLayout::new(&map, &ProjectKind::Library);
+        assert_eq!(layout.module_of("src/lib.c"), Some(&path(&[])));
+    }
+
+    #[test]
+    fn dependencies_follow_includes_and_symbols() {
+        let mut map = project();
+        map.symbols = SymbolTable {
+            symbols: vec![Symbol {
+                name: "list_push".into(),
+                kind: SymbolKind::Function,
+                linkage: Linkage::External,
+                declarations: Vec::new(),
+                definition: Some(span("src/util/list.c")),
+                conflicting_definitions: Vec::new(),
+                declaring_headers: vec!["src/util/list.h".into()],
+                referencing_tus: vec!["src/main.c".into()],
+            }],
+        };
+        let layout = ModuleLayout::new(&map, &ProjectKind::Executable);
+        let deps: Vec<&ModulePath> = layout.deps(&[]).collect();
+        assert_eq!(deps, [&path(&["type_"]), &path(&["util", "list"])]);
+        let deps: Vec<&ModulePath> = layout.deps(&path(&["util", "list"])).collect();
+        assert_eq!(deps, [&path(&["type_"])]);
+    }
+
+    #[test]
+    fn variant_files_get_gated_modules() {
+        let mut map = RichSourceMap::new();
+        for (file, value) in [("src/backend.c", "alpha"), ("src/backend.h", "")] {
+            let mut f = function(file, "backend");
+            if !value.is_empty() {
+                f.variant_tags = vec![
+                    ("BACKEND".into(), value.into()),
+                    ("BACKEND".into(), "beta".into()),
+                    ("MODE".into(), "fast".into()),
+                ];
+            }
+            map.app_functions.push(f);
+        }
+        let layout = ModuleLayout::new(&map, &ProjectKind::Library);
+        let gated = layout.module_of("src/backend.c").unwrap();
+        assert_eq!(gated, &path(&["backend_2"]));
+        assert_eq!(layout.module_of("src/backend.h"), Some(&path(&["backend"])));
+        assert_eq!(
+            layout.cfg(gated),
+            Some("all(any(BACKEND_alpha, BACKEND_beta), MODE_fast)")
+        );
+        assert_eq!(layout.cfg(&path(&["backend"])), None);
+    }
+}
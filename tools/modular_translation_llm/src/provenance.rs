@@ -0,0 +1,184 @@
+//! The provenance of a modular translation, which puts each C declaration in the module of its C
+//! file under the Rust name the [`NameMap`] gives it.
+
+use build_project_spec::{ProjectKind, ProjectSpec};
+use c_ast::RichSourceMap;
+use full_source::CargoPackage;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use name_map::NameMap;
+use provenance_map::{Origin, ProvenanceMap};
+use quantize_rust_spans::RustItemMap;
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::modules::{ModuleLayout, ModulePath};
+
+/// Links the items of a modular translation to the C declarations they were translated from.
+pub struct ModularProvenance;
+
+impl Tool for ModularProvenance {
+    fn name(&self) -> &'static str {
+        "modular_provenance"
+    }
+
+    /// Inputs:
+    /// 1. [`RustItemMap`] id -- the items of the package the translation produced.
+    /// 2. [`RichSourceMap`] id -- the C declarations it translated.
+    /// 3. [`ProjectSpec`] id -- the kind of project, which decides the crate root.
+    /// 4. [`NameMap`] id -- the Rust names the translation followed.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let item_map = context
+            .ir_snapshot
+            .get::<RustItemMap>(inputs[0])
+            .ok_or("modular_provenance: no RustItemMap in IR")?;
+        let package = context
+            .ir_snapshot
+            .get::<CargoPackage>(item_map.cargo_pkg_idx)
+            .ok_or("modular_provenance: no CargoPackage in IR")?;
+        let c_ast = context
+            .ir_snapshot
+            .get::<RichSourceMap>(inputs[1])
+            .ok_or("modular_provenance: no RichSourceMap in IR")?;
+        let project_kind = &context
+            .ir_snapshot
+            .get::<ProjectSpec>(inputs[2])
+            .ok_or("modular_provenance: no ProjectSpec in IR")?
+            .kind;
+        let names = context
+            .ir_snapshot
+            .get::<NameMap>(inputs[3])
+            .ok_or("modular_provenance: no NameMap in IR")?;
+
+        let map = translated_provenance(c_ast, names, project_kind, item_map, package);
+        info!(
+            "Provenance: {} translated items linked to their C declarations",
+            map.entries.len()
+        );
+        Ok(Box::new(map))
+    }
+}
+
+/// Links each C declaration of `c_ast` to the item named as `names` says in the module of its C
+/// file, in `package`, whose items `item_map` maps.
+fn translated_provenance(
+    c_ast: &RichSourceMap,
+    names: &NameMap,
+    project_kind: &ProjectKind,
+    item_map: &RustItemMap,
+    package: &CargoPackage,
+) -> ProvenanceMap {
+    let layout = ModuleLayout::new(c_ast, project_kind);
+    ProvenanceMap::link(
+        c_ast,
+        Some(names),
+        item_map,
+        package,
+        Origin::Translated,
+        |entity, file| {
+            layout
+                .module_of(&entity.span.file)
+                .is_some_and(|module| module_files(module, project_kind).contains(&file.into()))
+        },
+    )
+}
+
+/// The files the code of `module` may be in: `src/a/b.rs`, or `src/a/b/mod.rs` if it has
+/// submodules; the crate root for the root module.
+fn module_files(module: &ModulePath, project_kind: &ProjectKind) -> Vec<PathBuf> {
+    if module.is_empty() {
+        let root = match project_kind {
+            ProjectKind::Executable => "src/main.rs",
+            ProjectKind::Library => "src/lib.rs",
+        };
+        return vec![root.into()];
+    }
+    let path = module.join("/");
+    vec![
+        format!("src/{path}.rs").into(),
+        format!("src/{path}/mod.rs").into(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::{ClangAST, EntityKind, SourcePoint, SourceSpan, TopLevelEntity};
+    use harvest_core::fs::RawDir;
+
+    fn function(file: &str, name: &str) -> TopLevelEntity {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset: 0,
+        };
+        TopLevelEntity {
+            kind: EntityKind::FunctionDecl,
+            source_text: String::new(),
+            span: SourceSpan {
+                file: file.into(),
+                start: point.clone(),
+                end: point,
+            },
+            ast: Some(ClangAST::FunctionDecl {
+                name: name.into(),
+                storage_class: None,
+            }),
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    #[test]
+    fn items_are_linked_in_the_module_of_their_c_file() {
+        let mut c_ast = RichSourceMap::new();
+        c_ast.app_functions.push(function("src/main.c", "main"));
+        c_ast
+            .app_functions
+            .push(function("src/util.c", "parseArgs"));
+        let names = name_map::build_name_map(&c_ast, &ProjectKind::Executable);
+
+        // A stray copy of the function in the root is not the one translated from `util.c`.
+        let mut dir = RawDir::default();
+        let main = "mod util;\n\nfn parse_args() {}\n\nfn main() {}\n";
+        dir.set_file("src/main.rs", main.as_bytes().to_vec())
+            .unwrap();
+        dir.set_file("src/util.rs", b"pub fn parse_args() {}\n".to_vec())
+            .unwrap();
+        let package = CargoPackage { dir };
+        let item_map = RustItemMap::new(Id::new(), &package).unwrap();
+
+        let map = translated_provenance(
+            &c_ast,
+            &names,
+            &ProjectKind::Executable,
+            &item_map,
+            &package,
+        );
+        let linked: Vec<_> = map
+            .entries
+            .iter()
+            .map(|e| {
+                (
+                    e.c_name.as_str(),
+                    e.rust_file.to_str().unwrap(),
+                    e.rust_lines,
+                )
+            })
+            .collect();
+        assert_eq!(
+            linked,
+            [
+                ("main", "src/main.rs", (5, 5)),
+                ("parseArgs", "src/util.rs", (1, 1))
+            ]
+        );
+        assert!(map.entries.iter().all(|e| e.origin == Origin::Translated));
+    }
+}
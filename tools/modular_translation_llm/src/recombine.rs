@@ -1,27 +1,64 @@
 //! Utilities for recombining translated Rust declarations into a Cargo package.
 
 use build_project_spec::ProjectKind;
+use c_ast::EntityComments;
 use full_source::CargoPackage;
 use harvest_core::fs::RawDir;
-use std::collections::BTreeSet;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use syn::spanned::Spanned;
 use tracing::debug;
 
-use crate::translation::RustDeclaration;
+use crate::check::declared_name;
+use crate::docs;
+use crate::modules::{ModuleLayout, ModulePath};
 use crate::translation::TranslationResult;
 
-fn prepend_dependency_imports(translations: &[RustDeclaration], rust_code: String) -> String {
-    let imports = translations
-        .iter()
-        .flat_map(|decl| decl.dependencies.iter())
-        .map(|dep| dep.trim())
-        .filter(|dep| !dep.is_empty())
-        .map(|dep| format!("use {dep};"))
-        .collect::<BTreeSet<_>>()
-        .into_iter()
-        .collect::<Vec<_>>()
-        .join("\n");
-
-    (imports + "\n\n" + &rust_code).trim().into()
+/// File of the package listing the declarations that still had `cargo check` errors when
+/// translation moved on (see [`RustDeclaration::check_errors`](crate::RustDeclaration)), for the
+/// repair stages and reviewers. Only written if there are any.
+pub const CHECK_ERRORS_FILE: &str = "check_errors.json";
+
+/// One entry of [`CHECK_ERRORS_FILE`].
+#[derive(Serialize)]
+struct CheckFailure<'a> {
+    /// The C file the declaration was translated from.
+    source_file: &'a str,
+    /// The function or global the translation declares, if it parses.
+    declaration: Option<String>,
+    errors: &'a [String],
+}
+
+/// The translated code placed in one module.
+#[derive(Default)]
+struct ModuleCode {
+    macros: Vec<String>,
+    decls: Vec<String>,
+    /// Import paths requested by the declarations' `dependencies`.
+    imports: BTreeSet<String>,
+}
+
+impl ModuleCode {
+    fn is_empty(&self) -> bool {
+        self.macros.is_empty() && self.decls.is_empty()
+    }
+
+    /// Macros first, then declarations, separated by blank lines.
+    fn code(&self) -> String {
+        self.macros
+            .iter()
+            .chain(&self.decls)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn defines_macros(&self) -> bool {
+        self.macros
+            .iter()
+            .chain(&self.decls)
+            .any(|code| code.contains("macro_rules!"))
+    }
 }
 
 /// Recombines translated Rust declarations into a CargoPackage representation.
@@ -29,46 +66,425 @@ fn prepend_dependency_imports(translations: &[RustDeclaration], rust_code: Strin
 /// This function takes a translation result (declarations and Cargo.toml) and assembles them into
 /// a complete Cargo project structure with:
 /// - A Cargo.toml manifest (from the LLM translation response)
-/// - A src/main.rs (for executables) or src/lib.rs (for libraries)
+/// - A src/main.rs (for executables) or src/lib.rs (for libraries) as the crate root
+/// - One module per C file as laid out by `layout`, with `mod` declarations, imports of the
+///   modules each one uses, and (for libraries) `pub use` re-exports at the crate root
 /// - All necessary imports derived from declaration dependencies
-pub fn recombine_decls(
+pub(crate) fn recombine_decls(
     translation_result: TranslationResult,
     project_kind: &ProjectKind,
+    layout: &ModuleLayout,
 ) -> Result<CargoPackage, Box<dyn std::error::Error>> {
     debug!(
         "Recombining {} declarations",
         translation_result.translations.len()
     );
 
-    // Concatenate translated macros first, then translated declarations.
-    let macro_code = translation_result.macros.join("\n\n");
-    let decl_code = translation_result
-        .translations
-        .iter()
-        .map(|decl| decl.rust_code.as_str())
-        .collect::<Vec<_>>()
-        .join("\n\n");
-
-    let rust_code = if macro_code.is_empty() {
-        decl_code
-    } else if decl_code.is_empty() {
-        macro_code
-    } else {
-        format!("{macro_code}\n\n{decl_code}")
+    // Sort translated macros and declarations into modules, each carrying the comments of the C
+    // entity it came from as rustdoc. Items outside the crate root are made visible to the rest
+    // of the crate.
+    let mut modules: BTreeMap<ModulePath, ModuleCode> = BTreeMap::new();
+    modules.entry(Vec::new()).or_default();
+    // Declarations whose C file is unknown land in the root and may use any module.
+    let mut root_has_orphans = false;
+    let mut module_of = |file: Option<&String>| match file.and_then(|f| layout.module_of(f)) {
+        Some(module) => module.clone(),
+        None => {
+            root_has_orphans = true;
+            Vec::new()
+        }
     };
+    let no_comments = EntityComments::default();
+    for (i, code) in translation_result.macros.iter().enumerate() {
+        let comments = translation_result.macro_comments.get(i);
+        let module = module_of(translation_result.macro_files.get(i));
+        let code = crate_visible(code, &module);
+        let code = docs::document(&code, comments.unwrap_or(&no_comments));
+        modules.entry(module).or_default().macros.push(code);
+    }
+    for decl in &translation_result.translations {
+        let module = module_of(Some(&decl.source_file));
+        let code = crate_visible(&decl.rust_code, &module);
+        let entry = modules.entry(module).or_default();
+        entry.decls.push(docs::document(&code, &decl.comments));
+        entry.imports.extend(
+            decl.dependencies
+                .iter()
+                .map(|dep| dep.trim().to_string())
+                .filter(|dep| !dep.is_empty()),
+        );
+    }
 
-    let source_content = prepend_dependency_imports(&translation_result.translations, rust_code);
+    // Directories with no code of their own still need a module to hold their children.
+    let paths: Vec<ModulePath> = modules.keys().cloned().collect();
+    for path in paths {
+        for len in 1..path.len() {
+            modules.entry(path[..len].to_vec()).or_default();
+        }
+    }
 
-    // Determine the main source file name based on project kind
-    let source_file = match project_kind {
-        ProjectKind::Executable => "src/main.rs",
-        ProjectKind::Library => "src/lib.rs",
+    let (source_file, top_level_vis) = match project_kind {
+        ProjectKind::Executable => ("src/main.rs", ""),
+        ProjectKind::Library => ("src/lib.rs", "pub "),
     };
 
     // Create the directory structure
     let mut dir = RawDir::default();
     dir.set_file("Cargo.toml", translation_result.cargo_toml.into_bytes())?;
-    dir.set_file(source_file, source_content.into_bytes())?;
+    let failures: Vec<CheckFailure> = translation_result
+        .translations
+        .iter()
+        .filter(|decl| !decl.check_errors.is_empty())
+        .map(|decl| CheckFailure {
+            source_file: &decl.source_file,
+            declaration: declared_name(&decl.rust_code),
+            errors: &decl.check_errors,
+        })
+        .collect();
+    if !failures.is_empty() {
+        let json = serde_json::to_string_pretty(&failures)?;
+        dir.set_file(CHECK_ERRORS_FILE, json.into_bytes())?;
+    }
+    for (path, module) in &modules {
+        let mut header: Vec<String> = module.imports.iter().map(|i| format!("use {i};")).collect();
+
+        let crate_deps: Vec<&ModulePath> = if path.is_empty() && root_has_orphans {
+            modules
+                .iter()
+                .filter(|(p, m)| !p.is_empty() && !m.is_empty())
+                .map(|(p, _)| p)
+                .collect()
+        } else {
+            layout.deps(path).collect()
+        };
+        for dep in crate_deps {
+            header.push(format!(
+                "{}use crate::{}*;",
+                cfg_attr(layout, dep),
+                glob_prefix(dep)
+            ));
+        }
+
+        // Child modules, those defining macros first so the macros are in scope in the others.
+        let mut children: Vec<(&ModulePath, bool)> = modules
+            .keys()
+            .filter(|child| child.len() == path.len() + 1 && child.starts_with(path))
+            .map(|child| (child, defines_macros(&modules, child)))
+            .collect();
+        children.sort_by_key(|(_, macros)| !macros);
+        let vis = if path.is_empty() {
+            top_level_vis
+        } else {
+            "pub "
+        };
+        let mut mods = Vec::new();
+        for (child, macros) in children {
+            let mut decl = String::new();
+            if let Some(cfg) = layout.cfg(child) {
+                decl.push_str(&format!("#[cfg({cfg})]\n"));
+            }
+            if macros {
+                decl.push_str("#[macro_use]\n");
+            }
+            decl.push_str(&format!("{vis}mod {};", child.last().unwrap()));
+            mods.push(decl);
+        }
+
+        // A library exposes the items of every module at its root, as when it was a single file.
+        if path.is_empty() && matches!(project_kind, ProjectKind::Library) {
+            for (p, m) in &modules {
+                if !p.is_empty() && !m.is_empty() {
+                    mods.push(format!(
+                        "{}pub use {}*;",
+                        cfg_attr(layout, p),
+                        glob_prefix(p)
+                    ));
+                }
+            }
+        }
+
+        let content = [header.join("\n"), mods.join("\n"), module.code()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let file = if path.is_empty() {
+            source_file.to_string()
+        } else if modules
+            .keys()
+            .any(|p| p.len() > path.len() && p.starts_with(path))
+        {
+            format!("src/{}/mod.rs", path.join("/"))
+        } else {
+            format!("src/{}.rs", path.join("/"))
+        };
+        dir.set_file(&file, content.trim().as_bytes().to_vec())?;
+    }
 
     Ok(CargoPackage { dir })
 }
+
+/// Whether `module` or any module below it defines a `macro_rules!` macro.
+fn defines_macros(modules: &BTreeMap<ModulePath, ModuleCode>, module: &ModulePath) -> bool {
+    modules
+        .iter()
+        .any(|(p, m)| p.starts_with(module) && m.defines_macros())
+}
+
+/// `#[cfg(..)]` and a newline if `module` only exists in some build variants.
+fn cfg_attr(layout: &ModuleLayout, module: &[String]) -> String {
+    layout
+        .effective_cfg(module)
+        .map(|cfg| format!("#[cfg({cfg})]\n"))
+        .unwrap_or_default()
+}
+
+/// `module` as a path prefix below `crate::`, e.g. `util::list::`; empty for the root.
+fn glob_prefix(module: &[String]) -> String {
+    module.iter().map(|m| format!("{m}::")).collect()
+}
+
+/// Raises private top-level items of `rust_code`, the fields of its structs and unions and the
+/// methods and constants of its inherent impl blocks to `pub(crate)` so the other modules of the
+/// crate can use them. Leaves crate-root code, and code that does not parse, untouched.
+fn crate_visible(rust_code: &str, module: &[String]) -> String {
+    if module.is_empty() {
+        return rust_code.to_string();
+    }
+    let Ok(file) = syn::parse_file(rust_code) else {
+        return rust_code.to_string();
+    };
+    let inherited = |vis: &syn::Visibility| matches!(vis, syn::Visibility::Inherited);
+    let fields = |fields: &mut dyn Iterator<Item = &syn::Field>| -> Vec<proc_macro2::Span> {
+        fields
+            .filter(|field| inherited(&field.vis))
+            .map(|field| field.ident.as_ref().map_or(field.ty.span(), |i| i.span()))
+            .collect()
+    };
+    let mut spans = Vec::new();
+    for item in &file.items {
+        match item {
+            syn::Item::Const(i) if inherited(&i.vis) => spans.push(i.const_token.span),
+            syn::Item::Static(i) if inherited(&i.vis) => spans.push(i.static_token.span),
+            syn::Item::Fn(i) if inherited(&i.vis) => spans.push(i.sig.span()),
+            syn::Item::Enum(i) if inherited(&i.vis) => spans.push(i.enum_token.span),
+            syn::Item::Type(i) if inherited(&i.vis) => spans.push(i.type_token.span),
+            syn::Item::Trait(i) if inherited(&i.vis) => {
+                spans.push(i.unsafety.map_or(i.trait_token.span, |u| u.span))
+            }
+            syn::Item::Struct(i) => {
+                if inherited(&i.vis) {
+                    spans.push(i.struct_token.span);
+                }
+                spans.extend(fields(&mut i.fields.iter()));
+            }
+            syn::Item::Union(i) => {
+                if inherited(&i.vis) {
+                    spans.push(i.union_token.span);
+                }
+                spans.extend(fields(&mut i.fields.named.iter()));
+            }
+            // Trait impls take the visibility of the trait.
+            syn::Item::Impl(i) if i.trait_.is_none() => {
+                spans.extend(i.items.iter().filter_map(|member| match member {
+                    syn::ImplItem::Fn(f) if inherited(&f.vis) => Some(f.sig.span()),
+                    syn::ImplItem::Const(c) if inherited(&c.vis) => Some(c.const_token.span),
+                    _ => None,
+                }));
+            }
+            _ => {}
+        }
+    }
+    let mut offsets: Vec<usize> = spans.iter().map(|span| span.byte_range().start).collect();
+
+    // Insert back to front so earlier offsets stay valid.
+    offsets.sort_unstable_by(|a, b| b.cmp(a));
+    let mut code = rust_code.to_string();
+    for offset in offsets {
+        code.insert_str(offset, "pub(crate) ");
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translation::RustDeclaration;
+    use c_ast::{ClangAST, EntityKind, RichSourceMap, SourcePoint, SourceSpan, TopLevelEntity};
+
+    fn entity(file: &str, name: &str) -> TopLevelEntity {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset: 0,
+        };
+        TopLevelEntity {
+            kind: EntityKind::FunctionDecl,
+            source_text: String::new(),
+            span: SourceSpan {
+                file: file.into(),
+                start: point.clone(),
+                end: point,
+            },
+            ast: Some(ClangAST::FunctionDecl {
+                name: name.into(),
+                storage_class: None,
+            }),
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn decl(file: &str, code: &str, deps: &[&str]) -> RustDeclaration {
+        RustDeclaration {
+            rust_code: code.into(),
+            dependencies: deps.iter().map(|d| d.to_string()).collect(),
+            comments: Default::default(),
+            source_file: file.into(),
+            check_errors: Vec::new(),
+        }
+    }
+
+    fn result(translations: Vec<RustDeclaration>) -> TranslationResult {
+        TranslationResult {
+            macros: vec!["macro_rules! twice { ($x:expr) => { $x * 2 }; }".into()],
+            macro_comments: Vec::new(),
+            macro_files: vec!["src/util/math.h".into()],
+            translations,
+            cargo_toml: "[package]".into(),
+        }
+    }
+
+    fn file(package: &CargoPackage, path: &str) -> String {
+        let contents = package.dir.get_file(path).expect(path);
+        String::from_utf8(contents.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn single_file_project_stays_in_the_root() {
+        let mut map = RichSourceMap::new();
+        map.app_functions.push(entity("main.c", "main"));
+        let layout = ModuleLayout::new(&map, &ProjectKind::Executable);
+        let mut result = result(vec![decl("main.c", "fn main() {}", &["std::ffi::c_int"])]);
+        result.macro_files = vec!["main.c".into()];
+        let package = recombine_decls(result, &ProjectKind::Executable, &layout).unwrap();
+        assert_eq!(
+            file(&package, "src/main.rs"),
+            "use std::ffi::c_int;\n\n\
+             macro_rules! twice { ($x:expr) => { $x * 2 }; }\n\nfn main() {}"
+        );
+    }
+
+    #[test]
+    fn modules_follow_the_c_files() {
+        let mut map = RichSourceMap::new();
+        map.app_functions.push(entity("src/util/math.c", "add"));
+        map.app_functions.push(entity("src/util/math.h", "sub"));
+        map.app_functions.push(entity("src/io.c", "print"));
+        map.app_functions.push(entity("src/main.c", "main"));
+        let layout = ModuleLayout::new(&map, &ProjectKind::Executable);
+        let result = result(vec![
+            decl("src/util/math.c", "fn add() {}", &[]),
+            decl("src/io.c", "pub fn print() {}\nimpl S {}", &["std::io"]),
+            decl("src/main.c", "fn main() {}", &[]),
+        ]);
+        let package = recombine_decls(result, &ProjectKind::Executable, &layout).unwrap();
+        assert_eq!(
+            file(&package, "src/main.rs"),
+            "#[macro_use]\nmod util;\nmod io;\n\nfn main() {}"
+        );
+        assert_eq!(
+            file(&package, "src/util/mod.rs"),
+            "#[macro_use]\npub mod math;"
+        );
+        assert_eq!(
+            file(&package, "src/util/math.rs"),
+            "macro_rules! twice { ($x:expr) => { $x * 2 }; }\n\npub(crate) fn add() {}"
+        );
+        assert_eq!(
+            file(&package, "src/io.rs"),
+            "use std::io;\n\npub fn print() {}\nimpl S {}"
+        );
+    }
+
+    #[test]
+    fn fields_and_methods_are_visible_to_sibling_modules() {
+        let mut map = RichSourceMap::new();
+        map.app_types.push(entity("src/point.c", "point"));
+        map.app_functions.push(entity("src/main.c", "main"));
+        let layout = ModuleLayout::new(&map, &ProjectKind::Executable);
+        let result = result(vec![
+            decl(
+                "src/point.c",
+                "struct Point {\n    #[allow(dead_code)]\n    x: i32,\n    pub y: i32,\n}\n\
+                 impl Point {\n    const ORIGIN: i32 = 0;\n    unsafe fn norm(&self) -> i32 { self.x }\n}\n\
+                 impl Clone for Point {\n    fn clone(&self) -> Self { Point { x: self.x, y: self.y } }\n}\n\
+                 struct Pair(i32, pub i32);",
+                &[],
+            ),
+            decl(
+                "src/main.c",
+                "fn main() {\n    let p = point::Point { x: 1, y: 2 };\n    unsafe { p.norm() };\n}",
+                &[],
+            ),
+        ]);
+        let package = recombine_decls(result, &ProjectKind::Executable, &layout).unwrap();
+        assert_eq!(
+            file(&package, "src/point.rs"),
+            "pub(crate) struct Point {\n    #[allow(dead_code)]\n    pub(crate) x: i32,\n    pub y: i32,\n}\n\
+             impl Point {\n    pub(crate) const ORIGIN: i32 = 0;\n    pub(crate) unsafe fn norm(&self) -> i32 { self.x }\n}\n\
+             impl Clone for Point {\n    fn clone(&self) -> Self { Point { x: self.x, y: self.y } }\n}\n\
+             pub(crate) struct Pair(pub(crate) i32, pub i32);"
+        );
+    }
+
+    #[test]
+    fn check_errors_are_recorded_per_declaration() {
+        let mut map = RichSourceMap::new();
+        map.app_functions.push(entity("src/main.c", "main"));
+        let layout = ModuleLayout::new(&map, &ProjectKind::Executable);
+        let mut failing = decl("src/main.c", "fn helper() -> i32 { \"\" }", &[]);
+        failing.check_errors = vec!["error[E0308]: mismatched types".into()];
+        let clean = result(vec![decl("src/main.c", "fn main() {}", &[])]);
+        let package = recombine_decls(clean, &ProjectKind::Executable, &layout).unwrap();
+        assert!(package.dir.get_file(CHECK_ERRORS_FILE).is_err());
+
+        let result = result(vec![decl("src/main.c", "fn main() {}", &[]), failing]);
+        let package = recombine_decls(result, &ProjectKind::Executable, &layout).unwrap();
+        let recorded: serde_json::Value =
+            serde_json::from_str(&file(&package, CHECK_ERRORS_FILE)).unwrap();
+        assert_eq!(
+            recorded,
+            serde_json::json!([{
+                "source_file": "src/main.c",
+                "declaration": "helper",
+                "errors": ["error[E0308]: mismatched types"],
+            }])
+        );
+    }
+
+    #[test]
+    fn library_root_reexports_modules() {
+        let mut map = RichSourceMap::new();
+        map.app_functions.push(entity("src/util/math.c", "add"));
+        map.app_types.push(entity("src/util/math.h", "num"));
+        let mut gated = entity("src/backend.c", "run");
+        gated.variant_tags = vec![("BACKEND".into(), "alpha".into())];
+        map.app_functions.push(gated);
+        let layout = ModuleLayout::new(&map, &ProjectKind::Library);
+        let result = result(vec![
+            decl("src/util/math.c", "pub fn add() {}", &[]),
+            decl("src/backend.c", "pub fn run() {}", &[]),
+        ]);
+        let package = recombine_decls(result, &ProjectKind::Library, &layout).unwrap();
+        assert_eq!(
+            file(&package, "src/lib.rs"),
+            "#[macro_use]\npub mod util;\n#[cfg(BACKEND_alpha)]\npub mod backend;\n\
+             #[cfg(BACKEND_alpha)]\npub use backend::*;\npub use util::math::*;"
+        );
+        assert!(package.dir.get_file("src/main.rs").is_err());
+    }
+}
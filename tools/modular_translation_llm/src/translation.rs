@@ -8,16 +8,23 @@
 //! - No ordering constraints of function translations
 //! - Cargo.toml generated after function/global translation using aggregated dependencies
 
+use analyze_unsafe_constructs::UnsafeConstructReport;
 use build_config::BuildConfigIR;
 use build_project_spec::ProjectKind;
-use c_ast::TopLevelEntity;
+use c_ast::{EntityComments, EntityKind, SymbolTable, TopLevelEntity};
 use full_source::RawSource;
+use lower_types::LoweredTypes;
+use name_map::{NameKind, NameMap};
+use partial_translation::{TranslationUnits, extern_declaration};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::Config;
+use crate::check::ScratchCrate;
+use crate::context::ContextIndex;
+use crate::memory::{TranslationMemory, recall_batch, referenced};
 use crate::translation_llm::ModularTranslationLLM;
+use crate::{Config, TypeLowering};
 
 /// Represents a translated Rust declaration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +33,19 @@ pub struct RustDeclaration {
     pub rust_code: String,
     /// List of dependency module paths to import
     pub dependencies: Vec<String>,
+    /// Comments on the C declaration this was translated from, rendered as rustdoc during
+    /// recombination. Not part of the LLM response.
+    #[serde(skip)]
+    pub comments: EntityComments,
+    /// Project-relative path of the C file the declaration was read from; decides which module
+    /// of the output crate it is placed in. Not part of the LLM response.
+    #[serde(skip)]
+    pub source_file: String,
+    /// `cargo check` errors the declaration still had in the scratch crate when translation moved
+    /// on; empty if it checked cleanly or was not checked. Not part of the LLM response; recorded
+    /// in the package as [`CHECK_ERRORS_FILE`](crate::CHECK_ERRORS_FILE).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub check_errors: Vec<String>,
 }
 
 /// Result of the type translation containing only type declarations
@@ -50,6 +70,12 @@ pub struct MacroTranslationResult {
 #[derive(Debug, Deserialize)]
 pub struct TranslationResult {
     pub macros: Vec<String>,
+    /// Comments on the C macro definitions, parallel to `macros` (empty if the counts differ).
+    #[serde(default)]
+    pub macro_comments: Vec<EntityComments>,
+    /// C files the macros were defined in, parallel to `macros` (empty if the counts differ).
+    #[serde(default)]
+    pub macro_files: Vec<String>,
     pub translations: Vec<RustDeclaration>,
     pub cargo_toml: String,
 }
@@ -58,11 +84,14 @@ pub struct TranslationResult {
 ///
 /// This function translates C preprocessor macro definitions before all other passes.
 /// The output is a list of Rust code strings corresponding to each macro.
+///
+/// With a [`TranslationMemory`], only the macros it has no translation for are sent to the LLM.
 pub fn translate_macros(
     macro_definitions: &[TopLevelEntity],
     raw_source: &RawSource,
     project_kind: &ProjectKind,
     modular_llm: &ModularTranslationLLM,
+    memory: Option<&TranslationMemory>,
 ) -> Result<MacroTranslationResult, Box<dyn std::error::Error>> {
     debug!(
         "Starting macro translation for {} definitions",
@@ -73,8 +102,25 @@ pub fn translate_macros(
         return Ok(MacroTranslationResult { macros: Vec::new() });
     }
 
-    let translation_result =
-        modular_llm.translate_macros(macro_definitions, raw_source, project_kind)?;
+    let macros = recall_batch(
+        memory,
+        macro_definitions.len(),
+        |memory, i| {
+            let decl = &macro_definitions[i];
+            let dependencies = sources(macro_definitions, &decl.source_text);
+            memory_key(memory, "macro", decl, dependencies, modular_llm)
+        },
+        |misses| {
+            let decls: Vec<TopLevelEntity> = misses
+                .iter()
+                .map(|&i| macro_definitions[i].clone())
+                .collect();
+            Ok(modular_llm
+                .translate_macros(&decls, raw_source, project_kind)?
+                .macros)
+        },
+    )?;
+    let translation_result = MacroTranslationResult { macros };
 
     if translation_result.macros.len() != macro_definitions.len() {
         error!(
@@ -98,26 +144,83 @@ pub fn translate_macros(
 /// data layout for all types in the project. The results are then used as context
 /// for function and global variable translation.
 ///
+/// `lowered` holds the deterministic lowering of each declaration, if it has one (or nothing, if
+/// there are no lowerings). With [`TypeLowering::Direct`] a lowering is taken as the translation;
+/// otherwise it is given to the LLM as a reference whose layout it must keep.
+///
+/// With a [`TranslationMemory`], only the declarations it has no translation for are sent to the
+/// LLM. A declaration's memory key covers the C types it refers to and its reference.
+///
 /// Returns only the translated type declarations (no Cargo.toml).
 pub fn translate_types(
     type_decls: &[TopLevelEntity],
+    lowered: &[Option<&str>],
+    lowering: TypeLowering,
     raw_source: &RawSource,
     project_kind: &ProjectKind,
     modular_llm: &ModularTranslationLLM,
+    memory: Option<&TranslationMemory>,
 ) -> Result<TypeTranslationResult, Box<dyn std::error::Error>> {
     debug!(
         "Starting type translation for {} declarations",
         type_decls.len()
     );
 
-    if type_decls.is_empty() {
-        // No types to translate, return empty result
-        return Ok(TypeTranslationResult {
-            translations: Vec::new(),
-        });
+    let lowered_at = |i: usize| lowered.get(i).copied().flatten();
+    let is_direct = |i: usize| lowering == TypeLowering::Direct && lowered_at(i).is_some();
+    // Declarations that go to the LLM (or the memory).
+    let pending: Vec<usize> = (0..type_decls.len()).filter(|&i| !is_direct(i)).collect();
+    if lowering == TypeLowering::Direct {
+        info!(
+            "Type lowering: {} declarations lowered directly, {} left to the LLM",
+            type_decls.len() - pending.len(),
+            pending.len()
+        );
     }
 
-    let translation_result = modular_llm.translate_types(type_decls, raw_source, project_kind)?;
+    let fresh = if pending.is_empty() {
+        Vec::new()
+    } else {
+        recall_batch(
+            memory,
+            pending.len(),
+            |memory, j| {
+                let decl = &type_decls[pending[j]];
+                let mut dependencies = sources(type_decls, &decl.source_text);
+                dependencies.extend(lowered_at(pending[j]).map(str::to_string));
+                memory_key(memory, "type", decl, dependencies, modular_llm)
+            },
+            |misses| {
+                let decls: Vec<TopLevelEntity> = misses
+                    .iter()
+                    .map(|&j| type_decls[pending[j]].clone())
+                    .collect();
+                let references: Vec<Option<&str>> =
+                    misses.iter().map(|&j| lowered_at(pending[j])).collect();
+                Ok(modular_llm
+                    .translate_types(&decls, &references, raw_source, project_kind)?
+                    .translations)
+            },
+        )?
+    };
+
+    // LLM translations fill the gaps between the direct lowerings in order; a miscount leaves the
+    // result misaligned, as it would without lowerings.
+    let mut fresh = fresh.into_iter();
+    let mut translations: Vec<RustDeclaration> = (0..type_decls.len())
+        .filter_map(|i| match lowered_at(i) {
+            Some(code) if is_direct(i) => Some(RustDeclaration {
+                rust_code: code.to_string(),
+                dependencies: Vec::new(),
+                comments: EntityComments::default(),
+                source_file: String::new(),
+                check_errors: Vec::new(),
+            }),
+            _ => fresh.next(),
+        })
+        .collect();
+    translations.extend(fresh);
+    let translation_result = TypeTranslationResult { translations };
 
     if translation_result.translations.len() != type_decls.len() {
         error!(
@@ -137,10 +240,25 @@ pub fn translate_types(
 
 /// Translates function and global variable declarations to Rust using an LLM.
 ///
-/// This function translates FunctionDecl and VarDecl, with the type translations
-/// and interface translations provided as context. Each declaration is translated in its own request.
+/// This function translates FunctionDecl and VarDecl, with the macro, type and interface
+/// translations `context` selects for each declaration provided as context. Each declaration is
+/// translated in its own request.
+///
+/// When an [`UnsafeConstructReport`] is supplied, each function's flagged constructs are included
+/// in its request together with a suggested translation strategy.
+///
+/// With `max_check_retries`, each translation is checked with `cargo check` in a scratch crate
+/// built from the macro, type and interface translations, and is retranslated with its errors up
+/// to that many times. Errors left after the last retry are recorded in
+/// [`RustDeclaration::check_errors`].
+///
+/// With a [`TranslationMemory`], a declaration it has a translation for is not sent to the LLM.
+/// A declaration's memory key covers the context selected for it, so it is retranslated when a
+/// translation it refers to changes. Only translations without check errors are remembered, and
+/// a remembered translation is checked like a fresh one.
 ///
 /// Returns the translated declarations.
+#[allow(clippy::too_many_arguments)]
 pub fn translate_functions(
     function_and_global_decls: &[&TopLevelEntity],
     raw_source: &RawSource,
@@ -148,7 +266,11 @@ pub fn translate_functions(
     macro_translations: &MacroTranslationResult,
     type_translations: &TypeTranslationResult,
     interface_translations: &InterfaceTranslationResult,
+    context: &ContextIndex,
+    unsafe_report: Option<&UnsafeConstructReport>,
     modular_llm: &ModularTranslationLLM,
+    max_check_retries: Option<usize>,
+    memory: Option<&TranslationMemory>,
 ) -> Result<Vec<RustDeclaration>, Box<dyn std::error::Error>> {
     debug!(
         "Starting function/global translation for {} declarations",
@@ -160,18 +282,89 @@ pub fn translate_functions(
         return Ok(Vec::new());
     }
 
+    let mut scratch = match max_check_retries {
+        Some(_) => Some(ScratchCrate::new(
+            &macro_translations.macros,
+            &type_translations.translations,
+            &interface_translations.signatures,
+        )?),
+        None => None,
+    };
     let mut translations = Vec::new();
 
     for decl in function_and_global_decls {
-        let translation = modular_llm.translate_function_global(
-            decl,
-            raw_source,
-            project_kind,
-            macro_translations,
-            type_translations,
-            interface_translations,
-        )?;
-
+        let unsafe_constructs = unsafe_report
+            .and_then(|report| report.for_entity(decl))
+            .map(|analysis| analysis.prompt_notes())
+            .unwrap_or_default();
+        let decl_context = context.select(decl);
+        let key = memory.map(|memory| {
+            let dependencies = decl_context
+                .macros
+                .iter()
+                .chain(&decl_context.types)
+                .chain(&decl_context.interface)
+                .chain(&unsafe_constructs)
+                .cloned()
+                .collect();
+            memory_key(memory, "function", decl, dependencies, modular_llm)
+        });
+        let recalled = memory
+            .zip(key.as_deref())
+            .and_then(|(memory, key)| memory.get::<RustDeclaration>(key));
+        // A remembered translation may have been stored unchecked, or against other
+        // declarations, so it is checked like a fresh one; if it fails, its errors go to the
+        // first retry.
+        let from_memory = recalled.is_some();
+        let mut translation = match recalled {
+            Some(translation) => translation,
+            None => modular_llm.translate_function_global(
+                decl,
+                raw_source,
+                project_kind,
+                &decl_context,
+                unsafe_constructs.clone(),
+                None,
+            )?,
+        };
+
+        if let Some(scratch) = scratch.as_mut() {
+            let slot = scratch.slot(rust_name(decl, modular_llm));
+            let max_retries = max_check_retries.unwrap_or_default() + usize::from(from_memory);
+            let mut retries = 0;
+            loop {
+                translation.check_errors = scratch.check(slot, &translation)?;
+                if translation.check_errors.is_empty() {
+                    scratch.accept(slot, &translation);
+                    break;
+                }
+                if retries == max_retries {
+                    // Later declarations are checked against the stub instead.
+                    warn!(
+                        "`{}` still has {} cargo check errors after {} retries",
+                        decl.name().unwrap_or("<unnamed>"),
+                        translation.check_errors.len(),
+                        retries
+                    );
+                    break;
+                }
+                retries += 1;
+                translation = modular_llm.translate_function_global(
+                    decl,
+                    raw_source,
+                    project_kind,
+                    &decl_context,
+                    unsafe_constructs.clone(),
+                    Some((&translation.rust_code, &translation.check_errors.join("\n"))),
+                )?;
+            }
+        }
+
+        if let (Some(memory), Some(key)) = (memory, &key)
+            && translation.check_errors.is_empty()
+        {
+            memory.put(key, &translation);
+        }
         translations.push(translation);
     }
 
@@ -179,6 +372,17 @@ pub fn translate_functions(
         "Function/global translation complete: successfully translated {} declarations",
         translations.len()
     );
+    if scratch.is_some() {
+        let failing = translations
+            .iter()
+            .filter(|t| !t.check_errors.is_empty())
+            .count();
+        info!(
+            "{}/{} function/global translations pass cargo check",
+            translations.len() - failing,
+            translations.len()
+        );
+    }
 
     Ok(translations)
 }
@@ -188,14 +392,21 @@ pub fn translate_functions(
 /// This function translates FunctionDecl and VarDecl, with the type translations
 /// provided as context. All declarations are translated in a single batch.
 ///
+/// With a [`TranslationMemory`], only the declarations it has no signature for are sent to the
+/// LLM. A declaration's memory key covers the translations of the `type_decls` it refers to, and
+/// whether its signature must be a C ABI one.
+///
 /// Returns the translated signature lines.
+#[allow(clippy::too_many_arguments)]
 pub fn translate_interface(
     function_decls: &[TopLevelEntity],
     global_decls: &[TopLevelEntity],
     raw_source: &RawSource,
     project_kind: &ProjectKind,
+    type_decls: &[TopLevelEntity],
     type_translations: &TypeTranslationResult,
     modular_llm: &ModularTranslationLLM,
+    memory: Option<&TranslationMemory>,
 ) -> Result<InterfaceTranslationResult, Box<dyn std::error::Error>> {
     debug!(
         "Starting interface translation for {} function and {} global declarations",
@@ -209,12 +420,44 @@ pub fn translate_interface(
         });
     }
 
-    let signatures = modular_llm.translate_interface(
-        function_decls,
-        global_decls,
-        raw_source,
-        project_kind,
-        type_translations,
+    // Type translations stand for their C declarations only if they line up.
+    let type_codes: Vec<String> = if type_translations.translations.len() == type_decls.len() {
+        type_translations
+            .translations
+            .iter()
+            .map(|t| t.rust_code.clone())
+            .collect()
+    } else {
+        type_decls.iter().map(|d| d.source_text.clone()).collect()
+    };
+    let decls: Vec<&TopLevelEntity> = function_decls.iter().chain(global_decls).collect();
+    let signatures = recall_batch(
+        memory,
+        decls.len(),
+        |memory, i| {
+            let mut dependencies: Vec<String> = referenced(&decls[i].source_text, type_decls)
+                .into_iter()
+                .map(|t| type_codes[t].clone())
+                .collect();
+            if modular_llm.enforces_ffi_interface(decls[i], project_kind) {
+                dependencies.push("ffi_interface".to_string());
+            }
+            memory_key(memory, "interface", decls[i], dependencies, modular_llm)
+        },
+        |misses| {
+            let (functions, globals): (Vec<usize>, Vec<usize>) =
+                misses.iter().partition(|&&i| i < function_decls.len());
+            let functions: Vec<TopLevelEntity> =
+                functions.iter().map(|&i| decls[i].clone()).collect();
+            let globals: Vec<TopLevelEntity> = globals.iter().map(|&i| decls[i].clone()).collect();
+            modular_llm.translate_interface(
+                &functions,
+                &globals,
+                raw_source,
+                project_kind,
+                type_translations,
+            )
+        },
     )?;
 
     info!(
@@ -225,11 +468,165 @@ pub fn translate_interface(
     Ok(InterfaceTranslationResult { signatures })
 }
 
+/// The Rust name of the function or global `decl`.
+fn rust_name<'a>(
+    decl: &'a TopLevelEntity,
+    modular_llm: &'a ModularTranslationLLM,
+) -> Option<&'a str> {
+    let kind = match decl.kind {
+        EntityKind::FunctionDecl => NameKind::Function,
+        _ => NameKind::Global,
+    };
+    decl.name()
+        .map(|name| modular_llm.names().rust_name(kind, name))
+}
+
+/// The memory key of `decl` in `pass`, covering `dependencies` and the Rust names it must use.
+fn memory_key(
+    memory: &TranslationMemory,
+    pass: &str,
+    decl: &TopLevelEntity,
+    mut dependencies: Vec<String>,
+    modular_llm: &ModularTranslationLLM,
+) -> String {
+    let names = modular_llm.names().relevant([decl.source_text.as_str()]);
+    dependencies.push(serde_json::to_string(&names).unwrap_or_default());
+    memory.key(pass, &decl.source_text, &dependencies)
+}
+
+/// The C sources of the `decls` that `source` refers to.
+fn sources(decls: &[TopLevelEntity], source: &str) -> Vec<String> {
+    referenced(source, decls)
+        .into_iter()
+        .map(|i| decls[i].source_text.clone())
+        .collect()
+}
+
+/// Copies the C comments and source file of `decls` onto their translations. Translations are
+/// matched to declarations by position, so nothing is attached if the LLM returned a different
+/// count.
+fn attach_sources<'a>(
+    translations: &mut [RustDeclaration],
+    decls: impl ExactSizeIterator<Item = &'a TopLevelEntity>,
+) {
+    if translations.len() != decls.len() {
+        warn!(
+            "Not attaching C sources: {} translations for {} declarations",
+            translations.len(),
+            decls.len()
+        );
+        return;
+    }
+    for (translation, decl) in translations.iter_mut().zip(decls) {
+        translation.comments = decl.comments.clone();
+        translation.source_file = decl.span.file.clone();
+    }
+}
+
 fn collect_dependencies(translations: &[RustDeclaration]) -> Vec<String> {
     let deps = translations.iter().flat_map(|t| t.dependencies.iter());
     deps.collect::<BTreeSet<_>>().into_iter().cloned().collect()
 }
 
+/// Replaces the interface signature of each function and global that `units` leaves in C with
+/// its `extern "C"` declaration, exporting the C symbol of the functions renamed in C. Returns
+/// the declarations, attached to their C files.
+fn link_remaining(
+    app_functions: &[TopLevelEntity],
+    app_globals: &[TopLevelEntity],
+    interface: &mut InterfaceTranslationResult,
+    project_kind: &ProjectKind,
+    units: &TranslationUnits,
+) -> Result<Vec<RustDeclaration>, Box<dyn std::error::Error>> {
+    let decls = app_functions.len() + app_globals.len();
+    if interface.signatures.len() != decls {
+        return Err(format!(
+            "Cannot link the C code left untranslated: interface pass returned {} signatures for {} declarations",
+            interface.signatures.len(),
+            decls
+        )
+        .into());
+    }
+
+    let mut linked = Vec::new();
+    for (i, decl) in app_functions.iter().chain(app_globals).enumerate() {
+        let Some(name) = decl.name() else {
+            continue;
+        };
+        let is_function = i < app_functions.len();
+        if is_function && units.translates(name) {
+            continue;
+        }
+        let signature = &mut interface.signatures[i];
+        if is_function && name == "main" && matches!(project_kind, ProjectKind::Executable) {
+            // The C `main` is started by the Rust `main` `LinkRemainingC` adds.
+            signature.clear();
+            continue;
+        }
+        let export = (is_function && units.renamed.contains(name)).then_some(name);
+        *signature = extern_declaration(signature, &units.c_symbol(name), export)
+            .map_err(|e| format!("Cannot declare `{name}`, which stays in C: {e}"))?;
+        linked.push(RustDeclaration {
+            rust_code: signature.clone(),
+            dependencies: Vec::new(),
+            comments: decl.comments.clone(),
+            source_file: decl.span.file.clone(),
+            check_errors: Vec::new(),
+        });
+    }
+    info!(
+        "Partial translation: {} functions and globals stay in C",
+        linked.len()
+    );
+    Ok(linked)
+}
+
+/// The C names of the functions and globals of a partial translation that need a C ABI interface:
+/// those that stay in C, which the Rust code declares `extern "C"`, and the translated functions
+/// that C code may still call -- those `symbols` says a C file that keeps C code refers to. Globals
+/// always stay in C. An executable's `main` is left out, as no code calls it.
+fn ffi_declarations(
+    app_functions: &[TopLevelEntity],
+    app_globals: &[TopLevelEntity],
+    symbols: &SymbolTable,
+    units: &TranslationUnits,
+) -> BTreeSet<String> {
+    let translated = |f: &TopLevelEntity| f.name().is_some_and(|name| units.translates(name));
+    let kept_files: BTreeSet<&str> = app_functions
+        .iter()
+        .filter(|f| !translated(f))
+        .chain(app_globals)
+        .map(|decl| decl.span.file.as_str())
+        .collect();
+    app_functions
+        .iter()
+        .filter(|f| {
+            !translated(f)
+                || symbols.for_entity(f).is_some_and(|symbol| {
+                    symbol
+                        .referencing_tus
+                        .iter()
+                        .any(|file| kept_files.contains(file.as_str()))
+                })
+        })
+        .chain(app_globals)
+        .filter_map(|decl| decl.name())
+        .filter(|&name| name != "main")
+        .map(str::to_string)
+        .collect()
+}
+
+/// How [`translate_decls`] produces function bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionBodies {
+    /// Translate every function in full.
+    Translate,
+    /// Emit each function as its interface signature with a `todo!()` body, for skeleton-first
+    /// translation. The bodies are filled in later by
+    /// [`TranslateBodiesLlm`](crate::TranslateBodiesLlm).
+    Stub,
+}
+
 /// Orchestrates the translation of Clang declarations to Rust using an LLM.
 ///
 /// First, translates type declarations (TypedefDecl, RecordDecl, EnumDecl)
@@ -237,6 +634,19 @@ fn collect_dependencies(translations: &[RustDeclaration]) -> Vec<String> {
 /// Then, translates functions and globals (FunctionDecl, VarDecl) with type and interface context
 /// Finally, generates a Cargo.toml manifest based on collected dependencies from all translations.
 ///
+/// With [`FunctionBodies::Stub`], only globals are translated in the last step and functions are
+/// represented by their interface signatures.
+///
+/// With `units`, only the functions it selects are translated (or stubbed). The functions and
+/// globals of [`ffi_declarations`] get a C ABI interface, and the ones that stay in C are declared
+/// `extern "C"` from their signatures, as the context of the translated ones; an executable's
+/// `main` that stays in C gets no declaration.
+///
+/// Every request uses, and every translation is made to follow, the Rust names of `names`.
+///
+/// `lowered`, parallel to `app_types`, is used for type translation as `config.type_lowering`
+/// says.
+///
 /// Returns the combined translated declarations and a generated Cargo.toml manifest.
 #[allow(clippy::too_many_arguments)]
 pub fn translate_decls(
@@ -244,10 +654,16 @@ pub fn translate_decls(
     app_types: &[TopLevelEntity],
     app_globals: &[TopLevelEntity],
     app_functions: &[TopLevelEntity],
+    symbols: &SymbolTable,
     raw_source: &RawSource,
     project_kind: &ProjectKind,
     build_cfg: &BuildConfigIR,
+    unsafe_report: Option<&UnsafeConstructReport>,
+    mut names: NameMap,
+    lowered: Option<&LoweredTypes>,
     config: &Config,
+    bodies: FunctionBodies,
+    units: Option<&TranslationUnits>,
 ) -> Result<TranslationResult, Box<dyn std::error::Error>> {
     let total_decls = defines.len() + app_types.len() + app_globals.len() + app_functions.len();
 
@@ -264,30 +680,147 @@ pub fn translate_decls(
         return Err("No declarations to translate".into());
     }
 
-    let modular_llm = ModularTranslationLLM::build(config, build_cfg)?;
+    let ffi = units
+        .map(|units| ffi_declarations(app_functions, app_globals, symbols, units))
+        .unwrap_or_default();
+    // The C code left untranslated calls the translated functions by their C symbols.
+    for entry in &mut names.entries {
+        if entry.kind == NameKind::Function
+            && entry.rust_name != entry.c_name
+            && ffi.contains(&entry.c_name)
+        {
+            entry
+                .export_name
+                .get_or_insert_with(|| entry.c_name.clone());
+        }
+    }
+    let modular_llm =
+        ModularTranslationLLM::build(config, build_cfg, names)?.with_ffi_interface(ffi);
+    let memory = TranslationMemory::open(config, project_kind, build_cfg);
+    let memory = memory.as_ref();
 
     // Translate macros first
-    let macro_result = translate_macros(defines, raw_source, project_kind, &modular_llm)?;
+    let macro_result = translate_macros(defines, raw_source, project_kind, &modular_llm, memory)?;
+
+    let (macro_comments, macro_files) = if macro_result.macros.len() == defines.len() {
+        let comments = defines.iter().map(|d| d.comments.clone()).collect();
+        let files = defines.iter().map(|d| d.span.file.clone()).collect();
+        (comments, files)
+    } else {
+        (Vec::new(), Vec::new())
+    };
 
-    // Translate types
-    let type_result = translate_types(app_types, raw_source, project_kind, &modular_llm)?;
+    // Translate types, from their lowerings where the config asks for it
+    let lowered: Vec<Option<&str>> = match lowered {
+        _ if config.type_lowering == TypeLowering::Llm => Vec::new(),
+        Some(lowered) if lowered.types.len() == app_types.len() => lowered
+            .types
+            .iter()
+            .map(|t| t.rust_code.as_deref())
+            .collect(),
+        Some(lowered) => {
+            warn!(
+                "Ignoring type lowerings: {} lowerings for {} type declarations",
+                lowered.types.len(),
+                app_types.len()
+            );
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    let mut type_result = translate_types(
+        app_types,
+        &lowered,
+        config.type_lowering,
+        raw_source,
+        project_kind,
+        &modular_llm,
+        memory,
+    )?;
 
     // Translate interface (function and global signatures) with type context
-    let interface_result = translate_interface(
+    let mut interface_result = translate_interface(
         app_functions,
         app_globals,
         raw_source,
         project_kind,
+        app_types,
         &type_result,
         &modular_llm,
+        memory,
     )?;
 
-    // Combine globals and functions for function/global translation
-    let function_and_global_decls: Vec<_> =
-        app_globals.iter().chain(app_functions.iter()).collect();
+    // In a partial translation, what stays in C is declared from its signature, which then stands
+    // for it in the context of the rest.
+    let linked = match units {
+        Some(units) => link_remaining(
+            app_functions,
+            app_globals,
+            &mut interface_result,
+            project_kind,
+            units,
+        )?,
+        None => Vec::new(),
+    };
+    let functions: Vec<(usize, &TopLevelEntity)> = app_functions
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| units.is_none_or(|units| f.name().is_some_and(|n| units.translates(n))))
+        .collect();
+
+    // Combine globals and functions for function/global translation. Skeletons take functions
+    // from the interface pass instead; globals are translated in full either way, since a
+    // `todo!()` initializer does not compile. Globals that stay in C are not translated.
+    let globals: Vec<&TopLevelEntity> = match units {
+        Some(_) => Vec::new(),
+        None => app_globals.iter().collect(),
+    };
+    let mut stubs = match bodies {
+        FunctionBodies::Translate => Vec::new(),
+        FunctionBodies::Stub => {
+            let signatures = &interface_result.signatures;
+            if signatures.len() != app_functions.len() + app_globals.len() {
+                return Err(format!(
+                    "Cannot build a skeleton: interface pass returned {} signatures for {} declarations",
+                    signatures.len(),
+                    app_functions.len() + app_globals.len()
+                )
+                .into());
+            }
+            functions
+                .iter()
+                .map(|&(i, _)| RustDeclaration {
+                    rust_code: signatures[i].clone(),
+                    dependencies: Vec::new(),
+                    comments: EntityComments::default(),
+                    source_file: String::new(),
+                    check_errors: Vec::new(),
+                })
+                .collect()
+        }
+    };
+    let function_and_global_decls: Vec<_> = match bodies {
+        FunctionBodies::Translate => globals
+            .into_iter()
+            .chain(functions.iter().map(|&(_, f)| f))
+            .collect(),
+        FunctionBodies::Stub => globals,
+    };
+
+    // Each function or global request carries only the translations it refers to
+    let context = ContextIndex::new(
+        defines,
+        &macro_result,
+        app_types,
+        &type_result,
+        app_functions,
+        app_globals,
+        &interface_result,
+        config.scoped_context.then_some(config.context_token_budget),
+    );
 
     // Translate functions and globals with type context
-    let function_result = if function_and_global_decls.is_empty() {
+    let mut function_result = if function_and_global_decls.is_empty() {
         info!("No function or global declarations to translate");
         Vec::new()
     } else {
@@ -298,10 +831,22 @@ pub fn translate_decls(
             &macro_result,
             &type_result,
             &interface_result,
+            &context,
+            unsafe_report,
             &modular_llm,
+            config.check_decls.then_some(config.max_check_retries),
+            memory,
         )?
     };
 
+    attach_sources(&mut type_result.translations, app_types.iter());
+    attach_sources(&mut function_result, function_and_global_decls.into_iter());
+    if bodies == FunctionBodies::Stub {
+        attach_sources(&mut stubs, functions.iter().map(|&(_, f)| f));
+        function_result.extend(stubs);
+    }
+    function_result.extend(linked);
+
     // Combine results: types first, then functions/globals
     let mut combined_translations = type_result.translations;
     combined_translations.extend(function_result);
@@ -312,7 +857,20 @@ pub fn translate_decls(
     );
 
     let dependencies = collect_dependencies(&combined_translations);
-    let cargo_toml = modular_llm.generate_cargo_toml(dependencies, project_kind)?;
+    let cargo_toml_key = memory.map(|memory| memory.key("cargo_toml", "", &dependencies));
+    let cargo_toml = match memory
+        .zip(cargo_toml_key.as_deref())
+        .and_then(|(memory, key)| memory.get::<String>(key))
+    {
+        Some(cargo_toml) => cargo_toml,
+        None => {
+            let cargo_toml = modular_llm.generate_cargo_toml(dependencies, project_kind)?;
+            if let Some((memory, key)) = memory.zip(cargo_toml_key.as_deref()) {
+                memory.put(key, &cargo_toml);
+            }
+            cargo_toml
+        }
+    };
     let usage_by_call = modular_llm.usage_by_call();
     let usage_totals = modular_llm.usage_totals();
 
@@ -351,10 +909,102 @@ pub fn translate_decls(
         "token usage [total] - prompt: {}, output: {}, total: {}",
         usage_totals.prompt_tokens, usage_totals.output_tokens, usage_totals.total_tokens
     );
+    if let Some(memory) = memory {
+        let (hits, misses) = memory.stats();
+        info!("Translation memory: {hits} translations recalled, {misses} not found");
+    }
 
     Ok(TranslationResult {
         macros: macro_result.macros,
+        macro_comments,
+        macro_files,
         translations: combined_translations,
         cargo_toml,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::{ClangAST, Linkage, SourcePoint, SourceSpan, Symbol, SymbolKind};
+
+    fn decl(kind: EntityKind, ast: ClangAST, file: &str) -> TopLevelEntity {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset: 0,
+        };
+        TopLevelEntity {
+            kind,
+            source_text: String::new(),
+            span: SourceSpan {
+                file: file.into(),
+                start: point.clone(),
+                end: point,
+            },
+            ast: Some(ast),
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn function(name: &str, file: &str) -> TopLevelEntity {
+        let ast = ClangAST::FunctionDecl {
+            name: name.into(),
+            storage_class: None,
+        };
+        decl(EntityKind::FunctionDecl, ast, file)
+    }
+
+    fn symbol(name: &str, kind: SymbolKind, referencing_tus: &[&str]) -> Symbol {
+        Symbol {
+            name: name.into(),
+            kind,
+            linkage: Linkage::External,
+            declarations: Vec::new(),
+            definition: None,
+            conflicting_definitions: Vec::new(),
+            declaring_headers: Vec::new(),
+            referencing_tus: referencing_tus.iter().map(|tu| tu.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn only_declarations_c_code_reaches_get_a_c_abi() {
+        let functions = [
+            function("helper", "src/a.c"),
+            function("internal", "src/a.c"),
+            function("caller", "src/b.c"),
+            function("main", "src/b.c"),
+        ];
+        let globals = [decl(
+            EntityKind::VarDecl,
+            ClangAST::VarDecl {
+                name: "counter".into(),
+                storage_class: None,
+            },
+            "src/b.c",
+        )];
+        let symbols = SymbolTable {
+            symbols: vec![
+                symbol("helper", SymbolKind::Function, &["src/a.c", "src/b.c"]),
+                symbol("internal", SymbolKind::Function, &["src/a.c"]),
+                symbol("caller", SymbolKind::Function, &["src/b.c"]),
+                symbol("main", SymbolKind::Function, &[]),
+                symbol("counter", SymbolKind::Variable, &["src/a.c"]),
+            ],
+        };
+        let units = TranslationUnits {
+            functions: ["helper".to_string(), "internal".to_string()].into(),
+            renamed: BTreeSet::new(),
+        };
+
+        let ffi = ffi_declarations(&functions, &globals, &symbols, &units);
+        assert_eq!(
+            ffi,
+            ["caller", "counter", "helper"].map(String::from).into()
+        );
+    }
+}
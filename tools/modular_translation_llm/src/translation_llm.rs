@@ -7,15 +7,16 @@ use build_project_spec::ProjectKind;
 use c_ast::TopLevelEntity;
 use full_source::RawSource;
 use harvest_core::llm::{HarvestLLM, LLMUsageTotals, Usage, build_request};
+use name_map::{NameEntry, NameMap};
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeSet;
 use std::sync::Mutex;
 use tracing::warn;
 
 use crate::Config;
-use crate::translation::{
-    InterfaceTranslationResult, MacroTranslationResult, RustDeclaration, TypeTranslationResult,
-};
+use crate::context::PromptContext;
+use crate::translation::{MacroTranslationResult, RustDeclaration, TypeTranslationResult};
 
 fn declaration_source_text(decl: &TopLevelEntity) -> Result<String, Box<dyn std::error::Error>> {
     Ok(decl.source_text.clone())
@@ -36,6 +37,10 @@ const STRUCTURED_OUTPUT_SCHEMA_FUNCTIONS: &str =
 const STRUCTURED_OUTPUT_SCHEMA_INTERFACE: &str =
     include_str!("prompts/interface/structured_schema.json");
 
+/// Structured output JSON schema for the function body pass.
+const STRUCTURED_OUTPUT_SCHEMA_BODIES: &str =
+    include_str!("prompts/body_translation/structured_schema.json");
+
 /// Structured output JSON schema for Cargo.toml generation.
 const STRUCTURED_OUTPUT_SCHEMA_CARGO_TOML: &str =
     include_str!("prompts/cargo_toml/structured_schema.json");
@@ -52,6 +57,9 @@ const SYSTEM_PROMPT_FUNCTIONS: &str = include_str!("prompts/func_translation/sys
 /// System prompt for the interface pass.
 const SYSTEM_PROMPT_INTERFACE: &str = include_str!("prompts/interface/system_prompt.txt");
 
+/// System prompt for the function body pass.
+const SYSTEM_PROMPT_BODIES: &str = include_str!("prompts/body_translation/system_prompt.txt");
+
 /// System prompt for Cargo.toml generation.
 const SYSTEM_PROMPT_CARGO_TOML: &str = include_str!("prompts/cargo_toml/system_prompt.txt");
 
@@ -67,6 +75,12 @@ struct FunctionTranslationResult {
     pub translation: RustDeclaration,
 }
 
+/// Result of a function body pass response.
+#[derive(Debug, Deserialize)]
+struct BodyResult {
+    pub rust_code: String,
+}
+
 /// Result of interface pass response.
 #[derive(Debug, Deserialize)]
 struct InterfaceResult {
@@ -80,12 +94,13 @@ struct MacrosResult {
 }
 
 /// LLM abstraction layer for modular translation.
-/// Has support for 4 different types of LLM calls with different system prompts
+/// Has support for 6 different types of LLM calls with different system prompts
 // and structured output schemas:
 /// - types_llm: for translating type declarations
 /// - macros_llm: for translating macro definitions
 /// - interface_llm: for translating function and global variable signatures in a single batch
 /// - functions_llm: for translating function and global variable declarations one-by-one
+/// - bodies_llm: for translating function bodies one-by-one against a compiling skeleton crate
 /// - cargo_toml_llm: for generating Cargo.toml based on the list of dependencies used in the
 //    translated code
 pub struct ModularTranslationLLM {
@@ -93,7 +108,14 @@ pub struct ModularTranslationLLM {
     types_llm: HarvestLLM,
     interface_llm: HarvestLLM,
     functions_llm: HarvestLLM,
+    bodies_llm: HarvestLLM,
     cargo_toml_llm: HarvestLLM,
+    /// The Rust names every translation must use, given to each request and enforced on each
+    /// response.
+    names: NameMap,
+    /// C names of the functions and globals given a C ABI interface, as in a partial translation,
+    /// where C code calls translated functions and the globals stay in C.
+    ffi_interface: BTreeSet<String>,
     usage_totals_by_call: Mutex<ModularLLMUsageTotals>,
 }
 
@@ -103,6 +125,7 @@ enum LLMCallKind {
     Types,
     Interface,
     Functions,
+    Bodies,
     CargoToml,
 }
 
@@ -112,6 +135,7 @@ pub struct ModularLLMUsageTotals {
     pub types: LLMUsageTotals,
     pub interface: LLMUsageTotals,
     pub functions: LLMUsageTotals,
+    pub bodies: LLMUsageTotals,
     pub cargo_toml: LLMUsageTotals,
 }
 
@@ -122,16 +146,19 @@ impl ModularLLMUsageTotals {
                 + self.types.prompt_tokens
                 + self.interface.prompt_tokens
                 + self.functions.prompt_tokens
+                + self.bodies.prompt_tokens
                 + self.cargo_toml.prompt_tokens,
             output_tokens: self.macros.output_tokens
                 + self.types.output_tokens
                 + self.interface.output_tokens
                 + self.functions.output_tokens
+                + self.bodies.output_tokens
                 + self.cargo_toml.output_tokens,
             total_tokens: self.macros.total_tokens
                 + self.types.total_tokens
                 + self.interface.total_tokens
                 + self.functions.total_tokens
+                + self.bodies.total_tokens
                 + self.cargo_toml.total_tokens,
         }
     }
@@ -148,9 +175,13 @@ impl ModularTranslationLLM {
     /// too: that is where the cfg-gated declarations are actually emitted, so without it those
     /// translations hardcode the default configuration. When the IR is empty the extension is
     /// a no-op and each prompt is byte-identical to its static base.
+    ///
+    /// Each request includes the entries of `names` for the C names it contains, and the Rust
+    /// names are enforced on every translation returned.
     pub fn build(
         config: &Config,
         build_cfg: &BuildConfigIR,
+        names: NameMap,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let macros_system_prompt = build_system_prompt(SYSTEM_PROMPT_MACROS, build_cfg);
         let macros_llm = HarvestLLM::build(
@@ -176,6 +207,12 @@ impl ModularTranslationLLM {
             STRUCTURED_OUTPUT_SCHEMA_INTERFACE,
             &interface_system_prompt,
         )?;
+        let bodies_system_prompt = build_system_prompt(SYSTEM_PROMPT_BODIES, build_cfg);
+        let bodies_llm = HarvestLLM::build(
+            &config.llm,
+            STRUCTURED_OUTPUT_SCHEMA_BODIES,
+            &bodies_system_prompt,
+        )?;
         // The cargo_toml prompt additionally gains the "do not write [features]" guidance.
         let cargo_toml_system_prompt = build_system_prompt(SYSTEM_PROMPT_CARGO_TOML, build_cfg);
         let cargo_toml_llm = HarvestLLM::build(
@@ -189,7 +226,10 @@ impl ModularTranslationLLM {
             types_llm,
             interface_llm,
             functions_llm,
+            bodies_llm,
             cargo_toml_llm,
+            names,
+            ffi_interface: BTreeSet::new(),
             usage_totals_by_call: Mutex::new(ModularLLMUsageTotals::default()),
         })
     }
@@ -205,10 +245,35 @@ impl ModularTranslationLLM {
             LLMCallKind::Types => totals_by_call.types.add_usage(usage),
             LLMCallKind::Interface => totals_by_call.interface.add_usage(usage),
             LLMCallKind::Functions => totals_by_call.functions.add_usage(usage),
+            LLMCallKind::Bodies => totals_by_call.bodies.add_usage(usage),
             LLMCallKind::CargoToml => totals_by_call.cargo_toml.add_usage(usage),
         }
     }
 
+    /// The Rust names translations must use.
+    pub fn names(&self) -> &NameMap {
+        &self.names
+    }
+
+    /// Gives the functions and globals with the C names `names` a C ABI interface.
+    pub fn with_ffi_interface(mut self, names: BTreeSet<String>) -> Self {
+        self.ffi_interface = names;
+        self
+    }
+
+    /// Whether `decl` is given a C ABI interface: it is a public declaration of a library, or
+    /// [`Self::with_ffi_interface`] names it.
+    pub fn enforces_ffi_interface(
+        &self,
+        decl: &TopLevelEntity,
+        project_kind: &ProjectKind,
+    ) -> bool {
+        (matches!(project_kind, ProjectKind::Library) && decl.annotations.public)
+            || decl
+                .name()
+                .is_some_and(|name| self.ffi_interface.contains(name))
+    }
+
     pub fn usage_by_call(&self) -> ModularLLMUsageTotals {
         *self
             .usage_totals_by_call
@@ -237,9 +302,11 @@ impl ModularTranslationLLM {
         }
 
         #[derive(Serialize)]
-        struct RequestWithContext {
+        struct RequestWithContext<'a> {
             project_kind: String,
             declarations: Vec<DeclarationInput>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            names: Vec<&'a NameEntry>,
         }
 
         let project_kind_str = match project_kind {
@@ -252,12 +319,18 @@ impl ModularTranslationLLM {
             &RequestWithContext {
                 project_kind: project_kind_str.to_string(),
                 declarations: macro_sources.clone(),
+                names: self
+                    .names
+                    .relevant(macro_sources.iter().map(|d| d.source.as_str())),
             },
         )?;
 
         let (response, usage) = self.macros_llm.invoke(&request)?;
         self.record_usage(LLMCallKind::Macros, usage.as_ref());
-        let macro_result: MacrosResult = serde_json::from_str(&response)?;
+        let mut macro_result: MacrosResult = serde_json::from_str(&response)?;
+        for translation in &mut macro_result.macros {
+            *translation = self.names.enforce(translation);
+        }
 
         for (decl, translation) in macro_sources.iter().zip(macro_result.macros.iter()) {
             crate::info!(
@@ -279,25 +352,31 @@ impl ModularTranslationLLM {
     //               Used to retrieve the source text corresponding to each declaration.
     ///            - project_kind: the kind of project (executable or library) being translated.
     //               Used to decide whether we need to make these types #[repr(C)] (compatible with outside C code).
+    ///            - references: parallel to type_decls, the layout-preserving lowering of each
+    //               declaration that has one, which the translation must keep the layout of.
     pub fn translate_types(
         &self,
         type_decls: &[TopLevelEntity],
+        references: &[Option<&str>],
         _raw_source: &RawSource,
         project_kind: &ProjectKind,
     ) -> Result<TypeTranslationResult, Box<dyn std::error::Error>> {
         let mut decl_sources = Vec::new();
 
-        for decl in type_decls {
+        for (i, decl) in type_decls.iter().enumerate() {
             let source_text = declaration_source_text(decl)?;
-            decl_sources.push(DeclarationInput {
+            decl_sources.push(TypeDeclarationInput {
                 source: source_text,
+                reference: references.get(i).copied().flatten().map(str::to_string),
             });
         }
 
         #[derive(Serialize)]
-        struct RequestWithContext {
+        struct RequestWithContext<'a> {
             project_kind: String,
-            declarations: Vec<DeclarationInput>,
+            declarations: Vec<TypeDeclarationInput>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            names: Vec<&'a NameEntry>,
         }
 
         let project_kind_str = match project_kind {
@@ -310,12 +389,18 @@ impl ModularTranslationLLM {
             &RequestWithContext {
                 project_kind: project_kind_str.to_string(),
                 declarations: decl_sources.clone(),
+                names: self
+                    .names
+                    .relevant(decl_sources.iter().map(|d| d.source.as_str())),
             },
         )?;
 
         let (response, usage) = self.types_llm.invoke(&request)?;
         self.record_usage(LLMCallKind::Types, usage.as_ref());
-        let translation_result: TypeTranslationResult = serde_json::from_str(&response)?;
+        let mut translation_result: TypeTranslationResult = serde_json::from_str(&response)?;
+        for translation in &mut translation_result.translations {
+            translation.rust_code = self.names.enforce(&translation.rust_code);
+        }
         for (decl, translation) in decl_sources
             .iter()
             .zip(translation_result.translations.iter())
@@ -330,23 +415,28 @@ impl ModularTranslationLLM {
     }
 
     /// Translates a single function or global variable declaration to Rust using the
-    //  functions_llm, with the type translations provided as context.
+    //  functions_llm, with the macro, type and interface translations it refers to as context.
     /// Arguments: - decl: Clang AST node corresponding to either a FunctionDecl or VarDecl
     //               (global variable declaration)
     ///            - raw_source: the full source code of the project.
     //               Used to retrieve the source text corresponding to the declaration.
     ///            - project_kind: the kind of project (executable or library) being translated.
     //               Used to decide whether we need to make these declarations C-compatible.
-    ///            - type_translations: the result of translating type declarations.
-    //              Used as context for translating functions and globals.
+    ///            - context: the macro, type and interface translations selected for this
+    //               declaration. Used as context for translating functions and globals.
+    ///            - unsafe_constructs: notes on C constructs in this function that have no
+    //               direct safe-Rust equivalent, with a suggested strategy for each.
+    ///            - previous_attempt: a previous translation of the declaration and the
+    //               `cargo check` errors it caused, when retrying.
+    #[allow(clippy::too_many_arguments)]
     pub fn translate_function_global(
         &self,
         decl: &TopLevelEntity,
         _raw_source: &RawSource,
         project_kind: &ProjectKind,
-        macro_translations: &MacroTranslationResult,
-        type_translations: &TypeTranslationResult,
-        interface_translations: &InterfaceTranslationResult,
+        context: &PromptContext,
+        unsafe_constructs: Vec<String>,
+        previous_attempt: Option<(&str, &str)>,
     ) -> Result<RustDeclaration, Box<dyn std::error::Error>> {
         let source_text = declaration_source_text(decl)?;
 
@@ -355,12 +445,24 @@ impl ModularTranslationLLM {
         };
 
         #[derive(Serialize)]
-        struct RequestWithContext {
+        struct PreviousAttempt<'a> {
+            rust_code: &'a str,
+            errors: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct RequestWithContext<'a> {
             project_kind: String,
             macro_translations: Vec<String>,
             type_translations: Vec<String>,
             interface_translations: Vec<String>,
             declaration: DeclarationInput,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            names: Vec<&'a NameEntry>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            unsafe_constructs: Vec<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            previous_attempt: Option<PreviousAttempt<'a>>,
         }
 
         let project_kind_str = match project_kind {
@@ -368,27 +470,26 @@ impl ModularTranslationLLM {
             ProjectKind::Library => "library",
         };
 
-        let type_code: Vec<String> = type_translations
-            .translations
-            .iter()
-            .map(|t| t.rust_code.clone())
-            .collect();
-
         let request = build_request(
-            "Please translate the following C function or global variable declaration to Rust. Macro translations, type declarations, and function/global signatures have already been translated and are provided for context:",
+            "Please translate the following C function or global variable declaration to Rust. Macro translations, type declarations, and function/global signatures have already been translated, and those it refers to are provided for context:",
             &RequestWithContext {
                 project_kind: project_kind_str.to_string(),
-                macro_translations: macro_translations.macros.clone(),
-                type_translations: type_code,
-                interface_translations: interface_translations.signatures.clone(),
+                macro_translations: context.macros.clone(),
+                type_translations: context.types.clone(),
+                interface_translations: context.interface.clone(),
                 declaration: decl_source.clone(),
+                names: self.names.relevant([decl_source.source.as_str()]),
+                unsafe_constructs,
+                previous_attempt: previous_attempt
+                    .map(|(rust_code, errors)| PreviousAttempt { rust_code, errors }),
             },
         )?;
 
         let (response, usage) = self.functions_llm.invoke(&request)?;
         self.record_usage(LLMCallKind::Functions, usage.as_ref());
         let translation_result: FunctionTranslationResult = serde_json::from_str(&response)?;
-        let translations = translation_result.translation;
+        let mut translations = translation_result.translation;
+        translations.rust_code = self.names.enforce(&translations.rust_code);
         crate::info!(
             "Function/Global Translation complete:\n {} \n==>\n {}",
             decl_source.source,
@@ -397,6 +498,73 @@ impl ModularTranslationLLM {
         Ok(translations)
     }
 
+    /// Translates the body of a single function to Rust using the bodies_llm, keeping the
+    //  signature it was given in the skeleton crate.
+    /// Arguments: - decl: Clang AST node corresponding to a FunctionDecl
+    ///            - signature: the function's stub in the skeleton crate, with a `todo!()` body.
+    ///            - interface: the source files of the skeleton crate, and its Cargo.toml.
+    //               Used as context: the body is written against this frozen interface.
+    ///            - unsafe_constructs: notes on C constructs in this function that have no
+    //               direct safe-Rust equivalent, with a suggested strategy for each.
+    ///            - previous_errors: `cargo check` errors caused by the previous attempt, if any.
+    /// Returns the complete Rust function.
+    pub fn translate_body(
+        &self,
+        decl: &TopLevelEntity,
+        signature: &str,
+        interface: &[(String, String)],
+        unsafe_constructs: Vec<String>,
+        previous_errors: Option<&str>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        #[derive(Serialize)]
+        struct InterfaceFile<'a> {
+            path: &'a str,
+            contents: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct RequestWithContext<'a> {
+            interface: Vec<InterfaceFile<'a>>,
+            signature: &'a str,
+            declaration: DeclarationInput,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            names: Vec<&'a NameEntry>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            unsafe_constructs: Vec<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            previous_errors: Option<&'a str>,
+        }
+
+        let decl_source = DeclarationInput {
+            source: declaration_source_text(decl)?,
+        };
+        let request = build_request(
+            "Please translate the body of the following C function to Rust, keeping the given Rust signature. The crate it belongs to is provided for context, with `todo!()` as every function body:",
+            &RequestWithContext {
+                interface: interface
+                    .iter()
+                    .map(|(path, contents)| InterfaceFile { path, contents })
+                    .collect(),
+                signature,
+                declaration: decl_source.clone(),
+                names: self.names.relevant([decl_source.source.as_str()]),
+                unsafe_constructs,
+                previous_errors,
+            },
+        )?;
+
+        let (response, usage) = self.bodies_llm.invoke(&request)?;
+        self.record_usage(LLMCallKind::Bodies, usage.as_ref());
+        let mut body_result: BodyResult = serde_json::from_str(&response)?;
+        body_result.rust_code = self.names.enforce(&body_result.rust_code);
+        crate::info!(
+            "Body Translation complete:\n {} \n==>\n {}",
+            decl_source.source,
+            body_result.rust_code
+        );
+        Ok(body_result.rust_code)
+    }
+
     /// Translates function and global variable declarations to Rust signature lines using the interface_llm.
     /// Arguments: - function_decls: list of Clang AST nodes corresponding to FunctionDecl
     ///            - global_decls: list of Clang AST nodes corresponding to VarDecl
@@ -419,8 +587,7 @@ impl ModularTranslationLLM {
             let source_text = declaration_source_text(decl)?;
             decl_sources.push(InterfaceDeclarationInput {
                 source: source_text,
-                enforce_ffi_interface: matches!(project_kind, ProjectKind::Library)
-                    && decl.annotations.public,
+                enforce_ffi_interface: self.enforces_ffi_interface(decl, project_kind),
             });
         }
 
@@ -429,15 +596,17 @@ impl ModularTranslationLLM {
             let source_text = declaration_source_text(decl)?;
             decl_sources.push(InterfaceDeclarationInput {
                 source: source_text,
-                enforce_ffi_interface: false,
+                enforce_ffi_interface: self.enforces_ffi_interface(decl, project_kind),
             });
         }
 
         #[derive(Serialize)]
-        struct RequestWithContext {
+        struct RequestWithContext<'a> {
             project_kind: String,
             type_translations: Vec<String>,
             declarations: Vec<InterfaceDeclarationInput>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            names: Vec<&'a NameEntry>,
         }
 
         let project_kind_str = match project_kind {
@@ -457,12 +626,18 @@ impl ModularTranslationLLM {
                 project_kind: project_kind_str.to_string(),
                 type_translations: type_code,
                 declarations: decl_sources.clone(),
+                names: self
+                    .names
+                    .relevant(decl_sources.iter().map(|d| d.source.as_str())),
             },
         )?;
 
         let (response, usage) = self.interface_llm.invoke(&request)?;
         self.record_usage(LLMCallKind::Interface, usage.as_ref());
-        let interface_result: InterfaceResult = serde_json::from_str(&response)?;
+        let mut interface_result: InterfaceResult = serde_json::from_str(&response)?;
+        for signature in &mut interface_result.signatures {
+            *signature = self.names.enforce(signature);
+        }
 
         if interface_result.signatures.len() != decl_sources.len() {
             warn!(
@@ -531,6 +706,13 @@ struct DeclarationInput {
     source: String,
 }
 
+#[derive(Debug, Serialize, Clone)]
+struct TypeDeclarationInput {
+    source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+}
+
 #[derive(Debug, Serialize, Clone)]
 struct InterfaceDeclarationInput {
     source: String,
@@ -0,0 +1,131 @@
+//! Conversion of C identifiers to the Rust naming conventions.
+
+/// Rust keywords, strict and reserved, which cannot be used as plain identifiers.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Type and variant names of the prelude, which a type of the same name would shadow.
+const PRELUDE_TYPES: &[&str] = &[
+    "Box", "Err", "None", "Ok", "Option", "Result", "Some", "String", "Vec",
+];
+
+/// Whether `ident` is a Rust keyword.
+pub fn is_keyword(ident: &str) -> bool {
+    KEYWORDS.contains(&ident)
+}
+
+/// `fooBar`, `FOO_BAR` and `foo_bar` all become `foo_bar`.
+pub(crate) fn snake_case(name: &str) -> String {
+    escape(
+        words(name)
+            .iter()
+            .map(|w| w.to_ascii_lowercase())
+            .collect::<Vec<_>>()
+            .join("_"),
+    )
+}
+
+/// `fooBar`, `FOO_BAR` and `foo_bar` all become `FOO_BAR`.
+pub(crate) fn screaming_snake_case(name: &str) -> String {
+    escape(
+        words(name)
+            .iter()
+            .map(|w| w.to_ascii_uppercase())
+            .collect::<Vec<_>>()
+            .join("_"),
+    )
+}
+
+/// `foo_bar` and `FOO_BAR` become `FooBar`. A trailing `_t`, the C convention for type names,
+/// is dropped.
+pub fn upper_camel_case(name: &str) -> String {
+    let mut words = words(name);
+    if words.len() > 1 && words.last().is_some_and(|w| w == "t") {
+        words.pop();
+    }
+    let ident: String = words
+        .iter()
+        .map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(first) => {
+                    first.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase()
+                }
+                None => String::new(),
+            }
+        })
+        .collect();
+    if PRELUDE_TYPES.contains(&ident.as_str()) {
+        return ident + "_";
+    }
+    escape(ident)
+}
+
+/// Makes `ident` usable as a Rust identifier.
+fn escape(mut ident: String) -> String {
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if is_keyword(&ident) {
+        ident.push('_');
+    }
+    ident
+}
+
+/// Splits an identifier into words at underscores and at case changes: `HTTPServer_init`
+/// is `HTTP`, `Server`, `init`.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let chars: Vec<char> = part.chars().collect();
+        let mut word = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            if i > 0 && c.is_ascii_uppercase() {
+                let prev = chars[i - 1];
+                let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+                if prev.is_ascii_lowercase()
+                    || prev.is_ascii_digit()
+                    || (prev.is_ascii_uppercase() && next_lower)
+                {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            word.push(c);
+        }
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_rust_conventions() {
+        assert_eq!(snake_case("parseHTTPRequest"), "parse_http_request");
+        assert_eq!(snake_case("list_push"), "list_push");
+        assert_eq!(snake_case("Init"), "init");
+        assert_eq!(screaming_snake_case("gCounter"), "G_COUNTER");
+        assert_eq!(screaming_snake_case("MAX_LEN"), "MAX_LEN");
+        assert_eq!(upper_camel_case("node_t"), "Node");
+        assert_eq!(upper_camel_case("hash_table"), "HashTable");
+        assert_eq!(upper_camel_case("RGB_COLOR"), "RgbColor");
+        assert_eq!(upper_camel_case("vec2"), "Vec2");
+    }
+
+    #[test]
+    fn escapes_keywords_and_prelude_names() {
+        assert_eq!(snake_case("type"), "type_");
+        assert_eq!(snake_case("_"), "_");
+        assert_eq!(snake_case("__match"), "match_");
+        assert_eq!(upper_camel_case("self"), "Self_");
+        assert_eq!(upper_camel_case("option"), "Option_");
+        assert_eq!(upper_camel_case("t"), "T");
+    }
+}
@@ -0,0 +1,403 @@
+//! Enforcement of a [`NameMap`] on translated Rust code.
+//!
+//! Renames are applied as byte-range edits at the spans of the identifiers involved, so the rest
+//! of the code keeps its formatting. Only positions that refer to items are renamed: item
+//! declarations, type paths, value paths that are not local bindings, struct expressions and
+//! patterns, macro invocations and `use` trees. Identifiers inside macro arguments are left alone.
+
+use proc_macro2::TokenTree;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+
+use crate::{NameKind, NameMap};
+
+/// Path roots that never name a translated type.
+const PATH_ROOTS: &[&str] = &["crate", "self", "super", "Self"];
+
+pub(crate) fn enforce(names: &NameMap, code: &str) -> String {
+    let Ok(file) = syn::parse_file(code) else {
+        return code.to_string();
+    };
+    let mut renamer = Renamer::default();
+    for entry in &names.entries {
+        if entry.kind == NameKind::Type {
+            if entry.rust_name != entry.c_ident() {
+                renamer
+                    .types
+                    .entry(entry.c_ident().to_string())
+                    .or_insert_with(|| entry.rust_name.clone());
+            }
+            continue;
+        }
+        if entry.rust_name != entry.c_ident() {
+            renamer
+                .values
+                .entry(entry.c_ident().to_string())
+                .or_insert_with(|| entry.rust_name.clone());
+        }
+        if let Some(symbol) = &entry.export_name {
+            renamer
+                .exports
+                .insert(entry.c_ident().to_string(), symbol.clone());
+            renamer
+                .exports
+                .insert(entry.rust_name.clone(), symbol.clone());
+        }
+    }
+    renamer.visit_file(&file);
+    apply(code, renamer.edits)
+}
+
+#[derive(Default)]
+struct Renamer {
+    types: HashMap<String, String>,
+    values: HashMap<String, String>,
+    /// C symbol to keep for each exported function and static, by C and Rust name.
+    exports: HashMap<String, String>,
+    /// Bindings of the function being visited, which shadow values of the same name.
+    locals: BTreeSet<String>,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl Renamer {
+    fn rename(&mut self, ident: &syn::Ident, map: &HashMap<String, String>) -> bool {
+        match map.get(&ident.to_string()) {
+            Some(rust_name) => {
+                self.edits
+                    .push((ident.span().byte_range(), rust_name.clone()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn rename_type(&mut self, ident: &syn::Ident) -> bool {
+        let types = std::mem::take(&mut self.types);
+        let renamed = self.rename(ident, &types);
+        self.types = types;
+        renamed
+    }
+
+    fn rename_value(&mut self, ident: &syn::Ident) -> bool {
+        if self.locals.contains(&ident.to_string()) {
+            return false;
+        }
+        let values = std::mem::take(&mut self.values);
+        let renamed = self.rename(ident, &values);
+        self.values = values;
+        renamed
+    }
+
+    /// Renames the item a path refers to: the type at its root (`foo_t::new`, `color::RED`), or,
+    /// for a path from `crate`, `self` or `super`, the item at its end.
+    fn rename_path(&mut self, path: &syn::Path, value: bool) {
+        let segments: Vec<&syn::PathSegment> = path.segments.iter().collect();
+        let Some(first) = segments.first() else {
+            return;
+        };
+        if PATH_ROOTS.contains(&first.ident.to_string().as_str()) {
+            let last = &segments[segments.len() - 1].ident;
+            if !self.rename_value(last) {
+                self.rename_type(last);
+            }
+        } else if segments.len() == 1 && value {
+            if !self.rename_value(&first.ident) {
+                self.rename_type(&first.ident);
+            }
+        } else if path.leading_colon.is_none() {
+            self.rename_type(&first.ident);
+        }
+    }
+
+    /// Makes an exported function or static, the item `span` with `attrs`, keep its C symbol:
+    /// `no_mangle` becomes `export_name`, and an item with neither gets one if `needs_symbol`.
+    fn export(
+        &mut self,
+        attrs: &[syn::Attribute],
+        span: proc_macro2::Span,
+        needs_symbol: bool,
+        symbol: &str,
+    ) {
+        let export_name = format!("export_name = \"{symbol}\"");
+        let mut has_symbol = false;
+        for attr in attrs {
+            match &attr.meta {
+                // Edition 2024 requires the attribute to be marked unsafe.
+                syn::Meta::Path(path) if path.is_ident("no_mangle") => {
+                    self.edits
+                        .push((path.span().byte_range(), format!("unsafe({export_name})")));
+                    has_symbol = true;
+                }
+                syn::Meta::NameValue(nv) if nv.path.is_ident("export_name") => has_symbol = true,
+                syn::Meta::List(list) if list.path.is_ident("unsafe") => {
+                    for token in list.tokens.clone() {
+                        if let TokenTree::Ident(ident) = token {
+                            if ident == "no_mangle" {
+                                self.edits
+                                    .push((ident.span().byte_range(), export_name.clone()));
+                                has_symbol = true;
+                            } else if ident == "export_name" {
+                                has_symbol = true;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !has_symbol && needs_symbol {
+            let start = span.byte_range().start;
+            self.edits
+                .push((start..start, format!("#[unsafe({export_name})]\n")));
+        }
+    }
+
+    /// Visits a function body with its bindings shadowing values.
+    fn with_locals(&mut self, locals: BTreeSet<String>, visit: impl FnOnce(&mut Self)) {
+        let outer = self.locals.clone();
+        self.locals.extend(locals);
+        visit(self);
+        self.locals = outer;
+    }
+}
+
+impl<'ast> Visit<'ast> for Renamer {
+    fn visit_item_fn(&mut self, f: &'ast syn::ItemFn) {
+        let name = f.sig.ident.to_string();
+        if let Some(symbol) = self.exports.get(&name).cloned() {
+            self.export(&f.attrs, f.span(), f.sig.abi.is_some(), &symbol);
+        }
+        self.rename_value(&f.sig.ident);
+        let locals = bindings(|collector| collector.visit_item_fn(f));
+        self.with_locals(locals, |this| visit::visit_item_fn(this, f));
+    }
+
+    fn visit_impl_item_fn(&mut self, f: &'ast syn::ImplItemFn) {
+        let locals = bindings(|collector| collector.visit_impl_item_fn(f));
+        self.with_locals(locals, |this| visit::visit_impl_item_fn(this, f));
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast syn::ItemStruct) {
+        self.rename_type(&item.ident);
+        visit::visit_item_struct(self, item);
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast syn::ItemEnum) {
+        self.rename_type(&item.ident);
+        visit::visit_item_enum(self, item);
+    }
+
+    fn visit_item_union(&mut self, item: &'ast syn::ItemUnion) {
+        self.rename_type(&item.ident);
+        visit::visit_item_union(self, item);
+    }
+
+    fn visit_item_type(&mut self, item: &'ast syn::ItemType) {
+        self.rename_type(&item.ident);
+        visit::visit_item_type(self, item);
+    }
+
+    fn visit_item_static(&mut self, item: &'ast syn::ItemStatic) {
+        if let Some(symbol) = self.exports.get(&item.ident.to_string()).cloned() {
+            self.export(&item.attrs, item.span(), true, &symbol);
+        }
+        self.rename_value(&item.ident);
+        visit::visit_item_static(self, item);
+    }
+
+    fn visit_item_const(&mut self, item: &'ast syn::ItemConst) {
+        self.rename_value(&item.ident);
+        visit::visit_item_const(self, item);
+    }
+
+    fn visit_item_macro(&mut self, item: &'ast syn::ItemMacro) {
+        if let Some(ident) = &item.ident {
+            self.rename_value(ident);
+        }
+        visit::visit_item_macro(self, item);
+    }
+
+    fn visit_type_path(&mut self, tp: &'ast syn::TypePath) {
+        if tp.qself.is_none() {
+            self.rename_path(&tp.path, false);
+        }
+        for segment in &tp.path.segments {
+            self.visit_path_arguments(&segment.arguments);
+        }
+    }
+
+    fn visit_expr_path(&mut self, ep: &'ast syn::ExprPath) {
+        if ep.qself.is_none() {
+            self.rename_path(&ep.path, true);
+        }
+        for segment in &ep.path.segments {
+            self.visit_path_arguments(&segment.arguments);
+        }
+    }
+
+    fn visit_expr_struct(&mut self, es: &'ast syn::ExprStruct) {
+        self.rename_path(&es.path, false);
+        for field in &es.fields {
+            self.visit_expr(&field.expr);
+        }
+        if let Some(rest) = &es.rest {
+            self.visit_expr(rest);
+        }
+    }
+
+    fn visit_pat_struct(&mut self, ps: &'ast syn::PatStruct) {
+        self.rename_path(&ps.path, false);
+        for field in &ps.fields {
+            self.visit_pat(&field.pat);
+        }
+    }
+
+    fn visit_pat_tuple_struct(&mut self, ps: &'ast syn::PatTupleStruct) {
+        self.rename_path(&ps.path, false);
+        for pat in &ps.elems {
+            self.visit_pat(pat);
+        }
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        if let Some(ident) = mac.path.get_ident() {
+            self.rename_value(ident);
+        }
+    }
+
+    fn visit_use_name(&mut self, name: &'ast syn::UseName) {
+        if !self.rename_value(&name.ident) {
+            self.rename_type(&name.ident);
+        }
+    }
+
+    fn visit_use_rename(&mut self, rename: &'ast syn::UseRename) {
+        if !self.rename_value(&rename.ident) {
+            self.rename_type(&rename.ident);
+        }
+    }
+}
+
+/// Collects the names bound by patterns.
+#[derive(Default)]
+struct Bindings(BTreeSet<String>);
+
+impl<'ast> Visit<'ast> for Bindings {
+    fn visit_pat_ident(&mut self, pi: &'ast syn::PatIdent) {
+        self.0.insert(pi.ident.to_string());
+        visit::visit_pat_ident(self, pi);
+    }
+
+    // Nested items have their own scopes.
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+}
+
+fn bindings(visit: impl FnOnce(&mut Bindings)) -> BTreeSet<String> {
+    let mut collector = Bindings::default();
+    visit(&mut collector);
+    collector.0
+}
+
+fn apply(code: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+    edits.dedup_by(|a, b| a.0 == b.0);
+    let mut out = code.to_string();
+    for (range, replacement) in edits.into_iter().rev() {
+        out.replace_range(range, &replacement);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{NameEntry, NameKind, NameMap};
+
+    fn entry(c_name: &str, kind: NameKind, rust_name: &str) -> NameEntry {
+        NameEntry {
+            c_name: c_name.into(),
+            kind,
+            rust_name: rust_name.into(),
+            export_name: None,
+        }
+    }
+
+    fn names() -> NameMap {
+        let mut push = entry("listPush", NameKind::Function, "list_push");
+        push.export_name = Some("listPush".into());
+        let mut total = entry("gTotal", NameKind::Global, "G_TOTAL");
+        total.export_name = Some("gTotal".into());
+        NameMap {
+            entries: vec![
+                entry("node_t", NameKind::Type, "Node"),
+                entry("struct node", NameKind::Type, "Node"),
+                entry("color", NameKind::Type, "Color"),
+                push,
+                entry("count", NameKind::Global, "COUNT"),
+                total,
+                entry("MAX", NameKind::Macro, "max"),
+            ],
+        }
+    }
+
+    #[test]
+    fn renames_items_and_references() {
+        let code = "use crate::list::listPush;\n\n\
+            pub struct node_t {\n    pub next: Option<Box<node>>,\n    pub c: color,\n}\n\n\
+            fn total(n: &node_t) -> i32 {\n    let v = node { next: None, c: color::Red };\n    \
+            listPush(n, max!(1, 2)) + count.lock().unwrap().len() as i32\n}\n";
+        assert_eq!(
+            names().enforce(code),
+            "use crate::list::list_push;\n\n\
+            pub struct Node {\n    pub next: Option<Box<Node>>,\n    pub c: Color,\n}\n\n\
+            fn total(n: &Node) -> i32 {\n    let v = Node { next: None, c: Color::Red };\n    \
+            list_push(n, max!(1, 2)) + COUNT.lock().unwrap().len() as i32\n}\n"
+        );
+    }
+
+    #[test]
+    fn local_bindings_shadow_globals() {
+        let code = "fn f(count: i32) -> i32 {\n    count + 1\n}\n\nfn g() -> i32 {\n    count\n}";
+        assert_eq!(
+            names().enforce(code),
+            "fn f(count: i32) -> i32 {\n    count + 1\n}\n\nfn g() -> i32 {\n    COUNT\n}"
+        );
+    }
+
+    #[test]
+    fn exported_functions_keep_their_symbol() {
+        let code = "#[unsafe(no_mangle)]\npub extern \"C\" fn listPush() {}";
+        assert_eq!(
+            names().enforce(code),
+            "#[unsafe(export_name = \"listPush\")]\npub extern \"C\" fn list_push() {}"
+        );
+        let code = "pub extern \"C\" fn list_push() {}";
+        assert_eq!(
+            names().enforce(code),
+            "#[unsafe(export_name = \"listPush\")]\npub extern \"C\" fn list_push() {}"
+        );
+        let code = "#[no_mangle]\npub extern \"C\" fn listPush() {}";
+        assert_eq!(
+            names().enforce(code),
+            "#[unsafe(export_name = \"listPush\")]\npub extern \"C\" fn list_push() {}"
+        );
+        assert_eq!(names().enforce("not rust"), "not rust");
+    }
+
+    #[test]
+    fn exported_statics_keep_their_symbol() {
+        let code = "#[no_mangle]\npub static mut gTotal: i32 = 0;";
+        assert_eq!(
+            names().enforce(code),
+            "#[unsafe(export_name = \"gTotal\")]\npub static mut G_TOTAL: i32 = 0;"
+        );
+        let code = "pub static mut gTotal: i32 = 0;";
+        assert_eq!(
+            names().enforce(code),
+            "#[unsafe(export_name = \"gTotal\")]\npub static mut G_TOTAL: i32 = 0;"
+        );
+        let code = "static mut count: i32 = 0;";
+        assert_eq!(names().enforce(code), "static mut COUNT: i32 = 0;");
+    }
+}
@@ -0,0 +1,561 @@
+//! Deterministic C-to-Rust naming: decides once, for the whole project, which Rust identifier
+//! each C type, function, global, enumerator and macro is translated to.
+//!
+//! Without it every translation request picks its own names, so `foo_t`, `struct foo` and `Foo`
+//! drift between declarations. The [`NameMap`] is given to every translation prompt and then
+//! enforced on the translated code with [`NameMap::enforce`].
+
+use build_project_spec::{ProjectKind, ProjectSpec};
+use c_ast::{EntityKind, RichSourceMap, TopLevelEntity};
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use tracing::info;
+
+mod case;
+mod enforce;
+
+pub use case::{is_keyword, upper_camel_case};
+
+/// What a C name denotes, which decides its Rust naming convention and namespace.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NameKind {
+    /// A struct, union or enum tag, or a typedef: `UpperCamelCase`.
+    Type,
+    /// A function: `snake_case`.
+    Function,
+    /// A global variable: `SCREAMING_SNAKE_CASE`.
+    Global,
+    /// An enumerator or object-like macro: `SCREAMING_SNAKE_CASE`.
+    Constant,
+    /// A function-like macro: `snake_case`.
+    Macro,
+}
+
+impl NameKind {
+    /// Types live in their own namespace; everything else shares the value namespace.
+    fn is_type(self) -> bool {
+        self == NameKind::Type
+    }
+}
+
+/// The Rust name chosen for one C name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NameEntry {
+    /// The C spelling: `struct foo`, `union foo` or `enum foo` for tags, the identifier otherwise.
+    pub c_name: String,
+    pub kind: NameKind,
+    pub rust_name: String,
+    /// For a function or global exported from a library under a different Rust name: the C
+    /// symbol it must keep, with `#[export_name]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_name: Option<String>,
+}
+
+impl NameEntry {
+    /// The C identifier, without the tag keyword.
+    pub fn c_ident(&self) -> &str {
+        self.c_name
+            .rsplit_once(' ')
+            .map_or(self.c_name.as_str(), |(_, ident)| ident)
+    }
+}
+
+/// C name → Rust identifier, for every name the project declares.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameMap {
+    /// Sorted by kind, then C name.
+    pub entries: Vec<NameEntry>,
+}
+
+impl NameMap {
+    /// The Rust name of the C identifier `ident` of the given kind, or `ident` itself if the map
+    /// has no entry for it.
+    pub fn rust_name<'a>(&'a self, kind: NameKind, ident: &'a str) -> &'a str {
+        self.entries
+            .iter()
+            .find(|e| e.kind == kind && e.c_ident() == ident)
+            .map_or(ident, |e| e.rust_name.as_str())
+    }
+
+    /// The entries renaming an identifier that occurs in `sources`, for inclusion in a prompt.
+    /// Names that stay the same are left out.
+    pub fn relevant<'a>(&self, sources: impl IntoIterator<Item = &'a str>) -> Vec<&NameEntry> {
+        let idents: BTreeSet<&str> = sources
+            .into_iter()
+            .flat_map(|source| source.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_')))
+            .collect();
+        self.entries
+            .iter()
+            .filter(|e| e.rust_name != e.c_ident() && idents.contains(e.c_ident()))
+            .collect()
+    }
+
+    /// Renames the C names left in the Rust `code` to their Rust names; see [`enforce`].
+    pub fn enforce(&self, code: &str) -> String {
+        enforce::enforce(self, code)
+    }
+}
+
+/// Assigns Rust names as entities are added, resolving collisions within each namespace.
+#[derive(Default)]
+struct Builder {
+    entries: Vec<NameEntry>,
+    /// Index of the entry for each (is type, C name).
+    by_c_name: HashMap<(bool, String), usize>,
+    /// Rust names taken in each namespace.
+    taken: HashMap<(bool, String), String>,
+}
+
+impl Builder {
+    /// Adds `c_name` with the Rust name `rust_name` (or a numbered variant of it, if taken by a
+    /// different C name). A C name added twice keeps its first Rust name.
+    fn add(&mut self, c_name: String, kind: NameKind, rust_name: String) -> &mut NameEntry {
+        if let Some(&i) = self.by_c_name.get(&(kind.is_type(), c_name.clone())) {
+            return &mut self.entries[i];
+        }
+        let mut candidate = rust_name.clone();
+        let mut n = 2;
+        while self
+            .taken
+            .get(&(kind.is_type(), candidate.clone()))
+            .is_some_and(|owner| *owner != c_name)
+        {
+            candidate = match kind {
+                NameKind::Type => format!("{rust_name}{n}"),
+                _ => format!("{rust_name}_{n}"),
+            };
+            n += 1;
+        }
+        self.taken
+            .insert((kind.is_type(), candidate.clone()), c_name.clone());
+        self.by_c_name
+            .insert((kind.is_type(), c_name.clone()), self.entries.len());
+        self.entries.push(NameEntry {
+            c_name,
+            kind,
+            rust_name: candidate,
+            export_name: None,
+        });
+        self.entries.last_mut().unwrap()
+    }
+
+    fn has_type(&self, c_name: &str) -> bool {
+        self.by_c_name.contains_key(&(true, c_name.to_string()))
+    }
+
+    /// Adds the type `c_name` under the Rust name of the type `alias_of`, which must have been
+    /// added: aliases of one type share its name rather than being numbered apart.
+    fn add_alias(&mut self, c_name: String, alias_of: &str) {
+        let Some(&i) = self.by_c_name.get(&(true, alias_of.to_string())) else {
+            return;
+        };
+        if self.has_type(&c_name) {
+            return;
+        }
+        let rust_name = self.entries[i].rust_name.clone();
+        self.by_c_name
+            .insert((true, c_name.clone()), self.entries.len());
+        self.entries.push(NameEntry {
+            c_name,
+            kind: NameKind::Type,
+            rust_name,
+            export_name: None,
+        });
+    }
+
+    fn finish(mut self) -> NameMap {
+        self.entries
+            .sort_by(|a, b| (a.kind, &a.c_name).cmp(&(b.kind, &b.c_name)));
+        NameMap {
+            entries: self.entries,
+        }
+    }
+}
+
+/// The C spelling of a record or enum tag, e.g. `struct foo`.
+fn tag_name(entity: &TopLevelEntity) -> Option<String> {
+    let keyword = match entity.kind {
+        EntityKind::RecordDecl => {
+            if entity.source_text.trim_start().starts_with("union") {
+                "union"
+            } else {
+                "struct"
+            }
+        }
+        EntityKind::UnionDecl => "union",
+        EntityKind::EnumDecl => "enum",
+        _ => return None,
+    };
+    Some(format!("{keyword} {}", entity.name()?))
+}
+
+/// For `typedef struct foo foo_t;`, the tag the typedef names: `struct foo`.
+fn aliased_tag(typedef: &TopLevelEntity) -> Option<String> {
+    // Braces are kept as tokens, so an anonymous `typedef struct { .. }` aliases no tag.
+    let source = typedef.source_text.replace('{', " { ");
+    let mut tokens = source
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '{'))
+        .filter(|t| !t.is_empty())
+        .skip_while(|t| *t != "typedef")
+        .skip(1)
+        .skip_while(|t| matches!(*t, "const" | "volatile"));
+    let keyword = tokens.next()?;
+    let tag = tokens.next()?;
+    if !matches!(keyword, "struct" | "union" | "enum") || tag == "{" {
+        return None;
+    }
+    Some(format!("{keyword} {tag}"))
+}
+
+/// The enumerators of the C enum declared by `source`.
+fn enumerators(source: &str) -> Vec<String> {
+    let (Some(open), Some(close)) = (source.find('{'), source.rfind('}')) else {
+        return Vec::new();
+    };
+    if close < open {
+        return Vec::new();
+    }
+    source[open + 1..close]
+        .split(',')
+        .filter_map(|enumerator| {
+            let name = enumerator.split('=').next()?.trim();
+            (!name.is_empty()).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Builds the [`NameMap`] of every type, function, global, enumerator and macro in `map`.
+///
+/// Typedefs and the tags they name share one Rust name, the typedef's: `typedef struct node_s
+/// {..} node_t` makes both `struct node_s` and `node_t` `Node`. In a library, exported functions
+/// and globals whose Rust name differs from their C name keep the C symbol through `export_name`.
+pub fn build_name_map(map: &RichSourceMap, project_kind: &ProjectKind) -> NameMap {
+    let mut builder = Builder::default();
+
+    for entity in &map.app_types {
+        let tags: Vec<&TopLevelEntity> = std::iter::once(entity)
+            .chain(&entity.sub_entities)
+            .filter(|e| e.kind != EntityKind::TypedefDecl)
+            .collect();
+        match (entity.kind, entity.name()) {
+            (EntityKind::TypedefDecl, Some(name)) => {
+                let aliased = aliased_tag(entity);
+                match aliased.as_deref().filter(|tag| builder.has_type(tag)) {
+                    Some(tag) => builder.add_alias(name.to_string(), tag),
+                    None => {
+                        builder.add(
+                            name.to_string(),
+                            NameKind::Type,
+                            case::upper_camel_case(name),
+                        );
+                    }
+                }
+                for tag in tags.iter().filter_map(|t| tag_name(t)).chain(aliased) {
+                    builder.add_alias(tag, name);
+                }
+            }
+            _ => {
+                for tag in &tags {
+                    if let (Some(tag), Some(name)) = (tag_name(tag), tag.name()) {
+                        builder.add(tag, NameKind::Type, case::upper_camel_case(name));
+                    }
+                }
+            }
+        }
+        for tag in tags.iter().filter(|t| t.kind == EntityKind::EnumDecl) {
+            for enumerator in enumerators(&tag.source_text) {
+                let rust_name = case::screaming_snake_case(&enumerator);
+                builder.add(enumerator, NameKind::Constant, rust_name);
+            }
+        }
+    }
+
+    let exported = |entity: &TopLevelEntity| {
+        matches!(project_kind, ProjectKind::Library)
+            && map
+                .symbols
+                .for_entity(entity)
+                .is_some_and(|s| s.is_exported())
+    };
+
+    for function in &map.app_functions {
+        let Some(name) = function.name() else {
+            continue;
+        };
+        let rust_name = match name {
+            "main" => name.to_string(),
+            _ => case::snake_case(name),
+        };
+        let entry = builder.add(name.to_string(), NameKind::Function, rust_name);
+        if exported(function) && entry.rust_name != name {
+            entry.export_name = Some(name.to_string());
+        }
+    }
+
+    for global in &map.app_globals {
+        let Some(name) = global.name() else {
+            continue;
+        };
+        let rust_name = case::screaming_snake_case(name);
+        let entry = builder.add(name.to_string(), NameKind::Global, rust_name);
+        if exported(global) && entry.rust_name != name {
+            entry.export_name = Some(name.to_string());
+        }
+    }
+
+    for define in &map.defines {
+        let Some(name) = define.name() else {
+            continue;
+        };
+        // `#define NAME(` is function-like; `#define NAME (` is not.
+        let function_like = define
+            .source_text
+            .split_once(name)
+            .is_some_and(|(_, rest)| rest.starts_with('('));
+        let (kind, rust_name) = if function_like {
+            (NameKind::Macro, case::snake_case(name))
+        } else {
+            (NameKind::Constant, case::screaming_snake_case(name))
+        };
+        builder.add(name.to_string(), kind, rust_name);
+    }
+
+    builder.finish()
+}
+
+impl fmt::Display for NameMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "NameMap: {} names", self.entries.len())?;
+        for entry in &self.entries {
+            write!(f, "  {} -> {}", entry.c_name, entry.rust_name)?;
+            if let Some(symbol) = &entry.export_name {
+                write!(f, " (export_name = \"{symbol}\")")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for NameMap {
+    fn name(&self) -> &'static str {
+        "name_map"
+    }
+}
+
+pub struct BuildNameMap;
+
+impl Tool for BuildNameMap {
+    fn name(&self) -> &'static str {
+        "build_name_map"
+    }
+
+    /// Inputs:
+    /// 1. [`RichSourceMap`] id -- the declarations to name.
+    /// 2. [`ProjectSpec`] id -- whether the project is a library, whose exports keep C symbols.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let map = context
+            .ir_snapshot
+            .get::<RichSourceMap>(inputs[0])
+            .ok_or("build_name_map: no RichSourceMap in IR")?;
+        let project_spec = context
+            .ir_snapshot
+            .get::<ProjectSpec>(inputs[1])
+            .ok_or("build_name_map: no ProjectSpec in IR")?;
+        let names = build_name_map(map, &project_spec.kind);
+        info!(
+            "Name map: {} names, {} renamed",
+            names.entries.len(),
+            names
+                .entries
+                .iter()
+                .filter(|e| e.rust_name != e.c_ident())
+                .count()
+        );
+        Ok(Box::new(names))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::{ClangAST, SourcePoint, SourceSpan};
+
+    fn entity(kind: EntityKind, ast: Option<ClangAST>, source_text: &str) -> TopLevelEntity {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset: 0,
+        };
+        TopLevelEntity {
+            kind,
+            source_text: source_text.into(),
+            span: SourceSpan {
+                file: "list.c".into(),
+                start: point.clone(),
+                end: point,
+            },
+            ast,
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn typedef(name: &str, source_text: &str) -> TopLevelEntity {
+        entity(
+            EntityKind::TypedefDecl,
+            Some(ClangAST::TypedefDecl {
+                name: name.into(),
+                underlying: None,
+            }),
+            source_text,
+        )
+    }
+
+    fn record(name: &str, source_text: &str) -> TopLevelEntity {
+        entity(
+            EntityKind::RecordDecl,
+            Some(ClangAST::RecordDecl {
+                name: Some(name.into()),
+                tag_used: Some("struct".into()),
+                layout: None,
+            }),
+            source_text,
+        )
+    }
+
+    fn function(name: &str) -> TopLevelEntity {
+        entity(
+            EntityKind::FunctionDecl,
+            Some(ClangAST::FunctionDecl {
+                name: name.into(),
+                storage_class: None,
+            }),
+            "",
+        )
+    }
+
+    fn rust_name<'a>(names: &'a NameMap, c_name: &str) -> &'a str {
+        &names
+            .entries
+            .iter()
+            .find(|e| e.c_name == c_name)
+            .unwrap_or_else(|| panic!("no entry for {c_name}"))
+            .rust_name
+    }
+
+    fn sample_map() -> RichSourceMap {
+        let mut map = RichSourceMap::new();
+        let mut node_t = typedef("node_t", "typedef struct node_s { int v; } node_t");
+        node_t
+            .sub_entities
+            .push(record("node_s", "struct node_s { int v; }"));
+        map.app_types.push(node_t);
+        map.app_types
+            .push(record("list", "struct list { node_t *head; }"));
+        map.app_types
+            .push(typedef("list_t", "typedef struct list list_t;"));
+        map.app_types.push(record("List", "struct List { int x; }"));
+        map.app_types.push(entity(
+            EntityKind::EnumDecl,
+            Some(ClangAST::EnumDecl {
+                name: Some("color".into()),
+                layout: None,
+            }),
+            "enum color { Red, darkGreen = 2 }",
+        ));
+        map.app_functions.push(function("listPush"));
+        map.app_functions.push(function("main"));
+        map.app_functions.push(function("type"));
+        map.app_globals.push(entity(
+            EntityKind::VarDecl,
+            Some(ClangAST::VarDecl {
+                name: "gCount".into(),
+                storage_class: None,
+            }),
+            "int gCount = 0;",
+        ));
+        map.defines.push(entity(
+            EntityKind::MacroDefinition,
+            None,
+            "#define MAX(a, b) ((a) > (b) ? (a) : (b))",
+        ));
+        map.defines
+            .push(entity(EntityKind::MacroDefinition, None, "#define LEN (4)"));
+        map
+    }
+
+    #[test]
+    fn typedefs_and_their_tags_share_a_name() {
+        let names = build_name_map(&sample_map(), &ProjectKind::Executable);
+        assert_eq!(rust_name(&names, "node_t"), "Node");
+        assert_eq!(rust_name(&names, "struct node_s"), "Node");
+        // Declared before its typedef, so the typedef takes the tag's name.
+        assert_eq!(rust_name(&names, "struct list"), "List");
+        assert_eq!(rust_name(&names, "list_t"), "List");
+        assert_eq!(rust_name(&names, "struct List"), "List2");
+        assert_eq!(rust_name(&names, "enum color"), "Color");
+    }
+
+    #[test]
+    fn values_follow_rust_conventions() {
+        let names = build_name_map(&sample_map(), &ProjectKind::Executable);
+        assert_eq!(rust_name(&names, "listPush"), "list_push");
+        assert_eq!(rust_name(&names, "main"), "main");
+        assert_eq!(rust_name(&names, "type"), "type_");
+        assert_eq!(rust_name(&names, "gCount"), "G_COUNT");
+        assert_eq!(rust_name(&names, "Red"), "RED");
+        assert_eq!(rust_name(&names, "darkGreen"), "DARK_GREEN");
+        assert_eq!(rust_name(&names, "MAX"), "max");
+        assert_eq!(rust_name(&names, "LEN"), "LEN");
+        assert!(names.entries.iter().all(|e| e.export_name.is_none()));
+
+        let relevant: Vec<&str> = names
+            .relevant(["int f(list_t *l) { return listPush(l, LEN); }"])
+            .into_iter()
+            .map(|e| e.c_name.as_str())
+            .collect();
+        assert_eq!(relevant, vec!["list_t", "listPush"]);
+    }
+
+    #[test]
+    fn exported_library_symbols_keep_their_c_name() {
+        let mut map = sample_map();
+        for (name, kind) in [
+            ("listPush", c_ast::SymbolKind::Function),
+            ("gCount", c_ast::SymbolKind::Variable),
+        ] {
+            map.symbols.symbols.push(c_ast::Symbol {
+                name: name.into(),
+                kind,
+                linkage: c_ast::Linkage::External,
+                declarations: Vec::new(),
+                definition: None,
+                conflicting_definitions: Vec::new(),
+                declaring_headers: vec!["list.h".into()],
+                referencing_tus: Vec::new(),
+            });
+        }
+        let names = build_name_map(&map, &ProjectKind::Library);
+        let export_name = |c_name: &str| {
+            names
+                .entries
+                .iter()
+                .find(|e| e.c_name == c_name)
+                .and_then(|e| e.export_name.as_deref())
+        };
+        assert_eq!(export_name("listPush"), Some("listPush"));
+        assert_eq!(export_name("gCount"), Some("gCount"));
+        assert_eq!(export_name("type"), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! Edits the C sources of a partial translation so that they link with the Rust crate.
+//!
+//! - A translated function's definition becomes a prototype of the Rust function.
+//! - A function that stays in C but whose symbol Rust provides is renamed.
+//! - A `static` function or global the translated code uses loses `static`, so Rust can link to
+//!   it; so does every declaration of a translated function, which Rust defines.
+
+use crate::TranslationUnits;
+use c_ast::{RichSourceMap, TopLevelEntity};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// Replaces a byte range of a C file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Edit {
+    range: Range<usize>,
+    text: String,
+}
+
+/// The edits to make to each project-relative C file.
+pub(crate) fn edits(map: &RichSourceMap, units: &TranslationUnits) -> BTreeMap<String, Vec<Edit>> {
+    let used: BTreeSet<&str> = map
+        .app_functions
+        .iter()
+        .filter(|f| f.name().is_some_and(|name| units.translates(name)))
+        .flat_map(|f| identifiers(&f.source_text))
+        .collect();
+
+    let mut edits: BTreeMap<String, Vec<Edit>> = BTreeMap::new();
+    let mut edit = |entity: &TopLevelEntity, text: String| {
+        if text != entity.source_text {
+            let start = entity.span.start.offset as usize;
+            edits
+                .entry(entity.span.file.clone())
+                .or_default()
+                .push(Edit {
+                    range: start..start + entity.source_text.len(),
+                    text,
+                });
+        }
+    };
+    for function in &map.app_functions {
+        let Some(name) = function.name() else {
+            continue;
+        };
+        let text = &function.source_text;
+        if units.translates(name) {
+            edit(
+                function,
+                prototype(&strip_specifiers(text, &["static", "inline"])),
+            );
+            continue;
+        }
+        let mut text = text.clone();
+        if used.contains(name) {
+            text = strip_specifiers(&text, &["static", "inline"]);
+        }
+        if units.renamed.contains(name) {
+            text = rename(&text, name, &units.c_symbol(name));
+        }
+        edit(function, text);
+    }
+    for declaration in &map.app_func_sigs {
+        if declaration
+            .name()
+            .is_some_and(|name| units.translates(name) || used.contains(name))
+        {
+            let text = strip_specifiers(&declaration.source_text, &["static", "inline"]);
+            edit(declaration, text);
+        }
+    }
+    for global in &map.app_globals {
+        if global.name().is_some_and(|name| used.contains(name)) {
+            edit(global, strip_specifiers(&global.source_text, &["static"]));
+        }
+    }
+    edits
+}
+
+/// Applies `edits` to `source`. Of overlapping edits only the first is made, and edits outside
+/// `source` are dropped.
+pub(crate) fn apply(source: &str, mut edits: Vec<Edit>) -> String {
+    edits.sort_by_key(|edit| edit.range.start);
+    let mut out = String::with_capacity(source.len());
+    let mut copied = 0;
+    for edit in edits {
+        if edit.range.start < copied || source.get(edit.range.clone()).is_none() {
+            continue;
+        }
+        out.push_str(&source[copied..edit.range.start]);
+        out.push_str(&edit.text);
+        copied = edit.range.end;
+    }
+    out.push_str(&source[copied..]);
+    out
+}
+
+/// The identifiers in the C source `text`.
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|word| word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+}
+
+/// The prototype of the function `definition`: everything before its body, followed by `;`.
+fn prototype(definition: &str) -> String {
+    let mut depth = 0usize;
+    for (i, c) in definition.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '{' if depth == 0 => return format!("{};", definition[..i].trim_end()),
+            _ => {}
+        }
+    }
+    definition.to_string()
+}
+
+/// The declaration `text` without the specifiers `words` ahead of its declarator.
+fn strip_specifiers(text: &str, words: &[&str]) -> String {
+    let end = text.find(['(', '=', '{', ';']).unwrap_or(text.len());
+    let mut out = String::with_capacity(text.len());
+    let mut rest = &text[..end];
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+        let len = rest[start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len() - start);
+        let word = &rest[start..start + len];
+        if words.contains(&word) {
+            out.push_str(&rest[..start]);
+            // Drop the whitespace that separated the specifier from what follows.
+            rest = rest[start + len..].trim_start();
+        } else {
+            out.push_str(&rest[..start + len]);
+            rest = &rest[start + len..];
+        }
+    }
+    out.push_str(rest);
+    out.push_str(&text[end..]);
+    out
+}
+
+/// The declaration `text` with its first `name` token, the declarator's, replaced by `new_name`.
+fn rename(text: &str, name: &str, new_name: &str) -> String {
+    let mut offset = 0;
+    while let Some(found) = text[offset..].find(name) {
+        let start = offset + found;
+        let end = start + name.len();
+        let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let before = text[..start].chars().next_back().is_some_and(is_ident);
+        let after = text[end..].chars().next().is_some_and(is_ident);
+        if !before && !after {
+            return format!("{}{new_name}{}", &text[..start], &text[end..]);
+        }
+        offset = end;
+    }
+    text.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::{ClangAST, EntityKind, SourcePoint, SourceSpan};
+
+    const LIST_C: &str = "\
+static int count;
+static int bump(int by) { return count += by; }
+int list_len(const int *list) {
+    int n = 0;
+    while (list[n]) n++;
+    return bump(0) + n;
+}
+int list_sum(const int *list) { return list[0]; }
+";
+
+    /// The entity `text`, located at its first occurrence in `LIST_C`.
+    fn entity(kind: EntityKind, name: &str, text: &str) -> TopLevelEntity {
+        let offset = LIST_C.find(text).unwrap() as u32;
+        let point = |offset| SourcePoint {
+            line: 1,
+            column: 1,
+            offset,
+        };
+        let ast = match kind {
+            EntityKind::VarDecl => ClangAST::VarDecl {
+                name: name.into(),
+                storage_class: None,
+            },
+            _ => ClangAST::FunctionDecl {
+                name: name.into(),
+                storage_class: None,
+            },
+        };
+        TopLevelEntity {
+            kind,
+            source_text: text.into(),
+            span: SourceSpan {
+                file: "list.c".into(),
+                start: point(offset),
+                end: point(offset + text.len() as u32),
+            },
+            ast: Some(ast),
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    #[test]
+    fn translated_definitions_become_prototypes() {
+        assert_eq!(
+            prototype("static inline int f(int (*cb)(void)) __attribute__((x)) {\n  return 0;\n}"),
+            "static inline int f(int (*cb)(void)) __attribute__((x));"
+        );
+        assert_eq!(
+            strip_specifiers("static inline int f(int static_x);", &["static", "inline"]),
+            "int f(int static_x);"
+        );
+        assert_eq!(
+            strip_specifiers("const static char *name = \"static\"", &["static"]),
+            "const char *name = \"static\""
+        );
+        assert_eq!(
+            rename(
+                "int list_len(const int *list_lens)",
+                "list_len",
+                "harvest_c_list_len"
+            ),
+            "int harvest_c_list_len(const int *list_lens)"
+        );
+    }
+
+    #[test]
+    fn edits_link_the_remaining_code_to_rust() {
+        let mut map = RichSourceMap::new();
+        map.app_globals = vec![entity(EntityKind::VarDecl, "count", "static int count")];
+        map.app_functions = vec![
+            entity(
+                EntityKind::FunctionDecl,
+                "bump",
+                "static int bump(int by) { return count += by; }",
+            ),
+            entity(
+                EntityKind::FunctionDecl,
+                "list_len",
+                &LIST_C[LIST_C.find("int list_len").unwrap()..LIST_C.rfind("}\nint").unwrap() + 1],
+            ),
+            entity(
+                EntityKind::FunctionDecl,
+                "list_sum",
+                "int list_sum(const int *list) { return list[0]; }",
+            ),
+        ];
+        let units = TranslationUnits {
+            functions: ["list_len".to_string()].into(),
+            renamed: ["list_sum".to_string()].into(),
+        };
+
+        let mut edits = edits(&map, &units);
+        let edited = apply(LIST_C, edits.remove("list.c").unwrap());
+        assert_eq!(
+            edited,
+            "\
+static int count;
+int bump(int by) { return count += by; }
+int list_len(const int *list);
+int harvest_c_list_sum(const int *list) { return list[0]; }
+"
+        );
+        assert!(edits.is_empty());
+    }
+}
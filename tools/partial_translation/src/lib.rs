@@ -0,0 +1,251 @@
+//! Hybrid partial translation: only some C files and functions are translated to Rust, and the
+//! rest of the C code is compiled into the same crate, so a project can be migrated a few units
+//! at a time with a working (and difftestable) crate after every step.
+//!
+//! [`SelectTranslationUnits`] resolves the units named by the `translate_units` config into the
+//! C functions to translate. The modular translation then translates those functions, exporting
+//! each under its C symbol, and declares the functions and globals that stay in C with
+//! [`extern_declaration`]. Finally [`LinkRemainingC`] copies the C sources into the crate with
+//! the translated definitions reduced to prototypes, and generates a `build.rs` that compiles
+//! them with the `cc` crate.
+//!
+//! Functions that stay in C but whose symbol the Rust crate must provide -- the public functions
+//! of a library, whose symbols a Rust `cdylib` would not export, and the `main` of an executable
+//! -- are renamed in C with [`C_SYMBOL_PREFIX`] and reached through Rust wrappers. Globals always
+//! stay in C, so a library's public globals are not exported by its `cdylib` yet.
+
+use build_config::BuildConfigIR;
+use build_project_spec::ProjectSpec;
+use c_ast::RichSourceMap;
+use full_source::{CargoPackage, RawSource};
+use harvest_core::cargo_utils::CargoToml;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+mod c_edit;
+mod link;
+mod rust_decl;
+mod units;
+
+pub use rust_decl::extern_declaration;
+
+/// Prefix of the C symbol a function takes when the Rust crate provides its original symbol.
+pub const C_SYMBOL_PREFIX: &str = "harvest_c_";
+
+/// The directory of the Cargo package the remaining C sources are copied into.
+pub const C_SOURCE_DIR: &str = "csrc";
+
+/// The C functions a partial translation translates to Rust.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TranslationUnits {
+    /// C names of the functions translated to Rust.
+    pub functions: BTreeSet<String>,
+    /// C names of the functions that stay in C under [`C_SYMBOL_PREFIX`], because the Rust crate
+    /// provides their symbol.
+    pub renamed: BTreeSet<String>,
+}
+
+impl TranslationUnits {
+    /// Whether the function `name` is translated to Rust.
+    pub fn translates(&self, name: &str) -> bool {
+        self.functions.contains(name)
+    }
+
+    /// The symbol the C code defines the remaining function or global `name` under.
+    pub fn c_symbol(&self, name: &str) -> String {
+        if self.renamed.contains(name) {
+            format!("{C_SYMBOL_PREFIX}{name}")
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+impl fmt::Display for TranslationUnits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "TranslationUnits: {} functions translated",
+            self.functions.len()
+        )?;
+        for name in &self.functions {
+            writeln!(f, "  {name}")?;
+        }
+        for name in &self.renamed {
+            writeln!(f, "  {name} stays in C as {}", self.c_symbol(name))?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for TranslationUnits {
+    fn name(&self) -> &'static str {
+        "translation_units"
+    }
+}
+
+/// Resolves the `translate_units` and `translate_units_file` config into [`TranslationUnits`].
+pub struct SelectTranslationUnits;
+
+impl Tool for SelectTranslationUnits {
+    fn name(&self) -> &'static str {
+        "select_translation_units"
+    }
+
+    /// Inputs:
+    /// 1. [`RichSourceMap`] id -- the functions to choose from.
+    /// 2. [`ProjectSpec`] id -- whether public functions must keep their symbol.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let map = context
+            .ir_snapshot
+            .get::<RichSourceMap>(inputs[0])
+            .ok_or("select_translation_units: no RichSourceMap in IR")?;
+        let kind = &context
+            .ir_snapshot
+            .get::<ProjectSpec>(inputs[1])
+            .ok_or("select_translation_units: no ProjectSpec in IR")?
+            .kind;
+
+        let mut requested = context.config.translate_units.clone();
+        if let Some(path) = &context.config.translate_units_file {
+            let list = std::fs::read_to_string(path).map_err(|e| {
+                format!("select_translation_units: reading {}: {e}", path.display())
+            })?;
+            requested.extend(units::parse_list(&list));
+        }
+
+        let units = units::select(map, kind, &requested)
+            .map_err(|e| format!("select_translation_units: {e}"))?;
+        info!(
+            "Partial translation: {}/{} functions translated, the rest stays in C",
+            units.functions.len(),
+            map.app_functions.len()
+        );
+        Ok(Box::new(units))
+    }
+}
+
+/// Compiles the C code a partial translation leaves untranslated into the translated crate.
+pub struct LinkRemainingC;
+
+impl Tool for LinkRemainingC {
+    fn name(&self) -> &'static str {
+        "link_remaining_c"
+    }
+
+    /// Inputs:
+    /// 1. [`CargoPackage`] id -- the partial translation.
+    /// 2. [`RawSource`] id -- the C project.
+    /// 3. [`RichSourceMap`] id -- the C declarations to edit.
+    /// 4. [`TranslationUnits`] id -- what was translated.
+    /// 5. [`BuildConfigIR`] id -- the defines and variant sources of the C build.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let mut package = context
+            .ir_snapshot
+            .get::<CargoPackage>(inputs[0])
+            .ok_or("link_remaining_c: no CargoPackage in IR")?
+            .clone();
+        let raw_source = context
+            .ir_snapshot
+            .get::<RawSource>(inputs[1])
+            .ok_or("link_remaining_c: no RawSource in IR")?;
+        let map = context
+            .ir_snapshot
+            .get::<RichSourceMap>(inputs[2])
+            .ok_or("link_remaining_c: no RichSourceMap in IR")?;
+        let units = context
+            .ir_snapshot
+            .get::<TranslationUnits>(inputs[3])
+            .ok_or("link_remaining_c: no TranslationUnits in IR")?;
+        let build_cfg = context
+            .ir_snapshot
+            .get::<BuildConfigIR>(inputs[4])
+            .ok_or("link_remaining_c: no BuildConfigIR in IR")?;
+
+        // The C sources, with the translated functions reduced to prototypes.
+        let mut edits = c_edit::edits(map, units);
+        for (path, contents) in raw_source.dir.files_recursive() {
+            let contents = match edits.remove(path.to_string_lossy().as_ref()) {
+                Some(file_edits) => {
+                    c_edit::apply(&String::from_utf8_lossy(contents), file_edits).into_bytes()
+                }
+                None => contents.to_vec(),
+            };
+            package
+                .dir
+                .set_file(Path::new(C_SOURCE_DIR).join(&path), contents)
+                .map_err(|e| format!("link_remaining_c: adding {}: {e}", path.display()))?;
+        }
+
+        // Compiled are all C files of the project, whether or not they define anything;
+        // headers are found next to their includers.
+        let files = c_ast::source_files(raw_source);
+        let sources: BTreeSet<PathBuf> = files
+            .iter()
+            .map(|(path, _)| path.clone())
+            .filter(|path| path.extension().is_some_and(|e| e == "c"))
+            .collect();
+        let include_dirs: BTreeSet<PathBuf> = files
+            .iter()
+            .filter(|(path, _)| path.extension().is_some_and(|e| e == "h"))
+            .filter_map(|(path, _)| Some(path.parent()?.to_path_buf()))
+            .collect();
+
+        // A build script that is already there, e.g. from `emit_build_features`, keeps running.
+        let previous = package
+            .dir
+            .get_file("build.rs")
+            .ok()
+            .map(|script| String::from_utf8_lossy(script).into_owned());
+        if let Some(script) = &previous {
+            let module = link::build_features_module(script);
+            package
+                .dir
+                .set_file("build_features.rs", module.into_bytes())?;
+        }
+        let build_rs =
+            link::render_build_rs(&sources, &include_dirs, build_cfg, previous.is_some());
+        match package.dir.get_file_mut("build.rs") {
+            Ok(script) => *script = build_rs.into_bytes(),
+            Err(_) => {
+                package.dir.set_file("build.rs", build_rs.into_bytes())?;
+            }
+        }
+
+        let manifest = package
+            .dir
+            .get_file_mut("Cargo.toml")
+            .map_err(|e| format!("link_remaining_c: Cargo.toml missing in CargoPackage: {e}"))?;
+        let mut cargo = CargoToml::from_bytes(manifest)?;
+        cargo.set_build_script("build.rs");
+        cargo.add_build_dependency("cc", "1");
+        *manifest = cargo.into_bytes();
+
+        // An executable whose `main` stays in C starts through a Rust shim.
+        if units.renamed.contains("main") {
+            let main_rs = package
+                .dir
+                .get_file_mut("src/main.rs")
+                .map_err(|e| format!("link_remaining_c: no src/main.rs for the C `main`: {e}"))?;
+            main_rs.extend_from_slice(link::main_shim(&units.c_symbol("main")).as_bytes());
+        }
+
+        info!(
+            "link_remaining_c: compiling {} C files into the crate",
+            sources.len()
+        );
+        Ok(Box::new(package))
+    }
+}
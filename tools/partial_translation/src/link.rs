@@ -0,0 +1,268 @@
+//! The build script and entry point that link the remaining C code into the crate.
+
+use crate::C_SOURCE_DIR;
+use build_config::{BuildConfigIR, ConfigVarKind};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// The module the build script `script` becomes when the generated `build.rs` takes its place:
+/// its `main` is made public so the new build script can call it.
+pub(crate) fn build_features_module(script: &str) -> String {
+    script.replacen("fn main()", "pub fn main()", 1)
+}
+
+/// A build script compiling the project-relative C files `sources` into a static library linked
+/// into the crate. Headers are searched for in `include_dirs` and the C source root. The defines
+/// of `build_cfg` are passed for the enabled Cargo features, and the sources and include
+/// directories a variable selects are only compiled when its feature is. With `features`, the
+/// build script previously in the package runs first, from `build_features.rs`.
+pub(crate) fn render_build_rs(
+    sources: &BTreeSet<PathBuf>,
+    include_dirs: &BTreeSet<PathBuf>,
+    build_cfg: &BuildConfigIR,
+    features: bool,
+) -> String {
+    let in_csrc = |path: &Path| {
+        format!(
+            "{:?}",
+            Path::new(C_SOURCE_DIR).join(path).display().to_string()
+        )
+    };
+    let gated = gated_paths(build_cfg, sources, include_dirs);
+    let is_gated = |path: &PathBuf| gated.values().any(|paths| paths.contains(path));
+
+    let mut out = String::new();
+    out.push_str("// Auto-generated by HARVEST's link_remaining_c tool. Do not edit.\n");
+    out.push_str("// Compiles the C code that has not been translated to Rust yet.\n\n");
+    if features {
+        out.push_str("mod build_features;\n\n");
+    }
+    out.push_str("fn main() {\n");
+    if features {
+        out.push_str("    build_features::main();\n");
+    }
+    out.push_str("    let mut build = cc::Build::new();\n");
+    out.push_str("    build\n");
+    for source in sources.iter().filter(|path| !is_gated(path)) {
+        out.push_str(&format!("        .file({})\n", in_csrc(source)));
+    }
+    out.push_str(&format!("        .include({C_SOURCE_DIR:?})\n"));
+    for dir in include_dirs
+        .iter()
+        .filter(|dir| !dir.as_os_str().is_empty() && !is_gated(dir))
+    {
+        out.push_str(&format!("        .include({})\n", in_csrc(dir)));
+    }
+    out.push_str("        .warnings(false);\n");
+    out.push_str(&build_config::render_cc_defines(build_cfg, "build"));
+    for (conditions, paths) in &gated {
+        out.push_str(&format!("    if {} {{\n", conditions.join(" || ")));
+        for path in paths {
+            let method = if sources.contains(path) {
+                "file"
+            } else {
+                "include"
+            };
+            out.push_str(&format!("        build.{method}({});\n", in_csrc(path)));
+        }
+        out.push_str("    }\n");
+    }
+    out.push_str("    build.compile(\"harvest_c\");\n");
+    out.push_str("    println!(\"cargo:rerun-if-changed=build.rs\");\n");
+    out.push_str(&format!(
+        "    println!(\"cargo:rerun-if-changed={C_SOURCE_DIR}\");\n"
+    ));
+    out.push_str("}\n");
+    out
+}
+
+/// The `sources` and `include_dirs` that only build under some Cargo features, keyed by the
+/// feature tests of [`build_config::render_cc_defines`] any of which selects them: the files of a
+/// source selection's variant or of a conditional target, and everything under a subdirectory
+/// variant. Selections driven by a variable the tests do not cover are left ungated.
+fn gated_paths(
+    build_cfg: &BuildConfigIR,
+    sources: &BTreeSet<PathBuf>,
+    include_dirs: &BTreeSet<PathBuf>,
+) -> BTreeMap<Vec<String>, BTreeSet<PathBuf>> {
+    let kind = |name: &str| {
+        build_cfg
+            .variables
+            .iter()
+            .find(|var| var.name == name)
+            .map(|var| &var.kind)
+    };
+    let enum_test = |var: &str, value: &str| match kind(var) {
+        Some(ConfigVarKind::Enum { values, .. }) if values.iter().any(|v| v == value) => Some(
+            format!("has_{}_{}", var.to_lowercase(), value.to_lowercase()),
+        ),
+        _ => None,
+    };
+
+    let mut conditions: BTreeMap<&PathBuf, Vec<String>> = BTreeMap::new();
+    let mut gate = |path: &PathBuf, condition: String| {
+        if let Some(path) = sources.get(path).or_else(|| include_dirs.get(path)) {
+            let tests = conditions.entry(path).or_default();
+            if !tests.contains(&condition) {
+                tests.push(condition);
+            }
+        }
+    };
+    for selection in &build_cfg.source_selections {
+        for variant in &selection.variants {
+            if let Some(condition) = enum_test(&selection.driving_var, &variant.value) {
+                variant
+                    .files
+                    .iter()
+                    .for_each(|f| gate(f, condition.clone()));
+            }
+        }
+    }
+    for target in &build_cfg.conditional_targets {
+        let condition = match (kind(&target.gate_var), &target.gate_value) {
+            (Some(ConfigVarKind::Boolean), None) => {
+                Some(format!("has_{}", target.gate_var.to_lowercase()))
+            }
+            (_, Some(value)) => enum_test(&target.gate_var, value),
+            _ => None,
+        };
+        if let Some(condition) = condition {
+            target.files.iter().for_each(|f| gate(f, condition.clone()));
+        }
+    }
+    for selection in &build_cfg.subdir_selections {
+        for variant in &selection.variants {
+            if let Some(condition) = enum_test(&selection.driving_var, &variant.value) {
+                sources
+                    .iter()
+                    .chain(include_dirs)
+                    .filter(|path| path.starts_with(&variant.path))
+                    .for_each(|path| gate(path, condition.clone()));
+            }
+        }
+    }
+
+    let mut gated: BTreeMap<Vec<String>, BTreeSet<PathBuf>> = BTreeMap::new();
+    for (path, tests) in conditions {
+        gated.entry(tests).or_default().insert(path.clone());
+    }
+    gated
+}
+
+/// A Rust `main` that runs the C `main`, defined under `symbol`, with the program's arguments.
+pub(crate) fn main_shim(symbol: &str) -> String {
+    format!(
+        "
+/// Runs the C `main`, which has not been translated to Rust yet.
+fn main() {{
+    unsafe extern \"C\" {{
+        fn {symbol}(
+            argc: std::ffi::c_int,
+            argv: *mut *mut std::ffi::c_char,
+        ) -> std::ffi::c_int;
+    }}
+    let args: Vec<std::ffi::CString> = std::env::args_os()
+        .map(|arg| {{
+            let bytes = std::os::unix::ffi::OsStringExt::into_vec(arg);
+            std::ffi::CString::new(bytes).expect(\"argument contains a NUL byte\")
+        }})
+        .collect();
+    let mut argv: Vec<*mut std::ffi::c_char> =
+        args.iter().map(|arg| arg.as_ptr().cast_mut()).collect();
+    argv.push(std::ptr::null_mut());
+    let status = unsafe {{ {symbol}(args.len() as std::ffi::c_int, argv.as_mut_ptr()) }};
+    std::process::exit(status);
+}}
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use build_config::{
+        ConfigVariable, DefineKind, DefineMapping, SourceSelection, SourceVariant, SubdirSelection,
+        SubdirVariant,
+    };
+
+    #[test]
+    fn build_script_compiles_sources_after_previous_script() {
+        let sources = BTreeSet::from([PathBuf::from("src/list.c"), PathBuf::from("main.c")]);
+        let include_dirs = BTreeSet::from([PathBuf::from("include"), PathBuf::new()]);
+        let script = render_build_rs(&sources, &include_dirs, &BuildConfigIR::default(), true);
+        assert!(script.contains(
+            "mod build_features;\n\nfn main() {\n    build_features::main();\n    \
+             let mut build = cc::Build::new();\n"
+        ));
+        assert!(script.contains(
+            "        .file(\"csrc/main.c\")\n        .file(\"csrc/src/list.c\")\n        \
+             .include(\"csrc\")\n        .include(\"csrc/include\")\n        .warnings(false);\n    \
+             build.compile(\"harvest_c\");\n"
+        ));
+        assert_eq!(
+            build_features_module("fn main() {\n    println!(\"x\");\n}\n"),
+            "pub fn main() {\n    println!(\"x\");\n}\n"
+        );
+    }
+
+    #[test]
+    fn build_script_passes_defines_and_gates_variant_sources() {
+        let build_cfg = BuildConfigIR {
+            variables: vec![ConfigVariable {
+                name: "BACKEND".into(),
+                kind: ConfigVarKind::Enum {
+                    values: vec!["alpha".into(), "beta".into()],
+                    numeric: false,
+                },
+                default: Some("alpha".into()),
+            }],
+            defines: vec![DefineMapping {
+                c_name: "BACKEND_NAME".into(),
+                kind: DefineKind::QuotedString {
+                    var: "BACKEND".into(),
+                },
+                source_vars: vec!["BACKEND".into()],
+            }],
+            source_selections: vec![SourceSelection {
+                target: "app".into(),
+                driving_var: "BACKEND".into(),
+                variants: vec![SourceVariant {
+                    value: "alpha".into(),
+                    files: vec![PathBuf::from("src/backend_alpha.c")],
+                }],
+            }],
+            subdir_selections: vec![SubdirSelection {
+                driving_var: "BACKEND".into(),
+                variants: vec![SubdirVariant {
+                    value: "beta".into(),
+                    path: PathBuf::from("lib/beta"),
+                    defines: vec![],
+                    source_selections: vec![],
+                    conditional_targets: vec![],
+                    subdir_selections: vec![],
+                    targets: vec![],
+                }],
+            }],
+            ..BuildConfigIR::default()
+        };
+        let sources = BTreeSet::from([
+            PathBuf::from("main.c"),
+            PathBuf::from("src/backend_alpha.c"),
+            PathBuf::from("lib/beta/hash.c"),
+        ]);
+        let include_dirs = BTreeSet::from([PathBuf::from("lib/beta")]);
+        let script = render_build_rs(&sources, &include_dirs, &build_cfg, false);
+        assert!(script.contains(
+            "    build\n        .file(\"csrc/main.c\")\n        .include(\"csrc\")\n        \
+             .warnings(false);\n"
+        ));
+        assert!(script.contains("build.define(\"BACKEND_NAME\", format!("));
+        assert!(script.contains(
+            "    if has_backend_alpha {\n        build.file(\"csrc/src/backend_alpha.c\");\n    }\n"
+        ));
+        assert!(script.contains(
+            "    if has_backend_beta {\n        build.include(\"csrc/lib/beta\");\n        \
+             build.file(\"csrc/lib/beta/hash.c\");\n    }\n"
+        ));
+    }
+}
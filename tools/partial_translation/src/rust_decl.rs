@@ -0,0 +1,156 @@
+//! Rust declarations of the C functions and globals a partial translation leaves in C.
+
+use syn::spanned::Spanned;
+
+/// Prefix of the Rust wrapper that exports a C function under its original symbol.
+const EXPORT_PREFIX: &str = "harvest_export_";
+
+/// Turns the interface `signature` of a C function or global -- a Rust function with a
+/// `todo!()` body, or a static with a `todo!()` initializer -- into an `extern "C"` declaration
+/// of the C `symbol` defining it.
+///
+/// With `export`, a function is also exported under that symbol by a Rust wrapper calling the C
+/// definition, so that a Rust `cdylib` provides it.
+pub fn extern_declaration(
+    signature: &str,
+    symbol: &str,
+    export: Option<&str>,
+) -> Result<String, String> {
+    let file =
+        syn::parse_file(signature).map_err(|e| format!("cannot parse `{signature}`: {e}"))?;
+    let text = |span: proc_macro2::Span| &signature[span.byte_range()];
+    let link_name = |ident: &syn::Ident| {
+        if ident == symbol {
+            String::new()
+        } else {
+            format!("    #[link_name = \"{symbol}\"]\n")
+        }
+    };
+    match file.items.as_slice() {
+        [syn::Item::Fn(function)] => {
+            let sig = &function.sig;
+            if !sig.generics.params.is_empty() {
+                return Err(format!("`{}` is generic", sig.ident));
+            }
+            let end = match &sig.output {
+                syn::ReturnType::Default => sig.paren_token.span.close().byte_range().end,
+                syn::ReturnType::Type(_, ty) => ty.span().byte_range().end,
+            };
+            let declaration = &signature[sig.fn_token.span.byte_range().start..end];
+            let mut code = format!(
+                "unsafe extern \"C\" {{\n{}    pub {declaration};\n}}",
+                link_name(&sig.ident)
+            );
+            if let Some(export) = export {
+                code.push_str("\n\n");
+                code.push_str(&export_wrapper(sig, export, text)?);
+            }
+            Ok(code)
+        }
+        [syn::Item::Static(global)] => {
+            let mutability = match global.mutability {
+                syn::StaticMutability::Mut(_) => "mut ",
+                _ => "",
+            };
+            Ok(format!(
+                "unsafe extern \"C\" {{\n{}    pub static {mutability}{}: {};\n}}",
+                link_name(&global.ident),
+                global.ident,
+                text(global.ty.span())
+            ))
+        }
+        _ => Err(format!(
+            "`{signature}` is not the signature of one function or static"
+        )),
+    }
+}
+
+/// A Rust function exported as `export` that calls the declared C function `sig`.
+fn export_wrapper<'a>(
+    sig: &syn::Signature,
+    export: &str,
+    text: impl Fn(proc_macro2::Span) -> &'a str,
+) -> Result<String, String> {
+    if sig.variadic.is_some() {
+        return Err(format!("`{}` is variadic and cannot be wrapped", sig.ident));
+    }
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    for (i, input) in sig.inputs.iter().enumerate() {
+        let syn::FnArg::Typed(param) = input else {
+            return Err(format!("`{}` takes `self`", sig.ident));
+        };
+        params.push(format!("a{i}: {}", text(param.ty.span())));
+        args.push(format!("a{i}"));
+    }
+    let output = match &sig.output {
+        syn::ReturnType::Default => String::new(),
+        syn::ReturnType::Type(_, ty) => format!(" -> {}", text(ty.span())),
+    };
+    let ident = &sig.ident;
+    Ok(format!(
+        "#[unsafe(export_name = \"{export}\")]\n\
+         pub unsafe extern \"C\" fn {EXPORT_PREFIX}{ident}({}){output} {{\n    \
+         unsafe {{ {ident}({}) }}\n}}",
+        params.join(", "),
+        args.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declares_functions_and_globals() {
+        assert_eq!(
+            extern_declaration(
+                "#[unsafe(no_mangle)]\npub unsafe extern \"C\" fn list_len(list: *const List) -> usize { todo!() }",
+                "listLen",
+                None,
+            )
+            .unwrap(),
+            "unsafe extern \"C\" {\n    #[link_name = \"listLen\"]\n    pub fn list_len(list: *const List) -> usize;\n}"
+        );
+        assert_eq!(
+            extern_declaration("pub static mut COUNT: i32 = todo!();", "count", None).unwrap(),
+            "unsafe extern \"C\" {\n    #[link_name = \"count\"]\n    pub static mut COUNT: i32;\n}"
+        );
+        assert_eq!(
+            extern_declaration(
+                "pub extern \"C\" fn log(fmt: *const c_char, ...) { todo!() }",
+                "log",
+                None
+            )
+            .unwrap(),
+            "unsafe extern \"C\" {\n    pub fn log(fmt: *const c_char, ...);\n}"
+        );
+    }
+
+    #[test]
+    fn exports_renamed_functions_through_a_wrapper() {
+        let code = extern_declaration(
+            "pub extern \"C\" fn list_push(list: *mut List, value: Option<&i32>) { todo!() }",
+            "harvest_c_list_push",
+            Some("list_push"),
+        )
+        .unwrap();
+        assert_eq!(
+            code,
+            "unsafe extern \"C\" {\n    #[link_name = \"harvest_c_list_push\"]\n    \
+             pub fn list_push(list: *mut List, value: Option<&i32>);\n}\n\n\
+             #[unsafe(export_name = \"list_push\")]\n\
+             pub unsafe extern \"C\" fn harvest_export_list_push(a0: *mut List, a1: Option<&i32>) {\n    \
+             unsafe { list_push(a0, a1) }\n}"
+        );
+        assert!(
+            extern_declaration(
+                "pub fn log(fmt: *const c_char, ...) { todo!() }",
+                "x",
+                Some("log")
+            )
+            .is_err()
+        );
+        assert!(extern_declaration("pub struct List;", "List", None).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Resolves the configured translation units into the C functions they name.
+
+use crate::TranslationUnits;
+use build_project_spec::ProjectKind;
+use c_ast::{RichSourceMap, TopLevelEntity, annotate_visibility};
+
+/// The units of a units file: one per line, skipping blank lines and `#` comments.
+pub(crate) fn parse_list(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+}
+
+/// Whether `unit` names a C file rather than a function.
+fn is_file(unit: &str) -> bool {
+    unit.ends_with(".c") || unit.ends_with(".h")
+}
+
+/// Whether the project-relative path `file` is the file `unit`, given relative to the project or
+/// to any directory in it.
+fn file_matches(file: &str, unit: &str) -> bool {
+    let unit = unit.trim_start_matches("./");
+    file == unit || file.ends_with(&format!("/{unit}"))
+}
+
+/// The functions of `map` that `units` select, and the remaining functions whose symbol the Rust
+/// crate must provide. A unit that names no file or function of `map` is an error.
+pub(crate) fn select(
+    map: &RichSourceMap,
+    kind: &ProjectKind,
+    units: &[String],
+) -> Result<TranslationUnits, String> {
+    let mut functions: Vec<TopLevelEntity> = map.app_functions.clone();
+    annotate_visibility(&mut functions, &map.symbols);
+
+    let mut selected = TranslationUnits::default();
+    for unit in units {
+        let before = selected.functions.len();
+        if is_file(unit) {
+            if !map
+                .iter_all_entities()
+                .any(|entity| file_matches(&entity.span.file, unit))
+            {
+                return Err(format!("no C file `{unit}` in the project"));
+            }
+            selected.functions.extend(
+                functions
+                    .iter()
+                    .filter(|f| file_matches(&f.span.file, unit))
+                    .filter_map(|f| f.name().map(str::to_string)),
+            );
+        } else if functions.iter().any(|f| f.name() == Some(unit)) {
+            selected.functions.insert(unit.clone());
+        } else {
+            return Err(format!("no C function `{unit}` is defined in the project"));
+        }
+        if selected.functions.len() == before {
+            tracing::warn!("Translation unit `{unit}` selects no new function");
+        }
+    }
+
+    for function in &functions {
+        let Some(name) = function.name() else {
+            continue;
+        };
+        let keeps_symbol = match kind {
+            ProjectKind::Library => function.annotations.public,
+            ProjectKind::Executable => name == "main",
+        };
+        if keeps_symbol && !selected.translates(name) {
+            selected.renamed.insert(name.to_string());
+        }
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::{ClangAST, EntityKind, SourcePoint, SourceSpan};
+
+    fn function(file: &str, name: &str) -> TopLevelEntity {
+        let point = SourcePoint {
+            line: 1,
+            column: 1,
+            offset: 0,
+        };
+        TopLevelEntity {
+            kind: EntityKind::FunctionDecl,
+            source_text: format!("int {name}(void) {{ return 0; }}"),
+            span: SourceSpan {
+                file: file.into(),
+                start: point.clone(),
+                end: point,
+            },
+            ast: Some(ClangAST::FunctionDecl {
+                name: name.into(),
+                storage_class: None,
+            }),
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn map() -> RichSourceMap {
+        let mut map = RichSourceMap::new();
+        map.app_functions = vec![
+            function("src/list.c", "list_new"),
+            function("src/list.c", "list_free"),
+            function("src/util.c", "checksum"),
+            function("src/main.c", "main"),
+        ];
+        map
+    }
+
+    fn units(units: &[&str]) -> Vec<String> {
+        units.iter().map(|u| u.to_string()).collect()
+    }
+
+    #[test]
+    fn selects_files_and_functions() {
+        let selected = select(
+            &map(),
+            &ProjectKind::Executable,
+            &units(&["list.c", "checksum"]),
+        )
+        .unwrap();
+        let functions: Vec<&str> = selected.functions.iter().map(String::as_str).collect();
+        assert_eq!(functions, ["checksum", "list_free", "list_new"]);
+        // The executable's `main` stays in C behind a Rust `main`.
+        assert!(selected.renamed.contains("main"));
+        assert_eq!(selected.c_symbol("main"), "harvest_c_main");
+        assert_eq!(selected.c_symbol("list_new"), "list_new");
+    }
+
+    #[test]
+    fn translated_main_is_not_renamed() {
+        let selected = select(&map(), &ProjectKind::Executable, &units(&["./src/main.c"])).unwrap();
+        assert!(selected.translates("main"));
+        assert!(selected.renamed.is_empty());
+    }
+
+    #[test]
+    fn unknown_units_are_errors() {
+        for unit in ["missing.c", "list_push", "st.c"] {
+            assert!(select(&map(), &ProjectKind::Library, &units(&[unit])).is_err());
+        }
+    }
+
+    #[test]
+    fn units_file_skips_blanks_and_comments() {
+        let list = "# first step\nsrc/list.c\n\n  checksum  \n";
+        assert_eq!(
+            parse_list(list).collect::<Vec<_>>(),
+            ["src/list.c", "checksum"]
+        );
+    }
+}
@@ -0,0 +1,415 @@
+//! Records which C declaration each item of a translated crate came from, so that reviewers can
+//! jump between the C and the Rust, and repair prompts can show the C an item translates.
+//!
+//! A modular translation places each C declaration in the module of its C file, under the name
+//! the [`NameMap`] gives it, so it links its items exactly (`modular_translation_llm` provides
+//! the tool). [`MatchProvenance`] links the items of the other translations by name, on a
+//! best-effort basis. Repair patches and other edits move and resize the items, so
+//! [`UpdateProvenance`] carries a map over to each new version of the crate.
+
+use c_ast::{EntityKind, RichSourceMap, SourceSpan, TopLevelEntity};
+use full_source::CargoPackage;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use name_map::{NameKind, NameMap, upper_camel_case};
+use quantize_rust_spans::{ItemKind, RustItem, RustItemMap};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// How an item was linked to its C declaration.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    /// The translation put the item, translated from the declaration, where it is.
+    Translated,
+    /// The item is named as the declaration would be in Rust.
+    NameMatch,
+}
+
+/// The C declaration one Rust item was translated from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// The name the Rust item defines.
+    pub rust_name: String,
+    /// Path of the Rust file, relative to the root of the package.
+    pub rust_file: PathBuf,
+    /// Byte range of the item in `rust_file`.
+    pub rust_range: Range<usize>,
+    /// First and last line of the item in `rust_file`, counting from 1.
+    pub rust_lines: (usize, usize),
+    /// The C identifier of the declaration.
+    pub c_name: String,
+    pub c_span: SourceSpan,
+    pub origin: Origin,
+}
+
+/// A [Representation] linking the items of a [`CargoPackage`] to the C declarations they were
+/// translated from. Items without a C declaration, such as impl blocks, have no entry.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProvenanceMap {
+    pub entries: Vec<Provenance>,
+}
+
+impl ProvenanceMap {
+    /// Links each named C declaration of `c_ast` to an item of `package` defining one of the
+    /// Rust names it may have (see [`rust_names`]), in a file that `in_file` accepts for it.
+    /// `item_map` maps the items of `package`.
+    pub fn link(
+        c_ast: &RichSourceMap,
+        names: Option<&NameMap>,
+        item_map: &RustItemMap,
+        package: &CargoPackage,
+        origin: Origin,
+        in_file: impl Fn(&TopLevelEntity, &Path) -> bool,
+    ) -> ProvenanceMap {
+        let mut entries = Vec::new();
+        for entity in c_ast.iter_definitions().chain(&c_ast.defines) {
+            let Some(c_name) = entity.name() else {
+                continue;
+            };
+            let found = rust_names(entity, names).into_iter().find_map(|rust_name| {
+                find_item(item_map, &rust_name, |file| in_file(entity, file))
+            });
+            if let Some((file, item)) = found {
+                entries.push(Provenance {
+                    rust_name: item.name.clone().unwrap_or_default(),
+                    rust_file: file.clone(),
+                    rust_range: item.range.clone(),
+                    rust_lines: lines(package, file, &item.range),
+                    c_name: c_name.to_string(),
+                    c_span: entity.span.clone(),
+                    origin,
+                });
+            }
+        }
+        ProvenanceMap { entries }
+    }
+
+    /// This map carried over to `package`, a later version of the package it was made for: each
+    /// entry moves to the item defining its Rust name, in the same file if one there does.
+    /// Entries whose item is gone are left out. `item_map` maps the items of `package`.
+    pub fn rebase(&self, item_map: &RustItemMap, package: &CargoPackage) -> ProvenanceMap {
+        let entries = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let (file, item) =
+                    find_item(item_map, &entry.rust_name, |file| file == entry.rust_file)
+                        .or_else(|| find_item(item_map, &entry.rust_name, |_| true))?;
+                Some(Provenance {
+                    rust_file: file.clone(),
+                    rust_range: item.range.clone(),
+                    rust_lines: lines(package, file, &item.range),
+                    ..entry.clone()
+                })
+            })
+            .collect();
+        ProvenanceMap { entries }
+    }
+
+    /// The entry of the smallest linked item containing `range` of `file`.
+    pub fn for_item(&self, file: &Path, range: &Range<usize>) -> Option<&Provenance> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry.rust_file == file
+                    && entry.rust_range.start <= range.start
+                    && range.end <= entry.rust_range.end
+            })
+            .min_by_key(|entry| entry.rust_range.len())
+    }
+}
+
+/// The Rust names the C declaration `entity` may have been translated to, most likely first: the
+/// ones `names` gives it, its C name, and its C name in the naming convention of its kind.
+pub fn rust_names(entity: &TopLevelEntity, names: Option<&NameMap>) -> Vec<String> {
+    let Some(ident) = entity.name() else {
+        return Vec::new();
+    };
+    let (kinds, conventional): (&[NameKind], String) = match entity.kind {
+        EntityKind::TypedefDecl
+        | EntityKind::RecordDecl
+        | EntityKind::UnionDecl
+        | EntityKind::EnumDecl => (&[NameKind::Type], upper_camel_case(ident)),
+        EntityKind::FunctionDecl => (&[NameKind::Function], ident.to_string()),
+        EntityKind::VarDecl => (&[NameKind::Global], ident.to_ascii_uppercase()),
+        EntityKind::MacroDefinition => (
+            &[NameKind::Constant, NameKind::Macro],
+            ident.to_ascii_uppercase(),
+        ),
+        _ => return Vec::new(),
+    };
+    let mut candidates: Vec<String> = names
+        .into_iter()
+        .flat_map(|names| &names.entries)
+        .filter(|entry| kinds.contains(&entry.kind) && entry.c_ident() == ident)
+        .map(|entry| entry.rust_name.clone())
+        .collect();
+    for name in [ident.to_string(), conventional] {
+        if !candidates.contains(&name) {
+            candidates.push(name);
+        }
+    }
+    candidates
+}
+
+/// The first top-level item of `item_map`, in order of file, that defines `name` in a file
+/// `in_file` accepts. Impl blocks, imports and extern blocks define no name of their own.
+fn find_item<'a>(
+    item_map: &'a RustItemMap,
+    name: &str,
+    in_file: impl Fn(&Path) -> bool,
+) -> Option<(&'a PathBuf, &'a RustItem)> {
+    let mut files: Vec<_> = item_map
+        .items
+        .iter()
+        .filter(|(file, _)| in_file(file))
+        .collect();
+    files.sort_by_key(|(file, _)| *file);
+    files.into_iter().find_map(|(file, items)| {
+        items
+            .iter()
+            .find(|item| {
+                !matches!(
+                    item.kind,
+                    ItemKind::Impl | ItemKind::Use | ItemKind::ForeignMod
+                ) && item.name.as_deref() == Some(name)
+            })
+            .map(|item| (file, item))
+    })
+}
+
+/// The lines, counting from 1, on which `range` of `file` in `package` starts and ends.
+fn lines(package: &CargoPackage, file: &Path, range: &Range<usize>) -> (usize, usize) {
+    let source = package.dir.get_file(file).unwrap_or_default();
+    let line = |offset: usize| {
+        let before = &source[..offset.min(source.len())];
+        1 + before.iter().filter(|&&b| b == b'\n').count()
+    };
+    (line(range.start), line(range.end.max(range.start + 1) - 1))
+}
+
+impl fmt::Display for ProvenanceMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ProvenanceMap: {} items", self.entries.len())?;
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "  {}:{}-{} `{}` <- {}:{}-{} `{}` ({:?})",
+                entry.rust_file.display(),
+                entry.rust_lines.0,
+                entry.rust_lines.1,
+                entry.rust_name,
+                entry.c_span.file,
+                entry.c_span.start.line,
+                entry.c_span.end.line,
+                entry.c_name,
+                entry.origin
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for ProvenanceMap {
+    fn name(&self) -> &'static str {
+        "provenance_map"
+    }
+
+    /// Writes the map as JSON.
+    fn materialize(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Reads the [`RustItemMap`] of input `index` and the package it maps.
+fn item_map<'a>(
+    context: &'a RunContext,
+    inputs: &[Id],
+    index: usize,
+) -> Result<(&'a RustItemMap, &'a CargoPackage), Box<dyn std::error::Error>> {
+    let item_map = context
+        .ir_snapshot
+        .get::<RustItemMap>(inputs[index])
+        .ok_or("provenance_map: no RustItemMap in IR")?;
+    let package = context
+        .ir_snapshot
+        .get::<CargoPackage>(item_map.cargo_pkg_idx)
+        .ok_or("provenance_map: no CargoPackage in IR")?;
+    Ok((item_map, package))
+}
+
+/// Links the items of a package to the C declarations of the same names, for translations that
+/// do not record where they put each declaration.
+pub struct MatchProvenance;
+
+impl Tool for MatchProvenance {
+    fn name(&self) -> &'static str {
+        "match_provenance"
+    }
+
+    /// Inputs:
+    /// 1. [`RustItemMap`] id -- the items of the translated package.
+    /// 2. [`RichSourceMap`] id -- the C declarations it was translated from.
+    /// 3. Optionally, a [`NameMap`] id -- the Rust names of the C names.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let (item_map, package) = item_map(&context, &inputs, 0)?;
+        let c_ast = context
+            .ir_snapshot
+            .get::<RichSourceMap>(inputs[1])
+            .ok_or("match_provenance: no RichSourceMap in IR")?;
+        let names = inputs
+            .get(2)
+            .and_then(|id| context.ir_snapshot.get::<NameMap>(*id));
+        let map = ProvenanceMap::link(
+            c_ast,
+            names,
+            item_map,
+            package,
+            Origin::NameMatch,
+            |_, _| true,
+        );
+        info!(
+            "Provenance: {} items matched to C declarations by name",
+            map.entries.len()
+        );
+        Ok(Box::new(map))
+    }
+}
+
+/// Carries a [`ProvenanceMap`] over to a later version of its package.
+pub struct UpdateProvenance;
+
+impl Tool for UpdateProvenance {
+    fn name(&self) -> &'static str {
+        "update_provenance"
+    }
+
+    /// Inputs:
+    /// 1. [`ProvenanceMap`] id -- the map to carry over.
+    /// 2. [`RustItemMap`] id -- the items of the new version of the package.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let map = context
+            .ir_snapshot
+            .get::<ProvenanceMap>(inputs[0])
+            .ok_or("update_provenance: no ProvenanceMap in IR")?;
+        let (item_map, package) = item_map(&context, &inputs, 1)?;
+        let updated = map.rebase(item_map, package);
+        info!(
+            "Provenance: {} of {} items still linked",
+            updated.entries.len(),
+            map.entries.len()
+        );
+        Ok(Box::new(updated))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_ast::{ClangAST, SourcePoint};
+    use harvest_core::fs::RawDir;
+
+    fn entity(kind: EntityKind, ast: ClangAST, line: u32) -> TopLevelEntity {
+        let point = |line| SourcePoint {
+            line,
+            column: 1,
+            offset: 0,
+        };
+        TopLevelEntity {
+            kind,
+            source_text: String::new(),
+            span: SourceSpan {
+                file: "list.c".into(),
+                start: point(line),
+                end: point(line + 2),
+            },
+            ast: Some(ast),
+            annotations: Default::default(),
+            sub_entities: Vec::new(),
+            variant_tags: Vec::new(),
+            comments: Default::default(),
+        }
+    }
+
+    fn package(lib: &str) -> (RustItemMap, CargoPackage) {
+        let mut dir = RawDir::default();
+        dir.set_file("src/lib.rs", lib.as_bytes().to_vec()).unwrap();
+        let package = CargoPackage { dir };
+        (RustItemMap::new(Id::new(), &package).unwrap(), package)
+    }
+
+    #[test]
+    fn items_are_linked_by_name_and_follow_edits() {
+        let mut c_ast = RichSourceMap::new();
+        let record = ClangAST::RecordDecl {
+            name: Some("list_node".into()),
+            tag_used: Some("struct".into()),
+            layout: None,
+        };
+        c_ast
+            .app_types
+            .push(entity(EntityKind::RecordDecl, record, 1));
+        let function = |name: &str| ClangAST::FunctionDecl {
+            name: name.into(),
+            storage_class: None,
+        };
+        c_ast
+            .app_functions
+            .push(entity(EntityKind::FunctionDecl, function("list_len"), 5));
+        c_ast
+            .app_functions
+            .push(entity(EntityKind::FunctionDecl, function("gone"), 9));
+
+        let lib = "pub struct ListNode {\n    next: usize,\n}\n\n\
+                   impl ListNode {}\n\n\
+                   pub fn list_len(n: &ListNode) -> usize {\n    n.next\n}\n";
+        let (item_map, lib_package) = package(lib);
+        let map = ProvenanceMap::link(
+            &c_ast,
+            None,
+            &item_map,
+            &lib_package,
+            Origin::NameMatch,
+            |_, _| true,
+        );
+        let linked: Vec<_> = map
+            .entries
+            .iter()
+            .map(|e| (e.c_name.as_str(), e.rust_name.as_str(), e.rust_lines))
+            .collect();
+        assert_eq!(
+            linked,
+            [
+                ("list_node", "ListNode", (1, 3)),
+                ("list_len", "list_len", (7, 9))
+            ]
+        );
+
+        // A patch that grows the struct moves the function down.
+        let patched = lib.replace("    next: usize,\n", "    next: usize,\n    len: usize,\n");
+        let (item_map, patched_package) = package(&patched);
+        let rebased = map.rebase(&item_map, &patched_package);
+        let function = &rebased.entries[1];
+        assert_eq!(function.rust_lines, (8, 10));
+        assert_eq!(
+            &patched[function.rust_range.clone()],
+            "pub fn list_len(n: &ListNode) -> usize {\n    n.next\n}"
+        );
+        let body = patched.find("n.next").unwrap();
+        let found = rebased.for_item(Path::new("src/lib.rs"), &(body..body + 6));
+        assert_eq!(found.map(|e| e.c_span.start.line), Some(5));
+    }
+}
@@ -1,5 +1,6 @@
 //! A [Tool] and [Representation] to deconstruct a [CargoPackage] into
-//! the top-level items in each Rust source file.
+//! the items in each Rust source file: the top-level items, and the items nested in impl blocks,
+//! traits and inline modules.
 
 use full_source::CargoPackage;
 use harvest_core::tools::{RunContext, Tool};
@@ -49,27 +50,165 @@ impl PartialOrd for LineColumn {
     }
 }
 
-/// A [Representation] that maps top-level items in a [CargoPackage].
+/// The kind of a [RustItem].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ItemKind {
+    Const,
+    Enum,
+    ExternCrate,
+    Fn,
+    ForeignMod,
+    Impl,
+    Macro,
+    Mod,
+    Static,
+    Struct,
+    Trait,
+    TraitAlias,
+    Type,
+    Union,
+    Use,
+    Other,
+}
+
+/// An item of a Rust source file, with the items nested in it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RustItem {
+    pub kind: ItemKind,
+    /// The name the item declares; for an impl block, the name of its self type.
+    pub name: Option<String>,
+    /// The byte range of the item in its file, including its attributes and doc comments.
+    pub range: Range<usize>,
+    /// The items of an impl block, a trait or an inline module, in order.
+    pub children: Vec<RustItem>,
+}
+
+impl RustItem {
+    /// How a prompt refers to this item as the parent of another, such as
+    /// ``impl block of `Point` ``.
+    pub fn describe(&self) -> String {
+        let name = self.name.as_deref().unwrap_or("_");
+        match self.kind {
+            ItemKind::Impl => format!("impl block of `{name}`"),
+            ItemKind::Mod => format!("module `{name}`"),
+            ItemKind::Trait => format!("trait `{name}`"),
+            kind => format!("{kind:?} `{name}`"),
+        }
+    }
+}
+
+/// A [Representation] that maps the items in a [CargoPackage].
 ///
-/// Stores a list of spans of top-level items in each Rust file
-/// of a [CargoPackage].
+/// Stores the tree of items in each Rust file of a [CargoPackage], so that an error can be
+/// attributed to the smallest item containing it, such as one method of a large impl block.
 pub struct RustItemMap {
     /// [Representation] index of the [CargoPackage] from which this
     /// [Representation] is derived.
     pub cargo_pkg_idx: Id,
 
-    /// Stores a list of spans (starting [LineColumn] and ending
-    /// [LineColumn]) for top-level items in each Rust file in
-    /// a [CargoPackage]. Keys are full paths relative to the root of
-    /// the [CargoPackage].
-    pub items: HashMap<PathBuf, Vec<Range<usize>>>,
+    /// Stores the top-level items, with the items nested in them, of each Rust file in a
+    /// [CargoPackage]. Keys are full paths relative to the root of the [CargoPackage].
+    pub items: HashMap<PathBuf, Vec<RustItem>>,
+
+    /// The smallest items defining each name: functions, types, traits, constants, statics,
+    /// modules, macros, methods and other associated items under their own name, structs, unions
+    /// and enums under the names of their fields and variants as well, and extern blocks under
+    /// the names of their foreign items.
+    pub definitions: HashMap<String, Vec<(PathBuf, Range<usize>)>>,
+}
+
+impl RustItemMap {
+    /// Maps the items of `cargo_pkg`, whose [Representation] index is `cargo_pkg_idx`. A file
+    /// that does not parse gets no items.
+    pub fn new(
+        cargo_pkg_idx: Id,
+        cargo_pkg: &CargoPackage,
+    ) -> Result<RustItemMap, Box<dyn std::error::Error>> {
+        let mut items = HashMap::new();
+        let mut definitions: HashMap<String, Vec<(PathBuf, Range<usize>)>> = HashMap::new();
+
+        let source_files: Vec<_> = cargo_pkg
+            .dir
+            .files_recursive()
+            .into_iter()
+            .filter(|(path, _)| path.extension().is_some_and(|e| e == "rs"))
+            .collect();
+
+        for (path, source) in source_files {
+            let source = str::from_utf8(source)?;
+            match extract_items(source) {
+                Ok((file_items, names)) => {
+                    info!(
+                        "QuantizeRustSpans: split {} into {} items",
+                        path.display(),
+                        file_items.len()
+                    );
+
+                    for (name, range) in names {
+                        definitions
+                            .entry(name)
+                            .or_default()
+                            .push((path.clone(), range));
+                    }
+                    items.insert(path, file_items);
+                }
+                Err(e) => {
+                    warn!("syn failed to parse source, treating as single items: {e}");
+                    items.insert(path, vec![]);
+                }
+            }
+        }
+
+        let total_items: usize = items.values().map(|v| v.len()).sum();
+        info!(
+            "QuantizeRustSpans: found {} top-level item(s) across {} file(s)",
+            total_items,
+            items.len()
+        );
+        Ok(RustItemMap {
+            cargo_pkg_idx,
+            items,
+            definitions,
+        })
+    }
+
+    /// The item defining `name`, if exactly one item does.
+    pub fn definition(&self, name: &str) -> Option<&(PathBuf, Range<usize>)> {
+        match self.definitions.get(name)?.as_slice() {
+            [definition] => Some(definition),
+            _ => None,
+        }
+    }
+
+    /// The items of `file` containing `range`, from the top-level item to the smallest one.
+    pub fn enclosing_items(&self, file: &Path, range: &Range<usize>) -> Vec<&RustItem> {
+        let mut enclosing = Vec::new();
+        let mut items = self.items.get(file).map_or(&[][..], Vec::as_slice);
+        while let Some(item) = items
+            .iter()
+            .find(|item| item.range.start <= range.start && range.end <= item.range.end)
+        {
+            enclosing.push(item);
+            items = &item.children;
+        }
+        enclosing
+    }
 }
 
 impl fmt::Display for RustItemMap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn count(items: &[RustItem]) -> usize {
+            items.iter().map(|item| 1 + count(&item.children)).sum()
+        }
         writeln!(f, "RustItemMap ({} files):", self.items.len())?;
         for (path, items) in self.items.iter() {
-            writeln!(f, "\t{}: {} items", path.display(), items.len())?;
+            writeln!(
+                f,
+                "\t{}: {} top-level items, {} in all",
+                path.display(),
+                items.len(),
+                count(items)
+            )?;
         }
         Ok(())
     }
@@ -96,25 +235,176 @@ impl Representation for RustItemMap {
             }
             fs::write(full_path, serde_json::ser::to_vec(items)?)?;
         }
+        fs::write(
+            path.join("_definitions"),
+            serde_json::ser::to_vec(&self.definitions)?,
+        )?;
         Ok(())
     }
 }
 
-/// Split `source` into top-level items strings, each formatted with `prettyplease`.
-///
-/// Falls back to a single "whole-file" items if `syn` cannot parse the source.
-fn extract_top_level_spans(source: &str) -> Result<Vec<Range<usize>>, impl std::error::Error> {
-    syn::parse_file(source).map(|file| {
-        file.items
+/// A name defined in a file and the byte range of the item defining it.
+type Definition = (String, Range<usize>);
+
+/// The items of `source`, with the names each defines (see [`RustItemMap::definitions`]).
+fn extract_items(source: &str) -> syn::Result<(Vec<RustItem>, Vec<Definition>)> {
+    let file = syn::parse_file(source)?;
+    let mut definitions = Vec::new();
+    let items = file
+        .items
+        .iter()
+        .map(|item| item_tree(item, &mut definitions))
+        .collect();
+    Ok((items, definitions))
+}
+
+/// The tree of `item`, adding the names it and its nested items define to `definitions`.
+fn item_tree(item: &syn::Item, definitions: &mut Vec<Definition>) -> RustItem {
+    let range = item.span().byte_range();
+    let names = defined_names(item);
+    let name = match item {
+        syn::Item::Impl(item) => match &*item.self_ty {
+            syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+            _ => None,
+        },
+        syn::Item::ForeignMod(_) => None,
+        _ => names.first().cloned(),
+    };
+    definitions.extend(names.into_iter().map(|name| (name, range.clone())));
+    let children = match item {
+        syn::Item::Impl(item) => item
+            .items
+            .iter()
+            .map(|member| {
+                let (kind, ident) = match member {
+                    syn::ImplItem::Const(c) => (ItemKind::Const, Some(&c.ident)),
+                    syn::ImplItem::Fn(f) => (ItemKind::Fn, Some(&f.sig.ident)),
+                    syn::ImplItem::Type(t) => (ItemKind::Type, Some(&t.ident)),
+                    syn::ImplItem::Macro(_) => (ItemKind::Macro, None),
+                    _ => (ItemKind::Other, None),
+                };
+                member_item(kind, ident, member.span().byte_range(), definitions)
+            })
+            .collect(),
+        syn::Item::Trait(item) => item
+            .items
+            .iter()
+            .map(|member| {
+                let (kind, ident) = match member {
+                    syn::TraitItem::Const(c) => (ItemKind::Const, Some(&c.ident)),
+                    syn::TraitItem::Fn(f) => (ItemKind::Fn, Some(&f.sig.ident)),
+                    syn::TraitItem::Type(t) => (ItemKind::Type, Some(&t.ident)),
+                    syn::TraitItem::Macro(_) => (ItemKind::Macro, None),
+                    _ => (ItemKind::Other, None),
+                };
+                member_item(kind, ident, member.span().byte_range(), definitions)
+            })
+            .collect(),
+        syn::Item::Mod(item) => item
+            .content
             .iter()
-            .map(|item| item.span().byte_range())
+            .flat_map(|(_, items)| items)
+            .map(|item| item_tree(item, definitions))
+            .collect(),
+        _ => Vec::new(),
+    };
+    RustItem {
+        kind: item_kind(item),
+        name,
+        range,
+        children,
+    }
+}
+
+/// A member of an impl block or a trait, which defines its `ident`.
+fn member_item(
+    kind: ItemKind,
+    ident: Option<&syn::Ident>,
+    range: Range<usize>,
+    definitions: &mut Vec<Definition>,
+) -> RustItem {
+    let name = ident.map(|ident| ident.to_string());
+    definitions.extend(name.iter().map(|name| (name.clone(), range.clone())));
+    RustItem {
+        kind,
+        name,
+        range,
+        children: Vec::new(),
+    }
+}
+
+fn item_kind(item: &syn::Item) -> ItemKind {
+    match item {
+        syn::Item::Const(_) => ItemKind::Const,
+        syn::Item::Enum(_) => ItemKind::Enum,
+        syn::Item::ExternCrate(_) => ItemKind::ExternCrate,
+        syn::Item::Fn(_) => ItemKind::Fn,
+        syn::Item::ForeignMod(_) => ItemKind::ForeignMod,
+        syn::Item::Impl(_) => ItemKind::Impl,
+        syn::Item::Macro(_) => ItemKind::Macro,
+        syn::Item::Mod(_) => ItemKind::Mod,
+        syn::Item::Static(_) => ItemKind::Static,
+        syn::Item::Struct(_) => ItemKind::Struct,
+        syn::Item::Trait(_) => ItemKind::Trait,
+        syn::Item::TraitAlias(_) => ItemKind::TraitAlias,
+        syn::Item::Type(_) => ItemKind::Type,
+        syn::Item::Union(_) => ItemKind::Union,
+        syn::Item::Use(_) => ItemKind::Use,
+        _ => ItemKind::Other,
+    }
+}
+
+/// The names `item` itself defines, its own name first; the items nested in impl blocks, traits
+/// and modules define their own names.
+fn defined_names(item: &syn::Item) -> Vec<String> {
+    let fields = |fields: &syn::Fields| -> Vec<String> {
+        fields
+            .iter()
+            .filter_map(|field| field.ident.as_ref().map(|ident| ident.to_string()))
             .collect()
-    })
+    };
+    let (ident, members): (Option<&syn::Ident>, Vec<String>) = match item {
+        syn::Item::Const(item) => (Some(&item.ident), vec![]),
+        syn::Item::Enum(item) => (
+            Some(&item.ident),
+            item.variants.iter().map(|v| v.ident.to_string()).collect(),
+        ),
+        syn::Item::Fn(item) => (Some(&item.sig.ident), vec![]),
+        syn::Item::ForeignMod(item) => (
+            None,
+            item.items
+                .iter()
+                .filter_map(|item| match item {
+                    syn::ForeignItem::Fn(f) => Some(f.sig.ident.to_string()),
+                    syn::ForeignItem::Static(s) => Some(s.ident.to_string()),
+                    syn::ForeignItem::Type(t) => Some(t.ident.to_string()),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        syn::Item::Macro(item) => (item.ident.as_ref(), vec![]),
+        syn::Item::Mod(item) => (Some(&item.ident), vec![]),
+        syn::Item::Static(item) => (Some(&item.ident), vec![]),
+        syn::Item::Struct(item) => (Some(&item.ident), fields(&item.fields)),
+        syn::Item::Trait(item) => (Some(&item.ident), vec![]),
+        syn::Item::TraitAlias(item) => (Some(&item.ident), vec![]),
+        syn::Item::Type(item) => (Some(&item.ident), vec![]),
+        syn::Item::Union(item) => (
+            Some(&item.ident),
+            fields(&syn::Fields::Named(item.fields.clone())),
+        ),
+        _ => (None, vec![]),
+    };
+    ident
+        .map(|ident| ident.to_string())
+        .into_iter()
+        .chain(members)
+        .collect()
 }
 
 // Tool
 
-/// A [Tool] to deconstruct a [CargoPackage] into the top-level items
+/// A [Tool] to deconstruct a [CargoPackage] into the tree of items
 /// in each Rust source file.
 pub struct QuantizeRustSpans;
 
@@ -133,43 +423,68 @@ impl Tool for QuantizeRustSpans {
             .ir_snapshot
             .get::<CargoPackage>(cargo_pkg_idx)
             .ok_or("QuantizeRustSpans: no CargoPackage found in IR")?;
-        let mut items = HashMap::new();
+        Ok(Box::new(RustItemMap::new(cargo_pkg_idx, cargo_pkg)?))
+    }
+}
 
-        let source_files: Vec<_> = cargo_pkg
-            .dir
-            .files_recursive()
-            .into_iter()
-            .filter(|(path, _)| path.extension().is_some_and(|e| e == "rs"))
-            .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        for (path, source) in source_files {
-            let source = str::from_utf8(source)?;
-            match extract_top_level_spans(source) {
-                Ok(decls) => {
-                    info!(
-                        "QuantizeRustSpans: split {} into {} items",
-                        path.display(),
-                        decls.len()
-                    );
-
-                    items.insert(path, decls);
-                }
-                Err(e) => {
-                    warn!("syn failed to parse source, treating as single items: {e}");
-                    items.insert(path, vec![]);
-                }
-            }
-        }
+    #[test]
+    fn items_define_their_names_and_members() {
+        let source = "struct Point { x: i32, y: i32 }\n\
+                      impl Point { fn norm(&self) -> i32 { self.x } }\n\
+                      unsafe extern \"C\" { fn abs(x: i32) -> i32; }\n\
+                      mod geometry { pub trait Shape { fn area(&self) -> i32; } }\n";
+        let (items, definitions) = extract_items(source).unwrap();
+        let text = |range: &Range<usize>| &source[range.clone()];
+        let names: Vec<(&str, &str)> = definitions
+            .iter()
+            .map(|(name, range)| (name.as_str(), text(range)))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("Point", "struct Point { x: i32, y: i32 }"),
+                ("x", "struct Point { x: i32, y: i32 }"),
+                ("y", "struct Point { x: i32, y: i32 }"),
+                ("norm", "fn norm(&self) -> i32 { self.x }"),
+                ("abs", "unsafe extern \"C\" { fn abs(x: i32) -> i32; }"),
+                (
+                    "geometry",
+                    "mod geometry { pub trait Shape { fn area(&self) -> i32; } }"
+                ),
+                ("Shape", "pub trait Shape { fn area(&self) -> i32; }"),
+                ("area", "fn area(&self) -> i32;"),
+            ]
+        );
 
-        let total_items: usize = items.values().map(|v| v.len()).sum();
-        info!(
-            "QuantizeRustSpans: found {} top-level item(s) across {} file(s)",
-            total_items,
-            items.len()
+        let item_map = RustItemMap {
+            cargo_pkg_idx: Id::new(),
+            items: HashMap::from([(PathBuf::from("src/lib.rs"), items)]),
+            definitions: HashMap::new(),
+        };
+        let area = source.find("area").unwrap();
+        let enclosing = item_map.enclosing_items(Path::new("src/lib.rs"), &(area..area + 4));
+        let kinds: Vec<(ItemKind, Option<&str>)> = enclosing
+            .iter()
+            .map(|item| (item.kind, item.name.as_deref()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (ItemKind::Mod, Some("geometry")),
+                (ItemKind::Trait, Some("Shape")),
+                (ItemKind::Fn, Some("area")),
+            ]
+        );
+        let norm = source.find("self.x").unwrap();
+        let enclosing = item_map.enclosing_items(Path::new("src/lib.rs"), &(norm..norm + 6));
+        assert_eq!(enclosing[0].describe(), "impl block of `Point`");
+        assert_eq!(
+            text(&enclosing[1].range),
+            "fn norm(&self) -> i32 { self.x }"
         );
-        Ok(Box::new(RustItemMap {
-            cargo_pkg_idx,
-            items,
-        }))
     }
 }
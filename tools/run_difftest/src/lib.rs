@@ -13,8 +13,60 @@ pub struct DiffTestResult {
     pub passed: usize,
     pub failed: usize,
     pub total: usize,
-    /// Raw FAIL and WARN lines from the test run, for use by the fix tool.
-    pub failures: Vec<String>,
+    /// The tests that failed or could not run, from the FAIL and WARN lines of the test run.
+    pub failures: Vec<DiffTestFailure>,
+}
+
+impl DiffTestResult {
+    /// The tests in which the C and Rust functions returned different results.
+    pub fn mismatches(&self) -> impl Iterator<Item = &DiffTestFailure> {
+        self.failures.iter().filter(|f| f.mismatch)
+    }
+}
+
+/// A differential test that failed, or that could not run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffTestFailure {
+    /// The id of the test, such as `diff_T001`.
+    pub test: String,
+    /// The C name of the function tested.
+    pub function: String,
+    /// Whether the results differed (a FAIL line), rather than the test not running (WARN).
+    pub mismatch: bool,
+    /// What went wrong; for a mismatch, the call and the results of both functions, such as
+    /// `mismatch: add(1, 2) returned 3 in C, 4 in Rust`.
+    pub message: String,
+}
+
+impl DiffTestFailure {
+    /// Parses a FAIL or WARN line of the test run.
+    fn parse(line: &str) -> Option<Self> {
+        let (status, rest) = line.split_once(' ')?;
+        let mismatch = match status {
+            "FAIL" => true,
+            "WARN" => false,
+            _ => return None,
+        };
+        let (test, rest) = rest.split_once(' ')?;
+        let (function, message) = rest.split_once(' ').unwrap_or((rest, ""));
+        Some(Self {
+            test: test.to_string(),
+            function: function.to_string(),
+            mismatch,
+            message: message.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for DiffTestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let status = if self.mismatch { "FAIL" } else { "WARN" };
+        write!(
+            f,
+            "{status} {} {} {}",
+            self.test, self.function, self.message
+        )
+    }
 }
 
 impl std::fmt::Display for DiffTestResult {
@@ -69,26 +121,9 @@ impl Tool for RunDiffTest {
             }));
         };
 
-        // Materialize the Rust package with cdylib added, then build it.
+        // Build the Rust package as a cdylib.
         let rust_root = tempfile::tempdir()?;
-        cargo_package.materialize(rust_root.path())?;
-        let mut cargo_toml = CargoToml::open(&rust_root.path().join("Cargo.toml"))?;
-        cargo_toml.add_workspace();
-        cargo_toml.ensure_cdylib();
-        cargo_toml.save()?;
-
-        info!("Building Rust package as cdylib for diff testing...");
-        let status = Command::new("cargo")
-            .args(["build", "--release", "--lib"])
-            .current_dir(rust_root.path())
-            .status()
-            .map_err(|e| format!("run_difftest: failed to run cargo build: {e}"))?;
-        if !status.success() {
-            return Err("run_difftest: cargo build --release --lib failed".into());
-        }
-
-        let rust_so = find_so_in_dir(&rust_root.path().join("target/release"))
-            .ok_or("run_difftest: no .so found in target/release")?;
+        let rust_so = build_cdylib(cargo_package, rust_root.path())?;
 
         // Write difftest_suite.c and compile it.
         let difftest_root = tempfile::tempdir()?;
@@ -121,6 +156,32 @@ impl Tool for RunDiffTest {
     }
 }
 
+/// Materializes `cargo_package` into `root` as a `cdylib` and builds it in release mode, returning
+/// the path of the shared library.
+pub fn build_cdylib(
+    cargo_package: &CargoPackage,
+    root: &Path,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    cargo_package.materialize(root)?;
+    let mut cargo_toml = CargoToml::open(&root.join("Cargo.toml"))?;
+    cargo_toml.add_workspace();
+    cargo_toml.ensure_cdylib();
+    cargo_toml.save()?;
+
+    info!("Building Rust package as cdylib...");
+    let status = Command::new("cargo")
+        .args(["build", "--release", "--lib"])
+        .current_dir(root)
+        .status()
+        .map_err(|e| format!("build_cdylib: failed to run cargo build: {e}"))?;
+    if !status.success() {
+        return Err("build_cdylib: cargo build --release --lib failed".into());
+    }
+
+    find_so_in_dir(&root.join("target/release"))
+        .ok_or_else(|| "build_cdylib: no .so found in target/release".into())
+}
+
 fn parse_output(output: &str) -> Box<DiffTestResult> {
     let mut passed = 0usize;
     let mut failures = Vec::new();
@@ -128,8 +189,8 @@ fn parse_output(output: &str) -> Box<DiffTestResult> {
     for line in output.lines() {
         if line.starts_with("PASS ") {
             passed += 1;
-        } else if line.starts_with("FAIL ") || line.starts_with("WARN ") {
-            failures.push(line.to_string());
+        } else if let Some(failure) = DiffTestFailure::parse(line) {
+            failures.push(failure);
         }
     }
 
@@ -155,3 +216,33 @@ fn find_so_in_dir(dir: &Path) -> Option<PathBuf> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_keep_the_failing_call() {
+        let result = parse_output(
+            "PASS diff_T001 add\n\
+             FAIL diff_T002 add mismatch: add(INT_MAX, 1) returned -2147483648 in C, 0 in Rust\n\
+             WARN diff_T003 find symbol not found\n\
+             SUMMARY: 1 passed, 1 failed out of 2 tests\n",
+        );
+        assert_eq!((result.passed, result.failed, result.total), (1, 2, 3));
+        let mismatches: Vec<_> = result.mismatches().collect();
+        assert_eq!(
+            mismatches,
+            [&DiffTestFailure {
+                test: "diff_T002".into(),
+                function: "add".into(),
+                mismatch: true,
+                message: "mismatch: add(INT_MAX, 1) returned -2147483648 in C, 0 in Rust".into(),
+            }]
+        );
+        assert_eq!(
+            result.failures[1].to_string(),
+            "WARN diff_T003 find symbol not found"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Checks an executable translation against the test vectors of its C program: runs the built
+//! binary with the arguments and standard input of each test case and compares what it prints
+//! with the expected output, so that the mismatches can be repaired before the output is written.
+
+use harvest_core::config::unknown_field_warning;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+use try_cargo_build::CargoBuildResult;
+
+mod test_case;
+pub use test_case::{StdoutPattern, TestCase, parse_test_case_json, parse_test_vectors};
+
+/// How much of the output of a failing run a failure message shows.
+const MAX_OUTPUT_CHARS: usize = 2000;
+
+/// Configuration read from `[tools.run_test_vectors]`.
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// How long one run of the binary may take, in seconds.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    #[serde(flatten)]
+    unknown: HashMap<String, Value>,
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            unknown: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self) {
+        unknown_field_warning("tools.run_test_vectors", &self.unknown);
+    }
+
+    /// Reads the `run_test_vectors` config, using the defaults if there is none.
+    pub fn load(config: &harvest_core::config::Config) -> Result<Self, serde_json::Error> {
+        let config = match config.tools.get("run_test_vectors") {
+            Some(value) => Config::deserialize(value)?,
+            None => Config::default(),
+        };
+        config.validate();
+        Ok(config)
+    }
+}
+
+/// The test cases of a C program, in the order of their file names.
+pub struct TestVectors {
+    pub cases: Vec<TestCase>,
+}
+
+impl std::fmt::Display for TestVectors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Test vectors: {} test cases", self.cases.len())?;
+        for case in &self.cases {
+            writeln!(f, "  {}", case.filename)?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for TestVectors {
+    fn name(&self) -> &'static str {
+        "test_vectors"
+    }
+}
+
+/// Loads the test vectors in a directory (see [`parse_test_vectors`]).
+pub struct LoadTestVectors {
+    directory: PathBuf,
+}
+
+impl LoadTestVectors {
+    pub fn new(directory: &Path) -> LoadTestVectors {
+        LoadTestVectors {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl Tool for LoadTestVectors {
+    fn name(&self) -> &'static str {
+        "load_test_vectors"
+    }
+
+    fn run(
+        self: Box<Self>,
+        _context: RunContext,
+        _inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let mut cases = parse_test_vectors(&self.directory)?;
+        cases.sort_by(|a, b| a.filename.cmp(&b.filename));
+        info!(
+            "Loaded {} test cases from {}.",
+            cases.len(),
+            self.directory.display()
+        );
+        Ok(Box::new(TestVectors { cases }))
+    }
+}
+
+/// Runs the binary of a build on its test vectors.
+pub struct RunTestVectors;
+
+impl Tool for RunTestVectors {
+    fn name(&self) -> &'static str {
+        "run_test_vectors"
+    }
+
+    /// Inputs:
+    /// 1. [`TestVectors`] id -- the test cases to run.
+    /// 2. [`CargoBuildResult`] id -- a successful build of the executable to run them on.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let config = Config::load(&context.config)?;
+        let vectors = context
+            .ir_snapshot
+            .get::<TestVectors>(inputs[0])
+            .ok_or("run_test_vectors: no TestVectors in IR")?;
+        let build = context
+            .ir_snapshot
+            .get::<CargoBuildResult>(inputs[1])
+            .ok_or("run_test_vectors: no CargoBuildResult in IR")?;
+        let binary = build
+            .artifacts
+            .iter()
+            .find_map(|a| a.executable.as_ref())
+            .ok_or("run_test_vectors: the build produced no executable")?;
+
+        let result = run_cases(
+            binary.as_std_path(),
+            &vectors.cases,
+            Duration::from_secs(config.timeout_secs),
+        )?;
+        info!("Test vectors: {}/{} passed", result.passed, result.total);
+        Ok(Box::new(result))
+    }
+}
+
+/// Runs `binary` on each of `cases`, except those whose output depends on undefined behavior.
+fn run_cases(
+    binary: &Path,
+    cases: &[TestCase],
+    timeout: Duration,
+) -> Result<TestVectorResult, Box<dyn std::error::Error>> {
+    let mut result = TestVectorResult {
+        passed: 0,
+        total: 0,
+        failures: Vec::new(),
+    };
+    for case in cases.iter().filter(|case| case.has_ub.is_none()) {
+        result.total += 1;
+        let failure =
+            match exec_runner::run_with_timeout(binary, &case.argv, case.stdin.as_deref(), timeout)
+            {
+                Ok(output) => {
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    if case.stdout_matches(&stdout)? {
+                        result.passed += 1;
+                        continue;
+                    }
+                    TestVectorFailure {
+                        case: case.clone(),
+                        stdout: stdout.into_owned(),
+                        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                        rc: output.status.code(),
+                        error: None,
+                    }
+                }
+                Err(e) => TestVectorFailure {
+                    case: case.clone(),
+                    stdout: String::new(),
+                    stderr: String::new(),
+                    rc: None,
+                    error: Some(e.to_string()),
+                },
+            };
+        result.failures.push(failure);
+    }
+    Ok(result)
+}
+
+/// The results of running an executable on its test vectors.
+pub struct TestVectorResult {
+    pub passed: usize,
+    pub total: usize,
+    pub failures: Vec<TestVectorFailure>,
+}
+
+/// A test case whose run did not print the expected output.
+pub struct TestVectorFailure {
+    pub case: TestCase,
+    pub stdout: String,
+    pub stderr: String,
+    /// The exit code, if the binary exited rather than being killed by a signal.
+    pub rc: Option<i32>,
+    /// Why the binary could not be run to the end, such as a timeout.
+    pub error: Option<String>,
+}
+
+impl TestVectorFailure {
+    /// Describes the failing run: its input, the expected output and the actual one.
+    pub fn message(&self) -> String {
+        let case = &self.case;
+        let mut lines = vec![format!(
+            "test vector `{}`: running the program with arguments {:?}",
+            case.filename, case.argv
+        )];
+        if let Some(stdin) = &case.stdin {
+            lines[0].push_str(&format!(" and standard input:\n{}", truncate(stdin)));
+        }
+        let expected = if case.stdout.is_regex {
+            "should print output matching the regex"
+        } else {
+            "should print"
+        };
+        lines.push(format!("{expected}:\n{}", truncate(&case.stdout.pattern)));
+        match (&self.error, self.rc) {
+            (Some(error), _) => lines.push(format!("but it failed: {error}")),
+            (None, rc) => {
+                let rc = rc.map_or("killed by a signal".to_string(), |rc| {
+                    format!("exit code {rc}")
+                });
+                lines.push(format!("but printed ({rc}):\n{}", truncate(&self.stdout)));
+                if !self.stderr.is_empty() {
+                    lines.push(format!(
+                        "and on standard error:\n{}",
+                        truncate(&self.stderr)
+                    ));
+                }
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// `text` without trailing whitespace, cut to [`MAX_OUTPUT_CHARS`] characters.
+fn truncate(text: &str) -> String {
+    let text = text.trim_end();
+    match text.char_indices().nth(MAX_OUTPUT_CHARS) {
+        Some((end, _)) => format!("{}[... truncated]", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+impl std::fmt::Display for TestVectorResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "TestVectorResult: {}/{} passed", self.passed, self.total)?;
+        for failure in &self.failures {
+            writeln!(f, "  {} failed", failure.case.filename)?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for TestVectorResult {
+    fn name(&self) -> &'static str {
+        "test_vector_result"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatches_keep_the_input_and_both_outputs() {
+        let case = |filename: &str, stdin: &str, pattern: &str, is_regex: bool| TestCase {
+            stdin: Some(stdin.to_string()),
+            stdout: StdoutPattern {
+                pattern: pattern.to_string(),
+                is_regex,
+            },
+            filename: filename.to_string(),
+            ..TestCase::default()
+        };
+        let mut undefined = case("4.json", "x", "y", false);
+        undefined.has_ub = Some("overflow".to_string());
+        let cases = [
+            case("1.json", "hello\n", "hello", false),
+            case("2.json", "42\n", "^[0-9]+\\s*$", true),
+            case("3.json", "bye\n", "hello", false),
+            undefined,
+        ];
+
+        let result = run_cases(Path::new("cat"), &cases, Duration::from_secs(10)).unwrap();
+        assert_eq!((result.passed, result.total), (2, 3));
+        let [failure] = &result.failures[..] else {
+            panic!("expected one failure");
+        };
+        assert_eq!(
+            failure.message(),
+            "test vector `3.json`: running the program with arguments [] and standard input:\n\
+             bye\n\
+             should print:\nhello\n\
+             but printed (exit code 0):\nbye"
+        );
+    }
+}
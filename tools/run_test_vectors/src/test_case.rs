@@ -0,0 +1,123 @@
+//! The test vector format of the TRACTOR benchmarks: one JSON file per test case, giving the
+//! arguments and standard input of a run and the standard output it should print.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Represents the expected stdout pattern in a test case
+/// Used for tractor test cases
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct StdoutPattern {
+    pub pattern: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default)]
+    pub is_regex: bool,
+}
+
+/// Represents a test case with command arguments, input, and expected output
+/// Used for tractor test cases
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct TestCase {
+    #[serde(default)]
+    pub argv: Vec<String>,
+    #[serde(default)]
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub stdout: StdoutPattern,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub rc: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub has_ub: Option<String>,
+    #[serde(skip)] // Don't serialize/deserialize this field as it's not part of the JSON
+    pub filename: String,
+}
+
+impl TestCase {
+    /// Whether `stdout` is the output this test case expects: matching the pattern if it is a
+    /// regex, equal to it up to surrounding whitespace otherwise.
+    pub fn stdout_matches(&self, stdout: &str) -> Result<bool, regex::Error> {
+        if self.stdout.is_regex {
+            Ok(Regex::new(&self.stdout.pattern)?.is_match(stdout))
+        } else {
+            Ok(stdout.trim() == self.stdout.pattern.trim())
+        }
+    }
+}
+
+/// Parses a JSON file into a TestCase struct
+pub fn parse_test_case_json<P: AsRef<Path>>(
+    file_path: P,
+) -> Result<TestCase, Box<dyn std::error::Error>> {
+    let file_path = file_path.as_ref();
+
+    // Read the JSON content from the file
+    let json_str = fs::read_to_string(file_path).map_err(|e| {
+        format!(
+            "Failed to read test case file {}: {}",
+            file_path.display(),
+            e
+        )
+    })?;
+
+    let mut test_case: TestCase = serde_json::from_str(&json_str).map_err(|e| {
+        format!(
+            "Failed to parse test case JSON from {}: {}",
+            file_path.display(),
+            e
+        )
+    })?;
+
+    // Set the filename field to the file name
+    test_case.filename = file_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("")
+        .to_string();
+
+    Ok(test_case)
+}
+
+/// Reads all files in a directory and parses them as TestCase JSON files
+/// These sorts of files can be found in the test_vectors/ directory of the benchmark
+pub fn parse_test_vectors<P: AsRef<Path>>(
+    directory_path: P,
+) -> Result<Vec<TestCase>, Box<dyn std::error::Error>> {
+    let dir_path = directory_path.as_ref();
+
+    // Read directory entries
+    let entries = fs::read_dir(dir_path)
+        .map_err(|e| format!("Failed to read directory {}: {}", dir_path.display(), e))?;
+
+    // Process each file and collect successful test cases
+    let mut test_cases = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| {
+            format!(
+                "Failed to read directory entry in {}: {}",
+                dir_path.display(),
+                e
+            )
+        })?;
+        let file_path = entry.path();
+
+        // Only accept plain .json files (exclude backups like .json.bak, directories, etc.)
+        if file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext != "json")
+            .unwrap_or(true)
+        {
+            continue;
+        }
+
+        // Try to parse the file as a test case JSON
+        if let Ok(test_case) = parse_test_case_json(&file_path) {
+            test_cases.push(test_case);
+        }
+    }
+    Ok(test_cases)
+}
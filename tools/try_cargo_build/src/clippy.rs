@@ -0,0 +1,197 @@
+//! Runs `cargo clippy` on a package that builds, to find the warnings a cleanup pass fixes.
+
+use crate::{CompilerMessage, run_cargo};
+use cargo_metadata::diagnostic::{Diagnostic, DiagnosticLevel};
+use full_source::CargoPackage;
+use harvest_core::cargo_utils::CargoToml;
+use harvest_core::config::unknown_field_warning;
+use harvest_core::tools::{RunContext, Tool};
+use harvest_core::{Id, Representation};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::info;
+
+/// Configuration read from `[tools.try_cargo_clippy]`.
+#[derive(Debug, Deserialize)]
+pub struct ClippyConfig {
+    /// The rustc and clippy lints and lint groups whose warnings are reported, even where the
+    /// code allows them; all others are allowed.
+    #[serde(default = "default_lints")]
+    pub lints: Vec<String>,
+
+    /// How many cleanup passes fix the reported warnings once the package builds. No cleanup
+    /// without a `[tools.try_cargo_clippy]` section.
+    #[serde(default)]
+    pub max_passes: usize,
+
+    #[serde(flatten)]
+    unknown: HashMap<String, Value>,
+}
+
+fn default_lints() -> Vec<String> {
+    [
+        "unused_imports",
+        "unused_mut",
+        "unused_variables",
+        "clippy::correctness",
+        "clippy::suspicious",
+        "clippy::complexity",
+        "clippy::perf",
+        "clippy::style",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+impl Default for ClippyConfig {
+    fn default() -> Self {
+        Self {
+            lints: default_lints(),
+            max_passes: 0,
+            unknown: HashMap::new(),
+        }
+    }
+}
+
+impl ClippyConfig {
+    pub fn validate(&self) {
+        unknown_field_warning("tools.try_cargo_clippy", &self.unknown);
+    }
+
+    /// Reads the `try_cargo_clippy` config, using the defaults if there is none.
+    pub fn load(config: &harvest_core::config::Config) -> Result<Self, serde_json::Error> {
+        let config = match config.tools.get("try_cargo_clippy") {
+            Some(value) => ClippyConfig::deserialize(value)?,
+            None => ClippyConfig::default(),
+        };
+        config.validate();
+        Ok(config)
+    }
+}
+
+/// Runs `cargo clippy` in the Cargo project at `project_path`, reporting only `lints`.
+fn cargo_clippy(
+    project_path: &Path,
+    lints: &[String],
+) -> Result<CargoClippyResult, Box<dyn std::error::Error>> {
+    // `-W` would not override `-A warnings`, so the selected lints are forced to warn.
+    let mut args = vec!["--", "-A", "warnings"];
+    for lint in lints {
+        args.extend(["--force-warn", lint.as_str()]);
+    }
+    let run = run_cargo(project_path, "clippy", &args)?;
+    Ok(CargoClippyResult {
+        diagnostics: run.diagnostics,
+        success: run.success,
+        err: String::from_utf8(run.stderr)?,
+    })
+}
+
+/// Runs `cargo clippy` on a [`CargoPackage`], reporting the lints of `[tools.try_cargo_clippy]`.
+pub struct TryCargoClippy;
+
+impl Tool for TryCargoClippy {
+    fn name(&self) -> &'static str {
+        "try_cargo_clippy"
+    }
+
+    /// Inputs:
+    /// 1. [`CargoPackage`] id -- the package to lint.
+    fn run(
+        self: Box<Self>,
+        context: RunContext,
+        inputs: Vec<Id>,
+    ) -> Result<Box<dyn Representation>, Box<dyn std::error::Error>> {
+        let config = ClippyConfig::load(&context.config)?;
+        let cargo_package = context
+            .ir_snapshot
+            .get::<CargoPackage>(inputs[0])
+            .ok_or("No CargoPackage representation found in IR")?;
+        let root = tempfile::tempdir()?;
+        cargo_package.materialize(root.path())?;
+        let mut cargo = CargoToml::open(&root.path().join("Cargo.toml"))?;
+        cargo.add_workspace();
+        cargo.save()?;
+
+        let result = cargo_clippy(root.path(), &config.lints)?;
+        info!(
+            "cargo clippy reported {} warnings",
+            result.warnings().count()
+        );
+        Ok(Box::new(result))
+    }
+}
+
+/// A Representation that contains the results of running `cargo clippy`.
+#[derive(Clone)]
+pub struct CargoClippyResult {
+    pub diagnostics: Vec<CompilerMessage>,
+    pub success: bool,
+    pub err: String,
+}
+
+impl CargoClippyResult {
+    /// The warnings that point to code, leaving out summaries such as "N warnings emitted".
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .map(|d| &d.message)
+            .filter(|m| m.level == DiagnosticLevel::Warning && !m.spans.is_empty())
+    }
+}
+
+impl std::fmt::Display for CargoClippyResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "cargo clippy: {} warnings", self.warnings().count())?;
+        for warning in self.warnings() {
+            writeln!(f, "  {}", warning.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Representation for CargoClippyResult {
+    fn name(&self) -> &'static str {
+        "cargo_clippy_result"
+    }
+
+    fn materialize(&self, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn reports_only_the_selected_lints() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(
+            root.path().join("Cargo.toml"),
+            "[package]\nname = \"lints\"\nversion = \"0.1.0\"\nedition = \"2024\"\n\n[workspace]\n",
+        )
+        .unwrap();
+        fs::create_dir(root.path().join("src")).unwrap();
+        fs::write(
+            root.path().join("src/lib.rs"),
+            "pub fn double(x: i32) -> i32 {\n    let unused = 1;\n    return x * 2;\n}\n",
+        )
+        .unwrap();
+
+        let codes = |result: CargoClippyResult| -> Vec<String> {
+            result
+                .warnings()
+                .filter_map(|w| w.code.as_ref().map(|code| code.code.clone()))
+                .collect()
+        };
+        let style = cargo_clippy(root.path(), &["clippy::style".to_string()]).unwrap();
+        assert!(style.success);
+        assert_eq!(codes(style), ["clippy::needless_return"]);
+        let all = cargo_clippy(root.path(), &default_lints()).unwrap();
+        assert_eq!(codes(all), ["unused_variables", "clippy::needless_return"]);
+    }
+}
@@ -1,5 +1,6 @@
 //! Checks if a generated Rust project builds by materializing
 //! it to a root and running `cargo build --release`.
+use cargo_metadata::diagnostic::DiagnosticLevel;
 pub use cargo_metadata::{Artifact, CompilerMessage};
 use full_source::CargoPackage;
 use harvest_core::cargo_utils::CargoToml;
@@ -11,6 +12,9 @@ use std::sync::Arc;
 use tempfile::TempDir;
 use tracing::info;
 
+mod clippy;
+pub use clippy::{CargoClippyResult, ClippyConfig, TryCargoClippy};
+
 pub struct TryCargoBuild;
 // Either a vector of compiled artifact filenames (on success)
 // or a string containing error messages (on failure).
@@ -33,16 +37,55 @@ fn try_cargo_build(
     cargo.normalize_name(name_source);
     cargo.save()?;
 
-    // Run cargo build in the project directory
+    let run = run_cargo(&project_path, "build", &[])?;
+
+    if run.success {
+        info!("Project builds successfully!");
+    }
+    Ok(CargoBuildResult {
+        artifacts: run.artifacts,
+        diagnostics: run.diagnostics,
+        success: run.success,
+        err: String::from_utf8(run.stderr)?,
+        root,
+    })
+}
+
+/// Runs `cargo check` in the Cargo project at `project_path`, for quick checks of an edit that
+/// need no artifacts. Returns whether the check passed and the compiler's diagnostics.
+pub fn cargo_check(
+    project_path: &Path,
+) -> Result<(bool, Vec<CompilerMessage>), Box<dyn std::error::Error>> {
+    let run = run_cargo(project_path, "check", &[])?;
+    Ok((run.success, run.diagnostics))
+}
+
+/// The outcome of a cargo invocation.
+struct CargoRun {
+    artifacts: Vec<Artifact>,
+    diagnostics: Vec<CompilerMessage>,
+    success: bool,
+    stderr: Vec<u8>,
+}
+
+/// Runs `cargo <subcommand> --release --message-format=json <args>` in `project_path` and sorts
+/// its messages into artifacts and compiler diagnostics.
+fn run_cargo(
+    project_path: &Path,
+    subcommand: &str,
+    args: &[&str],
+) -> Result<CargoRun, Box<dyn std::error::Error>> {
     let output = Command::new("cargo")
-        .arg("build")
+        .arg(subcommand)
         .arg("--release")
         .arg("--message-format=json")
-        .current_dir(&project_path)
+        .args(args)
+        .current_dir(project_path)
         .output()
         .map_err(|e| {
             format!(
-                "Failed to run cargo build in {}: {}",
+                "Failed to run cargo {} in {}: {}",
+                subcommand,
                 project_path.display(),
                 e
             )
@@ -69,16 +112,11 @@ fn try_cargo_build(
             _ => {}
         }
     }
-
-    if success {
-        info!("Project builds successfully!");
-    }
-    Ok(CargoBuildResult {
+    Ok(CargoRun {
         artifacts,
         diagnostics,
         success,
-        err: String::from_utf8(output.stderr)?,
-        root,
+        stderr: output.stderr,
     })
 }
 
@@ -120,6 +158,29 @@ impl CargoBuildResult {
     pub fn root_path(&self) -> &Path {
         self.root.path()
     }
+
+    /// One signature per error of the build, sorted: the error code, the file of its primary span
+    /// and its message. Line numbers are left out, so an error that an edit elsewhere in the file
+    /// moved keeps its signature.
+    pub fn error_signatures(&self) -> Vec<String> {
+        let mut signatures: Vec<String> = self
+            .diagnostics
+            .iter()
+            .map(|d| &d.message)
+            .filter(|m| m.level == DiagnosticLevel::Error)
+            .map(|m| {
+                let code = m.code.as_ref().map_or("", |code| code.code.as_str());
+                let file = m
+                    .spans
+                    .iter()
+                    .find(|span| span.is_primary)
+                    .map_or("", |span| span.file_name.as_str());
+                format!("{code} {file}: {}", m.message)
+            })
+            .collect();
+        signatures.sort();
+        signatures
+    }
 }
 
 impl std::fmt::Display for CargoBuildResult {
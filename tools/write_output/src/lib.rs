@@ -1,7 +1,9 @@
-//! Copies a built Cargo package from its temporary build directory to the configured output path.
+//! Copies a built Cargo package from its temporary build directory to the configured output path,
+//! along with the map of the C declarations its items were translated from.
 
 use harvest_core::tools::{RunContext, Tool};
 use harvest_core::{Id, Representation};
+use provenance_map::ProvenanceMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::info;
@@ -9,11 +11,18 @@ use try_cargo_build::CargoBuildResult;
 
 pub struct WriteOutput;
 
+/// Where, in the output directory, the [`ProvenanceMap`] of the package is written.
+pub const PROVENANCE_FILE: &str = "provenance.json";
+
 impl Tool for WriteOutput {
     fn name(&self) -> &'static str {
         "write_output"
     }
 
+    /// Inputs:
+    /// 1. [`CargoBuildResult`] id -- the build whose package to write.
+    /// 2. Optionally, a [`ProvenanceMap`] id -- the provenance of the items of that package,
+    ///    written to [`PROVENANCE_FILE`].
     fn run(
         self: Box<Self>,
         context: RunContext,
@@ -29,6 +38,17 @@ impl Tool for WriteOutput {
         copy_dir_all(src, dst)?;
         info!("Output written to {}", dst.display());
 
+        if let Some(provenance) = inputs
+            .get(1)
+            .and_then(|id| context.ir_snapshot.get::<ProvenanceMap>(*id))
+        {
+            provenance.materialize(&dst.join(PROVENANCE_FILE))?;
+            info!(
+                "Provenance of {} items written to {PROVENANCE_FILE}",
+                provenance.entries.len()
+            );
+        }
+
         let executable = build_result
             .artifacts
             .iter()
@@ -25,6 +25,15 @@ pub struct Args {
     #[arg(long, conflicts_with = "agentic")]
     pub modular: bool,
 
+    /// Translate a compiling skeleton crate first, then the function bodies (requires --modular).
+    #[arg(long, requires = "modular")]
+    pub skeleton_first: bool,
+
+    /// Translate only the C files and functions listed in this file, one per line, and link the
+    /// rest of the C code into the crate (requires --modular).
+    #[arg(long, requires = "modular")]
+    pub translate_units_file: Option<PathBuf>,
+
     /// Use the agentic translation tool.
     #[arg(long, conflicts_with = "modular")]
     pub agentic: bool,
@@ -38,6 +47,11 @@ pub struct Args {
     #[arg(long, requires = "agentic", value_parser = parse_agent_kind)]
     pub agentic_agent: Option<AgentKind>,
 
+    /// Check an executable translation against the test vectors in this directory, and repair
+    /// the output it gets wrong.
+    #[arg(long)]
+    pub test_vectors: Option<PathBuf>,
+
     /// Path to the directory containing the C code to translate.
     // Should always be present unless using a subcommand like --print-config-path
     pub input: Option<PathBuf>,
@@ -113,6 +127,24 @@ fn load_config(args: &Args, config_dir: &Path) -> Config {
             .expect("settings override failed");
     }
 
+    if args.skeleton_first {
+        settings = settings
+            .set_override("skeleton_first", "true")
+            .expect("settings override failed");
+    }
+
+    if let Some(path) = &args.translate_units_file {
+        settings = settings
+            .set_override("translate_units_file", path.to_string_lossy().as_ref())
+            .expect("settings override failed");
+    }
+
+    if let Some(path) = &args.test_vectors {
+        settings = settings
+            .set_override("test_vectors", path.to_string_lossy().as_ref())
+            .expect("settings override failed");
+    }
+
     if args.agentic {
         settings = settings
             .set_override("agentic", "true")
@@ -2,31 +2,48 @@
 //! `translate` binary, but is exposed as a library crate as well.
 
 pub mod cli;
+mod repair;
 mod runner;
 mod scheduler;
 pub mod util;
 
-use build_c_artifact::BuildCArtifact;
+use analyze_unsafe_constructs::AnalyzeUnsafeConstructs;
+use apply_rustc_suggestions::ApplyRustcSuggestions;
+use build_c_artifact::{BuildCArtifact, CArtifact};
 use build_config::BuildConfig;
 use build_project_spec::{BuildProjectSpec, ProjectKind, ProjectSpec};
 use c_ast::ParseToAst;
+use check_abi::{AbiCheckResult, CheckAbi};
+use check_layout::{CheckLayout, LayoutCheckResult};
 use emit_build_features::EmitBuildFeatures;
-use fix_declarations_llm::FixDeclarationsLlm;
+use fix_declarations_llm::{ApplyDeclarationFixes, FixDeclarationsLlm};
+use full_source::CargoPackage;
 use generate_difftest_suite::GenerateDiffTestSuite;
 use harvest_core::config::Config;
+use harvest_core::tools::Tool;
 use harvest_core::utils::get_version;
-use harvest_core::{HarvestIR, diagnostics};
+use harvest_core::{HarvestIR, Id, diagnostics};
 use load_raw_source::LoadRawSource;
-use modular_translation_llm::ModularTranslationLlm;
+use lower_types::LowerTypes;
+use modular_translation_llm::{
+    ModularProvenance, ModularSkeletonLlm, ModularTranslationLlm, TranslateBodiesLlm,
+};
+use name_map::BuildNameMap;
+use partial_translation::{LinkRemainingC, SelectTranslationUnits};
+use provenance_map::{MatchProvenance, UpdateProvenance};
 use quantize_rust_spans::QuantizeRustSpans;
 use raw_source_to_cargo_llm::RawSourceToCargoLlm;
+use repair::{Progress, RepairTracker};
 use run_difftest::{DiffTestResult, RunDiffTest};
+use run_test_vectors::{LoadTestVectors, RunTestVectors, TestVectorResult};
 use runner::ToolRunner;
 use scheduler::Scheduler;
 use std::sync::Arc;
-use tracing::info;
+use tracing::{info, warn};
 use translate_agentic::TranslateAgentic;
-use try_cargo_build::{CargoBuildResult, TryCargoBuild};
+use try_cargo_build::{
+    CargoBuildResult, CargoClippyResult, ClippyConfig, TryCargoBuild, TryCargoClippy,
+};
 use verify_fix_agentic::VerifyFixAgentic;
 use write_output::WriteOutput;
 
@@ -41,78 +58,185 @@ pub fn transpile(config: Arc<Config>) -> Result<HarvestIR, Box<dyn std::error::E
     info!("Harvest version: {}", get_version());
     info!("Transpiling with: {}", config.model_info().unwrap());
 
-    // Setup a schedule for the transpilation.
-    let load_src = scheduler.queue(LoadRawSource::new(&config.input));
-    let build_cfg = scheduler.queue_after(BuildConfig, &[load_src]);
-    let project_spec = scheduler.queue_after(BuildProjectSpec, &[load_src, build_cfg]);
-    let translate = if config.agentic {
-        let t = scheduler.queue_after(TranslateAgentic, &[load_src, project_spec, build_cfg]);
-        if config.agentic_verify {
-            scheduler.queue_after(VerifyFixAgentic, &[t, load_src, build_cfg])
+    let result: Result<(), Box<dyn std::error::Error>> = (|| {
+        // Setup a schedule for the transpilation.
+        let load_src = scheduler.queue(LoadRawSource::new(&config.input));
+        let build_cfg = scheduler.queue_after(BuildConfig, &[load_src]);
+        let project_spec = scheduler.queue_after(BuildProjectSpec, &[load_src, build_cfg]);
+        // With `skeleton_first`, the inputs TranslateBodiesLlm needs once the skeleton builds.
+        let mut skeleton_inputs = None;
+        // With a modular translation, the AST and names the interface checks of library projects
+        // need, which the provenance map and the repair context draw on as well.
+        let mut layout_inputs = None;
+        // With a partial translation, the inputs LinkRemainingC needs besides the translation.
+        let mut link_inputs = None;
+        let translate = if config.agentic {
+            let t = scheduler.queue_after(TranslateAgentic, &[load_src, project_spec, build_cfg]);
+            if config.agentic_verify {
+                scheduler.queue_after(VerifyFixAgentic, &[t, load_src, build_cfg])
+            } else {
+                t
+            }
+        } else if config.modular {
+            // ParseToAst takes BuildConfigIR as a second input so it can stamp
+            // each TopLevelEntity with its variant_tags. When the IR is empty the
+            // tags collapse to `Vec::new()` and serialized output is byte-equal
+            // to the form produced without a BuildConfigIR input
+            // (see TopLevelEntity::variant_tags docs).
+            //
+            // The analyses feeding the translation are optional inputs of it: each runs as a stage
+            // of its own, and only the ones that succeed are passed on.
+            let (scheduler, runner, ir) = (&mut scheduler, &mut runner, &mut ir);
+            let parse_ast = scheduler.queue_after(ParseToAst, &[load_src, build_cfg]);
+            let analysis = AnalyzeUnsafeConstructs;
+            let unsafe_report = run_stage(scheduler, runner, ir, &config, analysis, &[load_src])?;
+            if !ir.contains_id(parse_ast) {
+                return Err("transpile: the C source could not be parsed".into());
+            }
+            let inputs = [parse_ast, project_spec];
+            let names = run_stage(scheduler, runner, ir, &config, BuildNameMap, &inputs)?;
+            let inputs: Vec<Id> = [parse_ast].into_iter().chain(names).collect();
+            let lowered = run_stage(scheduler, runner, ir, &config, LowerTypes, &inputs)?;
+            for (analysis, id) in [
+                ("unsafe-construct analysis", unsafe_report),
+                ("name map", names),
+                ("type lowering", lowered),
+            ] {
+                if id.is_none() {
+                    warn!("The {analysis} failed; translating without it");
+                }
+            }
+            let mut inputs: Vec<Id> = [load_src, parse_ast, project_spec, build_cfg]
+                .into_iter()
+                .chain(unsafe_report)
+                .chain(names)
+                .chain(lowered)
+                .collect();
+            if config.is_partial() {
+                let units = run_stage(
+                    scheduler,
+                    runner,
+                    ir,
+                    &config,
+                    SelectTranslationUnits,
+                    &[parse_ast, project_spec],
+                )?
+                .ok_or("transpile: no translation units could be selected")?;
+                inputs.push(units);
+                link_inputs = Some([load_src, parse_ast, units]);
+            }
+            layout_inputs = names.map(|names| (parse_ast, names));
+            if config.skeleton_first {
+                let optional: Vec<Id> = unsafe_report.into_iter().chain(names).collect();
+                skeleton_inputs = Some((parse_ast, optional));
+                scheduler.queue_after(ModularSkeletonLlm, &inputs)
+            } else {
+                scheduler.queue_after(ModularTranslationLlm, &inputs)
+            }
         } else {
-            t
-        }
-    } else if config.modular {
-        // ParseToAst takes BuildConfigIR as a second input so it can stamp
-        // each TopLevelEntity with its variant_tags. When the IR is empty the
-        // tags collapse to `Vec::new()` and serialized output is byte-equal
-        // to the form produced without a BuildConfigIR input
-        // (see TopLevelEntity::variant_tags docs).
-        let parse_ast = scheduler.queue_after(ParseToAst, &[load_src, build_cfg]);
-        scheduler.queue_after(
-            ModularTranslationLlm,
-            &[load_src, parse_ast, project_spec, build_cfg],
-        )
-    } else {
-        scheduler.queue_after(RawSourceToCargoLlm, &[load_src, project_spec, build_cfg])
-    };
-    // EmitBuildFeatures consumes the translated CargoPackage plus the
-    // BuildConfigIR and produces a (possibly mutated) CargoPackage. On
-    // is_empty IRs (projects without a `configuration.json`, which is
-    // the vast majority of the current TRACTOR corpus) it is a no-op
-    // pass-through, so byte-for-byte behavior is preserved.
-    let translate = scheduler.queue_after(EmitBuildFeatures, &[translate, build_cfg]);
-    let mut current_pkg_id = translate;
-    let mut current_build_id = scheduler.queue_after(TryCargoBuild, &[current_pkg_id]);
+            scheduler.queue_after(RawSourceToCargoLlm, &[load_src, project_spec, build_cfg])
+        };
+        // EmitBuildFeatures consumes the translated CargoPackage plus the
+        // BuildConfigIR and produces a (possibly mutated) CargoPackage. On
+        // is_empty IRs (projects without a `configuration.json`, which is
+        // the vast majority of the current TRACTOR corpus) it is a no-op
+        // pass-through, so byte-for-byte behavior is preserved.
+        let mut translate = scheduler.queue_after(EmitBuildFeatures, &[translate, build_cfg]);
+        // A partial translation compiles the C code it left untranslated into the crate.
+        if let Some([load_src, parse_ast, units]) = link_inputs {
+            translate = scheduler.queue_after(
+                LinkRemainingC,
+                &[translate, load_src, parse_ast, units, build_cfg],
+            );
+        }
+        let mut current_pkg_id = translate;
+        let mut current_build_id = scheduler.queue_after(TryCargoBuild, &[current_pkg_id]);
 
-    let result: Result<(), Box<dyn std::error::Error>> = (|| {
         // Run until all tasks are complete, respecting the dependencies declared in `queue_after`
         scheduler.run_all(&mut runner, &mut ir, config.clone())?;
 
+        // Which C declaration each Rust item was translated from, which follows the package
+        // through repair to the output, and the C source of the declarations to fix, for the
+        // repair context.
+        let (c_source, provenance) = map_provenance(
+            &mut scheduler,
+            &mut runner,
+            &mut ir,
+            &config,
+            [load_src, build_cfg, project_spec],
+            layout_inputs,
+            current_pkg_id,
+        )?;
+
         // Repair loop -- skipped for agentic, which has its own repair mechanism.
         if !config.agentic {
-            for _ in 0..config.max_repair_passes {
-                let success = ir
-                    .get::<CargoBuildResult>(current_build_id)
-                    .ok_or("transpile: no CargoBuildResult in IR")?
-                    .success;
-                if success {
-                    break;
-                }
-                let quantize = scheduler.queue_after(QuantizeRustSpans, &[current_pkg_id]);
-                let fix = scheduler.queue_after(FixDeclarationsLlm, &[quantize, current_build_id]);
-                let new_build = scheduler.queue_after(TryCargoBuild, &[fix]);
-                scheduler.run_all(&mut runner, &mut ir, config.clone())?;
-                current_pkg_id = fix;
-                current_build_id = new_build;
-            }
+            (current_pkg_id, current_build_id) = repair(
+                &mut scheduler,
+                &mut runner,
+                &mut ir,
+                &config,
+                &c_source,
+                current_pkg_id,
+                current_build_id,
+            )?;
+        }
+
+        // Skeleton-first: the skeleton now builds (or repair gave up), so translate the
+        // function bodies against it and repair the complete crate.
+        if let Some((parse_ast, optional)) = skeleton_inputs {
+            let mut inputs = vec![current_pkg_id, parse_ast, project_spec, build_cfg];
+            inputs.extend(optional);
+            current_pkg_id = scheduler.queue_after(TranslateBodiesLlm, &inputs);
+            current_build_id = scheduler.queue_after(TryCargoBuild, &[current_pkg_id]);
+            scheduler.run_all(&mut runner, &mut ir, config.clone())?;
+            (current_pkg_id, current_build_id) = repair(
+                &mut scheduler,
+                &mut runner,
+                &mut ir,
+                &config,
+                &c_source,
+                current_pkg_id,
+                current_build_id,
+            )?;
         }
 
-        // Differential testing: for library projects, generate a C test harness that
-        // exercises the public API through both the original C build and the translated
-        // Rust candidate, and report how many calls diverge. Not yet wired into a repair
-        // loop, and executable projects are not yet supported (see generate_exec_difftests
-        // / run_exec_difftest).
         let is_library = matches!(
             ir.get::<ProjectSpec>(project_spec)
                 .ok_or("transpile: no ProjectSpec in IR")?
                 .kind,
             ProjectKind::Library
         );
+
+        // Interface checks: a library must export the symbols of the C library, and its public
+        // types must keep their C size, alignment and field offsets, none of which the build
+        // checks. Skipped for agentic, like the repair loop.
+        //
+        // Differential testing: for library projects, generate a C test harness that
+        // exercises the public API through both the original C build and the translated
+        // Rust candidate, and repair the functions whose results diverge. The difftests also
+        // guard the cleanup. Executable projects are not yet supported (see
+        // generate_exec_difftests / run_exec_difftest).
+        // A library's difftest inputs, `[diff_suite, c_artifact]`, and its difftest result.
+        let mut difftest = None;
         if is_library {
             let c_artifact = scheduler.queue_after(BuildCArtifact, &[load_src, project_spec]);
+            scheduler.run_all(&mut runner, &mut ir, config.clone())?;
+            if !config.agentic {
+                (current_pkg_id, current_build_id) = repair_interface(
+                    &mut scheduler,
+                    &mut runner,
+                    &mut ir,
+                    &config,
+                    &c_source,
+                    c_artifact,
+                    layout_inputs.map(|(parse_ast, names)| [load_src, parse_ast, names]),
+                    current_pkg_id,
+                    current_build_id,
+                )?;
+            }
+
             let diff_suite = scheduler.queue_after(GenerateDiffTestSuite, &[load_src]);
-            let diff_result_id =
+            let mut diff_result_id =
                 scheduler.queue_after(RunDiffTest, &[diff_suite, c_artifact, current_pkg_id]);
             scheduler.run_all(&mut runner, &mut ir, config.clone())?;
             let diff_result = ir
@@ -122,9 +246,97 @@ pub fn transpile(config: Arc<Config>) -> Result<HarvestIR, Box<dyn std::error::E
                 "Diff test: {}/{} passed ({} failed)",
                 diff_result.passed, diff_result.total, diff_result.failed
             );
+            if !config.agentic {
+                (current_pkg_id, current_build_id, diff_result_id) = repair_behavior(
+                    &mut scheduler,
+                    &mut runner,
+                    &mut ir,
+                    &config,
+                    &c_source,
+                    "difftests",
+                    |scheduler, pkg_id, _| {
+                        scheduler.queue_after(RunDiffTest, &[diff_suite, c_artifact, pkg_id])
+                    },
+                    |ir, id| {
+                        let result = ir.get::<DiffTestResult>(id)?;
+                        Some((result.passed, result.mismatches().next().is_some()))
+                    },
+                    diff_result_id,
+                    current_pkg_id,
+                    current_build_id,
+                )?;
+            }
+            difftest = Some(([diff_suite, c_artifact], diff_result_id));
+        }
+
+        // Test vectors: an executable that builds is run on the test cases of the C program, and
+        // the output it gets wrong is repaired. Skipped for agentic, like the repair loop.
+        let builds = ir
+            .get::<CargoBuildResult>(current_build_id)
+            .is_some_and(|build| build.success);
+        if let Some(dir) = &config.test_vectors
+            && !is_library
+            && !config.agentic
+            && builds
+        {
+            let vectors = scheduler.queue(LoadTestVectors::new(dir));
+            let vectors_result =
+                scheduler.queue_after(RunTestVectors, &[vectors, current_build_id]);
+            scheduler.run_all(&mut runner, &mut ir, config.clone())?;
+            (current_pkg_id, current_build_id, _) = repair_behavior(
+                &mut scheduler,
+                &mut runner,
+                &mut ir,
+                &config,
+                &c_source,
+                "test vectors",
+                |scheduler, _, build_id| {
+                    scheduler.queue_after(RunTestVectors, &[vectors, build_id])
+                },
+                |ir, id| {
+                    let result = ir.get::<TestVectorResult>(id)?;
+                    Some((result.passed, !result.failures.is_empty()))
+                },
+                vectors_result,
+                current_pkg_id,
+                current_build_id,
+            )?;
+        }
+
+        // Cleanup: fix the warnings of rustc and clippy once the package builds, keeping it
+        // building and passing its difftests. Skipped for agentic, like the repair loop.
+        if !config.agentic {
+            (current_pkg_id, current_build_id) = cleanup(
+                &mut scheduler,
+                &mut runner,
+                &mut ir,
+                &config,
+                &c_source,
+                difftest,
+                current_pkg_id,
+                current_build_id,
+            )?;
         }
 
-        scheduler.queue_after(WriteOutput, &[current_build_id]);
+        // The provenance map is carried over to the final package and written out with it.
+        let mut output_inputs = vec![current_build_id];
+        if let Some(provenance) = provenance {
+            let (scheduler, runner, ir) = (&mut scheduler, &mut runner, &mut ir);
+            let inputs = [current_pkg_id];
+            let quantize = run_stage(scheduler, runner, ir, &config, QuantizeRustSpans, &inputs)?;
+            let updated = match quantize {
+                Some(quantize) => {
+                    let inputs = [provenance, quantize];
+                    run_stage(scheduler, runner, ir, &config, UpdateProvenance, &inputs)?
+                }
+                None => None,
+            };
+            if updated.is_none() {
+                warn!("The provenance map could not be carried over; the output gets none");
+            }
+            output_inputs.extend(updated);
+        }
+        scheduler.queue_after(WriteOutput, &output_inputs);
         scheduler.run_all(&mut runner, &mut ir, config)?;
 
         Ok(())
@@ -137,6 +349,495 @@ pub fn transpile(config: Arc<Config>) -> Result<HarvestIR, Box<dyn std::error::E
     Ok(ir)
 }
 
+/// Links the items of the translated package `pkg_id` to the C declarations they were translated
+/// from: exactly for a modular translation, whose `[parse_ast, names]` are `c_decls`, and by name
+/// for the others, once their C source is parsed. Each stage runs only if the previous one
+/// succeeded, and a failure leaves the map out rather than failing the run. Returns the ids of the
+/// C declarations, their Rust names and the [`provenance_map::ProvenanceMap`] -- those that could
+/// be built -- for the repair context, and the id of the map.
+fn map_provenance(
+    scheduler: &mut Scheduler,
+    runner: &mut ToolRunner,
+    ir: &mut HarvestIR,
+    config: &Arc<Config>,
+    [load_src, build_cfg, project_spec]: [Id; 3],
+    c_decls: Option<(Id, Id)>,
+    pkg_id: Id,
+) -> Result<(Vec<Id>, Option<Id>), Box<dyn std::error::Error>> {
+    let modular = c_decls.is_some();
+    let (parse_ast, names) = match c_decls {
+        Some((parse_ast, names)) => (parse_ast, Some(names)),
+        None => {
+            let inputs = [load_src, build_cfg];
+            let Some(parse_ast) = run_stage(scheduler, runner, ir, config, ParseToAst, &inputs)?
+            else {
+                warn!("The C source could not be parsed; the output gets no provenance map");
+                return Ok((Vec::new(), None));
+            };
+            let inputs = [parse_ast, project_spec];
+            let names = run_stage(scheduler, runner, ir, config, BuildNameMap, &inputs)?;
+            if names.is_none() {
+                warn!("No name map could be built; provenance is matched on C names only");
+            }
+            (parse_ast, names)
+        }
+    };
+    let mut c_source: Vec<Id> = [parse_ast].into_iter().chain(names).collect();
+    let inputs = [pkg_id];
+    let Some(quantize) = run_stage(scheduler, runner, ir, config, QuantizeRustSpans, &inputs)?
+    else {
+        warn!("The translated package could not be mapped; the output gets no provenance map");
+        return Ok((c_source, None));
+    };
+    let provenance = match names {
+        Some(names) if modular => {
+            let inputs = [quantize, parse_ast, project_spec, names];
+            run_stage(scheduler, runner, ir, config, ModularProvenance, &inputs)?
+        }
+        _ => {
+            let inputs: Vec<Id> = [quantize, parse_ast].into_iter().chain(names).collect();
+            run_stage(scheduler, runner, ir, config, MatchProvenance, &inputs)?
+        }
+    };
+    if provenance.is_none() {
+        warn!("No provenance map could be built; the output gets none");
+    }
+    c_source.extend(provenance);
+    Ok((c_source, provenance))
+}
+
+/// Runs `tool` on `inputs`, which must all be in `ir`, and returns the id of its result if it
+/// succeeded.
+fn run_stage<T: Tool>(
+    scheduler: &mut Scheduler,
+    runner: &mut ToolRunner,
+    ir: &mut HarvestIR,
+    config: &Arc<Config>,
+    tool: T,
+    inputs: &[Id],
+) -> Result<Option<Id>, Box<dyn std::error::Error>> {
+    let id = scheduler.queue_after(tool, inputs);
+    scheduler.run_all(runner, ir, config.clone())?;
+    Ok(ir.contains_id(id).then_some(id))
+}
+
+/// Runs up to `config.max_repair_passes` LLM-based repair passes on the package `pkg_id`, whose
+/// build result is `build_id`, stopping once it builds or `config.repair_patience` passes in a
+/// row have not reduced its errors. Before each pass, and after the last, the fixes rustc
+/// suggests are applied. The repair context draws on the C declarations, Rust names and
+/// provenance map of `c_source` (see [`map_provenance`]). A pass that fails ends the repair.
+/// Returns the package and build result with the fewest errors.
+fn repair(
+    scheduler: &mut Scheduler,
+    runner: &mut ToolRunner,
+    ir: &mut HarvestIR,
+    config: &Arc<Config>,
+    c_source: &[Id],
+    mut pkg_id: Id,
+    mut build_id: Id,
+) -> Result<(Id, Id), Box<dyn std::error::Error>> {
+    let mut tracker = RepairTracker::new(config.repair_patience);
+    for pass in 0..=config.max_repair_passes {
+        (pkg_id, build_id) = apply_suggestions(scheduler, runner, ir, config, pkg_id, build_id)?;
+        let build = ir
+            .get::<CargoBuildResult>(build_id)
+            .ok_or("transpile: no CargoBuildResult in IR")?;
+        let signatures = build.error_signatures();
+        let errors = signatures.len();
+        let progress = tracker.record(pkg_id, build_id, build.success, signatures);
+        if pass > 0 {
+            info!("Repair pass {pass}: {errors} errors ({progress:?})");
+        }
+        if progress == Progress::Built || pass == config.max_repair_passes {
+            break;
+        }
+        if tracker.stalled() {
+            warn!(
+                "Repair stopped after {pass} passes: {} passes without fewer errors",
+                config.repair_patience
+            );
+            break;
+        }
+        let fixed = fix_declarations(
+            scheduler,
+            runner,
+            ir,
+            config,
+            c_source,
+            pkg_id,
+            build_id,
+            &[],
+        )?;
+        let Some(fixed) = fixed else {
+            warn!("Repair pass {} failed; stopping the repair", pass + 1);
+            break;
+        };
+        (pkg_id, build_id) = fixed;
+    }
+    let Some((best_pkg, best_build, best_pass, best_errors)) = tracker.best() else {
+        return Ok((pkg_id, build_id));
+    };
+    if best_build != build_id {
+        info!("Keeping the package of repair pass {best_pass}, with {best_errors} errors");
+    }
+    Ok((best_pkg, best_build))
+}
+
+/// Fixes the declarations of the package `pkg_id` that its build result `build_id` and the check
+/// results `findings` point to, with the repair context of `c_source` (see [`repair`]), applies
+/// the fixes and builds the fixed package. Each stage runs only if the previous one succeeded.
+/// Returns the fixed package and its build result, or `None` if a stage failed, so a repair pass
+/// can be dropped rather than fail the run.
+#[allow(clippy::too_many_arguments)]
+fn fix_declarations(
+    scheduler: &mut Scheduler,
+    runner: &mut ToolRunner,
+    ir: &mut HarvestIR,
+    config: &Arc<Config>,
+    c_source: &[Id],
+    pkg_id: Id,
+    build_id: Id,
+    findings: &[Id],
+) -> Result<Option<(Id, Id)>, Box<dyn std::error::Error>> {
+    let Some(quantize) = run_stage(scheduler, runner, ir, config, QuantizeRustSpans, &[pkg_id])?
+    else {
+        return Ok(None);
+    };
+    let fix_inputs: Vec<Id> = [quantize, build_id]
+        .into_iter()
+        .chain(findings.iter().copied())
+        .chain(c_source.iter().copied())
+        .collect();
+    let Some(fix) = run_stage(
+        scheduler,
+        runner,
+        ir,
+        config,
+        FixDeclarationsLlm,
+        &fix_inputs,
+    )?
+    else {
+        return Ok(None);
+    };
+    let Some(fixed) = run_stage(scheduler, runner, ir, config, ApplyDeclarationFixes, &[fix])?
+    else {
+        return Ok(None);
+    };
+    let new_build = run_stage(scheduler, runner, ir, config, TryCargoBuild, &[fixed])?;
+    Ok(new_build.map(|new_build| (fixed, new_build)))
+}
+
+/// Applies the fixes rustc suggests for the errors of the build `build_id` of `pkg_id` and
+/// rebuilds, up to `max_passes` times (see [`apply_rustc_suggestions::Config`]), while the build
+/// fails with suggestions left to apply. Stops early once a pass changes nothing, because none of
+/// its suggestions applied, or fails, in which case that pass is dropped. Returns the final
+/// package and build result.
+fn apply_suggestions(
+    scheduler: &mut Scheduler,
+    runner: &mut ToolRunner,
+    ir: &mut HarvestIR,
+    config: &Arc<Config>,
+    mut pkg_id: Id,
+    mut build_id: Id,
+) -> Result<(Id, Id), Box<dyn std::error::Error>> {
+    let suggestions_config = apply_rustc_suggestions::Config::load(config)?;
+    for _ in 0..suggestions_config.max_passes {
+        let build = ir
+            .get::<CargoBuildResult>(build_id)
+            .ok_or("transpile: no CargoBuildResult in IR")?;
+        if build.success
+            || apply_rustc_suggestions::suggestions(
+                &build.diagnostics,
+                suggestions_config.maybe_incorrect,
+            )
+            .is_empty()
+        {
+            break;
+        }
+        let Some(fix) = run_stage(
+            scheduler,
+            runner,
+            ir,
+            config,
+            ApplyRustcSuggestions,
+            &[pkg_id, build_id],
+        )?
+        else {
+            warn!("Applying rustc suggestions failed; keeping the package without them");
+            break;
+        };
+        let files = |id: Id| {
+            ir.get::<CargoPackage>(id)
+                .map(|package| package.dir.files_recursive())
+        };
+        if files(fix) == files(pkg_id) {
+            info!("No rustc suggestion could be applied");
+            break;
+        }
+        let Some(new_build) = run_stage(scheduler, runner, ir, config, TryCargoBuild, &[fix])?
+        else {
+            warn!("Building the package with rustc's suggestions failed; keeping it without them");
+            break;
+        };
+        pkg_id = fix;
+        build_id = new_build;
+    }
+    Ok((pkg_id, build_id))
+}
+
+/// Runs up to `config.max_repair_passes` passes that fix what the package `pkg_id` does wrong
+/// according to the check result `result_id`, each followed by the build [`repair`] loop and a new
+/// run of the check. `check` queues the check of a package and its build result, and `outcome`
+/// reads how many of the `tests` of a check result passed and whether any failed. Stops once no
+/// test fails, the package does not build, or a pass fails or does not make more tests pass, in
+/// which case that pass is dropped. Returns the final package, build result and check result.
+#[allow(clippy::too_many_arguments)]
+fn repair_behavior(
+    scheduler: &mut Scheduler,
+    runner: &mut ToolRunner,
+    ir: &mut HarvestIR,
+    config: &Arc<Config>,
+    c_source: &[Id],
+    tests: &str,
+    check: impl Fn(&mut Scheduler, Id, Id) -> Id,
+    outcome: impl Fn(&HarvestIR, Id) -> Option<(usize, bool)>,
+    mut result_id: Id,
+    mut pkg_id: Id,
+    mut build_id: Id,
+) -> Result<(Id, Id, Id), Box<dyn std::error::Error>> {
+    for pass in 1..=config.max_repair_passes {
+        let success = ir
+            .get::<CargoBuildResult>(build_id)
+            .ok_or("transpile: no CargoBuildResult in IR")?
+            .success;
+        let Some((passed, failures)) = outcome(ir, result_id) else {
+            warn!("The {tests} did not run");
+            break;
+        };
+        if !success || !failures {
+            break;
+        }
+
+        let fixed = fix_declarations(
+            scheduler,
+            runner,
+            ir,
+            config,
+            c_source,
+            pkg_id,
+            build_id,
+            &[result_id],
+        )?;
+        let Some((fix, new_build)) = fixed else {
+            warn!("Repair pass {pass} of the {tests} failed; keeping the package from before it");
+            break;
+        };
+        let (fixed_pkg, fixed_build) =
+            repair(scheduler, runner, ir, config, c_source, fix, new_build)?;
+        let success = ir
+            .get::<CargoBuildResult>(fixed_build)
+            .ok_or("transpile: no CargoBuildResult in IR")?
+            .success;
+        if !success {
+            warn!(
+                "Repair pass {pass} of the {tests} broke the build; keeping the package from before it"
+            );
+            break;
+        }
+        let new_result = check(scheduler, fixed_pkg, fixed_build);
+        scheduler.run_all(runner, ir, config.clone())?;
+        let Some((new_passed, _)) = outcome(ir, new_result) else {
+            warn!("The {tests} did not run after repair pass {pass}");
+            break;
+        };
+        info!("Repair pass {pass} of the {tests}: {new_passed} passed, {passed} before");
+        if new_passed <= passed {
+            warn!("Repair pass {pass} of the {tests} dropped: no more of them pass");
+            break;
+        }
+        (pkg_id, build_id, result_id) = (fixed_pkg, fixed_build, new_result);
+    }
+    Ok((pkg_id, build_id, result_id))
+}
+
+/// Runs up to `max_passes` (see [`ClippyConfig`]) passes that fix the warnings `cargo clippy`
+/// reports for the package `pkg_id`, whose build result `build_id` succeeded. A pass is kept
+/// only if the package still builds with fewer warnings and, given `difftest` -- the
+/// `[diff_suite, c_artifact]` inputs of [`RunDiffTest`] and the result for `pkg_id` -- passes as
+/// many difftests; otherwise, or if the pass fails, the cleanup stops. Returns the final package
+/// and build result.
+#[allow(clippy::too_many_arguments)]
+fn cleanup(
+    scheduler: &mut Scheduler,
+    runner: &mut ToolRunner,
+    ir: &mut HarvestIR,
+    config: &Arc<Config>,
+    c_source: &[Id],
+    difftest: Option<([Id; 2], Id)>,
+    mut pkg_id: Id,
+    mut build_id: Id,
+) -> Result<(Id, Id), Box<dyn std::error::Error>> {
+    let clippy_config = ClippyConfig::load(config)?;
+    let success = ir
+        .get::<CargoBuildResult>(build_id)
+        .ok_or("transpile: no CargoBuildResult in IR")?
+        .success;
+    if clippy_config.max_passes == 0 || !success {
+        return Ok((pkg_id, build_id));
+    }
+    let passed = |ir: &HarvestIR, id: Id| ir.get::<DiffTestResult>(id).map(|r| r.passed);
+    let mut difftests_passed = difftest.and_then(|(_, result)| passed(ir, result));
+    let mut clippy = scheduler.queue_after(TryCargoClippy, &[pkg_id]);
+    scheduler.run_all(runner, ir, config.clone())?;
+
+    for pass in 1..=clippy_config.max_passes {
+        let Some(warnings) = ir
+            .get::<CargoClippyResult>(clippy)
+            .map(|c| c.warnings().count())
+        else {
+            warn!("cargo clippy did not run");
+            break;
+        };
+        info!("Cleanup: {warnings} warnings");
+        if warnings == 0 {
+            break;
+        }
+
+        let fixed = fix_declarations(
+            scheduler,
+            runner,
+            ir,
+            config,
+            c_source,
+            pkg_id,
+            build_id,
+            &[clippy],
+        )?;
+        let Some((fix, new_build)) = fixed else {
+            warn!("Cleanup pass {pass} failed; keeping the package from before it");
+            break;
+        };
+        let new_clippy = scheduler.queue_after(TryCargoClippy, &[fix]);
+        let new_difftest = difftest.map(|([diff_suite, c_artifact], _)| {
+            scheduler.queue_after(RunDiffTest, &[diff_suite, c_artifact, fix])
+        });
+        scheduler.run_all(runner, ir, config.clone())?;
+
+        let builds = ir
+            .get::<CargoBuildResult>(new_build)
+            .is_some_and(|b| b.success);
+        let fewer_warnings = ir
+            .get::<CargoClippyResult>(new_clippy)
+            .is_some_and(|c| c.warnings().count() < warnings);
+        let new_passed = new_difftest.and_then(|id| passed(ir, id));
+        let keeps_difftests = difftests_passed.is_none_or(|before| new_passed >= Some(before));
+        if !(builds && fewer_warnings && keeps_difftests) {
+            warn!(
+                "Cleanup pass {pass} dropped: builds: {builds}, fewer warnings: \
+                 {fewer_warnings}, keeps difftests passing: {keeps_difftests}"
+            );
+            break;
+        }
+        (pkg_id, build_id, clippy) = (fix, new_build, new_clippy);
+        difftests_passed = new_passed.or(difftests_passed);
+    }
+    Ok((pkg_id, build_id))
+}
+
+/// Runs up to `config.max_repair_passes` passes that check the interface of the library `pkg_id`
+/// against the C library and fix the differences, each followed by the build [`repair`] loop.
+/// The exported symbols are checked against `c_artifact` if it was built, and the type layouts if
+/// `layout_inputs` -- the `[load_src, parse_ast, names]` inputs of [`CheckLayout`] -- are given.
+/// The repair context draws on `c_source`, as in [`repair`]. Stops once no check finds a
+/// difference, the package does not build, or no check can run. A fix that fails, or after which
+/// the package no longer builds, is dropped.
+#[allow(clippy::too_many_arguments)]
+fn repair_interface(
+    scheduler: &mut Scheduler,
+    runner: &mut ToolRunner,
+    ir: &mut HarvestIR,
+    config: &Arc<Config>,
+    c_source: &[Id],
+    c_artifact: Id,
+    layout_inputs: Option<[Id; 3]>,
+    mut pkg_id: Id,
+    mut build_id: Id,
+) -> Result<(Id, Id), Box<dyn std::error::Error>> {
+    let names = layout_inputs.map(|[_, _, names]| names);
+    for _ in 0..config.max_repair_passes {
+        let success = ir
+            .get::<CargoBuildResult>(build_id)
+            .ok_or("transpile: no CargoBuildResult in IR")?
+            .success;
+        if !success {
+            break;
+        }
+        let abi = ir.get::<CArtifact>(c_artifact).is_some().then(|| {
+            let inputs: Vec<Id> = [c_artifact, pkg_id].into_iter().chain(names).collect();
+            scheduler.queue_after(CheckAbi, &inputs)
+        });
+        let layout = layout_inputs.map(|[load_src, parse_ast, names]| {
+            scheduler.queue_after(CheckLayout, &[load_src, parse_ast, pkg_id, names])
+        });
+        scheduler.run_all(runner, ir, config.clone())?;
+
+        let mut findings = Vec::new();
+        if let Some(abi) = abi {
+            match ir.get::<AbiCheckResult>(abi) {
+                Some(result) => {
+                    info!(
+                        "ABI check: {}/{} symbols match",
+                        result.matching(),
+                        result.c_symbols
+                    );
+                    if !result.problems.is_empty() {
+                        findings.push(abi);
+                    }
+                }
+                None => warn!("ABI check did not run"),
+            }
+        }
+        if let Some(layout) = layout {
+            match ir.get::<LayoutCheckResult>(layout) {
+                Some(result) => {
+                    info!(
+                        "Layout check: {}/{} types match",
+                        result.checked - result.mismatches.len(),
+                        result.checked
+                    );
+                    if !result.mismatches.is_empty() {
+                        findings.push(layout);
+                    }
+                }
+                None => warn!("Layout check did not run"),
+            }
+        }
+        if findings.is_empty() {
+            break;
+        }
+
+        let fixed = fix_declarations(
+            scheduler, runner, ir, config, c_source, pkg_id, build_id, &findings,
+        )?;
+        let Some((fix, new_build)) = fixed else {
+            warn!("The interface fixes failed; keeping the package from before them");
+            break;
+        };
+        let (fixed_pkg, fixed_build) =
+            repair(scheduler, runner, ir, config, c_source, fix, new_build)?;
+        let success = ir
+            .get::<CargoBuildResult>(fixed_build)
+            .ok_or("transpile: no CargoBuildResult in IR")?
+            .success;
+        if !success {
+            warn!("The interface fixes broke the build; keeping the package from before them");
+            break;
+        }
+        (pkg_id, build_id) = (fixed_pkg, fixed_build);
+    }
+    Ok((pkg_id, build_id))
+}
+
 #[cfg(not(miri))]
 #[cfg(test)]
 mod emit_build_features_tests {
@@ -0,0 +1,151 @@
+//! Tracks how the build errors change over the repair passes, so that the repair loop can stop
+//! once it stops making progress and keep the best package it produced rather than the last.
+
+use harvest_core::Id;
+
+/// How the build of a repair pass compares with the builds before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Progress {
+    /// The package builds.
+    Built,
+    /// Fewer errors than any build before, or the first build.
+    Improved,
+    /// The same errors as the previous build.
+    Unchanged,
+    /// The same errors as a build before the previous one.
+    Oscillating,
+    /// More errors than the best build.
+    Regressed,
+    /// Different errors, but not fewer than the best build.
+    NoBetter,
+}
+
+/// One recorded build.
+struct Pass {
+    pkg_id: Id,
+    build_id: Id,
+    success: bool,
+    signatures: Vec<String>,
+}
+
+impl Pass {
+    /// Orders builds from best to worst: a build that succeeds, then by error count.
+    fn rank(&self) -> (bool, usize) {
+        (!self.success, self.signatures.len())
+    }
+}
+
+/// The builds of the repair loop, the best of them, and how long since the last improvement.
+pub(crate) struct RepairTracker {
+    passes: Vec<Pass>,
+    best: usize,
+    patience: usize,
+    without_progress: usize,
+}
+
+impl RepairTracker {
+    /// A tracker that reports the loop stalled after `patience` passes in a row without progress.
+    pub(crate) fn new(patience: usize) -> Self {
+        Self {
+            passes: Vec::new(),
+            best: 0,
+            patience,
+            without_progress: 0,
+        }
+    }
+
+    /// Records the build `build_id` of the package `pkg_id`, which failed with the errors
+    /// `signatures` (see [`try_cargo_build::CargoBuildResult::error_signatures`]) unless
+    /// `success`.
+    pub(crate) fn record(
+        &mut self,
+        pkg_id: Id,
+        build_id: Id,
+        success: bool,
+        signatures: Vec<String>,
+    ) -> Progress {
+        let pass = Pass {
+            pkg_id,
+            build_id,
+            success,
+            signatures,
+        };
+        let progress = match self.passes.split_last() {
+            _ if pass.success => Progress::Built,
+            None => Progress::Improved,
+            Some(_) if pass.rank() < self.passes[self.best].rank() => Progress::Improved,
+            Some((previous, _)) if previous.signatures == pass.signatures => Progress::Unchanged,
+            Some((_, earlier)) if earlier.iter().any(|p| p.signatures == pass.signatures) => {
+                Progress::Oscillating
+            }
+            Some(_) if pass.rank() > self.passes[self.best].rank() => Progress::Regressed,
+            Some(_) => Progress::NoBetter,
+        };
+        if matches!(progress, Progress::Built | Progress::Improved) {
+            self.best = self.passes.len();
+            self.without_progress = 0;
+        } else {
+            self.without_progress += 1;
+        }
+        self.passes.push(pass);
+        progress
+    }
+
+    /// Whether the last `patience` passes all failed to improve on the best build.
+    pub(crate) fn stalled(&self) -> bool {
+        self.without_progress >= self.patience
+    }
+
+    /// The package and build result of the best build recorded, with its index among the
+    /// recorded builds and its error count.
+    pub(crate) fn best(&self) -> Option<(Id, Id, usize, usize)> {
+        let best = self.passes.get(self.best)?;
+        Some((best.pkg_id, best.build_id, self.best, best.signatures.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(signatures: &[&str]) -> Vec<String> {
+        signatures.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn keeps_the_best_build_and_detects_stalls() {
+        let ids: Vec<[Id; 2]> = (0..6).map(|_| Id::new_array()).collect();
+        let mut tracker = RepairTracker::new(3);
+        let mut record = |i: usize, signatures: &[&str]| {
+            let [pkg, build] = ids[i];
+            tracker.record(pkg, build, false, errors(signatures))
+        };
+        assert_eq!(record(0, &["E0308 a", "E0425 b"]), Progress::Improved);
+        assert_eq!(record(1, &["E0308 a"]), Progress::Improved);
+        assert_eq!(record(2, &["E0599 c"]), Progress::NoBetter);
+        assert_eq!(record(3, &["E0308 a"]), Progress::Oscillating);
+        assert_eq!(record(4, &["E0308 a"]), Progress::Unchanged);
+        assert!(tracker.stalled());
+        assert_eq!(
+            tracker.best(),
+            Some((ids[1][0], ids[1][1], 1, 1)),
+            "the first build with the fewest errors is kept"
+        );
+
+        let mut tracker = RepairTracker::new(1);
+        let [pkg, build] = ids[0];
+        tracker.record(pkg, build, false, errors(&["E0308 a"]));
+        let [pkg, build] = ids[5];
+        assert_eq!(
+            tracker.record(pkg, build, false, errors(&["E0308 a", "E0425 b"])),
+            Progress::Regressed
+        );
+        assert!(tracker.stalled());
+        assert_eq!(tracker.best().map(|best| best.0), Some(ids[0][0]));
+        assert_eq!(
+            tracker.record(pkg, build, true, Vec::new()),
+            Progress::Built
+        );
+        assert_eq!(tracker.best().map(|best| best.0), Some(pkg));
+    }
+}